@@ -0,0 +1,439 @@
+use crate::app::{App, HostField, HostForm, HostFormKind, InputMode};
+use crate::models::SshHost;
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::time::Instant;
+
+impl HostForm {
+    pub fn new(kind: HostFormKind, base: SshHost) -> Self {
+        let fields: &[HostField] = match kind {
+            HostFormKind::Move(_) => &[HostField::Group],
+            _ => &HostField::ALL,
+        };
+        let fields = fields
+            .iter()
+            .map(|&field| (field, Self::initial_value(&base, field)))
+            .collect();
+
+        Self {
+            kind,
+            base,
+            fields,
+            focused: 0,
+            error: None,
+        }
+    }
+
+    fn initial_value(host: &SshHost, field: HostField) -> String {
+        match field {
+            HostField::Alias => host.alias.clone(),
+            HostField::Host => host.host.clone(),
+            HostField::User => host.user.clone(),
+            HostField::Port => host.port.map(|p| p.to_string()).unwrap_or_default(),
+            HostField::Group => host.group.clone().unwrap_or_default(),
+            HostField::Description => host.description.clone().unwrap_or_default(),
+            HostField::IdentityFile => host.identity_file.clone().unwrap_or_default(),
+            HostField::ProxyJump => host.proxy_jump.join(","),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.kind {
+            HostFormKind::Add => " Add Host ".to_string(),
+            HostFormKind::Edit(alias) => format!(" Edit {} ", alias),
+            HostFormKind::Clone => format!(" Clone {} ", self.base.alias),
+            HostFormKind::Move(alias) => format!(" Move {} to Group ", alias),
+        }
+    }
+
+    fn value(&self, field: HostField) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, value)| value.trim())
+    }
+
+    fn focused_value_mut(&mut self) -> Option<&mut String> {
+        self.fields.get_mut(self.focused).map(|(_, value)| value)
+    }
+
+    /// Validate the form and build the resulting host. `hosts` is used to
+    /// reject aliases that are already taken.
+    pub fn build_host(&self, hosts: &[SshHost]) -> Result<SshHost, String> {
+        let mut host = self.base.clone();
+        host.session_known_hosts = None;
+
+        let original_alias = match &self.kind {
+            HostFormKind::Edit(alias) | HostFormKind::Move(alias) => Some(alias.as_str()),
+            HostFormKind::Add | HostFormKind::Clone => None,
+        };
+
+        for (field, _) in &self.fields {
+            let value = self.value(*field).unwrap_or_default();
+            let required = |name: &str| -> Result<String, String> {
+                if value.is_empty() {
+                    Err(format!("{} is required", name))
+                } else if value.contains(char::is_whitespace) {
+                    Err(format!("{} must not contain spaces", name))
+                } else {
+                    Ok(value.to_string())
+                }
+            };
+            let optional = || (!value.is_empty()).then(|| value.to_string());
+
+            match field {
+                HostField::Alias => {
+                    host.alias = required("Alias")?;
+                    let taken = hosts.iter().any(|other| {
+                        other.alias == host.alias && Some(other.alias.as_str()) != original_alias
+                    });
+                    if taken {
+                        return Err(format!("Alias '{}' is already in use", host.alias));
+                    }
+                }
+                HostField::Host => host.host = required("Host")?,
+                HostField::User => host.user = required("User")?,
+                HostField::Port => {
+                    host.port = if value.is_empty() {
+                        Some(22)
+                    } else {
+                        match value.parse::<u16>() {
+                            Ok(port) if port > 0 => Some(port),
+                            _ => return Err("Port must be a number from 1 to 65535".to_string()),
+                        }
+                    }
+                }
+                HostField::Group => {
+                    if value.is_empty() {
+                        return Err("Group is required".to_string());
+                    }
+                    host.group = Some(value.to_string());
+                }
+                HostField::Description => host.description = optional(),
+                HostField::IdentityFile => host.identity_file = optional(),
+                HostField::ProxyJump => {
+                    host.proxy_jump = value
+                        .split(',')
+                        .map(|jump| jump.trim().to_string())
+                        .filter(|jump| !jump.is_empty())
+                        .collect();
+                    if host.proxy_jump.iter().any(|jump| jump.contains(char::is_whitespace)) {
+                        return Err("Proxy jump hosts must not contain spaces".to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(host)
+    }
+}
+
+impl App {
+    /// Open the form for a new host, in the selected group when it's a
+    /// custom one
+    pub fn open_add_host_form(&mut self) {
+        let mut base = SshHost::new(String::new(), String::new(), String::new());
+        base.group = self
+            .get_current_group()
+            .filter(|group| *group != "Ungrouped")
+            .map(str::to_string);
+        self.open_host_form(HostFormKind::Add, base);
+    }
+
+    pub fn open_edit_host_form(&mut self) {
+        if let Some(host) = self.selected_custom_host("edited") {
+            self.open_host_form(HostFormKind::Edit(host.alias.clone()), host);
+        }
+    }
+
+    /// Clone the selected host into hosts.toml. Hosts from ~/.ssh/config can
+    /// be cloned too, which is how they become editable.
+    pub fn open_clone_host_form(&mut self) {
+        let Some(mut host) = self.get_current_selected_host().cloned() else {
+            return;
+        };
+        if host.group.is_none() {
+            // Reachability is shown in the description of ssh config hosts
+            host.description = None;
+        }
+        host.alias = format!("{}-copy", host.alias);
+        self.open_host_form(HostFormKind::Clone, host);
+    }
+
+    pub fn open_move_host_form(&mut self) {
+        if let Some(host) = self.selected_custom_host("moved") {
+            self.open_host_form(HostFormKind::Move(host.alias.clone()), host);
+        }
+    }
+
+    /// Ask for confirmation before deleting the selected host
+    pub fn request_delete_host(&mut self) {
+        if let Some(host) = self.selected_custom_host("deleted") {
+            self.pending_delete = Some(host.alias);
+        }
+    }
+
+    fn open_host_form(&mut self, kind: HostFormKind, base: SshHost) {
+        tracing::info!("Opening host form: {:?}", kind);
+        self.host_form = Some(HostForm::new(kind, base));
+        self.input_mode = InputMode::HostForm;
+    }
+
+    /// Selected host if it lives in hosts.toml, otherwise explain why it
+    /// can't be `action`
+    fn selected_custom_host(&mut self, action: &str) -> Option<SshHost> {
+        let host = self.get_current_selected_host()?.clone();
+        if host.group.is_none() {
+            self.status_message = Some((
+                format!(
+                    "{} comes from ~/.ssh/config and can't be {} here, clone it with [c]",
+                    host.alias, action
+                ),
+                Instant::now(),
+            ));
+            return None;
+        }
+        Some(host)
+    }
+
+    pub fn handle_host_form_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(form) = &mut self.host_form else {
+            self.input_mode = InputMode::Normal;
+            return Ok(());
+        };
+
+        match key.code {
+            KeyCode::Esc => {
+                self.host_form = None;
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => self.submit_host_form()?,
+            KeyCode::Tab | KeyCode::Down => {
+                form.focused = (form.focused + 1) % form.fields.len();
+            }
+            KeyCode::BackTab | KeyCode::Up => {
+                form.focused = (form.focused + form.fields.len() - 1) % form.fields.len();
+            }
+            KeyCode::Backspace => {
+                if let Some(value) = form.focused_value_mut() {
+                    value.pop();
+                }
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(value) = form.focused_value_mut() {
+                    value.clear();
+                }
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(value) = form.focused_value_mut() {
+                    value.push(c);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Keys for the delete confirmation: [y] deletes, anything else cancels
+    pub fn handle_delete_confirm_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(alias) = self.pending_delete.take() else {
+            return Ok(());
+        };
+        if !matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            self.status_message = Some(("Delete cancelled".to_string(), Instant::now()));
+            return Ok(());
+        }
+
+        let deleted = self.config_manager.delete_host(&alias);
+        match deleted.and_then(|()| self.reload_hosts(None)) {
+            Ok(()) => {
+                self.status_message = Some((format!("Deleted {}", alias), Instant::now()));
+            }
+            Err(e) => {
+                tracing::error!("Failed to delete host {}: {}", alias, e);
+                self.status_message = Some((format!("Delete failed: {}", e), Instant::now()));
+            }
+        }
+        Ok(())
+    }
+
+    fn submit_host_form(&mut self) -> Result<()> {
+        let Some(form) = self.host_form.as_mut() else {
+            return Ok(());
+        };
+        let host = match form.build_host(&self.hosts) {
+            Ok(host) => host,
+            Err(message) => {
+                form.error = Some(message);
+                return Ok(());
+            }
+        };
+        let kind = form.kind.clone();
+
+        let previous_alias = match &kind {
+            HostFormKind::Add | HostFormKind::Clone => None,
+            HostFormKind::Edit(alias) | HostFormKind::Move(alias) => Some(alias.as_str()),
+        };
+        let saved = self.config_manager.save_host(&host, previous_alias);
+        if let Err(e) = saved.and_then(|()| self.reload_hosts(Some(&host.alias))) {
+            tracing::error!("Failed to save hosts: {}", e);
+            if let Some(form) = self.host_form.as_mut() {
+                form.error = Some(format!("Save failed: {}", e));
+            }
+            return Ok(());
+        }
+
+        self.host_form = None;
+        self.input_mode = InputMode::Normal;
+        let done = match kind {
+            HostFormKind::Add => "Added",
+            HostFormKind::Edit(_) => "Saved",
+            HostFormKind::Clone => "Cloned to",
+            HostFormKind::Move(_) => "Moved",
+        };
+        self.status_message = Some((format!("{} {}", done, host.alias), Instant::now()));
+        Ok(())
+    }
+
+    /// Reload the hosts after hosts.toml changed, keeping `select` selected
+    /// if given
+    pub(super) fn reload_hosts(&mut self, select: Option<&str>) -> Result<()> {
+        self.load_all_hosts()?;
+
+        if let Some(alias) = select {
+            self.select_host_by_alias(alias);
+        }
+        Ok(())
+    }
+
+    /// Select the group of `alias` and the host itself
+    pub fn select_host_by_alias(&mut self, alias: &str) {
+        let Some(host) = self.find_host(alias) else {
+            return;
+        };
+        let group = host.group.clone().unwrap_or_else(|| "Ungrouped".to_string());
+        let Some(group_index) = self.groups.iter().position(|g| *g == group) else {
+            return;
+        };
+
+        self.selected_group = group_index;
+        self.group_list_state.select(Some(group_index));
+        self.update_hosts_for_selected_group();
+
+        if let Some(position) = self
+            .hosts_in_current_group
+            .iter()
+            .position(|&i| self.hosts[i].alias == alias)
+        {
+            self.selected_host = position;
+            self.host_list_state.select(Some(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(kind: HostFormKind, base: SshHost) -> HostForm {
+        HostForm::new(kind, base)
+    }
+
+    fn set(form: &mut HostForm, field: HostField, value: &str) {
+        form.fields.iter_mut().find(|(f, _)| *f == field).unwrap().1 = value.to_string();
+    }
+
+    fn existing() -> Vec<SshHost> {
+        let mut web = SshHost::new("web".into(), "10.0.0.1".into(), "deploy".into());
+        web.group = Some("Work".into());
+        web.options.insert("Compression".into(), "yes".into());
+        vec![web]
+    }
+
+    #[test]
+    fn add_form_validates_fields() {
+        let hosts = existing();
+        let mut add = form(
+            HostFormKind::Add,
+            SshHost::new(String::new(), String::new(), String::new()),
+        );
+        assert_eq!(add.build_host(&hosts).unwrap_err(), "Alias is required");
+
+        set(&mut add, HostField::Alias, "web");
+        set(&mut add, HostField::Host, "10.0.0.9");
+        set(&mut add, HostField::User, "root");
+        set(&mut add, HostField::Group, "Lab");
+        assert_eq!(add.build_host(&hosts).unwrap_err(), "Alias 'web' is already in use");
+
+        set(&mut add, HostField::Alias, "lab");
+        set(&mut add, HostField::Port, "70000");
+        assert_eq!(
+            add.build_host(&hosts).unwrap_err(),
+            "Port must be a number from 1 to 65535"
+        );
+
+        set(&mut add, HostField::Port, "");
+        set(&mut add, HostField::ProxyJump, "bastion, ops@jump:2200");
+        let host = add.build_host(&hosts).unwrap();
+        assert_eq!(host.port, Some(22));
+        assert_eq!(host.group.as_deref(), Some("Lab"));
+        assert_eq!(host.proxy_jump, ["bastion", "ops@jump:2200"]);
+        assert_eq!(host.description, None);
+    }
+
+    #[test]
+    fn edit_keeps_alias_and_hidden_settings() {
+        let hosts = existing();
+        let mut edit = form(HostFormKind::Edit("web".into()), hosts[0].clone());
+        set(&mut edit, HostField::Description, "Frontend");
+        let host = edit.build_host(&hosts).unwrap();
+        assert_eq!(host.alias, "web");
+        assert_eq!(host.description.as_deref(), Some("Frontend"));
+        assert_eq!(host.options["Compression"], "yes");
+    }
+
+    #[test]
+    fn move_form_only_changes_group() {
+        let hosts = existing();
+        let mut move_form = form(HostFormKind::Move("web".into()), hosts[0].clone());
+        assert_eq!(move_form.fields.len(), 1);
+        set(&mut move_form, HostField::Group, " ");
+        assert_eq!(move_form.build_host(&hosts).unwrap_err(), "Group is required");
+        set(&mut move_form, HostField::Group, "Home");
+        let host = move_form.build_host(&hosts).unwrap();
+        assert_eq!(host.group.as_deref(), Some("Home"));
+        assert_eq!(host.host, "10.0.0.1");
+    }
+
+    #[test]
+    fn saving_leaves_hosts_shadowed_by_ssh_config_alone() {
+        use crate::config::{AppConfig, ConfigManager};
+
+        let dir = std::env::temp_dir().join(format!("sshr-form-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ssh_config"), "Host nas\n    HostName 192.0.2.5\n").unwrap();
+        std::fs::write(
+            dir.join("hosts.toml"),
+            "[[groups]]\nname = \"Work\"\n\n\
+             [[groups.hosts]]\nalias = \"nas\"\nhost = \"192.0.2.6\"\nuser = \"admin\"\n\n\
+             [[groups.hosts]]\nalias = \"web\"\nhost = \"192.0.2.7\"\nuser = \"deploy\"\n",
+        )
+        .unwrap();
+
+        let config = AppConfig {
+            ssh_file_config: dir.join("ssh_config").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        let mut app = App::with_config(ConfigManager::in_dir(dir.clone()), config);
+        app.load_all_hosts().unwrap();
+        assert_eq!(app.find_host("nas").unwrap().group, None);
+
+        app.pending_delete = Some("web".into());
+        app.handle_delete_confirm_key(KeyEvent::from(KeyCode::Char('y'))).unwrap();
+        let saved = std::fs::read_to_string(dir.join("hosts.toml")).unwrap();
+        assert!(saved.contains("alias = \"nas\""));
+        assert!(!saved.contains("alias = \"web\""));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
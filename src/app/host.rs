@@ -1,8 +1,8 @@
-use crate::app::{App, InputMode};
+use crate::app::App;
 use crate::models::SshHost;
+use crate::ssh_config::SshConfig;
 use anyhow::{Context, Result};
 use std::collections::HashSet;
-use std::fs;
 use std::net::ToSocketAddrs;
 
 impl App {
@@ -11,7 +11,7 @@ impl App {
         // Extract unique group names from hosts
         let mut groups: Vec<String> = self.hosts
             .iter()
-            .filter_map(|host| host.group.clone())
+            .map(|host| host.group.clone().unwrap_or_else(|| "Ungrouped".to_string()))
             .collect::<std::collections::HashSet<_>>()
             .into_iter()
             .collect();
@@ -37,6 +37,11 @@ impl App {
         self.update_hosts_for_selected_group();
     }
 
+    /// Look up a host by its alias
+    pub fn find_host(&self, alias: &str) -> Option<&SshHost> {
+        self.hosts.iter().find(|host| host.alias == alias)
+    }
+
     pub fn load_all_hosts(&mut self) -> Result<()> {
         self.load_ssh_config()
             .context("Failed to load SSH config")?;
@@ -57,16 +62,8 @@ impl App {
     }
 
     pub fn load_ssh_config(&mut self) -> Result<()> {
-        // Clear only system-loaded hosts to allow custom hosts to persist across reloads
-        self.hosts.retain(|h| h.group.is_some()); // Retain only custom hosts (those with a group)
-
-        if !self.ssh_config_path.exists() {
-            tracing::warn!(
-                "System SSH config file not found at {:?}",
-                self.ssh_config_path
-            );
-            return Ok(());
-        }
+        // Start from scratch, custom hosts are read again from hosts.toml right after
+        self.hosts.clear();
 
         if !self.ssh_config_path.exists() {
             tracing::warn!(
@@ -76,57 +73,14 @@ impl App {
             return Ok(());
         }
 
-        let config_content =
-            fs::read_to_string(&self.ssh_config_path).context("Failed to read SSH config file")?;
-
-        let mut current_host: Option<SshHost> = None;
-
-        for line in config_content.lines() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-
-            if line.to_lowercase().starts_with("host ") {
-                // Save previous host if exists
-                if let Some(host) = current_host.take() {
-                    // Check if a host with this alias already exists from custom config
-                    if !self.hosts.iter().any(|h| h.alias == host.alias) {
-                        self.hosts.push(host);
-                    } else {
-                        tracing::warn!(
-                            "Skipping SSH config host '{}' as it's duplicated by a custom host.",
-                            host.alias
-                        );
-                    }
-                }
-
-                // Start new host
-                let alias = line[5..].trim().to_string();
-                current_host = Some(SshHost::new(alias, String::new(), "root".to_string()));
-            } else if let Some(host) = &mut current_host {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() < 2 {
-                    continue;
-                }
-
-                match parts[0].to_lowercase().as_str() {
-                    "hostname" => host.host = parts[1].to_string(),
-                    "user" => host.user = parts[1].to_string(),
-                    "port" => {
-                        if let Ok(port) = parts[1].parse::<u16>() {
-                            host.port = Some(port);
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
+        let ssh_config = SshConfig::load(&self.ssh_config_path)
+            .context("Failed to parse SSH config file")?;
+        let config_hosts = ssh_config.hosts();
 
-        tracing::info!("Loaded {} hosts from SSH config", self.hosts.len());
+        tracing::info!("Loaded {} hosts from SSH config", config_hosts.len());
 
-        // Don't forget to add the last host
-        if let Some(host) = current_host {
+        for host in config_hosts {
+            // Check if a host with this alias already exists from custom config
             if !self.hosts.iter().any(|h| h.alias == host.alias) {
                 self.hosts.push(host);
             } else {
@@ -186,8 +140,13 @@ impl App {
                 Ok(())
             }
             Err(e) => {
-                tracing::error!("Failed to load custom hosts: {}", e);
-                // Don't propagate error, just log it, so app can still run.
+                tracing::error!("Failed to load custom hosts: {:#}", e);
+                // Don't propagate error so the app can still run, but say why
+                // the custom hosts are missing
+                self.status_message = Some((
+                    format!("Custom hosts not loaded: {:#}", e),
+                    std::time::Instant::now(),
+                ));
                 Ok(())
             }
         }
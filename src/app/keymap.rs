@@ -1,7 +1,11 @@
 use crate::app::ActivePanel;
 use crate::app::{App, InputMode};
 use crate::app_event::SshEvent;
+use crate::editor;
+use crate::keybindings::{KeyMatch, KeyPress, Mode};
+use crate::known_hosts::HostKeyDecision;
 use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
 use std::sync::mpsc;
@@ -34,6 +38,30 @@ impl App {
         self.groups.get(self.selected_group).map(|s| s.as_str())
     }
 
+    /// Feed a key press to the keymap of `mode`. Keys that start a chord are
+    /// held until the chord completes or a key breaks it, in which case that
+    /// key is looked up on its own.
+    pub fn action_for_key(&mut self, mode: Mode, key: &KeyEvent) -> KeyMatch {
+        self.action_for_key_in(&[mode], key)
+    }
+
+    /// `action_for_key` for views whose keys are layered over another
+    /// mode's, looked up in the first of `modes` that knows them
+    pub fn action_for_key_in(&mut self, modes: &[Mode], key: &KeyEvent) -> KeyMatch {
+        self.pending_keys.push(KeyPress::from_event(key));
+        match self.keymap.resolve_layered(modes, &self.pending_keys) {
+            KeyMatch::Pending => KeyMatch::Pending,
+            KeyMatch::Unbound if self.pending_keys.len() > 1 => {
+                self.pending_keys.clear();
+                self.action_for_key_in(modes, key)
+            }
+            matched => {
+                self.pending_keys.clear();
+                matched
+            }
+        }
+    }
+
     // update_hosts_for_selected_group is now implemented in state.rs
     // Handle key
     pub fn handle_key_enter<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
@@ -62,7 +90,7 @@ impl App {
             });
 
             // Redraw UI to show loading
-            terminal.draw(|f| crate::ui::hosts_list::draw::<B>(f, self))?;
+            terminal.draw(|f| crate::ui::hosts_list::draw(f, self))?;
         }
         Ok(())
     }
@@ -72,9 +100,20 @@ impl App {
         Ok(())
     }
 
-    pub fn handle_key_e(&mut self) -> Result<()> {
-        // Get the path to the hosts file
-        let hosts_path = self.config_manager.get_hosts_path();
+    /// Edit hosts.toml in the user's editor, then reload it
+    pub fn handle_key_e<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        self.edit_hosts_file(terminal, None)
+    }
+
+    /// Run the editor on hosts.toml with the TUI suspended, optionally at
+    /// `line`. Hosts are only reloaded when the file still parses, otherwise
+    /// the error dialog offers to reopen the editor at the error.
+    pub fn edit_hosts_file<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        line: Option<usize>,
+    ) -> Result<()> {
+        let hosts_path = self.config_manager.get_hosts_path().to_path_buf();
 
         // Create the file if it doesn't exist
         if !hosts_path.exists() {
@@ -84,19 +123,46 @@ impl App {
             std::fs::write(&hosts_path, "")?;
         }
 
-        // TODO: Can use nvim, vim, nano if exist instead of default text editor
-        // Open the file with the default text editor
-        if let Err(e) = open::that(&hosts_path) {
-            tracing::error!("Failed to open editor: {}", e);
-            return Err(anyhow::anyhow!("Failed to open editor: {}", e));
+        Self::suspend_tui(terminal)?;
+        let edited = editor::open_in_editor(&hosts_path, line, &self.editors);
+        Self::resume_tui(terminal)?;
+        edited?;
+
+        if let Err(error) = self.config_manager.check_hosts_file() {
+            tracing::warn!("hosts.toml is invalid after editing: {}", error);
+            self.hosts_file_error = Some(error);
+            return Ok(());
         }
 
-        // Reload the config after the editor is closed
         self.load_all_hosts()?;
-
+        self.status_message = Some(("Hosts reloaded".to_string(), Instant::now()));
         Ok(())
     }
 
+    /// Keys for the invalid hosts.toml dialog: [Enter]/[e] reopen the editor
+    /// at the error, [Esc] keep the hosts loaded before the edit
+    pub fn handle_hosts_file_error_key<B: Backend>(
+        &mut self,
+        key: KeyEvent,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        match key.code {
+            KeyCode::Enter | KeyCode::Char('e') => {
+                let line = self.hosts_file_error.take().and_then(|error| error.line);
+                self.edit_hosts_file(terminal, line)
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.hosts_file_error = None;
+                self.status_message = Some((
+                    "hosts.toml has errors, custom hosts were not reloaded".to_string(),
+                    Instant::now(),
+                ));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn handle_key_esc(&mut self) -> Result<()> {
         self.input_mode = InputMode::Normal;
         Ok(())
@@ -127,4 +193,31 @@ impl App {
         self.previous_group();
         Ok(())
     }
+
+    /// Keys for the host key dialog: [o] accept once, [a] accept and save,
+    /// [Esc]/[n] abort
+    pub fn handle_host_key_prompt_key(&mut self, key: KeyEvent) -> Result<()> {
+        let decision = match key.code {
+            KeyCode::Char('o') => HostKeyDecision::AcceptOnce,
+            KeyCode::Char('a') => HostKeyDecision::AcceptAndSave,
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => HostKeyDecision::Abort,
+            _ => return Ok(()),
+        };
+
+        if let Some(prompt) = self.host_key_prompt.take() {
+            if prompt.reply.send(decision).is_err() {
+                tracing::warn!("Connection thread is gone, host key decision dropped");
+            }
+            self.status_message = Some((
+                match decision {
+                    HostKeyDecision::AcceptOnce => "Host key accepted for this session",
+                    HostKeyDecision::AcceptAndSave => "Host key saved to known_hosts",
+                    HostKeyDecision::Abort => "Host key rejected, connection aborted",
+                }
+                .to_string(),
+                Instant::now(),
+            ));
+        }
+        Ok(())
+    }
 }
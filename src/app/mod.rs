@@ -1,6 +1,9 @@
+mod form;
 mod host;
+mod mouse;
 mod state;
+mod theme;
 pub mod keymap;
 pub mod types;
 
-pub use types::{App, InputMode, ActivePanel, FilteredHost};
+pub use types::{App, InputMode, ActivePanel, FilteredHost, HostField, HostForm, HostFormKind};
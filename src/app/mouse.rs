@@ -0,0 +1,189 @@
+//! Mouse in the host list
+//!
+//! A click selects the group or host under the pointer, a double click on a
+//! host connects to it and the wheel moves through the list under the
+//! pointer. SFTP mode gets the events handed over.
+
+use anyhow::Result;
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::backend::Backend;
+use ratatui::Terminal;
+use std::time::{Duration, Instant};
+
+use crate::app::{ActivePanel, App, InputMode};
+use crate::sftp_logic::mouse::list_index;
+
+/// Longest time between the clicks of a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+impl App {
+    /// Whether a left click now at `column`, `row` completes a double click,
+    /// remembering it for the next one otherwise
+    fn is_double_click(&mut self, column: u16, row: u16) -> bool {
+        let now = Instant::now();
+        let double = matches!(
+            self.last_click,
+            Some((at, last_column, last_row))
+                if (last_column, last_row) == (column, row)
+                    && now.duration_since(at) <= DOUBLE_CLICK
+        );
+        self.last_click = if double { None } else { Some((now, column, row)) };
+        double
+    }
+
+    pub fn handle_mouse_event<B: Backend>(
+        &mut self,
+        event: MouseEvent,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        let double_click = event.kind == MouseEventKind::Down(MouseButton::Left)
+            && self.is_double_click(event.column, event.row);
+        if self.input_mode == InputMode::Sftp {
+            if let Some(sftp_state) = &mut self.sftp_state {
+                sftp_state.handle_mouse(event, double_click);
+            }
+            return Ok(());
+        }
+        // Dialogs and the host form are left to the keyboard
+        if self.input_mode == InputMode::HostForm
+            || self.host_form.is_some()
+            || self.pending_delete.is_some()
+            || self.hosts_file_error.is_some()
+            || self.host_key_prompt.is_some()
+            || self.is_connecting
+        {
+            return Ok(());
+        }
+        let Some(&(panel, area)) = self
+            .list_areas
+            .iter()
+            .find(|(_, area)| area.contains((event.column, event.row).into()))
+        else {
+            return Ok(());
+        };
+        let searching = self.input_mode == InputMode::Search;
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let offset = match panel {
+                    ActivePanel::Groups => self.group_list_state.offset(),
+                    ActivePanel::Hosts => self.host_list_state.offset(),
+                };
+                let Some(index) = list_index(area, offset, event.column, event.row) else {
+                    return Ok(());
+                };
+                match panel {
+                    ActivePanel::Groups if !searching && index < self.groups.len() => {
+                        if index != self.selected_group {
+                            self.selected_group = index;
+                            self.group_list_state.select(Some(index));
+                            self.update_hosts_for_selected_group();
+                            self.selected_host = 0;
+                            self.host_list_state.select(Some(0));
+                        }
+                        self.active_panel = ActivePanel::Groups;
+                        if double_click {
+                            self.switch_to_hosts();
+                        }
+                    }
+                    ActivePanel::Hosts if searching && index < self.filtered_hosts.len() => {
+                        self.search_selected = index;
+                        self.host_list_state.select(Some(index));
+                        if double_click {
+                            self.handle_key_enter(terminal)?;
+                            self.clear_search();
+                        }
+                    }
+                    ActivePanel::Hosts
+                        if !searching && index < self.hosts_in_current_group.len() =>
+                    {
+                        self.active_panel = ActivePanel::Hosts;
+                        self.selected_host = index;
+                        self.host_list_state.select(Some(index));
+                        if double_click {
+                            self.handle_key_enter(terminal)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let up = event.kind == MouseEventKind::ScrollUp;
+                match panel {
+                    ActivePanel::Groups if searching => {}
+                    ActivePanel::Groups => {
+                        self.active_panel = ActivePanel::Groups;
+                        if up {
+                            self.previous_group();
+                        } else {
+                            self.next_group();
+                        }
+                    }
+                    ActivePanel::Hosts if searching => {
+                        if up {
+                            self.search_select_previous();
+                        } else {
+                            self.search_select_next();
+                        }
+                    }
+                    ActivePanel::Hosts => {
+                        self.active_panel = ActivePanel::Hosts;
+                        if up {
+                            self.select_previous();
+                        } else {
+                            self.select_next();
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AppConfig, ConfigManager};
+    use crossterm::event::KeyModifiers;
+    use ratatui::backend::TestBackend;
+    use ratatui::layout::Rect;
+
+    fn click(column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn clicks_select_groups_and_hosts() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        // Nothing is read from this directory, it doesn't even exist
+        let config_dir = std::env::temp_dir().join(format!("sshr-mouse-{}", std::process::id()));
+        let mut app = App {
+            groups: vec!["web".into(), "db".into()],
+            hosts_in_current_group: vec![0, 1, 2],
+            list_areas: vec![
+                (ActivePanel::Groups, Rect::new(0, 0, 20, 10)),
+                (ActivePanel::Hosts, Rect::new(20, 0, 60, 10)),
+            ],
+            ..App::with_config(ConfigManager::in_dir(config_dir), AppConfig::default())
+        };
+
+        app.handle_mouse_event(click(30, 3), &mut terminal).unwrap();
+        assert_eq!((app.active_panel, app.selected_host), (ActivePanel::Hosts, 2));
+        app.handle_mouse_event(click(30, 7), &mut terminal).unwrap();
+        assert_eq!(app.selected_host, 2);
+
+        // Clicks on the same row of both lists are not a double click
+        app.handle_mouse_event(click(30, 1), &mut terminal).unwrap();
+        assert_eq!(app.selected_host, 0);
+        app.handle_mouse_event(click(5, 1), &mut terminal).unwrap();
+        assert_eq!((app.active_panel, app.selected_group), (ActivePanel::Groups, 0));
+        app.handle_mouse_event(click(5, 1), &mut terminal).unwrap();
+        assert_eq!(app.active_panel, ActivePanel::Hosts);
+    }
+}
@@ -1,11 +1,14 @@
-use crate::sftp_logic::types::{UploadProgress, DownloadProgress};
 use crate::app::App;
-use crate::config::ConfigManager;
+use crate::config::{AppConfig, ConfigManager, SftpConfig};
+use crate::editor;
 use crate::models::SshHost;
+use crate::sftp_logic::conflict::ConflictChoice;
+use crate::sftp_logic::file_ops::FileOperation;
+use crate::sftp_logic::sync::SyncDirection;
 use crate::sftp_logic::AppSftpState;
 use crate::ui;
 use anyhow::{Context, Result};
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
@@ -17,7 +20,10 @@ use std::sync::mpsc::{self, Sender};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc as tokio_mpsc;
 
-use crate::app_event::{SftpEvent, SshEvent, TransferEvent};
+use crate::app_event::{HostKeyPrompt, SftpEvent, SshEvent, TransferEvent};
+use crate::known_hosts::{self, HostKeyDecision, HostKeyInfo};
+use crate::keybindings::{Action, KeyMatch, Keymap, Mode};
+use crate::theme::{self, Palette};
 use ratatui::{backend::Backend, widgets::ListState, Terminal};
 use std::path::PathBuf;
 use std::thread;
@@ -35,8 +41,32 @@ impl Default for App {
             eprintln!("Failed to load config: {}", e);
             std::process::exit(1);
         });
+        Self::with_config(config_manager, app_config)
+    }
+}
 
+impl App {
+    /// App using `config_manager` for its files and `app_config` for its
+    /// settings, without any hosts loaded
+    pub fn with_config(config_manager: ConfigManager, app_config: AppConfig) -> Self {
         let ssh_config_path = PathBuf::from(app_config.ssh_file_config.clone());
+        let editors = app_config.editors.clone();
+        let sftp_config = app_config.sftp.clone();
+
+        let themes = theme::available_themes(&app_config.themes);
+        let theme_index = themes
+            .iter()
+            .position(|t| t.name == app_config.default_theme)
+            .unwrap_or(0);
+        let palette = Palette::from_theme(&themes[theme_index]).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using the built-in dark theme", e);
+            Palette::default()
+        });
+
+        let keymap = Keymap::from_config(&app_config.keybindings).unwrap_or_else(|e| {
+            eprintln!("Failed to load keybindings: {:#}", e);
+            std::process::exit(1);
+        });
 
         tracing::info!("SSH config path: {:?}", ssh_config_path);
         Self {
@@ -47,10 +77,21 @@ impl Default for App {
             active_panel: ActivePanel::Groups,
             ssh_config_path,
             config_manager,
+            editors,
+            sftp_config,
+            themes,
+            theme_index,
+            theme: palette,
+            keymap,
+            pending_keys: Vec::new(),
             input_mode: InputMode::Normal,
             is_connecting: false,
             connecting_host: None,
             status_message: None,
+            host_key_prompt: None,
+            host_form: None,
+            pending_delete: None,
+            hosts_file_error: None,
             // SSH
             ssh_receiver: None,
             ssh_ready_for_terminal: false,
@@ -72,11 +113,12 @@ impl Default for App {
 
             host_list_state: ListState::default(),
             group_list_state: ListState::default(),
+
+            list_areas: Vec::new(),
+            last_click: None,
         }
     }
-}
 
-impl App {
     pub fn new() -> Result<Self> {
         let mut app = Self::default();
         app.load_all_hosts().context("Failed to load hosts")?;
@@ -151,12 +193,6 @@ impl App {
         }
     }
 
-    pub fn get_current_host(&self) -> Option<&SshHost> {
-        self.hosts_in_current_group
-            .get(self.selected_host)
-            .and_then(|&idx| self.hosts.get(idx))
-    }
-
     // Improve navigation
     pub fn select_next(&mut self) {
         match self.active_panel {
@@ -203,35 +239,39 @@ impl App {
     }
 
     fn transition_to_ssh_mode<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
-        // Disable TUI mode
-        disable_raw_mode().context("Failed to disable raw mode for SSH")?;
-        let mut stdout = std::io::stdout();
-        execute!(&mut stdout, LeaveAlternateScreen, DisableMouseCapture)
-            .context("Failed to leave alternate screen for SSH")?;
-        terminal
-            .show_cursor()
-            .context("Failed to show cursor for SSH")?;
-
+        Self::suspend_tui(terminal)?;
         tracing::info!("TUI disabled for SSH mode - main thread will suspend polling");
         Ok(())
     }
 
     fn restore_tui_mode<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
-        // Re-enable TUI mode
-        enable_raw_mode().context("Failed to re-enable raw mode post-SSH")?;
+        Self::resume_tui(terminal)?;
+        tracing::info!("TUI restored after SSH session - resuming main thread polling");
+        Ok(())
+    }
+
+    /// Give the terminal back to a foreground program (ssh, an editor)
+    pub(crate) fn suspend_tui<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
+        disable_raw_mode().context("Failed to disable raw mode")?;
         let mut stdout = std::io::stdout();
-        execute!(&mut stdout, EnterAlternateScreen, EnableMouseCapture)
-            .context("Failed to re-enter alternate screen post-SSH")?;
+        execute!(&mut stdout, LeaveAlternateScreen, DisableMouseCapture)
+            .context("Failed to leave alternate screen")?;
+        terminal.show_cursor().context("Failed to show cursor")?;
+        Ok(())
+    }
 
-        terminal
-            .clear()
-            .context("Failed to clear terminal post-SSH")?;
-        tracing::info!("TUI restored after SSH session - resuming main thread polling");
+    /// Take the terminal back after `suspend_tui`
+    pub(crate) fn resume_tui<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
+        enable_raw_mode().context("Failed to re-enable raw mode")?;
+        let mut stdout = std::io::stdout();
+        execute!(&mut stdout, EnterAlternateScreen, EnableMouseCapture)
+            .context("Failed to re-enter alternate screen")?;
+        terminal.clear().context("Failed to clear terminal")?;
         Ok(())
     }
 
     // Worker function run in SSH thread
-    pub fn ssh_thread_worker(sender: Sender<SshEvent>, host: SshHost) {
+    pub fn ssh_thread_worker(sender: Sender<SshEvent>, mut host: SshHost) {
         tracing::info!("SSH thread started for host: {}", host.alias);
 
         // Send event connecting
@@ -240,6 +280,17 @@ impl App {
             return;
         }
 
+        // Make sure the host key is trusted before anything else talks to the host
+        if let Err(e) = known_hosts::verify_host_key(&mut host, |info| {
+            Self::ask_host_key(info, |prompt| {
+                sender.send(SshEvent::HostKeyPrompt(prompt)).is_ok()
+            })
+        }) {
+            tracing::error!("Host key check failed for {}: {}", host.alias, e);
+            let _ = sender.send(SshEvent::Error(format!("Host key check failed: {}", e)));
+            return;
+        }
+
         // Perform SSH connection test first
         match Self::test_ssh_connection(&host) {
             Ok(_) => {
@@ -275,32 +326,43 @@ impl App {
         tracing::info!("SSH thread ending for host: {}", host.alias);
     }
 
+    /// Hand a host key decision to the UI thread and block until it answers.
+    /// A dropped prompt counts as an abort.
+    fn ask_host_key<F>(info: &HostKeyInfo, send: F) -> HostKeyDecision
+    where
+        F: FnOnce(HostKeyPrompt) -> bool,
+    {
+        let (reply, answer) = mpsc::channel();
+        let prompt = HostKeyPrompt {
+            info: info.clone(),
+            reply,
+        };
+        if !send(prompt) {
+            return HostKeyDecision::Abort;
+        }
+        answer.recv().unwrap_or(HostKeyDecision::Abort)
+    }
+
     // Test SSH connection trước khi thực sự connect
     fn test_ssh_connection(host: &SshHost) -> Result<()> {
         use std::process::Command;
 
-        let port_str = host.port.unwrap_or(22).to_string();
-
         tracing::info!(
-            "Testing SSH connection to {}@{}:{}",
-            host.user,
-            host.host,
-            port_str
+            "Testing SSH connection to {}:{}",
+            host.destination(),
+            host.port.unwrap_or(22)
         );
 
         // Test connection with short timeout
         let output = Command::new("ssh")
-            .arg(format!("{}@{}", host.user, host.host))
-            .arg("-p")
-            .arg(&port_str)
+            .args(host.ssh_command_args())
             .arg("-o")
             .arg("ConnectTimeout=5")
             .arg("-o")
             .arg("BatchMode=yes")
             .arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg("-o")
             .arg("LogLevel=ERROR") // Reduce verbose output
+            .arg(host.destination())
             .arg("exit")
             .output()
             .context("Failed to test SSH connection")?;
@@ -318,24 +380,12 @@ impl App {
 
     // Execute SSH connection (blocking) - This gives complete control to SSH
     fn execute_ssh_blocking(host: &SshHost) -> Result<()> {
-        use std::process::Command;
+        let mut command = Self::ssh_session_command(host);
 
-        let port_str = host.port.unwrap_or(22).to_string();
-        let connection_str = format!("{}@{}", host.user, host.host);
-
-        tracing::info!("Executing SSH: ssh {} -p {}", connection_str, port_str);
+        tracing::info!("Executing SSH: {:?}", command);
 
         // Execute SSH with full control of terminal
-        let status = Command::new("ssh")
-            .arg(&connection_str)
-            .arg("-p")
-            .arg(&port_str)
-            .arg("-o")
-            .arg("ConnectTimeout=30")
-            .arg("-o")
-            .arg("ServerAliveInterval=60")
-            .arg("-o")
-            .arg("ServerAliveCountMax=3")
+        let status = command
             .stdin(std::process::Stdio::inherit())
             .stdout(std::process::Stdio::inherit())
             .stderr(std::process::Stdio::inherit())
@@ -352,6 +402,21 @@ impl App {
         }
     }
 
+    /// The interactive ssh command for a host, shared by the TUI and `sshr connect`
+    pub fn ssh_session_command(host: &SshHost) -> std::process::Command {
+        let mut command = std::process::Command::new("ssh");
+        command
+            .args(host.ssh_session_args())
+            .arg("-o")
+            .arg("ConnectTimeout=30")
+            .arg("-o")
+            .arg("ServerAliveInterval=60")
+            .arg("-o")
+            .arg("ServerAliveCountMax=3")
+            .arg(host.destination());
+        command
+    }
+
     // Process SSH events from channel
     pub fn process_ssh_events<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<bool> {
         if let Some(receiver) = &self.ssh_receiver {
@@ -363,6 +428,12 @@ impl App {
                             Some(("Testing connection...".to_string(), Instant::now()));
                         return Ok(false);
                     }
+                    SshEvent::HostKeyPrompt(prompt) => {
+                        self.status_message =
+                            Some(("Waiting for host key confirmation".to_string(), Instant::now()));
+                        self.host_key_prompt = Some(prompt);
+                        return Ok(true);
+                    }
                     SshEvent::Connected => {
                         self.status_message = Some((
                             "Connection successful! Launching SSH...".to_string(),
@@ -438,7 +509,7 @@ impl App {
                 })
                 .collect();
 
-            results.sort_by(|a, b| b.score.cmp(&a.score));
+            results.sort_by_key(|r| std::cmp::Reverse(r.score));
             self.filtered_hosts = results;
         }
 
@@ -458,7 +529,7 @@ impl App {
                 .hosts_in_current_group
                 .get(self.selected_host)
                 .and_then(|&idx| self.hosts.get(idx)),
-            InputMode::Sftp => None,
+            InputMode::Sftp | InputMode::HostForm => None,
         }
     }
 
@@ -509,84 +580,258 @@ impl App {
     /// Enter SFTP mode with the currently selected host
     pub fn enter_sftp_mode<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
         if let Some(selected_host) = self.get_current_selected_host().cloned() {
-            // Create channel for SFTP connection events
-            let (sftp_sender, sftp_receiver) = mpsc::channel::<SftpEvent>();
-            self.sftp_receiver = Some(sftp_receiver);
-
-            // Create channel for transfer progress events
-            let (transfer_sender, transfer_receiver) = tokio_mpsc::channel::<TransferEvent>(100);
-            self.transfer_receiver = Some(transfer_receiver);
-
-            // Turn on loading status
-            self.is_sftp_loading = true;
-            self.sftp_ready_for_terminal = true;
-            self.status_message = Some((
-                format!("Initializing SFTP for {}...", selected_host.alias),
-                Instant::now(),
-            ));
-
-            // Initialize AppSftpState asynchronously
-            let host_clone = selected_host.clone();
-            thread::spawn(move || {
-                Self::sftp_thread_worker(sftp_sender, host_clone, transfer_sender);
-            });
-
-            // Redraw UI to show loading
-            terminal.draw(|f| draw::<B>(f, self))?;
+            self.start_sftp_session(selected_host, terminal)?;
         }
         Ok(())
     }
 
+    /// Start SFTP mode for a given host, also used by `sshr sftp <alias>`
+    pub fn start_sftp_session<B: Backend>(
+        &mut self,
+        selected_host: SshHost,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        // Create channel for SFTP connection events
+        let (sftp_sender, sftp_receiver) = mpsc::channel::<SftpEvent>();
+        self.sftp_receiver = Some(sftp_receiver);
+
+        // Create channel for transfer progress events
+        let (transfer_sender, transfer_receiver) = tokio_mpsc::channel::<TransferEvent>(100);
+        self.transfer_receiver = Some(transfer_receiver);
+
+        // Turn on loading status
+        self.is_sftp_loading = true;
+        self.sftp_ready_for_terminal = true;
+        self.status_message = Some((
+            format!("Initializing SFTP for {}...", selected_host.alias),
+            Instant::now(),
+        ));
+
+        // Initialize AppSftpState asynchronously
+        let host_clone = selected_host.clone();
+        let sftp_config = self.sftp_config.clone();
+        thread::spawn(move || {
+            Self::sftp_thread_worker(sftp_sender, host_clone, sftp_config, transfer_sender);
+        });
+
+        // Redraw UI to show loading
+        terminal.draw(|f| draw(f, self))?;
+        Ok(())
+    }
+
     pub fn exit_sftp_mode(&mut self) {
         tracing::info!("Exiting SFTP mode");
+        let mut message = "Exited SFTP mode".to_string();
+        if let Some(sftp_state) = &self.sftp_state {
+            let alias = &sftp_state.host.alias;
+            if let Err(e) = self.config_manager.save_recent_dirs(alias, &sftp_state.recent) {
+                tracing::warn!("Failed to save recent directories of {}: {:#}", alias, e);
+                message = format!("Failed to save recent directories: {:#}", e);
+            }
+        }
         self.sftp_state = None;
         self.input_mode = InputMode::Normal;
         self.is_sftp_loading = false;
         self.sftp_ready_for_terminal = false;
-        self.status_message = Some(("Exited SFTP mode".to_string(), Instant::now()));
+        self.status_message = Some((message, Instant::now()));
     }
 
-    pub async fn handle_sftp_key(&mut self, key: KeyEvent) -> Result<()> {
+    pub async fn handle_sftp_key<B: Backend>(
+        &mut self,
+        key: KeyEvent,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        // Any key closes the summary of the last directory transfer
+        if let Some(sftp_state) = &mut self.sftp_state {
+            if sftp_state.transfer_summary.take().is_some() {
+                return Ok(());
+            }
+        }
+        if self.sftp_state.as_ref().is_some_and(|state| state.conflicts.is_some()) {
+            self.handle_conflict_key(key);
+            return Ok(());
+        }
         if let Some(sftp_state) = &mut self.sftp_state {
-            match key.code {
-                KeyCode::Char('q') => {
+            // An open file operation prompt takes every key until it closes
+            if sftp_state.prompt.is_some() {
+                Self::handle_file_prompt_key(sftp_state, key);
+                return Ok(());
+            }
+            // An edited file waiting to overwrite remote changes: [y] uploads
+            // it, any other key keeps the local copy
+            if sftp_state.pending_edit.is_some() {
+                sftp_state.confirm_edit_upload(matches!(
+                    key.code,
+                    KeyCode::Char('y') | KeyCode::Char('Y')
+                ));
+                return Ok(());
+            }
+        }
+        if self.sftp_state.as_ref().is_some_and(|state| state.history.is_some()) {
+            self.handle_history_key(key);
+            return Ok(());
+        }
+        if self.sftp_state.as_ref().is_some_and(|state| state.places.is_some()) {
+            self.handle_places_key(key);
+            return Ok(());
+        }
+        if self.sftp_state.as_ref().is_some_and(|state| state.sync.is_some()) {
+            self.handle_sync_key(key);
+            return Ok(());
+        }
+        if let Some(sftp_state) = &mut self.sftp_state {
+            if sftp_state.filtering {
+                Self::handle_filter_key(sftp_state, key);
+                return Ok(());
+            }
+            // After the jump key, a character selects the next entry
+            // starting with it
+            if sftp_state.jumping {
+                sftp_state.jumping = false;
+                sftp_state.clear_status_message();
+                if let KeyCode::Char(c) = key.code {
+                    sftp_state.jump_to(c);
+                }
+                return Ok(());
+            }
+        }
+
+        let action = match self.action_for_key(Mode::Sftp, &key) {
+            KeyMatch::Action(action) => action,
+            KeyMatch::Pending | KeyMatch::Unbound => return Ok(()),
+        };
+
+        let mut bookmarked = None;
+        if let Some(sftp_state) = &mut self.sftp_state {
+            match action {
+                Action::Quit => {
                     self.exit_sftp_mode();
                 }
-                KeyCode::Up => {
+                Action::Up => {
                     sftp_state.navigate_up();
                 }
-                KeyCode::Down => {
+                Action::Down => {
                     sftp_state.navigate_down();
                 }
-                KeyCode::Enter => {
+                Action::Open => {
                     if let Err(e) = sftp_state.open_selected() {
                         sftp_state.set_status_message(&format!("Error: {}", e));
                     }
                 }
-                KeyCode::Backspace => {
-                    if let Err(e) = sftp_state.open_selected() {
-                        // Assuming Backspace goes to parent
+                Action::Back => {
+                    if let Err(e) = sftp_state.go_back() {
                         sftp_state.set_status_message(&format!("Error: {}", e));
                     }
                 }
-                KeyCode::Tab => {
+                Action::SwitchPanel => {
                     sftp_state.switch_panel();
                 }
-                KeyCode::Char('u') => {
-                    if sftp_state.upload_progress.is_none() {
-                        sftp_state.upload_file();
-                    } else {
-                        sftp_state.set_status_message("Upload already in progress");
-                    }
+                Action::Mark => {
+                    sftp_state.toggle_mark();
+                }
+                Action::Upload => {
+                    sftp_state.upload_selected();
+                }
+                Action::Download => {
+                    sftp_state.download_selected();
+                }
+                Action::PauseTransfer => {
+                    sftp_state.toggle_pause_selected_transfer();
+                }
+                Action::CancelTransfer => {
+                    sftp_state.cancel_selected_transfer();
+                }
+                Action::RetryTransfer => {
+                    sftp_state.retry_selected_transfer();
+                }
+                Action::ClearTransfers => {
+                    sftp_state.clear_finished_transfers();
+                }
+                Action::ToggleVerify => {
+                    sftp_state.toggle_verify();
+                }
+                Action::Rename => {
+                    sftp_state.start_file_operation(FileOperation::Rename);
+                }
+                Action::DeleteEntry => {
+                    sftp_state.start_file_operation(FileOperation::Delete);
+                }
+                Action::MakeDir => {
+                    sftp_state.start_file_operation(FileOperation::MakeDir);
+                }
+                Action::NewFile => {
+                    sftp_state.start_file_operation(FileOperation::NewFile);
+                }
+                Action::Chmod => {
+                    sftp_state.start_file_operation(FileOperation::Chmod);
+                }
+                Action::Chown => {
+                    sftp_state.start_file_operation(FileOperation::Chown);
+                }
+                Action::CycleSort => {
+                    sftp_state.cycle_sort_key();
+                }
+                Action::ReverseSort => {
+                    sftp_state.reverse_sort();
+                }
+                Action::DirectoriesFirst => {
+                    sftp_state.toggle_directories_first();
+                }
+                Action::ToggleDetails => {
+                    sftp_state.toggle_details();
+                }
+                Action::TogglePreview => {
+                    sftp_state.toggle_preview();
                 }
-                KeyCode::Char('d') => {
-                    if sftp_state.download_progress.is_none() {
-                        sftp_state.download_file();
-                    } else {
-                        sftp_state.set_status_message("Download already in progress");
+                Action::ToggleHidden => {
+                    sftp_state.toggle_hidden();
+                }
+                Action::Filter => {
+                    sftp_state.start_filter();
+                }
+                Action::PageUp => {
+                    sftp_state.page_up();
+                }
+                Action::PageDown => {
+                    sftp_state.page_down();
+                }
+                Action::First => {
+                    sftp_state.select_first();
+                }
+                Action::Last => {
+                    sftp_state.select_last();
+                }
+                Action::JumpToLetter => {
+                    sftp_state.start_jump();
+                }
+                Action::EditFile => {
+                    if let Err(e) = Self::edit_remote_file(sftp_state, terminal, &self.editors) {
+                        tracing::error!("Failed to edit remote file: {:#}", e);
+                        sftp_state.set_status_message(&format!("Edit failed: {:#}", e));
                     }
                 }
-                KeyCode::Char('r') => {
+                Action::Sync => {
+                    sftp_state.start_sync(false);
+                }
+                Action::LimitTransfer => {
+                    sftp_state.start_transfer_limit();
+                }
+                Action::ToggleThrottle => {
+                    sftp_state.toggle_throttle();
+                }
+                Action::History => {
+                    sftp_state.open_history();
+                }
+                Action::GoTo => {
+                    sftp_state.start_file_operation(FileOperation::GoTo);
+                }
+                Action::Bookmark if sftp_state.toggle_bookmark() => {
+                    bookmarked = Some(sftp_state.host.clone());
+                }
+                Action::Places => {
+                    sftp_state.open_places();
+                }
+                Action::Reload => {
                     if let Err(e) = sftp_state.refresh_local() {
                         sftp_state.set_status_message(&format!("Local refresh error: {}", e));
                     }
@@ -597,22 +842,267 @@ impl App {
                 _ => {}
             }
         }
+        if let Some(host) = bookmarked {
+            self.save_bookmarks(&host);
+        }
         Ok(())
     }
 
+    /// Write the bookmarks of `host` to hosts.toml. Hosts that only come
+    /// from ~/.ssh/config keep them for this session.
+    fn save_bookmarks(&mut self, host: &SshHost) {
+        let saved = match self
+            .hosts
+            .iter()
+            .find(|saved| saved.alias == host.alias && saved.group.is_some())
+        {
+            Some(saved) => {
+                let saved = SshHost {
+                    local_bookmarks: host.local_bookmarks.clone(),
+                    remote_bookmarks: host.remote_bookmarks.clone(),
+                    ..saved.clone()
+                };
+                self.config_manager
+                    .save_host(&saved, None)
+                    .and_then(|()| self.reload_hosts(Some(&host.alias)))
+            }
+            None => Err(anyhow::anyhow!(
+                "{} is not in hosts.toml, bookmarks last for this session",
+                host.alias
+            )),
+        };
+        if let Err(e) = saved {
+            tracing::warn!("Failed to save bookmarks of {}: {:#}", host.alias, e);
+            if let Some(sftp_state) = &mut self.sftp_state {
+                sftp_state.set_status_message(&format!("Bookmark not saved: {:#}", e));
+            }
+        }
+    }
+
+    /// Keys of a transfer whose target exists, looked up in the conflict
+    /// keymap: overwrite, skip, rename or overwrite if newer, apply the
+    /// choice to all of them, or `cancel` to skip the rest
+    fn handle_conflict_key(&mut self, key: KeyEvent) {
+        let KeyMatch::Action(action) = self.action_for_key(Mode::Conflict, &key) else {
+            return;
+        };
+        let Some(sftp_state) = &mut self.sftp_state else {
+            return;
+        };
+        match action {
+            Action::Overwrite => sftp_state.resolve_conflict(ConflictChoice::Overwrite),
+            Action::Skip => sftp_state.resolve_conflict(ConflictChoice::Skip),
+            Action::Rename => sftp_state.resolve_conflict(ConflictChoice::Rename),
+            Action::OverwriteIfNewer => {
+                sftp_state.resolve_conflict(ConflictChoice::OverwriteIfNewer)
+            }
+            Action::ApplyToAll => sftp_state.toggle_conflict_apply_to_all(),
+            Action::Cancel => sftp_state.cancel_conflicts(),
+            _ => {}
+        }
+    }
+
+    /// Keys of the transfer history, looked up in the SFTP keymap: `open`
+    /// runs the selected transfer again, `quit`, `history` and [Esc] close
+    /// the history
+    fn handle_history_key(&mut self, key: KeyEvent) {
+        let action = match self.action_for_key(Mode::Sftp, &key) {
+            KeyMatch::Action(action) => Some(action),
+            KeyMatch::Pending => return,
+            KeyMatch::Unbound => None,
+        };
+        let Some(sftp_state) = &mut self.sftp_state else {
+            return;
+        };
+        let Some(history) = &mut sftp_state.history else {
+            return;
+        };
+        match action {
+            _ if key.code == KeyCode::Esc => sftp_state.history = None,
+            Some(Action::Quit | Action::History) => sftp_state.history = None,
+            Some(Action::Up) => history.navigate_up(),
+            Some(Action::Down) => history.navigate_down(),
+            Some(Action::Open) => sftp_state.rerun_selected_history(),
+            _ => {}
+        }
+    }
+
+    /// Keys of the bookmarks and recent directories, looked up in the SFTP
+    /// keymap: `open` goes to the selected one, `quit` and [Esc] close the
+    /// list
+    fn handle_places_key(&mut self, key: KeyEvent) {
+        let action = match self.action_for_key(Mode::Sftp, &key) {
+            KeyMatch::Action(action) => Some(action),
+            KeyMatch::Pending => return,
+            KeyMatch::Unbound => None,
+        };
+        let Some(sftp_state) = &mut self.sftp_state else {
+            return;
+        };
+        let Some(places) = &mut sftp_state.places else {
+            return;
+        };
+        match action {
+            _ if key.code == KeyCode::Esc => sftp_state.places = None,
+            Some(Action::Quit) => sftp_state.places = None,
+            Some(Action::Up) => places.navigate_up(),
+            Some(Action::Down) => places.navigate_down(),
+            Some(Action::Open) => sftp_state.go_to_selected_place(),
+            _ => {}
+        }
+    }
+
+    /// Keys for a file operation prompt: typing edits the input, [Enter]
+    /// applies and [Esc] cancels. Deletes are confirmed with [y], any other
+    /// key cancels them.
+    fn handle_file_prompt_key(sftp_state: &mut AppSftpState, key: KeyEvent) {
+        let Some(prompt) = &mut sftp_state.prompt else {
+            return;
+        };
+        if prompt.operation == FileOperation::Delete {
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                sftp_state.submit_file_prompt();
+            } else {
+                sftp_state.prompt = None;
+                sftp_state.set_status_message("Delete cancelled");
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Esc => sftp_state.prompt = None,
+            KeyCode::Enter => sftp_state.submit_file_prompt(),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                prompt.input.clear();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                prompt.input.push(c);
+            }
+            _ => {}
+        }
+    }
+
+    /// Keys of the directory comparison, looked up in the sync keymap and
+    /// then the SFTP one for moving around and `quit`. [Esc] closes it too.
+    /// A plan waiting for confirmation is applied with [y], any other key
+    /// goes back.
+    fn handle_sync_key(&mut self, key: KeyEvent) {
+        if let Some(sftp_state) = &mut self.sftp_state {
+            if let Some(view) = sftp_state.sync.as_mut().filter(|view| view.plan.is_some()) {
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    sftp_state.apply_sync();
+                } else {
+                    view.plan = None;
+                }
+                return;
+            }
+        }
+
+        let action = match self.action_for_key_in(&[Mode::Sync, Mode::Sftp], &key) {
+            KeyMatch::Action(action) => Some(action),
+            KeyMatch::Pending => return,
+            KeyMatch::Unbound => None,
+        };
+        let Some(sftp_state) = &mut self.sftp_state else {
+            return;
+        };
+        let Some(view) = &mut sftp_state.sync else {
+            return;
+        };
+        match action {
+            _ if key.code == KeyCode::Esc => sftp_state.sync = None,
+            Some(Action::Quit) => sftp_state.sync = None,
+            Some(Action::Up) => view.navigate_up(),
+            Some(Action::Down) => view.navigate_down(),
+            Some(Action::First) => view.selected = 0,
+            Some(Action::Last) => view.selected = view.entries.len().saturating_sub(1),
+            Some(Action::Mark) => view.toggle_mark(),
+            Some(Action::ToggleDelete) => view.toggle_delete(),
+            Some(Action::Upload) => sftp_state.plan_sync(SyncDirection::Upload),
+            Some(Action::Download) => sftp_state.plan_sync(SyncDirection::Download),
+            Some(Action::SyncMarked) => sftp_state.plan_sync(SyncDirection::Marked),
+            Some(Action::ToggleChecksum) => {
+                let checksum = !view.checksum;
+                sftp_state.start_sync(checksum);
+            }
+            _ => {}
+        }
+    }
+
+    /// Download the selected remote file, open it in the editor with the TUI
+    /// suspended and upload it back if it was changed
+    fn edit_remote_file<B: Backend>(
+        sftp_state: &mut AppSftpState,
+        terminal: &mut Terminal<B>,
+        editors: &[String],
+    ) -> Result<()> {
+        let Some(edit) = sftp_state.start_remote_edit()? else {
+            return Ok(());
+        };
+        Self::suspend_tui(terminal)?;
+        let edited = editor::open_in_editor(&edit.local_path, None, editors);
+        Self::resume_tui(terminal)?;
+        if let Err(e) = edited {
+            edit.discard();
+            return Err(e);
+        }
+        sftp_state.finish_remote_edit(edit);
+        Ok(())
+    }
+
+    /// Keys while typing the filter of a file panel: the list narrows with
+    /// each key, [Enter] keeps the filter and [Esc] drops it. Up and Down
+    /// still move the selection.
+    fn handle_filter_key(sftp_state: &mut AppSftpState, key: KeyEvent) {
+        let mut filter = sftp_state.active_filter().unwrap_or_default().to_string();
+        match key.code {
+            KeyCode::Esc => {
+                sftp_state.filtering = false;
+                filter.clear();
+            }
+            KeyCode::Enter => {
+                sftp_state.filtering = false;
+                return;
+            }
+            KeyCode::Up => return sftp_state.navigate_up(),
+            KeyCode::Down => return sftp_state.navigate_down(),
+            KeyCode::Backspace => {
+                filter.pop();
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                filter.clear();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                filter.push(c);
+            }
+            _ => return,
+        }
+        sftp_state.set_filter(filter);
+    }
+
     // Process SFTP events from channel
-    pub fn process_sftp_events<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<bool> {
+    pub fn process_sftp_events<B: Backend>(&mut self, _terminal: &mut Terminal<B>) -> Result<bool> {
         if let Some(receiver) = &self.sftp_receiver {
             // Non-blocking receive
             if let Ok(event) = receiver.try_recv() {
                 match event {
-                    SftpEvent::PreConnected(sftp_state) => {
-                        self.sftp_state = Some(sftp_state);
+                    SftpEvent::PreConnected(mut sftp_state) => {
+                        let alias = sftp_state.host.alias.clone();
+                        match self.config_manager.load_recent_dirs(&alias) {
+                            Ok(recent) => sftp_state.restore_recent(recent),
+                            Err(e) => tracing::warn!("Failed to load recent directories: {:#}", e),
+                        }
+                        sftp_state.history_file =
+                            Some(self.config_manager.transfer_history_file().to_path_buf());
+                        self.sftp_state = Some(*sftp_state);
                         self.input_mode = InputMode::Sftp;
                         self.status_message = Some((
                             format!(
                                 "SFTP mode active for {}",
-                                self.sftp_state.as_ref().unwrap().ssh_host
+                                self.sftp_state.as_ref().unwrap().host.host
                             ),
                             Instant::now(),
                         ));
@@ -623,6 +1113,12 @@ impl App {
                             Some(("Testing connection...".to_string(), Instant::now()));
                         return Ok(false);
                     }
+                    SftpEvent::HostKeyPrompt(prompt) => {
+                        self.status_message =
+                            Some(("Waiting for host key confirmation".to_string(), Instant::now()));
+                        self.host_key_prompt = Some(prompt);
+                        return Ok(true);
+                    }
                     SftpEvent::Connected => {
                         self.status_message = Some((
                             "Connection successful! Launching SFTP...".to_string(),
@@ -655,7 +1151,7 @@ impl App {
         }
         // Check if we need to redraw due to upload progress
         if let Some(sftp_state) = &self.sftp_state {
-            if sftp_state.upload_progress.is_some() || sftp_state.download_progress.is_some() {
+            if sftp_state.queue.is_active() {
                 return Ok(true);
             }
         }
@@ -664,57 +1160,25 @@ impl App {
 
     // Process transfer events from channel
     pub fn process_transfer_events(&mut self) -> Result<bool> {
+        let mut redraw = false;
         if let Some(receiver) = &mut self.transfer_receiver {
-            if let Ok(event) = receiver.try_recv() {
+            // Transfers send progress for every chunk, so drain everything
+            // pending rather than one event per frame
+            while let Ok(event) = receiver.try_recv() {
                 if let Some(sftp_state) = &mut self.sftp_state {
-                    match event {
-                        TransferEvent::UploadProgress(file_name, uploaded, total) => {
-                            sftp_state.upload_progress = Some(UploadProgress {
-                                file_name,
-                                uploaded_size: uploaded,
-                                total_size: total,
-                            });
-                        }
-                        TransferEvent::UploadComplete(file_name) => {
-                            sftp_state.upload_progress = None;
-                            tracing::info!("Successfully uploaded {}", file_name);
-                            sftp_state.set_status_message(&format!("Successfully uploaded {}", file_name));
-                            let _ = sftp_state.refresh_remote();
-                        }
-                        TransferEvent::UploadError(file_name, error) => {
-                            sftp_state.upload_progress = None;
-                            sftp_state.set_status_message(&format!("Upload failed for {}: {}", file_name, error));
-                            let _ = sftp_state.refresh_remote();
-                        }
-                        TransferEvent::DownloadProgress(file_name, downloaded, total) => {
-                            tracing::info!("Downloading {}", file_name);
-                            sftp_state.download_progress = Some(DownloadProgress {
-                                file_name,
-                                downloaded_size: downloaded,
-                                total_size: total,
-                            });
-                        }
-                        TransferEvent::DownloadComplete(file_name) => {
-                            sftp_state.download_progress = None;
-                            sftp_state.set_status_message(&format!("Successfully downloaded {}", file_name));
-                            let _ = sftp_state.refresh_local();
-                        }
-                        TransferEvent::DownloadError(file_name, error) => {
-                            sftp_state.download_progress = None;
-                            sftp_state.set_status_message(&format!("Download failed for {}: {}", file_name, error));
-                        }
-                    }
-                    return Ok(true); // Redraw needed
+                    sftp_state.apply_transfer_event(event);
+                    redraw = true;
                 }
             }
         }
-        Ok(false)
+        Ok(redraw)
     }
 
     // Worker function run in SFTP thread
     fn sftp_thread_worker(
         sender: Sender<SftpEvent>,
-        host: SshHost,
+        mut host: SshHost,
+        config: SftpConfig,
         transfer_tx: tokio_mpsc::Sender<TransferEvent>,
     ) {
         tracing::info!("SFTP thread started for host: {}", host.alias);
@@ -725,18 +1189,23 @@ impl App {
             return;
         }
 
+        if let Err(e) = known_hosts::verify_host_key(&mut host, |info| {
+            Self::ask_host_key(info, |prompt| {
+                sender.send(SftpEvent::HostKeyPrompt(prompt)).is_ok()
+            })
+        }) {
+            tracing::error!("Host key check failed for {}: {}", host.alias, e);
+            let _ = sender.send(SftpEvent::Error(format!("Host key check failed: {}", e)));
+            return;
+        }
+
         // Perform SSH connection test first
-        match AppSftpState::new(
-            &host.user,
-            &host.host,
-            host.port.unwrap_or(22),
-            transfer_tx,
-        ) {
+        match AppSftpState::new(&host, config, transfer_tx) {
             Ok(sftp_state) => {
                 tracing::info!("SFTP connection test successful for {}", host.alias);
 
                 // Send PreConnected event
-                if sender.send(SftpEvent::PreConnected(sftp_state)).is_err() {
+                if sender.send(SftpEvent::PreConnected(Box::new(sftp_state))).is_err() {
                     tracing::error!("Failed to send PreConnected event");
                     return;
                 }
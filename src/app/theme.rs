@@ -0,0 +1,43 @@
+use crate::app::App;
+use crate::theme::Palette;
+use anyhow::Result;
+use std::time::Instant;
+
+impl App {
+    /// Switch to the theme called `name`
+    pub fn set_theme(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .themes
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.themes.iter().map(|t| t.name.as_str()).collect();
+                anyhow::anyhow!("Unknown theme '{}' (available: {})", name, names.join(", "))
+            })?;
+
+        self.theme = Palette::from_theme(&self.themes[index])?;
+        self.theme_index = index;
+        tracing::info!("Theme set to {}", name);
+        Ok(())
+    }
+
+    /// Cycle to the next theme, skipping themes with invalid colors
+    pub fn next_theme(&mut self) {
+        let total = self.themes.len();
+        for step in 1..=total {
+            let index = (self.theme_index + step) % total;
+            match Palette::from_theme(&self.themes[index]) {
+                Ok(palette) => {
+                    self.theme = palette;
+                    self.theme_index = index;
+                    self.status_message = Some((
+                        format!("Theme: {}", self.themes[index].name),
+                        Instant::now(),
+                    ));
+                    return;
+                }
+                Err(e) => tracing::warn!("Skipping theme: {:#}", e),
+            }
+        }
+    }
+}
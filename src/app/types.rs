@@ -1,9 +1,13 @@
 use std::path::PathBuf;
 
 use crate::sftp_logic::AppSftpState;
-use crate::{config::ConfigManager, models::SshHost};
+use crate::config::{ConfigManager, HostsFileError, SftpConfig, Theme};
+use crate::keybindings::{KeyPress, Keymap};
+use crate::theme::Palette;
+use crate::models::SshHost;
 
-use crate::app_event::{SftpEvent, SshEvent, TransferEvent};
+use crate::app_event::{HostKeyPrompt, SftpEvent, SshEvent, TransferEvent};
+use ratatui::layout::Rect;
 use ratatui::widgets::ListState;
 use std::sync::mpsc::Receiver;
 use tokio::sync::mpsc as tokio_mpsc;
@@ -19,6 +23,67 @@ pub enum InputMode {
     Normal,
     Search,
     Sftp,
+    HostForm,
+}
+
+/// Fields of the host form, in display order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostField {
+    Alias,
+    Host,
+    User,
+    Port,
+    Group,
+    Description,
+    IdentityFile,
+    ProxyJump,
+}
+
+impl HostField {
+    pub const ALL: [HostField; 8] = [
+        HostField::Alias,
+        HostField::Host,
+        HostField::User,
+        HostField::Port,
+        HostField::Group,
+        HostField::Description,
+        HostField::IdentityFile,
+        HostField::ProxyJump,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HostField::Alias => "Alias",
+            HostField::Host => "Host",
+            HostField::User => "User",
+            HostField::Port => "Port",
+            HostField::Group => "Group",
+            HostField::Description => "Description",
+            HostField::IdentityFile => "Identity file",
+            HostField::ProxyJump => "Proxy jump",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostFormKind {
+    Add,
+    /// Edit the custom host with this alias
+    Edit(String),
+    Clone,
+    /// Move the custom host with this alias to another group
+    Move(String),
+}
+
+/// Modal form for adding, editing, cloning and moving custom hosts
+#[derive(Debug, Clone)]
+pub struct HostForm {
+    pub kind: HostFormKind,
+    /// Host the form started from, keeps the settings the form doesn't show
+    pub base: SshHost,
+    pub fields: Vec<(HostField, String)>,
+    pub focused: usize,
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,10 +102,30 @@ pub struct App {
     pub active_panel: ActivePanel,
     pub ssh_config_path: PathBuf,
     pub config_manager: ConfigManager,
+    pub editors: Vec<String>,
+    pub sftp_config: SftpConfig,
+
+    // Themes from sshr.toml plus the built-in ones, and the active palette
+    pub themes: Vec<Theme>,
+    pub theme_index: usize,
+    pub theme: Palette,
+
+    // Keys bound to actions, and the keys of a chord typed so far
+    pub keymap: Keymap,
+    pub pending_keys: Vec<KeyPress>,
     pub input_mode: InputMode,
 
     pub status_message: Option<(String, std::time::Instant)>,
 
+    // Host key dialog waiting for an answer
+    pub host_key_prompt: Option<HostKeyPrompt>,
+
+    // Host management
+    pub host_form: Option<HostForm>,
+    pub pending_delete: Option<String>,
+    // hosts.toml failed to parse after editing
+    pub hosts_file_error: Option<HostsFileError>,
+
     // SSH Mode
     pub is_connecting: bool,
     pub connecting_host: Option<SshHost>,
@@ -66,4 +151,8 @@ pub struct App {
 
     pub host_list_state: ListState,
     pub group_list_state: ListState,
+
+    // Mouse: where the lists were last drawn, and the last left click
+    pub list_areas: Vec<(ActivePanel, Rect)>,
+    pub last_click: Option<(std::time::Instant, u16, u16)>,
 }
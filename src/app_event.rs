@@ -1,8 +1,23 @@
+use crate::known_hosts::{HostKeyDecision, HostKeyInfo};
+use crate::sftp_logic::checksum::ChecksumMismatch;
+use crate::sftp_logic::preview::Preview;
+use crate::sftp_logic::queue::TransferId;
+use crate::sftp_logic::sync::SyncView;
+use crate::sftp_logic::types::{FileProgress, TransferSummary, TreeProgress};
 use crate::sftp_logic::AppSftpState;
+use std::sync::mpsc::Sender;
+
+/// Host key decision requested by a connection thread, answered on `reply`
+#[derive(Debug, Clone)]
+pub struct HostKeyPrompt {
+    pub info: HostKeyInfo,
+    pub reply: Sender<HostKeyDecision>,
+}
 
 #[derive(Debug, Clone)]
 pub enum SshEvent {
     Connecting,
+    HostKeyPrompt(HostKeyPrompt),
     Connected,
     Error(String),
     Disconnected,
@@ -11,19 +26,36 @@ pub enum SshEvent {
 #[derive(Debug, Clone)]
 pub enum SftpEvent {
     Connecting,
-    PreConnected(AppSftpState),
+    HostKeyPrompt(HostKeyPrompt),
+    PreConnected(Box<AppSftpState>),
     Connected,
     #[allow(dead_code)]
     Disconnected,
     Error(String),
 }
 
+/// Updates from a transfer of the SFTP queue, and other work SFTP mode does
+/// off the UI thread
 #[derive(Debug, Clone)]
 pub enum TransferEvent {
-    UploadProgress(String, u64, u64),
-    UploadComplete(String),
-    UploadError(String, String),
-    DownloadProgress(String, u64, u64),
-    DownloadComplete(String),
-    DownloadError(String, String),
+    /// Progress of the current file, and of the whole directory for
+    /// directory transfers
+    Progress(TransferId, FileProgress, Option<TreeProgress>),
+    /// Finished, with the summary of a directory transfer and the SHA-256
+    /// of a verified file
+    Complete(TransferId, Option<TransferSummary>, Option<String>),
+    Failed(TransferId, String),
+    Cancelled(TransferId),
+    /// Lost the connection, trying again for the given time with the error
+    Retrying(TransferId, u32, String),
+    /// Copied, now comparing the SHA-256 hashes of both sides
+    Verifying(TransferId),
+    /// Copied, but these files differ from their source. Directory
+    /// transfers list them in their summary as well.
+    Mismatch(TransferId, Vec<ChecksumMismatch>, Option<TransferSummary>),
+    /// Preview of an entry, read for the preview pane
+    Preview(Box<Preview>),
+    /// Differences between the local and the remote directory, or why they
+    /// could not be compared
+    Compared(Box<Result<SyncView, String>>),
 }
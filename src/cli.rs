@@ -0,0 +1,460 @@
+//! Non-interactive subcommands (`sshr list`, `sshr connect`, ...)
+//!
+//! Every command loads hosts through `App::load_all_hosts`, so the hosts and
+//! their settings are exactly what the TUI would use.
+
+use anyhow::{bail, Context, Result};
+use clap::{Subcommand, ValueEnum};
+use std::io::{self, BufRead, Write};
+use std::process::Command as ProcessCommand;
+
+use crate::app::App;
+use crate::config::{HostGroup, HostsConfig};
+use crate::known_hosts::{self, HostKeyDecision, HostKeyInfo};
+use crate::models::{self, SshHost};
+use crate::sftp_logic::history::{self, TransferRecord};
+use crate::sftp_logic::queue::TransferDirection;
+use crate::shell;
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List hosts
+    List {
+        /// Only show hosts in this group
+        #[arg(short, long)]
+        group: Option<String>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+    /// Connect to a host with ssh, without starting the TUI
+    Connect {
+        /// Host alias
+        alias: String,
+    },
+    /// Print the resolved ssh command line and settings of a host
+    Show {
+        /// Host alias
+        alias: String,
+    },
+    /// Print every host in another format, to migrate or back up hosts
+    Export {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::SshConfig)]
+        format: ExportFormat,
+    },
+    /// Open the TUI directly in SFTP mode for a host
+    Sftp {
+        /// Host alias
+        alias: String,
+    },
+    /// Print finished SFTP transfers, newest first
+    History {
+        /// Only show transfers of this host
+        alias: Option<String>,
+        /// How many transfers to show, 0 for all
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ListFormat {
+    Table,
+    Json,
+    Plain,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// `Host` blocks for ~/.ssh/config
+    SshConfig,
+    /// Groups in the format of hosts.toml
+    Toml,
+    Json,
+}
+
+/// Run a subcommand that does not need the TUI
+pub fn run(app: &App, command: Command) -> Result<()> {
+    match command {
+        Command::List { group, format } => list(app, group.as_deref(), format),
+        Command::Connect { alias } => connect(find_host(app, &alias)?.clone()),
+        Command::Show { alias } => show(find_host(app, &alias)?),
+        Command::Export { format } => export(app, format),
+        Command::Sftp { .. } => bail!("sftp runs inside the TUI"),
+        Command::History {
+            alias,
+            limit,
+            format,
+        } => history(app, alias.as_deref(), limit, format),
+    }
+}
+
+pub fn find_host<'a>(app: &'a App, alias: &str) -> Result<&'a SshHost> {
+    app.find_host(alias)
+        .ok_or_else(|| anyhow::anyhow!("No host with alias '{}'", alias))
+}
+
+fn group_name(host: &SshHost) -> &str {
+    host.group.as_deref().unwrap_or("Ungrouped")
+}
+
+fn list(app: &App, group: Option<&str>, format: ListFormat) -> Result<()> {
+    let hosts: Vec<&SshHost> = app
+        .hosts
+        .iter()
+        .filter(|host| group.is_none_or(|g| group_name(host).eq_ignore_ascii_case(g)))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        ListFormat::Json => {
+            let json = serde_json::to_string_pretty(&hosts).context("Failed to serialize hosts")?;
+            writeln!(stdout, "{}", json)?;
+        }
+        ListFormat::Plain => {
+            for host in hosts {
+                writeln!(stdout, "{}", host.alias)?;
+            }
+        }
+        ListFormat::Table => {
+            let rows: Vec<[String; 5]> = hosts
+                .iter()
+                .map(|host| {
+                    [
+                        host.alias.clone(),
+                        group_name(host).to_string(),
+                        host.user.clone(),
+                        host.host.clone(),
+                        host.port.unwrap_or(22).to_string(),
+                    ]
+                })
+                .collect();
+            let header = ["ALIAS", "GROUP", "USER", "HOST", "PORT"].map(str::to_string);
+
+            let mut widths = header.clone().map(|h| h.len());
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+
+            for row in std::iter::once(&header).chain(&rows) {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect();
+                writeln!(stdout, "{}", line.join("  ").trim_end())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export(app: &App, format: ExportFormat) -> Result<()> {
+    let output = match format {
+        ExportFormat::SshConfig => app
+            .hosts
+            .iter()
+            .map(ssh_config_block)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ExportFormat::Toml => {
+            let mut config = HostsConfig::default();
+            for host in &app.hosts {
+                let name = group_name(host);
+                let index = match config.groups.iter().position(|group| group.name == name) {
+                    Some(index) => index,
+                    None => {
+                        config.groups.push(HostGroup {
+                            name: name.to_string(),
+                            description: None,
+                            hosts: Vec::new(),
+                        });
+                        config.groups.len() - 1
+                    }
+                };
+                let mut host = host.clone();
+                host.group = None;
+                config.groups[index].hosts.push(host);
+            }
+            toml::to_string_pretty(&config).context("Failed to serialize hosts")?
+        }
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&app.hosts).context("Failed to serialize hosts")? + "\n"
+        }
+    };
+    io::stdout().lock().write_all(output.as_bytes())?;
+    Ok(())
+}
+
+/// A `Host` block with the settings of `host`, in ssh_config syntax
+fn ssh_config_block(host: &SshHost) -> String {
+    let mut lines = Vec::new();
+    if let Some(description) = &host.description {
+        lines.push(format!("# {}", description));
+    }
+    lines.push(format!("Host {}", host.alias));
+    let mut option = |keyword: &str, value: &str| lines.push(format!("    {} {}", keyword, value));
+    option("HostName", &host.host);
+    option("User", &host.user);
+    option("Port", &host.port.unwrap_or(22).to_string());
+    if let Some(identity_file) = &host.identity_file {
+        option("IdentityFile", &models::quote_option_arg(identity_file));
+    }
+    if !host.proxy_jump.is_empty() {
+        option("ProxyJump", &host.proxy_jump.join(","));
+    }
+    for spec in &host.local_forwards {
+        option("LocalForward", &forward_args(spec));
+    }
+    for spec in &host.remote_forwards {
+        option("RemoteForward", &forward_args(spec));
+    }
+    if let Some(spec) = &host.dynamic_forward {
+        option("DynamicForward", spec);
+    }
+    if let Some(forward_agent) = host.forward_agent {
+        option("ForwardAgent", if forward_agent { "yes" } else { "no" });
+    }
+    if !host.env.is_empty() {
+        let vars: Vec<String> = host
+            .env
+            .iter()
+            .map(|(key, value)| models::quote_option_arg(&format!("{}={}", key, value)))
+            .collect();
+        option("SetEnv", &vars.join(" "));
+    }
+    for (key, value) in &host.options {
+        option(key, &models::quote_option_arg(value));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Turn a `-L`/`-R` spec such as `8080:localhost:80` into the
+/// `8080 localhost:80` form ssh_config expects
+fn forward_args(spec: &str) -> String {
+    let parts: Vec<&str> = spec.rsplitn(3, ':').collect();
+    match parts.as_slice() {
+        [port, host, listen] => format!("{} {}:{}", listen, host, port),
+        _ => spec.to_string(),
+    }
+}
+
+fn history(app: &App, alias: Option<&str>, limit: usize, format: ListFormat) -> Result<()> {
+    let records = history::load(app.config_manager.transfer_history_file())?;
+    let records: Vec<&TransferRecord> = records
+        .iter()
+        .rev()
+        .filter(|record| alias.is_none_or(|alias| record.host == alias))
+        .take(if limit == 0 { usize::MAX } else { limit })
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    for record in records {
+        let direction = match record.direction {
+            TransferDirection::Upload => "upload",
+            TransferDirection::Download => "download",
+        };
+        let outcome = record.error.as_deref().unwrap_or("ok");
+        match format {
+            ListFormat::Json => writeln!(stdout, "{}", serde_json::to_string(record)?)?,
+            ListFormat::Plain => writeln!(
+                stdout,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                record.time,
+                record.host,
+                direction,
+                record.local_path.display(),
+                record.remote_path,
+                outcome
+            )?,
+            ListFormat::Table => writeln!(
+                stdout,
+                "{}  {:<12} {:<8} {} {} {}  {} B in {:.1}s  {}",
+                record.time,
+                record.host,
+                direction,
+                record.local_path.display(),
+                if record.direction == TransferDirection::Upload { "->" } else { "<-" },
+                record.remote_path,
+                record.bytes,
+                record.seconds,
+                outcome
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn connect(mut host: SshHost) -> Result<()> {
+    known_hosts::verify_host_key(&mut host, ask_on_terminal)?;
+
+    let mut command = App::ssh_session_command(&host);
+    tracing::info!("Executing SSH: {:?}", command);
+
+    // A key accepted for this session only is removed along with the host,
+    // so wait for ssh instead of replacing sshr with it
+    if host.session_known_hosts.is_some() {
+        let status = command.status().context("Failed to execute ssh")?;
+        drop(host);
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    // Replace sshr with ssh so signals and exit codes behave as if ssh was run directly
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let err = command.exec();
+        Err(err).context("Failed to execute ssh")
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = command.status().context("Failed to execute ssh")?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn show(host: &SshHost) -> Result<()> {
+    let command = App::ssh_session_command(host);
+    let mut stdout = io::stdout().lock();
+
+    writeln!(stdout, "{}", command_line(&command))?;
+    writeln!(stdout)?;
+
+    let mut field = |name: &str, value: &str| writeln!(stdout, "{:<16} {}", name, value);
+    field("alias", &host.alias)?;
+    field("group", group_name(host))?;
+    field("host", &host.host)?;
+    field("user", &host.user)?;
+    field("port", &host.port.unwrap_or(22).to_string())?;
+    if let Some(description) = &host.description {
+        field("description", description)?;
+    }
+    if let Some(identity_file) = &host.identity_file {
+        field("identity_file", identity_file)?;
+    }
+    if !host.proxy_jump.is_empty() {
+        field("proxy_jump", &host.proxy_jump.join(","))?;
+    }
+    for spec in &host.local_forwards {
+        field("local_forward", spec)?;
+    }
+    for spec in &host.remote_forwards {
+        field("remote_forward", spec)?;
+    }
+    if let Some(spec) = &host.dynamic_forward {
+        field("dynamic_forward", spec)?;
+    }
+    if let Some(forward_agent) = host.forward_agent {
+        field("forward_agent", if forward_agent { "yes" } else { "no" })?;
+    }
+    for (key, value) in &host.env {
+        field("env", &format!("{}={}", key, value))?;
+    }
+    field(
+        "host_key_policy",
+        &format!("{:?}", host.host_key_policy).to_lowercase(),
+    )?;
+    if let Some(verify) = host.verify_transfers {
+        field("verify_transfers", if verify { "yes" } else { "no" })?;
+    }
+    if let Some(compress) = host.compress_transfers {
+        field("compress_transfers", if compress { "yes" } else { "no" })?;
+    }
+    if let Some(limit) = host.bandwidth_limit_kb {
+        field("bandwidth_limit_kb", &limit.to_string())?;
+    }
+    if let Some(dir) = &host.local_start_dir {
+        field("local_start_dir", dir)?;
+    }
+    if let Some(dir) = &host.remote_start_dir {
+        field("remote_start_dir", dir)?;
+    }
+    for dir in &host.local_bookmarks {
+        field("local_bookmark", dir)?;
+    }
+    for dir in &host.remote_bookmarks {
+        field("remote_bookmark", dir)?;
+    }
+    for (key, value) in &host.options {
+        field("option", &format!("{}={}", key, value))?;
+    }
+    Ok(())
+}
+
+/// Render a command with POSIX shell quoting so it can be copy-pasted
+fn command_line(command: &ProcessCommand) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| shell::quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Host key prompt for `sshr connect`, on the plain terminal
+fn ask_on_terminal(info: &HostKeyInfo) -> HostKeyDecision {
+    if info.changed {
+        eprintln!("WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!");
+    } else {
+        eprintln!("The authenticity of host '{}' can't be established.", info.alias);
+    }
+    eprintln!("{} key fingerprint is {}.", info.key_type, info.fingerprint);
+    eprint!("Accept once [o], accept and save [a], abort [N]: ");
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return HostKeyDecision::Abort;
+    }
+    match answer.trim() {
+        "o" | "O" => HostKeyDecision::AcceptOnce,
+        "a" | "A" => HostKeyDecision::AcceptAndSave,
+        _ => HostKeyDecision::Abort,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_command_line_matches_session_command() {
+        let mut host = SshHost::new("web".into(), "web.example.com".into(), "deploy".into());
+        host.proxy_jump = vec!["bastion".into()];
+        assert_eq!(
+            command_line(&App::ssh_session_command(&host)),
+            "ssh -p 22 -J bastion -o ConnectTimeout=30 -o ServerAliveInterval=60 \
+             -o ServerAliveCountMax=3 deploy@web.example.com"
+        );
+    }
+
+    #[test]
+    fn exports_ssh_config_blocks() {
+        let mut host = SshHost::new("web".into(), "web.example.com".into(), "deploy".into());
+        host.description = Some("Front end".into());
+        host.proxy_jump = vec!["bastion".into(), "gate".into()];
+        host.local_forwards = vec!["127.0.0.1:8080:localhost:80".into()];
+        host.remote_forwards = vec!["9000".into()];
+        host.env.insert("LANG".into(), "C".into());
+        host.options.insert("Compression".into(), "yes".into());
+        assert_eq!(
+            ssh_config_block(&host),
+            "# Front end\n\
+             Host web\n    \
+             HostName web.example.com\n    \
+             User deploy\n    \
+             Port 22\n    \
+             ProxyJump bastion,gate\n    \
+             LocalForward 127.0.0.1:8080 localhost:80\n    \
+             RemoteForward 9000\n    \
+             SetEnv LANG=C\n    \
+             Compression yes\n"
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::{Context, Result};
-use dirs;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
 
 use crate::models::SshHost;
 
@@ -29,6 +30,284 @@ pub struct AppConfig {
     pub default_theme: String,
     pub themes: Vec<Theme>,
     pub ssh_file_config: String,
+    /// Editors tried in order when neither $VISUAL nor $EDITOR is set
+    #[serde(default = "default_editors")]
+    pub editors: Vec<String>,
+    /// Keys bound to actions, on top of the defaults
+    #[serde(default, skip_serializing_if = "KeybindingsConfig::is_empty")]
+    pub keybindings: KeybindingsConfig,
+    #[serde(default)]
+    pub sftp: SftpConfig,
+}
+
+/// How directory transfers treat symbolic links
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkMode {
+    /// Transfer what the link points to, skipping broken links and loops
+    #[default]
+    Follow,
+    /// Leave links out of the transfer
+    Skip,
+}
+
+/// What the SFTP panels are sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    /// Modification time
+    Modified,
+    /// Extension, then name
+    Extension,
+}
+
+impl SortKey {
+    /// The key after this one, back to `Name` after the last
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Extension,
+            SortKey::Extension => SortKey::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+            SortKey::Extension => "extension",
+        }
+    }
+}
+
+/// Metadata column of the SFTP panels, shown with the details
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Size,
+    Modified,
+    Permissions,
+    /// Owner and group
+    Owner,
+    /// Target of symbolic links, after the name
+    Link,
+}
+
+/// `[sftp]` table of sshr.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpConfig {
+    #[serde(default)]
+    pub symlinks: SymlinkMode,
+    /// How many queued transfers run at the same time
+    #[serde(default = "default_concurrent_transfers")]
+    pub concurrent_transfers: usize,
+    /// How many times a transfer reconnects after losing the connection
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Compare SHA-256 hashes of both sides after each transfer, unless the
+    /// host sets `verify_transfers`
+    #[serde(default)]
+    pub verify: bool,
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default)]
+    pub sort_descending: bool,
+    /// List directories before files whatever the sort
+    #[serde(default = "default_true")]
+    pub directories_first: bool,
+    /// Start with the metadata columns shown
+    #[serde(default)]
+    pub details: bool,
+    /// Columns shown with the details, in this order
+    #[serde(default = "default_columns")]
+    pub columns: Vec<Column>,
+    /// Show dotfiles
+    #[serde(default = "default_true")]
+    pub show_hidden: bool,
+    /// How much of a file the preview pane reads, in KiB
+    #[serde(default = "default_preview_kb")]
+    pub preview_kb: usize,
+    /// Compress the SFTP connection, unless the host sets
+    /// `compress_transfers`
+    #[serde(default)]
+    pub compression: bool,
+    /// KiB per second each transfer may use, unless the host sets
+    /// `bandwidth_limit_kb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_limit_kb: Option<u64>,
+    /// KiB per second all transfers together are slowed to while throttled
+    #[serde(default = "default_throttle_kb")]
+    pub throttle_kb: u64,
+}
+
+impl Default for SftpConfig {
+    fn default() -> Self {
+        Self {
+            symlinks: SymlinkMode::default(),
+            concurrent_transfers: default_concurrent_transfers(),
+            retries: default_retries(),
+            verify: false,
+            sort: SortKey::default(),
+            sort_descending: false,
+            directories_first: true,
+            details: false,
+            columns: default_columns(),
+            show_hidden: true,
+            preview_kb: default_preview_kb(),
+            compression: false,
+            bandwidth_limit_kb: None,
+            throttle_kb: default_throttle_kb(),
+        }
+    }
+}
+
+fn default_concurrent_transfers() -> usize {
+    2
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+fn default_preview_kb() -> usize {
+    16
+}
+
+fn default_throttle_kb() -> u64 {
+    256
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_columns() -> Vec<Column> {
+    vec![
+        Column::Size,
+        Column::Modified,
+        Column::Permissions,
+        Column::Owner,
+        Column::Link,
+    ]
+}
+
+/// Directories of one host visited in SFTP mode, most recent first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecentDirs {
+    #[serde(default)]
+    pub local: Vec<String>,
+    #[serde(default)]
+    pub remote: Vec<String>,
+}
+
+impl RecentDirs {
+    /// Directories kept for each side
+    const LIMIT: usize = 20;
+
+    /// Move `dir` to the front of `dirs`
+    pub fn visit(dirs: &mut Vec<String>, dir: &str) {
+        dirs.retain(|visited| visited != dir);
+        dirs.insert(0, dir.to_string());
+        dirs.truncate(Self::LIMIT);
+    }
+}
+
+/// sftp_history.toml, the recent directories of each host by alias
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecentDirsFile {
+    #[serde(default)]
+    hosts: BTreeMap<String, RecentDirs>,
+}
+
+/// Keys of an action in sshr.toml, `"q"` or `["q", "ctrl+c"]`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    pub fn keys(&self) -> &[String] {
+        match self {
+            KeyList::One(key) => std::slice::from_ref(key),
+            KeyList::Many(keys) => keys,
+        }
+    }
+}
+
+/// `[keybindings.<mode>]` tables of sshr.toml, each mapping an action name to
+/// the keys that replace its default ones
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct KeybindingsConfig {
+    #[serde(default)]
+    pub normal: BTreeMap<String, KeyList>,
+    #[serde(default)]
+    pub search: BTreeMap<String, KeyList>,
+    #[serde(default)]
+    pub sftp: BTreeMap<String, KeyList>,
+    #[serde(default)]
+    pub sync: BTreeMap<String, KeyList>,
+    #[serde(default)]
+    pub conflict: BTreeMap<String, KeyList>,
+}
+
+impl KeybindingsConfig {
+    fn is_empty(&self) -> bool {
+        self.normal.is_empty()
+            && self.search.is_empty()
+            && self.sftp.is_empty()
+            && self.sync.is_empty()
+            && self.conflict.is_empty()
+    }
+}
+
+fn default_editors() -> Vec<String> {
+    ["nvim", "vim", "vi", "nano"].map(String::from).to_vec()
+}
+
+/// Why hosts.toml could not be loaded, with the position of the problem
+#[derive(Debug, Clone)]
+pub struct HostsFileError {
+    pub message: String,
+    /// 1-based line and column
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl HostsFileError {
+    fn from_toml(error: &toml::de::Error, content: &str) -> Self {
+        let (line, column) = match error.span() {
+            Some(span) => {
+                let before = &content[..span.start.min(content.len())];
+                let line = before.matches('\n').count() + 1;
+                let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        Self {
+            message: error.message().trim().to_string(),
+            line,
+            column,
+        }
+    }
+}
+
+impl std::fmt::Display for HostsFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "line {}, column {}: {}", line, column, self.message)
+            }
+            _ => write!(f, "{}", self.message),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -38,7 +317,7 @@ pub struct HostGroup {
     pub hosts: Vec<SshHost>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct HostsConfig {
     pub groups: Vec<HostGroup>,
 }
@@ -76,22 +355,22 @@ impl Default for AppConfig {
             default_theme: "default".to_string(),
             themes: vec![Theme::default()],
             ssh_file_config: ssh_config_path.to_str().unwrap().to_string(),
+            editors: default_editors(),
+            keybindings: KeybindingsConfig::default(),
+            sftp: SftpConfig::default(),
         }
     }
 }
 
-impl Default for HostsConfig {
-    fn default() -> Self {
-        Self { groups: Vec::new() }
-    }
-}
-
 #[derive(Debug)]
 pub struct ConfigManager {
     #[allow(dead_code)]
     config_dir: PathBuf,
     config_file: PathBuf,
     hosts_file: PathBuf,
+    /// Recent directories of each host, not the transfer history
+    recent_dirs_file: PathBuf,
+    transfer_history_file: PathBuf,
 }
 
 impl ConfigManager {
@@ -105,14 +384,23 @@ impl ConfigManager {
             fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
         }
 
-        let config_file = config_dir.join("sshr.toml");
-        let hosts_file = config_dir.join("hosts.toml");
+        Ok(Self::in_dir(config_dir))
+    }
 
-        Ok(Self {
+    /// Manager of the files in `config_dir`, which is not created
+    pub fn in_dir(config_dir: PathBuf) -> Self {
+        Self {
+            config_file: config_dir.join("sshr.toml"),
+            hosts_file: config_dir.join("hosts.toml"),
+            recent_dirs_file: config_dir.join("sftp_history.toml"),
+            transfer_history_file: config_dir.join("transfer_history.jsonl"),
             config_dir,
-            config_file,
-            hosts_file,
-        })
+        }
+    }
+
+    /// JSON-lines file finished SFTP transfers are appended to
+    pub fn transfer_history_file(&self) -> &Path {
+        &self.transfer_history_file
     }
 
     // pub fn get_config_dir(&self) -> &Path {
@@ -137,8 +425,11 @@ impl ConfigManager {
             config.themes.push(Theme::default());
         }
 
-        // Ensure the default theme exists
-        if !config.themes.iter().any(|t| t.name == config.default_theme) {
+        // Ensure the default theme exists, built-in themes count too
+        if !crate::theme::available_themes(&config.themes)
+            .iter()
+            .any(|t| t.name == config.default_theme)
+        {
             config.default_theme = config.themes[0].name.clone();
         }
 
@@ -151,20 +442,6 @@ impl ConfigManager {
         Ok(())
     }
 
-    // TODO: Add theme support
-    // pub fn get_theme(&self, theme_name: Option<&str>) -> Result<Theme> {
-    //     let config = self.load_config()?;
-    //     let theme_name = theme_name.unwrap_or(&config.default_theme);
-
-    //     config
-    //         .themes
-    //         .iter()
-    //         .find(|t| t.name == *theme_name)
-    //         .or_else(|| config.themes.first())
-    //         .cloned()
-    //         .ok_or_else(|| anyhow::anyhow!("No themes available"))
-    // }
-
     // pub fn get_config_path(&self) -> &Path {
     //     &self.config_file
     // }
@@ -179,6 +456,7 @@ impl ConfigManager {
             .context("Failed to read hosts file")?;
 
         let config: HostsConfig = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("{}", HostsFileError::from_toml(&e, &content)))
             .context("Failed to parse hosts file")?;
 
         // Flatten groups into a single vector of hosts
@@ -194,26 +472,451 @@ impl ConfigManager {
         Ok(hosts)
     }
 
-    // pub fn save_hosts(&self, groups: &[HostGroup]) -> Result<()> {
-    //     // Create hosts file if it doesn't exist
-    //     if !self.hosts_file.exists() {
-    //         fs::write(&self.hosts_file, "").context("Failed to create hosts file")?;
-    //     }
-
-    //     let config = HostsConfig {
-    //         groups: groups.to_vec(),
-    //     };
-        
-    //     let toml = toml::to_string_pretty(&config)
-    //         .context("Failed to serialize hosts")?;
-            
-    //     fs::write(&self.hosts_file, toml)
-    //         .context("Failed to write hosts file")?;
-            
-    //     Ok(())
-    // }
+    /// Check that hosts.toml parses, reporting where it doesn't
+    pub fn check_hosts_file(&self) -> Result<(), HostsFileError> {
+        let content = match fs::read_to_string(&self.hosts_file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(HostsFileError {
+                    message: format!("Failed to read hosts file: {}", e),
+                    line: None,
+                    column: None,
+                })
+            }
+        };
+
+        toml::from_str::<HostsConfig>(&content)
+            .map(|_| ())
+            .map_err(|e| HostsFileError::from_toml(&e, &content))
+    }
+
+    /// Save `host` to hosts.toml, in place of `previous_alias` when it was
+    /// renamed or moved.
+    ///
+    /// Only that host's table changes: comments, key order and formatting of
+    /// the rest of the file survive, and so do hosts sshr didn't load, such as
+    /// those shadowed by ~/.ssh/config.
+    pub fn save_host(&self, host: &SshHost, previous_alias: Option<&str>) -> Result<()> {
+        self.edit_hosts_file(|doc| write_host(doc, host, previous_alias))?;
+        tracing::info!("Saved host {} to {:?}", host.alias, self.hosts_file);
+        Ok(())
+    }
+
+    /// Remove the host `alias` from hosts.toml
+    pub fn delete_host(&self, alias: &str) -> Result<()> {
+        self.edit_hosts_file(|doc| remove_host(doc, alias))?;
+        tracing::info!("Deleted host {} from {:?}", alias, self.hosts_file);
+        Ok(())
+    }
+
+    fn edit_hosts_file<F>(&self, edit: F) -> Result<()>
+    where
+        F: FnOnce(&mut DocumentMut) -> Result<()>,
+    {
+        let content = if self.hosts_file.exists() {
+            fs::read_to_string(&self.hosts_file).context("Failed to read hosts file")?
+        } else {
+            String::new()
+        };
+
+        let mut doc: DocumentMut = content.parse().context("Failed to parse hosts file")?;
+        edit(&mut doc)?;
+        fs::write(&self.hosts_file, doc.to_string()).context("Failed to write hosts file")
+    }
 
     pub fn get_hosts_path(&self) -> &Path {
         &self.hosts_file
     }
+
+    fn load_recent_dirs_file(&self) -> Result<RecentDirsFile> {
+        let path = &self.recent_dirs_file;
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RecentDirsFile::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Directories visited on `alias` in earlier SFTP sessions
+    pub fn load_recent_dirs(&self, alias: &str) -> Result<RecentDirs> {
+        Ok(self.load_recent_dirs_file()?.hosts.remove(alias).unwrap_or_default())
+    }
+
+    /// Replace the directories visited on `alias`, keeping other hosts. A
+    /// file that can't be read is left alone rather than overwritten.
+    pub fn save_recent_dirs(&self, alias: &str, recent: &RecentDirs) -> Result<()> {
+        let mut file = self.load_recent_dirs_file()?;
+        file.hosts.insert(alias.to_string(), recent.clone());
+        let content = toml::to_string(&file).context("Failed to serialize recent directories")?;
+        fs::write(&self.recent_dirs_file, content)
+            .with_context(|| format!("Failed to write {}", self.recent_dirs_file.display()))
+    }
+}
+
+/// Keys of a host table that sshr owns. Anything else in a host table is left
+/// alone when the host is updated.
+const HOST_KEYS: &[&str] = &[
+    "alias",
+    "host",
+    "user",
+    "port",
+    "description",
+    "group",
+    "identity_file",
+    "proxy_jump",
+    "local_forwards",
+    "remote_forwards",
+    "dynamic_forward",
+    "forward_agent",
+    "env",
+    "set_env",
+    "options",
+    "host_key_policy",
+    "verify_transfers",
+    "compress_transfers",
+    "bandwidth_limit_kb",
+    "local_start_dir",
+    "remote_start_dir",
+    "local_bookmarks",
+    "remote_bookmarks",
+];
+
+/// Alias of a `[[groups.hosts]]` table
+fn table_alias(table: &Table) -> Option<&str> {
+    table.get("alias").and_then(|item| item.as_str())
+}
+
+/// Name of a `[[groups]]` table
+fn group_name(group: &Table) -> &str {
+    group.get("name").and_then(|item| item.as_str()).unwrap_or_default()
+}
+
+/// Write `host` to a hosts document. The table of `previous_alias`, or of
+/// the host's own alias, is updated in place when it is in the host's group,
+/// and moved there otherwise. Every other table is left alone.
+fn write_host(doc: &mut DocumentMut, host: &SshHost, previous_alias: Option<&str>) -> Result<()> {
+    let name = host
+        .group
+        .as_deref()
+        .with_context(|| format!("{} has no group to be saved in", host.alias))?;
+    let alias = previous_alias.unwrap_or(&host.alias);
+    let groups = array_of_tables_mut(doc.as_table_mut(), "groups")
+        .context("`groups` in hosts file is not an array of tables")?;
+
+    for group in groups.iter_mut().filter(|group| group_name(group) == name) {
+        let tables = array_of_tables_mut(group, "hosts")
+            .with_context(|| format!("`hosts` of group '{}' is not an array of tables", name))?;
+        if let Some(table) = tables.iter_mut().find(|table| table_alias(table) == Some(alias)) {
+            return update_host_table(table, host);
+        }
+    }
+
+    remove_host(doc, alias)?;
+    let groups = array_of_tables_mut(doc.as_table_mut(), "groups")
+        .context("`groups` in hosts file is not an array of tables")?;
+    let position = groups.iter().position(|group| group_name(group) == name);
+    match position.and_then(|index| groups.get_mut(index)) {
+        Some(group) => array_of_tables_mut(group, "hosts")
+            .with_context(|| format!("`hosts` of group '{}' is not an array of tables", name))?
+            .push(host_table(host)?),
+        None => {
+            let mut tables = ArrayOfTables::new();
+            tables.push(host_table(host)?);
+            let mut group = Table::new();
+            group.insert("name", toml_edit::value(name));
+            group.insert("hosts", Item::ArrayOfTables(tables));
+            groups.push(group);
+        }
+    }
+    Ok(())
+}
+
+/// Drop the tables of `alias` from a hosts document, and the groups left
+/// empty by that
+fn remove_host(doc: &mut DocumentMut, alias: &str) -> Result<()> {
+    let groups = array_of_tables_mut(doc.as_table_mut(), "groups")
+        .context("`groups` in hosts file is not an array of tables")?;
+    let mut emptied = Vec::new();
+    for (index, group) in groups.iter_mut().enumerate() {
+        let Some(tables) = group.get_mut("hosts").and_then(Item::as_array_of_tables_mut) else {
+            continue;
+        };
+        let before = tables.len();
+        tables.retain(|table| table_alias(table) != Some(alias));
+        if tables.len() < before && tables.is_empty() {
+            emptied.push(index);
+        }
+    }
+    for index in emptied.into_iter().rev() {
+        groups.remove(index);
+    }
+    Ok(())
+}
+
+/// Get `key` of `table` as an array of tables, creating it if missing and
+/// converting an inline array of tables if needed
+fn array_of_tables_mut<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut ArrayOfTables> {
+    let item = table
+        .entry(key)
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()));
+    if item.is_value() {
+        let converted = std::mem::take(item).into_array_of_tables().ok()?;
+        *item = Item::ArrayOfTables(converted);
+    }
+    item.as_array_of_tables_mut()
+}
+
+/// Serialize a host as a table of a `[[groups.hosts]]` entry
+fn host_table(host: &SshHost) -> Result<Table> {
+    // The group comes from the enclosing `[[groups]]`, not the host itself
+    let host = SshHost {
+        group: None,
+        ..host.clone()
+    };
+    let serialized = toml::to_string(&host).context("Failed to serialize host")?;
+    let doc: DocumentMut = serialized
+        .parse()
+        .context("Failed to parse serialized host")?;
+
+    let mut table = Table::new();
+    for (key, item) in doc.iter() {
+        let item = match item.clone() {
+            // Maps such as `env` and `options` are written inline
+            Item::Table(nested) => Item::Value(Value::InlineTable(nested.into_inline_table())),
+            other => other,
+        };
+        table.insert(key, item);
+    }
+    table.fmt();
+    Ok(table)
+}
+
+/// Update an existing host table with the values of `host`, keeping the
+/// decoration (comments, spacing) of every value that didn't change
+fn update_host_table(table: &mut Table, host: &SshHost) -> Result<()> {
+    let fresh = host_table(host)?;
+
+    let stale: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| HOST_KEYS.contains(&key.as_str()) && !fresh.contains_key(key))
+        .collect();
+    for key in stale {
+        table.remove(&key);
+    }
+
+    for (key, item) in fresh.iter() {
+        match table.get_mut(key) {
+            Some(existing) if normalized(existing) == normalized(item) => {}
+            // Maps written as `[groups.hosts.env]` sections stay sections
+            Some(Item::Table(section)) if item.is_inline_table() => {
+                let entries = item.as_inline_table().cloned().unwrap_or_default();
+                section.retain(|key, _| entries.contains_key(key));
+                for (key, value) in entries.iter() {
+                    let unchanged = section
+                        .get(key)
+                        .is_some_and(|old| normalized(old) == normalized(&Item::Value(value.clone())));
+                    if !unchanged {
+                        section.insert(key, Item::Value(value.clone()));
+                    }
+                }
+            }
+            Some(existing) => {
+                let decor = existing.as_value().map(|value| value.decor().clone());
+                *existing = item.clone();
+                if let (Some(decor), Some(value)) = (decor, existing.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+            None => {
+                table.insert(key, item.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Value of an item without any formatting, for comparisons
+fn normalized(item: &Item) -> Option<String> {
+    let mut value = match item {
+        Item::Table(table) => Value::InlineTable(table.clone().into_inline_table()),
+        _ => item.as_value()?.clone(),
+    };
+    match &mut value {
+        Value::Array(array) => array.fmt(),
+        Value::InlineTable(table) => table.fmt(),
+        _ => {}
+    }
+    value.decor_mut().clear();
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS_FILE: &str = r#"# My hosts
+[[groups]]
+name = "Work"
+description = "Office machines"
+
+# Main web server
+[[groups.hosts]]
+alias = "web"   # keep this comment
+host = "10.0.0.1"
+user = "deploy"
+port = 22
+description = "Frontend"
+
+[[groups.hosts]]
+alias = "db"
+host = "10.0.0.2"
+user = "postgres"
+port = 5432
+
+[[groups]]
+name = "Home"
+
+[[groups.hosts]]
+alias = "nas"
+host = "192.168.1.10"
+user = "admin"
+port = 22
+
+# Old NAS firmware
+[groups.hosts.options]
+HostKeyAlgorithms = "+ssh-rsa"
+Compression = "yes"
+"#;
+
+    fn load(content: &str) -> Vec<SshHost> {
+        let config: HostsConfig = toml::from_str(content).unwrap();
+        config
+            .groups
+            .into_iter()
+            .flat_map(|group| {
+                let name = group.name;
+                group.hosts.into_iter().map(move |mut host| {
+                    host.group = Some(name.clone());
+                    host
+                })
+            })
+            .collect()
+    }
+
+    fn edit(content: &str, edit: impl FnOnce(&mut DocumentMut)) -> String {
+        let mut doc: DocumentMut = content.parse().unwrap();
+        edit(&mut doc);
+        doc.to_string()
+    }
+
+    #[test]
+    fn unchanged_hosts_round_trip_verbatim() {
+        let saved = edit(HOSTS_FILE, |doc| {
+            for host in load(HOSTS_FILE) {
+                write_host(doc, &host, None).unwrap();
+            }
+        });
+        assert_eq!(saved, HOSTS_FILE);
+    }
+
+    #[test]
+    fn edit_keeps_comments_and_other_hosts() {
+        let mut hosts = load(HOSTS_FILE);
+        hosts[0].port = Some(2222);
+        hosts[0].description = None;
+        hosts[0].identity_file = Some("~/.ssh/work".into());
+        hosts[2].options.remove("Compression");
+
+        let saved = edit(HOSTS_FILE, |doc| {
+            write_host(doc, &hosts[0], Some("web")).unwrap();
+            write_host(doc, &hosts[2], Some("nas")).unwrap();
+        });
+        assert!(saved.starts_with("# My hosts\n"));
+        assert!(saved.contains("# Main web server\n"));
+        assert!(saved.contains("alias = \"web\"   # keep this comment\n"));
+        assert!(saved.contains("port = 2222\n"));
+        assert!(saved.contains("identity_file = \"~/.ssh/work\"\n"));
+        assert!(!saved.contains("Frontend"));
+        assert!(!saved.contains("group ="));
+        assert!(saved.contains("# Old NAS firmware\n[groups.hosts.options]\nHostKeyAlgorithms = \"+ssh-rsa\"\n"));
+        assert!(!saved.contains("Compression"));
+        assert_eq!(load(&saved).len(), 3);
+    }
+
+    #[test]
+    fn add_delete_and_move_hosts() {
+        let mut hosts = load(HOSTS_FILE);
+        // Delete db, move nas to Work, add a host to a new group
+        hosts[2].group = Some("Work".into());
+        let mut lab = SshHost::new("lab".into(), "10.9.0.1".into(), "root".into());
+        lab.group = Some("Lab".into());
+        lab.env.insert("LANG".into(), "C.UTF-8".into());
+
+        let saved = edit(HOSTS_FILE, |doc| {
+            remove_host(doc, "db").unwrap();
+            write_host(doc, &hosts[2], Some("nas")).unwrap();
+            write_host(doc, &lab, None).unwrap();
+        });
+        let reloaded = load(&saved);
+        let summary: Vec<(&str, &str)> = reloaded
+            .iter()
+            .map(|host| (host.alias.as_str(), host.group.as_deref().unwrap()))
+            .collect();
+        assert_eq!(summary, [("web", "Work"), ("nas", "Work"), ("lab", "Lab")]);
+        assert_eq!(reloaded[2].env["LANG"], "C.UTF-8");
+
+        // Home became empty and is gone, Work keeps its description
+        assert!(!saved.contains("name = \"Home\""));
+        assert!(saved.contains("description = \"Office machines\""));
+        assert!(saved.contains("env = { LANG = \"C.UTF-8\" }"));
+    }
+
+    #[test]
+    fn reports_error_location() {
+        let content = "[[groups]]\nname = \"Work\"\n\n[[groups.hosts]]\nalias = \"web\"\nhost = 10.0.0.1\n";
+        let error = toml::from_str::<HostsConfig>(content).unwrap_err();
+        let error = HostsFileError::from_toml(&error, content);
+        assert_eq!(error.line, Some(6));
+        assert!(error.column.is_some());
+
+        let content = "[[groups]]\nname = \"Work\"\n\n[[groups.hosts]]\nalias = \"web\"\n";
+        let error = toml::from_str::<HostsConfig>(content).unwrap_err();
+        let error = HostsFileError::from_toml(&error, content);
+        assert!(error.message.contains("missing field `host`"), "{}", error.message);
+        assert_eq!(error.line, Some(4));
+    }
+
+    #[test]
+    fn saves_into_empty_file() {
+        let mut host = SshHost::new("web".into(), "10.0.0.1".into(), "deploy".into());
+        host.group = Some("Work".into());
+        let saved = edit("", |doc| write_host(doc, &host, None).unwrap());
+        let reloaded = load(&saved);
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded[0].alias, "web");
+        assert_eq!(reloaded[0].group.as_deref(), Some("Work"));
+    }
+
+    #[test]
+    fn keeps_recent_dirs_file_it_cannot_parse() {
+        let dir = std::env::temp_dir().join(format!("sshr-recent-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manager = ConfigManager::in_dir(dir.clone());
+        let recent = RecentDirs {
+            remote: vec!["/srv".into()],
+            ..Default::default()
+        };
+        manager.save_recent_dirs("web", &recent).unwrap();
+        assert_eq!(manager.load_recent_dirs("web").unwrap().remote, ["/srv"]);
+
+        let broken = "[hosts.web\nremote = [\"/srv\"]\n";
+        fs::write(dir.join("sftp_history.toml"), broken).unwrap();
+        assert!(manager.save_recent_dirs("db", &recent).is_err());
+        assert_eq!(fs::read_to_string(dir.join("sftp_history.toml")).unwrap(), broken);
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
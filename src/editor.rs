@@ -0,0 +1,102 @@
+//! Run the user's text editor in the foreground
+//!
+//! `$VISUAL` wins over `$EDITOR`, then the `editors` list from sshr.toml is
+//! tried in order. Entries may carry arguments, e.g. `code --wait`.
+
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+/// Editor commands to try, most preferred first
+pub fn editor_candidates(configured: &[String]) -> Vec<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .chain(configured.iter().cloned())
+        .filter(|editor| !editor.trim().is_empty())
+        .collect()
+}
+
+/// Open `path` in the first editor that can be started and wait for it to
+/// exit. `line` moves the cursor there for editors that support it.
+///
+/// The TUI must be suspended by the caller.
+pub fn open_in_editor(path: &Path, line: Option<usize>, configured: &[String]) -> Result<()> {
+    let candidates = editor_candidates(configured);
+
+    for editor in &candidates {
+        let mut parts = editor.split_whitespace();
+        let Some(program) = parts.next() else {
+            continue;
+        };
+
+        let mut command = Command::new(program);
+        command.args(parts).args(file_args(program, path, line));
+        tracing::info!("Opening editor: {:?}", command);
+
+        match command.status() {
+            Ok(status) => {
+                if !status.success() {
+                    tracing::warn!("Editor {} exited with {}", program, status);
+                }
+                return Ok(());
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                tracing::debug!("Editor {} not found, trying the next one", program);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to run editor {}", program));
+            }
+        }
+    }
+
+    anyhow::bail!(
+        "No editor found (tried {}), set $EDITOR or `editors` in sshr.toml",
+        if candidates.is_empty() {
+            "none".to_string()
+        } else {
+            candidates.join(", ")
+        }
+    )
+}
+
+/// Arguments that open `path`, at `line` when the editor has a known syntax
+/// for it
+fn file_args(program: &str, path: &Path, line: Option<usize>) -> Vec<String> {
+    let path = path.to_string_lossy().to_string();
+    let Some(line) = line else {
+        return vec![path];
+    };
+
+    let name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match name.as_str() {
+        "vi" | "vim" | "nvim" | "gvim" | "nano" | "pico" | "emacs" | "emacsclient" | "micro"
+        | "kak" | "joe" | "mg" => vec![format!("+{}", line), path],
+        "hx" | "helix" | "subl" | "zed" => vec![format!("{}:{}", path, line)],
+        "code" | "codium" => vec!["--goto".to_string(), format!("{}:{}", path, line)],
+        _ => vec![path],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jumps_to_line_for_known_editors() {
+        let path = Path::new("/tmp/hosts.toml");
+        assert_eq!(file_args("nvim", path, Some(12)), ["+12", "/tmp/hosts.toml"]);
+        assert_eq!(file_args("/usr/bin/nano", path, Some(3)), ["+3", "/tmp/hosts.toml"]);
+        assert_eq!(file_args("hx", path, Some(7)), ["/tmp/hosts.toml:7"]);
+        assert_eq!(
+            file_args("code", path, Some(7)),
+            ["--goto", "/tmp/hosts.toml:7"]
+        );
+        assert_eq!(file_args("ed", path, Some(7)), ["/tmp/hosts.toml"]);
+        assert_eq!(file_args("vim", path, None), ["/tmp/hosts.toml"]);
+    }
+}
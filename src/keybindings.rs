@@ -0,0 +1,667 @@
+//! Actions of each input mode and the keys bound to them
+//!
+//! Keys are written like `q`, `E`, `enter`, `ctrl+c` or `shift+tab`. Several
+//! keys separated by spaces, such as `g g`, make a chord that triggers once
+//! all of them are pressed in order.
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::config::{KeyList, KeybindingsConfig};
+
+/// Input modes with their own keymap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Normal,
+    Search,
+    Sftp,
+    /// The directory comparison, on top of the SFTP keys
+    Sync,
+    /// The dialog of a transfer whose target exists
+    Conflict,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Search => "search",
+            Mode::Sftp => "sftp",
+            Mode::Sync => "sync",
+            Mode::Conflict => "conflict",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Up,
+    Down,
+    Left,
+    Right,
+    Connect,
+    Cancel,
+    Reload,
+    Search,
+    Sftp,
+    NextPanel,
+    PreviousGroup,
+    NextTheme,
+    AddHost,
+    EditHost,
+    CloneHost,
+    MoveHost,
+    DeleteHost,
+    EditHostsFile,
+    DeleteChar,
+    Open,
+    Back,
+    SwitchPanel,
+    Upload,
+    Download,
+    Mark,
+    PauseTransfer,
+    CancelTransfer,
+    RetryTransfer,
+    ClearTransfers,
+    ToggleVerify,
+    Rename,
+    DeleteEntry,
+    MakeDir,
+    NewFile,
+    Chmod,
+    Chown,
+    CycleSort,
+    ReverseSort,
+    DirectoriesFirst,
+    ToggleDetails,
+    ToggleHidden,
+    Filter,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    JumpToLetter,
+    EditFile,
+    TogglePreview,
+    Sync,
+    Bookmark,
+    Places,
+    GoTo,
+    LimitTransfer,
+    ToggleThrottle,
+    History,
+    ToggleDelete,
+    SyncMarked,
+    ToggleChecksum,
+    Overwrite,
+    Skip,
+    OverwriteIfNewer,
+    ApplyToAll,
+}
+
+impl Action {
+    /// Name of the action in sshr.toml
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Connect => "connect",
+            Action::Cancel => "cancel",
+            Action::Reload => "reload",
+            Action::Search => "search",
+            Action::Sftp => "sftp",
+            Action::NextPanel => "next_panel",
+            Action::PreviousGroup => "previous_group",
+            Action::NextTheme => "next_theme",
+            Action::AddHost => "add_host",
+            Action::EditHost => "edit_host",
+            Action::CloneHost => "clone_host",
+            Action::MoveHost => "move_host",
+            Action::DeleteHost => "delete_host",
+            Action::EditHostsFile => "edit_hosts_file",
+            Action::DeleteChar => "delete_char",
+            Action::Open => "open",
+            Action::Back => "back",
+            Action::SwitchPanel => "switch_panel",
+            Action::Upload => "upload",
+            Action::Download => "download",
+            Action::Mark => "mark",
+            Action::PauseTransfer => "pause_transfer",
+            Action::CancelTransfer => "cancel_transfer",
+            Action::RetryTransfer => "retry_transfer",
+            Action::ClearTransfers => "clear_transfers",
+            Action::ToggleVerify => "toggle_verify",
+            Action::Rename => "rename",
+            Action::DeleteEntry => "delete",
+            Action::MakeDir => "make_dir",
+            Action::NewFile => "new_file",
+            Action::Chmod => "chmod",
+            Action::Chown => "chown",
+            Action::CycleSort => "cycle_sort",
+            Action::ReverseSort => "reverse_sort",
+            Action::DirectoriesFirst => "directories_first",
+            Action::ToggleDetails => "toggle_details",
+            Action::ToggleHidden => "toggle_hidden",
+            Action::Filter => "filter",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::JumpToLetter => "jump",
+            Action::EditFile => "edit",
+            Action::TogglePreview => "toggle_preview",
+            Action::Sync => "sync",
+            Action::Bookmark => "bookmark",
+            Action::Places => "places",
+            Action::GoTo => "go_to",
+            Action::LimitTransfer => "limit_transfer",
+            Action::ToggleThrottle => "toggle_throttle",
+            Action::History => "history",
+            Action::ToggleDelete => "toggle_delete",
+            Action::SyncMarked => "sync_marked",
+            Action::ToggleChecksum => "toggle_checksum",
+            Action::Overwrite => "overwrite",
+            Action::Skip => "skip",
+            Action::OverwriteIfNewer => "overwrite_if_newer",
+            Action::ApplyToAll => "apply_to_all",
+        }
+    }
+}
+
+/// Actions of each mode with their default keys, in the order the footer
+/// lists them
+fn default_bindings(mode: Mode) -> &'static [(Action, &'static [&'static str])] {
+    match mode {
+        Mode::Normal => &[
+            (Action::Up, &["up", "k"]),
+            (Action::Down, &["down", "j"]),
+            (Action::Left, &["left"]),
+            (Action::Right, &["right"]),
+            (Action::NextPanel, &["tab"]),
+            (Action::PreviousGroup, &["shift+tab"]),
+            (Action::Connect, &["enter"]),
+            (Action::Search, &["s"]),
+            (Action::Sftp, &["f"]),
+            (Action::AddHost, &["a"]),
+            (Action::EditHost, &["E"]),
+            (Action::CloneHost, &["c"]),
+            (Action::MoveHost, &["m"]),
+            (Action::DeleteHost, &["d"]),
+            (Action::EditHostsFile, &["e"]),
+            (Action::Reload, &["r"]),
+            (Action::NextTheme, &["t"]),
+            (Action::Cancel, &["esc"]),
+            (Action::Quit, &["q", "Q", "ctrl+c"]),
+        ],
+        Mode::Search => &[
+            (Action::Up, &["up"]),
+            (Action::Down, &["down"]),
+            (Action::Connect, &["enter"]),
+            (Action::DeleteChar, &["backspace", "delete"]),
+            (Action::Cancel, &["esc"]),
+        ],
+        Mode::Sftp => &[
+            (Action::Up, &["up"]),
+            (Action::Down, &["down"]),
+            (Action::PageUp, &["pageup"]),
+            (Action::PageDown, &["pagedown"]),
+            (Action::First, &["home"]),
+            (Action::Last, &["end"]),
+            (Action::JumpToLetter, &["'"]),
+            (Action::Filter, &["/"]),
+            (Action::ToggleHidden, &["."]),
+            (Action::Open, &["enter"]),
+            (Action::Back, &["backspace"]),
+            (Action::SwitchPanel, &["tab"]),
+            (Action::Mark, &["space"]),
+            (Action::Upload, &["u"]),
+            (Action::Download, &["d"]),
+            (Action::PauseTransfer, &["p"]),
+            (Action::CancelTransfer, &["x"]),
+            (Action::RetryTransfer, &["R"]),
+            (Action::ClearTransfers, &["C"]),
+            (Action::LimitTransfer, &["L"]),
+            (Action::ToggleThrottle, &["T"]),
+            (Action::History, &["H"]),
+            (Action::ToggleVerify, &["v"]),
+            (Action::Rename, &["n"]),
+            (Action::DeleteEntry, &["delete"]),
+            (Action::MakeDir, &["m"]),
+            (Action::NewFile, &["t"]),
+            (Action::Chmod, &["c"]),
+            (Action::Chown, &["o"]),
+            (Action::EditFile, &["e"]),
+            (Action::CycleSort, &["s"]),
+            (Action::ReverseSort, &["S"]),
+            (Action::DirectoriesFirst, &["F"]),
+            (Action::ToggleDetails, &["i"]),
+            (Action::TogglePreview, &["P"]),
+            (Action::Sync, &["="]),
+            (Action::GoTo, &["g"]),
+            (Action::Bookmark, &["b"]),
+            (Action::Places, &["B"]),
+            (Action::Reload, &["r"]),
+            (Action::Quit, &["q"]),
+        ],
+        Mode::Sync => &[
+            (Action::Upload, &["u"]),
+            (Action::Download, &["d"]),
+            (Action::Mark, &["space"]),
+            (Action::SyncMarked, &["enter"]),
+            (Action::ToggleDelete, &["x"]),
+            (Action::ToggleChecksum, &["c"]),
+        ],
+        Mode::Conflict => &[
+            (Action::Overwrite, &["o"]),
+            (Action::Skip, &["s"]),
+            (Action::Rename, &["r"]),
+            (Action::OverwriteIfNewer, &["n"]),
+            (Action::ApplyToAll, &["a"]),
+            (Action::Cancel, &["esc"]),
+        ],
+    }
+}
+
+/// A single key with its modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is part of the character itself, and Shift+Tab arrives as
+        // either BackTab or Tab with Shift depending on the terminal
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+
+    /// Parse a single key such as `q`, `ctrl+c` or `pagedown`
+    pub fn parse(value: &str) -> Result<Self> {
+        let (modifier_names, key) = match value.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None if value == "+" => ("", "+"),
+            None => match value.rsplit_once('+') {
+                Some((modifiers, key)) => (modifiers, key),
+                None => ("", value),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("Unknown modifier '{}' in key '{}'", name, value),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                lower => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => bail!("Unknown key '{}'", value),
+                },
+            },
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Delete => write!(f, "Del"),
+            KeyCode::Insert => write!(f, "Ins"),
+            KeyCode::Home => write!(f, "Home"),
+            KeyCode::End => write!(f, "End"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// One key or a chord
+type Binding = Vec<KeyPress>;
+
+/// Parse a binding, one key or a chord of space separated keys
+fn parse_binding(value: &str) -> Result<Binding> {
+    let keys = value
+        .split_whitespace()
+        .map(KeyPress::parse)
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        bail!("Empty key binding");
+    }
+    Ok(keys)
+}
+
+fn binding_label(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Result of feeding the keys pressed so far to a keymap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMatch {
+    Action(Action),
+    /// The keys start a chord, wait for the next one
+    Pending,
+    Unbound,
+}
+
+/// Keys bound to the actions of every mode
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    modes: HashMap<Mode, Vec<(Action, Vec<Binding>)>>,
+}
+
+impl Keymap {
+    /// Build the keymap from the defaults and the `[keybindings]` tables of
+    /// sshr.toml, failing on unknown actions or keys and on conflicts
+    pub fn from_config(config: &KeybindingsConfig) -> Result<Self> {
+        let mut modes = HashMap::new();
+        for (mode, overrides) in [
+            (Mode::Normal, &config.normal),
+            (Mode::Search, &config.search),
+            (Mode::Sftp, &config.sftp),
+            (Mode::Sync, &config.sync),
+            (Mode::Conflict, &config.conflict),
+        ] {
+            let bindings = Self::mode_bindings(mode, overrides)
+                .with_context(|| format!("Invalid [keybindings.{}]", mode.name()))?;
+            modes.insert(mode, bindings);
+        }
+        Ok(Self { modes })
+    }
+
+    fn mode_bindings(
+        mode: Mode,
+        overrides: &BTreeMap<String, KeyList>,
+    ) -> Result<Vec<(Action, Vec<Binding>)>> {
+        let defaults = default_bindings(mode);
+        for name in overrides.keys() {
+            if !defaults.iter().any(|(action, _)| action.name() == name) {
+                let names: Vec<&str> = defaults.iter().map(|(action, _)| action.name()).collect();
+                bail!(
+                    "Unknown action '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                );
+            }
+        }
+
+        let mut bindings = Vec::new();
+        for (action, default_keys) in defaults {
+            let keys = match overrides.get(action.name()) {
+                Some(keys) => keys
+                    .keys()
+                    .iter()
+                    .map(|key| parse_binding(key))
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Action '{}'", action.name()))?,
+                None => default_keys
+                    .iter()
+                    .map(|key| parse_binding(key).expect("default key bindings are valid"))
+                    .collect(),
+            };
+            bindings.push((*action, keys));
+        }
+
+        // A binding that equals or starts another one would hide it
+        let all: Vec<(Action, &Binding)> = bindings
+            .iter()
+            .flat_map(|(action, keys)| keys.iter().map(move |keys| (*action, keys)))
+            .collect();
+        for (i, (action, keys)) in all.iter().enumerate() {
+            for (other_action, other_keys) in &all[i + 1..] {
+                if keys == other_keys && action == other_action {
+                    continue;
+                }
+                if other_keys.starts_with(keys) || keys.starts_with(other_keys) {
+                    bail!(
+                        "'{}' of {} conflicts with '{}' of {}",
+                        binding_label(keys),
+                        action.name(),
+                        binding_label(other_keys),
+                        other_action.name()
+                    );
+                }
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    /// Look up the keys pressed so far in `mode`
+    pub fn resolve(&self, mode: Mode, pressed: &[KeyPress]) -> KeyMatch {
+        let mut pending = false;
+        for (action, keys) in &self.modes[&mode] {
+            for keys in keys {
+                if keys.as_slice() == pressed {
+                    return KeyMatch::Action(*action);
+                }
+                pending |= keys.starts_with(pressed);
+            }
+        }
+        if pending {
+            KeyMatch::Pending
+        } else {
+            KeyMatch::Unbound
+        }
+    }
+
+    /// Look up the keys pressed so far in the first of `modes` that binds
+    /// or starts them, for views that add their own keys to another mode's
+    pub fn resolve_layered(&self, modes: &[Mode], pressed: &[KeyPress]) -> KeyMatch {
+        modes
+            .iter()
+            .map(|mode| self.resolve(*mode, pressed))
+            .find(|matched| *matched != KeyMatch::Unbound)
+            .unwrap_or(KeyMatch::Unbound)
+    }
+
+    /// Keys of an action for display, e.g. `↑/k`, or `None` when unbound
+    pub fn label(&self, mode: Mode, action: Action) -> Option<String> {
+        let (_, keys) = self.modes[&mode].iter().find(|(a, _)| *a == action)?;
+        if keys.is_empty() {
+            return None;
+        }
+        Some(
+            keys.iter()
+                .map(|keys| binding_label(keys))
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// Key labels and descriptions of the bound actions in `hints`, for footers
+    pub fn hints<'a>(&self, mode: Mode, hints: &[(Action, &'a str)]) -> Vec<(String, &'a str)> {
+        hints
+            .iter()
+            .filter_map(|(action, description)| Some((self.label(mode, *action)?, *description)))
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_config(&KeybindingsConfig::default()).expect("default key bindings are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: &str) -> KeyPress {
+        KeyPress::parse(value).unwrap()
+    }
+
+    fn config(mode_toml: &str) -> KeybindingsConfig {
+        toml::from_str(mode_toml).unwrap()
+    }
+
+    #[test]
+    fn parses_keys_and_modifiers() {
+        assert_eq!(
+            key("q"),
+            KeyPress::new(KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            key("ctrl+c"),
+            KeyPress::new(KeyCode::Char('c'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(key("shift+e"), key("E"));
+        assert_eq!(
+            key("shift+tab"),
+            KeyPress::new(KeyCode::BackTab, KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            key("ctrl++"),
+            KeyPress::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(key("F5"), KeyPress::new(KeyCode::F(5), KeyModifiers::NONE));
+        assert!(KeyPress::parse("hyper+x").is_err());
+        assert!(KeyPress::parse("nope").is_err());
+
+        assert_eq!(key("ctrl+alt+pagedown").to_string(), "Ctrl+Alt+PgDn");
+    }
+
+    #[test]
+    fn resolves_defaults_and_chords() {
+        let keymap = Keymap::from_config(&config(
+            "[normal]\nquit = [\"g q\", \"ctrl+c\"]\nnext_theme = \"g t\"\n",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            keymap.resolve(Mode::Normal, &[key("j")]),
+            KeyMatch::Action(Action::Down)
+        );
+        assert_eq!(keymap.resolve(Mode::Normal, &[key("g")]), KeyMatch::Pending);
+        assert_eq!(
+            keymap.resolve(Mode::Normal, &[key("g"), key("t")]),
+            KeyMatch::Action(Action::NextTheme)
+        );
+        assert_eq!(keymap.resolve(Mode::Normal, &[key("q")]), KeyMatch::Unbound);
+        assert_eq!(
+            keymap.resolve(Mode::Sftp, &[key("q")]),
+            KeyMatch::Action(Action::Quit)
+        );
+        assert_eq!(
+            keymap.label(Mode::Normal, Action::Quit).unwrap(),
+            "g q/Ctrl+c"
+        );
+        assert_eq!(keymap.label(Mode::Normal, Action::Up).unwrap(), "↑/k");
+    }
+
+    #[test]
+    fn rejects_conflicts_and_unknown_actions() {
+        let error = Keymap::from_config(&config("[sftp]\nupload = \"d\"\n")).unwrap_err();
+        assert!(format!("{:#}", error).contains("'d' of upload conflicts with 'd' of download"));
+
+        let error = Keymap::from_config(&config("[normal]\nreload = \"s s\"\n")).unwrap_err();
+        assert!(format!("{:#}", error).contains("conflicts"));
+
+        let error = Keymap::from_config(&config("[search]\nupload = \"u\"\n")).unwrap_err();
+        assert!(format!("{:#}", error).contains("Unknown action 'upload'"));
+
+        // Unbinding an action frees its keys
+        let keymap =
+            Keymap::from_config(&config("[sftp]\ndownload = []\nupload = \"d\"\n")).unwrap();
+        assert_eq!(
+            keymap.resolve(Mode::Sftp, &[key("d")]),
+            KeyMatch::Action(Action::Upload)
+        );
+        assert_eq!(keymap.label(Mode::Sftp, Action::Download), None);
+    }
+
+    #[test]
+    fn layers_sync_keys_over_sftp_keys() {
+        let keymap = Keymap::from_config(&config("[sftp]\nup = \"k\"\n")).unwrap();
+        let layers = [Mode::Sync, Mode::Sftp];
+        assert_eq!(
+            keymap.resolve_layered(&layers, &[key("c")]),
+            KeyMatch::Action(Action::ToggleChecksum)
+        );
+        assert_eq!(
+            keymap.resolve_layered(&layers, &[key("k")]),
+            KeyMatch::Action(Action::Up)
+        );
+        assert_eq!(keymap.resolve_layered(&layers, &[key("g")]), KeyMatch::Action(Action::GoTo));
+        assert_eq!(keymap.resolve_layered(&layers, &[key("z")]), KeyMatch::Unbound);
+    }
+}
@@ -0,0 +1,387 @@
+//! Host key verification against the user's known_hosts files
+//!
+//! Before sshr runs any background ssh command for a host it probes the
+//! connection with strict host key checking, so whatever the user's ssh
+//! config and known_hosts say is respected. When the key is unknown or has
+//! changed, the key the server presents is captured into a private
+//! known_hosts file and the host's `HostKeyPolicy` decides what happens next.
+//! Neither step authenticates or forwards anything to the server.
+
+use anyhow::{Context, Result};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::models::{HostKeyPolicy, SshHost};
+use crate::ssh_config::expand_tilde;
+
+/// Result of probing a host with strict checking enabled
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostKeyStatus {
+    /// The key matched known_hosts, or the user's config accepts it
+    Known,
+    Unknown,
+    Changed,
+}
+
+/// Key presented by a server that is not trusted yet
+#[derive(Debug, Clone)]
+pub struct HostKeyInfo {
+    pub alias: String,
+    /// Name the key is recorded under, e.g. `host` or `[host]:2222`
+    pub known_hosts_name: String,
+    pub key_type: String,
+    pub fingerprint: String,
+    pub changed: bool,
+}
+
+/// Options that stop the key checks at the key exchange: no authentication,
+/// agent, forwarding or shared connection reaches the server
+const KEY_CHECK_OPTIONS: [&str; 7] = [
+    "PreferredAuthentications=none",
+    "ForwardAgent=no",
+    "ForwardX11=no",
+    "ClearAllForwardings=yes",
+    "ControlPath=none",
+    "PermitLocalCommand=no",
+    "BatchMode=yes",
+];
+
+static KEY_DIR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Private directory holding a presented key. It is removed once the last
+/// host trusting the key for the session is dropped.
+#[derive(Debug)]
+pub struct SessionKnownHosts {
+    dir: PathBuf,
+}
+
+impl SessionKnownHosts {
+    fn create() -> Result<Self> {
+        // Only the user can write the key that gets trusted
+        let dir = std::env::temp_dir().join(format!(
+            "sshr-known-hosts-{}-{}",
+            std::process::id(),
+            KEY_DIR_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// known_hosts file inside the directory
+    pub fn file(&self) -> PathBuf {
+        self.dir.join("known_hosts")
+    }
+}
+
+impl Drop for SessionKnownHosts {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// User's answer to the host key dialog
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostKeyDecision {
+    /// Trust the key for this sshr session only
+    AcceptOnce,
+    /// Write the key to the user's known_hosts file
+    AcceptAndSave,
+    Abort,
+}
+
+/// Make sure the host key of `host` is trusted before connecting.
+///
+/// `ask` is called when the policy requires a decision from the user. On
+/// `AcceptOnce` the host is pointed at the private known_hosts file, so every
+/// later ssh invocation with this `SshHost` trusts the same key.
+pub fn verify_host_key<F>(host: &mut SshHost, ask: F) -> Result<()>
+where
+    F: FnOnce(&HostKeyInfo) -> HostKeyDecision,
+{
+    if host.host_key_policy == HostKeyPolicy::Off {
+        tracing::warn!("Host key checking disabled for {}", host.alias);
+        return Ok(());
+    }
+
+    let status = probe(host)?;
+    tracing::info!("Host key status for {}: {:?}", host.alias, status);
+
+    if status == HostKeyStatus::Known {
+        return Ok(());
+    }
+    let changed = status == HostKeyStatus::Changed;
+
+    match host.host_key_policy {
+        HostKeyPolicy::Strict => {
+            anyhow::bail!(
+                "Host key for {} is {} and host_key_policy is strict",
+                host.alias,
+                if changed { "changed" } else { "unknown" }
+            );
+        }
+        HostKeyPolicy::AcceptNew if changed => {
+            anyhow::bail!("Host key for {} has changed, refusing to connect", host.alias);
+        }
+        _ => {}
+    }
+
+    let key_dir = SessionKnownHosts::create()?;
+    let info = fetch_presented_key(host, changed, &key_dir.file())?;
+
+    let decision = if host.host_key_policy == HostKeyPolicy::AcceptNew {
+        HostKeyDecision::AcceptAndSave
+    } else {
+        ask(&info)
+    };
+    tracing::info!("Host key decision for {}: {:?}", host.alias, decision);
+
+    match decision {
+        HostKeyDecision::AcceptOnce => {
+            host.session_known_hosts = Some(Arc::new(key_dir));
+            Ok(())
+        }
+        HostKeyDecision::AcceptAndSave => save_key(host, &info, &key_dir.file()),
+        HostKeyDecision::Abort => {
+            anyhow::bail!("Host key for {} was not accepted", host.alias)
+        }
+    }
+}
+
+/// Connect with strict checking and classify the failure, if any
+fn probe(host: &SshHost) -> Result<HostKeyStatus> {
+    let mut command = Command::new("ssh");
+    for option in KEY_CHECK_OPTIONS {
+        command.arg("-o").arg(option);
+    }
+    let output = command
+        .args(host.ssh_command_args())
+        .arg("-o")
+        .arg("StrictHostKeyChecking=yes")
+        .arg("-o")
+        .arg("ConnectTimeout=5")
+        .arg(host.destination())
+        .arg("exit")
+        .output()
+        .context("Failed to run ssh to check the host key")?;
+
+    Ok(classify_ssh_error(&String::from_utf8_lossy(&output.stderr)))
+}
+
+fn classify_ssh_error(stderr: &str) -> HostKeyStatus {
+    if stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED")
+        || (stderr.contains("Host key for") && stderr.contains("has changed"))
+    {
+        HostKeyStatus::Changed
+    } else if stderr.contains("Host key verification failed")
+        || stderr.contains("host key is known for")
+    {
+        HostKeyStatus::Unknown
+    } else {
+        HostKeyStatus::Known
+    }
+}
+
+/// Record the key the server presents in `key_file` and read back its type
+/// and fingerprint
+fn fetch_presented_key(host: &SshHost, changed: bool, key_file: &Path) -> Result<HostKeyInfo> {
+    // Our options go first so they win over the host's own options
+    let mut command = Command::new("ssh");
+    for option in KEY_CHECK_OPTIONS {
+        command.arg("-o").arg(option);
+    }
+    let _ = command
+        .arg("-o")
+        .arg(format!("UserKnownHostsFile={}", key_file.display()))
+        .arg("-o")
+        .arg("GlobalKnownHostsFile=/dev/null")
+        .arg("-o")
+        .arg("StrictHostKeyChecking=accept-new")
+        .arg("-o")
+        .arg("HashKnownHosts=no")
+        .arg("-o")
+        .arg("ConnectTimeout=10")
+        .arg("-o")
+        .arg("LogLevel=ERROR")
+        .args(host.ssh_command_args())
+        .arg(host.destination())
+        .arg("exit")
+        .output()
+        .context("Failed to run ssh to fetch the host key")?;
+
+    let recorded = fs::read_to_string(key_file).unwrap_or_default();
+    let known_hosts_name = recorded
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not retrieve the host key of {}", host.alias))?
+        .to_string();
+
+    let output = Command::new("ssh-keygen")
+        .arg("-l")
+        .arg("-f")
+        .arg(key_file)
+        .output()
+        .context("Failed to run ssh-keygen")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ssh-keygen failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let (fingerprint, key_type) = parse_fingerprint_line(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow::anyhow!("Unexpected ssh-keygen output"))?;
+
+    Ok(HostKeyInfo {
+        alias: host.alias.clone(),
+        known_hosts_name,
+        key_type,
+        fingerprint,
+        changed,
+    })
+}
+
+/// Parse `256 SHA256:abc... host (ED25519)` into fingerprint and key type
+fn parse_fingerprint_line(output: &str) -> Option<(String, String)> {
+    let line = output.lines().next()?;
+    let fingerprint = line.split_whitespace().nth(1)?.to_string();
+    let key_type = line
+        .rsplit_once('(')
+        .map(|(_, rest)| rest.trim_end_matches(')').to_string())?;
+    Some((fingerprint, key_type))
+}
+
+/// Append the accepted key in `key_file` to the user's known_hosts file,
+/// replacing any stale entry for the same name first
+fn save_key(host: &SshHost, info: &HostKeyInfo, key_file: &Path) -> Result<()> {
+    let known_hosts = user_known_hosts_file(host);
+    if let Some(parent) = known_hosts.parent() {
+        fs::create_dir_all(parent).context("Failed to create known_hosts directory")?;
+    }
+
+    if info.changed && known_hosts.exists() {
+        let output = Command::new("ssh-keygen")
+            .arg("-R")
+            .arg(&info.known_hosts_name)
+            .arg("-f")
+            .arg(&known_hosts)
+            .output()
+            .context("Failed to run ssh-keygen -R")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to remove old host key: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+
+    let entry = fs::read_to_string(key_file).context("Failed to read fetched host key")?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&known_hosts)
+        .with_context(|| format!("Failed to open {}", known_hosts.display()))?;
+    file.write_all(entry.as_bytes())
+        .context("Failed to write known_hosts")?;
+
+    tracing::info!(
+        "Saved {} key for {} to {}",
+        info.key_type,
+        info.known_hosts_name,
+        known_hosts.display()
+    );
+    Ok(())
+}
+
+/// First `UserKnownHostsFile` from the effective ssh configuration
+fn user_known_hosts_file(host: &SshHost) -> PathBuf {
+    let configured = Command::new("ssh")
+        .arg("-G")
+        .args(host.ssh_command_args())
+        .arg(host.destination())
+        .output()
+        .ok()
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find_map(|line| line.strip_prefix("userknownhostsfile "))
+                .and_then(|files| files.split_whitespace().next())
+                .map(expand_tilde)
+        });
+
+    configured.unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| Path::new("~").to_path_buf())
+            .join(".ssh")
+            .join("known_hosts")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_openssh_messages() {
+        let changed = "@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+            @    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n\
+            @@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+            Host key for web.example.com has changed and you have requested strict checking.\n\
+            Host key verification failed.\n";
+        assert_eq!(classify_ssh_error(changed), HostKeyStatus::Changed);
+
+        let unknown = "No ED25519 host key is known for web.example.com and you have requested strict checking.\n\
+            Host key verification failed.\n";
+        assert_eq!(classify_ssh_error(unknown), HostKeyStatus::Unknown);
+
+        let auth = "deploy@web.example.com: Permission denied (publickey).\n";
+        assert_eq!(classify_ssh_error(auth), HostKeyStatus::Known);
+        assert_eq!(classify_ssh_error(""), HostKeyStatus::Known);
+    }
+
+    #[test]
+    fn parses_ssh_keygen_fingerprint() {
+        let output = "256 SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s [web.example.com]:2222 (ED25519)\n";
+        assert_eq!(
+            parse_fingerprint_line(output),
+            Some((
+                "SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s".to_string(),
+                "ED25519".to_string()
+            ))
+        );
+        assert_eq!(parse_fingerprint_line(""), None);
+    }
+
+    #[test]
+    fn session_keys_are_private_and_removed_with_the_last_host() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let key_dir = SessionKnownHosts::create().unwrap();
+        let (dir, file) = (key_dir.dir.clone(), key_dir.file());
+        fs::write(&file, "host ssh-ed25519 AAAA\n").unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        let mut host = SshHost::new("lab".into(), "192.0.2.1".into(), "root".into());
+        host.session_known_hosts = Some(Arc::new(key_dir));
+        let copy = host.clone();
+        drop(host);
+        assert!(file.exists());
+        drop(copy);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn off_policy_skips_verification() {
+        let mut host = SshHost::new("lab".into(), "192.0.2.1".into(), "root".into());
+        host.host_key_policy = HostKeyPolicy::Off;
+        verify_host_key(&mut host, |_| panic!("must not ask")).unwrap();
+        assert_eq!(host.ssh_command_args()[2..4], ["-o", "StrictHostKeyChecking=no"]);
+    }
+}
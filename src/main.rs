@@ -15,10 +15,17 @@ use std::{io, time::Duration};
 use tracing_subscriber::{fmt, EnvFilter};
 
 mod app_event;
+mod cli;
 mod config;
+mod editor;
+mod keybindings;
+mod known_hosts;
 mod models;
 mod sftp_logic;
 mod sftp_ui;
+mod shell;
+mod ssh_config;
+mod theme;
 mod app;
 mod ui;
 
@@ -27,13 +34,19 @@ use ui::{
 };
 
 use crate::app::{App, InputMode};
+use crate::keybindings::{Action, KeyMatch, Mode};
 
 /// A TUI for managing and connecting to SSH hosts
 /// Git: https://github.com/hoangneeee/sshr
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    // No need for a custom version flag as clap provides it by default
+    #[command(subcommand)]
+    command: Option<cli::Command>,
+
+    /// Color theme to start with, e.g. dark, light, solarized, high-contrast
+    #[arg(short, long)]
+    theme: Option<String>,
 }
 
 fn setup_logging() -> Result<()> {
@@ -71,7 +84,7 @@ fn setup_logging() -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let _args = Args::parse();
+    let args = Args::parse();
 
     // Setup logging
     if let Err(e) = setup_logging() {
@@ -80,7 +93,17 @@ async fn main() -> Result<()> {
     }
 
     // Initialize the app with configuration
-    let app = App::new().context("Failed to initialize application")?;
+    let mut app = App::new().context("Failed to initialize application")?;
+    if let Some(theme) = &args.theme {
+        app.set_theme(theme)?;
+    }
+
+    // Subcommands other than `sftp` don't need the TUI at all
+    let sftp_host = match args.command {
+        Some(cli::Command::Sftp { alias }) => Some(cli::find_host(&app, &alias)?.clone()),
+        Some(command) => return cli::run(&app, command),
+        None => None,
+    };
 
     // Setup terminal
     enable_raw_mode().context("Failed to enable raw mode")?;
@@ -93,7 +116,13 @@ async fn main() -> Result<()> {
 
     // Run the application
     tracing::info!("Running application");
-    let res = run_app(&mut terminal, app).await;
+    let res = match sftp_host {
+        Some(host) => match app.start_sftp_session(host, &mut terminal) {
+            Ok(()) => run_app(&mut terminal, app).await,
+            Err(e) => Err(e),
+        },
+        None => run_app(&mut terminal, app).await,
+    };
 
     // Restore terminal
     disable_raw_mode().context("Failed to disable raw mode")?;
@@ -127,6 +156,9 @@ async fn run_app<B: ratatui::backend::Backend>(
         let _ = app.process_sftp_events::<B>(terminal)?;
         
         app.process_transfer_events()?;
+        if let Some(sftp_state) = app.sftp_state.as_mut().filter(|state| state.show_preview) {
+            sftp_state.update_preview();
+        }
 
         // If we're in SSH mode, suspend the main loop until SSH ends
         if app.ssh_ready_for_terminal {
@@ -146,7 +178,7 @@ async fn run_app<B: ratatui::backend::Backend>(
             }
 
             // Force redraw when returning from SSH
-            terminal.draw(|f| draw::<B>(f, &mut app))?;
+            terminal.draw(|f| draw(f, &mut app))?;
             continue;
         }
 
@@ -154,12 +186,12 @@ async fn run_app<B: ratatui::backend::Backend>(
         terminal.draw(|f: &mut ratatui::Frame<'_>| match app.input_mode {
             InputMode::Sftp => {
                 if let Some(sftp_state) = &mut app.sftp_state {
-                    sftp_ui::draw_sftp::<B>(f, sftp_state);
+                    sftp_ui::draw_sftp(f, sftp_state, &app.theme, &app.keymap);
                 } else {
-                    draw::<B>(f, &mut app);
+                    draw(f, &mut app);
                 }
             }
-            _ => draw::<B>(f, &mut app),
+            _ => draw(f, &mut app),
         })?;
 
         // Handle terminal events with appropriate timeout
@@ -170,14 +202,22 @@ async fn run_app<B: ratatui::backend::Backend>(
         };
 
         if event::poll(poll_timeout).context("Event poll failed")? {
-            if let CrosstermEvent::Key(key_event) = event::read().context("Event read failed")? {
-                // Only handle keys if not connecting and not in SSH mode
-                if key_event.kind == event::KeyEventKind::Press
-                    && !app.is_connecting
-                    && !app.ssh_ready_for_terminal
+            match event::read().context("Event read failed")? {
+                // Only handle keys if not connecting and not in SSH mode,
+                // unless a host key dialog is waiting for an answer
+                CrosstermEvent::Key(key_event)
+                    if key_event.kind == event::KeyEventKind::Press
+                        && (!app.is_connecting || app.host_key_prompt.is_some())
+                        && !app.ssh_ready_for_terminal =>
                 {
                     handle_key_events(&mut app, key_event, terminal).await?;
                 }
+                CrosstermEvent::Mouse(mouse_event)
+                    if !app.is_connecting && !app.ssh_ready_for_terminal =>
+                {
+                    app.handle_mouse_event(mouse_event, terminal)?;
+                }
+                _ => {}
             }
         }
 
@@ -190,12 +230,12 @@ async fn run_app<B: ratatui::backend::Backend>(
             terminal.draw(|f| match app.input_mode {
                 InputMode::Sftp => {
                     if let Some(sftp_state) = &mut app.sftp_state {
-                        sftp_ui::draw_sftp::<B>(f, sftp_state);
+                        sftp_ui::draw_sftp(f, sftp_state, &app.theme, &app.keymap);
                     } else {
-                        draw::<B>(f, &mut app);
+                        draw(f, &mut app);
                     }
                 }
-                _ => draw::<B>(f, &mut app),
+                _ => draw(f, &mut app),
             })?;
         }
     }
@@ -206,96 +246,139 @@ async fn handle_key_events<B: ratatui::backend::Backend>(
     key_event: crossterm::event::KeyEvent,
     terminal: &mut Terminal<B>,
 ) -> Result<()> {
+    // A pending host key dialog takes every key until it is answered
+    if app.host_key_prompt.is_some() {
+        return app.handle_host_key_prompt_key(key_event);
+    }
+    if app.hosts_file_error.is_some() {
+        if let Err(e) = app.handle_hosts_file_error_key(key_event, terminal) {
+            tracing::error!("Failed to open editor: {}", e);
+            app.status_message = Some((format!("Failed to open editor: {}", e), Instant::now()));
+        }
+        return Ok(());
+    }
+    if app.pending_delete.is_some() {
+        return app.handle_delete_confirm_key(key_event);
+    }
+
     match app.input_mode {
-        InputMode::Normal => match key_event.code {
-            KeyCode::Char('q') | KeyCode::Char('Q') => {
-                app.handle_key_q()?;
-            }
-            KeyCode::Char('c') if key_event.modifiers == KeyModifiers::CONTROL => {
-                app.should_quit = true;
-            }
-            KeyCode::Char('s') => {
-                // Enter search mode
-                app.enter_search_mode();
-            }
-            KeyCode::Tab => {
-                if key_event.modifiers.contains(KeyModifiers::SHIFT) {
-                    app.handle_shift_tab()?;
-                } else {
+        InputMode::Normal => {
+            let action = match app.action_for_key(Mode::Normal, &key_event) {
+                KeyMatch::Action(action) => action,
+                KeyMatch::Pending | KeyMatch::Unbound => return Ok(()),
+            };
+            match action {
+                Action::Quit => {
+                    app.handle_key_q()?;
+                }
+                Action::Search => {
+                    // Enter search mode
+                    app.enter_search_mode();
+                }
+                Action::NextPanel => {
                     app.handle_key_tab()?;
                 }
-            }
-            KeyCode::Right => {
-                app.handle_key_right()?;
-            }
-            KeyCode::Left => {
-                app.handle_key_left()?;
-            }
-            KeyCode::Char('f') => {
-                // Enter SFTP mode
-                app.enter_sftp_mode(terminal)?;
-            }
-            KeyCode::Up | KeyCode::Char('k') => {
-                app.select_previous();
-            }
-            KeyCode::Down | KeyCode::Char('j') => {
-                app.select_next();
-            }
-            KeyCode::Char('e') => {
-                if let Err(e) = app.handle_key_e() {
-                    tracing::error!("Failed to open editor: {}", e);
-                    app.status_message =
-                        Some((format!("Failed to open editor: {}", e), Instant::now()));
+                Action::PreviousGroup => {
+                    app.handle_shift_tab()?;
                 }
-            }
-            KeyCode::Esc => {
-                app.handle_key_esc()?;
-            }
-            KeyCode::Enter => {
-                app.handle_key_enter(terminal)?;
-            }
-            KeyCode::Char('r') => {
-                tracing::info!("Reloading SSH config...");
-                if let Err(e) = app.load_all_hosts() {
-                    tracing::error!("Failed to reload SSH config: {}", e);
-                    app.status_message = Some((format!("Reload failed: {}", e), Instant::now()));
-                } else {
-                    app.status_message =
-                        Some(("Config reloaded successfully".to_string(), Instant::now()));
+                Action::Right => {
+                    app.handle_key_right()?;
+                }
+                Action::Left => {
+                    app.handle_key_left()?;
+                }
+                Action::Sftp => {
+                    // Enter SFTP mode
+                    app.enter_sftp_mode(terminal)?;
+                }
+                Action::Up => {
+                    app.select_previous();
+                }
+                Action::Down => {
+                    app.select_next();
+                }
+                Action::NextTheme => {
+                    app.next_theme();
+                }
+                Action::AddHost => {
+                    app.open_add_host_form();
+                }
+                Action::EditHost => {
+                    app.open_edit_host_form();
+                }
+                Action::CloneHost => {
+                    app.open_clone_host_form();
+                }
+                Action::MoveHost => {
+                    app.open_move_host_form();
+                }
+                Action::DeleteHost => {
+                    app.request_delete_host();
+                }
+                Action::EditHostsFile => {
+                    if let Err(e) = app.handle_key_e(terminal) {
+                        tracing::error!("Failed to open editor: {}", e);
+                        app.status_message =
+                            Some((format!("Failed to open editor: {}", e), Instant::now()));
+                    }
                 }
+                Action::Cancel => {
+                    app.handle_key_esc()?;
+                }
+                Action::Connect => {
+                    app.handle_key_enter(terminal)?;
+                }
+                Action::Reload => {
+                    tracing::info!("Reloading SSH config...");
+                    if let Err(e) = app.load_all_hosts() {
+                        tracing::error!("Failed to reload SSH config: {}", e);
+                        app.status_message =
+                            Some((format!("Reload failed: {}", e), Instant::now()));
+                    } else {
+                        app.status_message =
+                            Some(("Config reloaded successfully".to_string(), Instant::now()));
+                    }
+                }
+                _ => {}
             }
-            _ => {}
-        },
+        }
         InputMode::Search => {
-            match key_event.code {
-                KeyCode::Char(c) => {
-                    app.search_query.push(c);
-                    app.filter_hosts();
-                }
-                KeyCode::Backspace | KeyCode::Delete => {
+            match app.action_for_key(Mode::Search, &key_event) {
+                KeyMatch::Action(Action::DeleteChar) => {
                     app.search_query.pop();
                     app.filter_hosts();
                 }
-                KeyCode::Enter => {
+                KeyMatch::Action(Action::Connect) => {
                     // Connect to selected filtered host
                     app.handle_key_enter(terminal)?;
                     app.clear_search();
                 }
-                KeyCode::Esc => {
+                KeyMatch::Action(Action::Cancel) => {
                     app.clear_search();
                 }
-                KeyCode::Up => {
+                KeyMatch::Action(Action::Up) => {
                     app.search_select_previous();
                 }
-                KeyCode::Down => {
+                KeyMatch::Action(Action::Down) => {
                     app.search_select_next();
                 }
+                // Keys that aren't bound are typed into the query
+                KeyMatch::Unbound => {
+                    if let KeyCode::Char(c) = key_event.code {
+                        if !key_event.modifiers.contains(KeyModifiers::CONTROL) {
+                            app.search_query.push(c);
+                            app.filter_hosts();
+                        }
+                    }
+                }
                 _ => {}
             }
         }
         
+        InputMode::HostForm => app.handle_host_form_key(key_event)?,
+
         // SFTP INPUT MODE
-        InputMode::Sftp => app.handle_sftp_key(key_event).await?,
+        InputMode::Sftp => app.handle_sftp_key(key_event, terminal).await?,
     }
     Ok(())
 }
@@ -1,4 +1,38 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::known_hosts::SessionKnownHosts;
+
+/// ssh options that only make sense for an interactive session and must not
+/// leak into the background `ssh` commands run for tests and SFTP sessions
+const SESSION_ONLY_OPTIONS: &[&str] = &[
+    "dynamicforward",
+    "forkafterauthentication",
+    "localcommand",
+    "localforward",
+    "permitlocalcommand",
+    "remotecommand",
+    "remoteforward",
+    "requesttty",
+    "sessiontype",
+    "stdinnull",
+];
+
+/// How sshr treats a host key that is not in known_hosts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyPolicy {
+    /// Show the fingerprint and let the user decide
+    #[default]
+    Ask,
+    /// Only connect when known_hosts already has a matching key
+    Strict,
+    /// Save unknown keys automatically, refuse changed ones
+    AcceptNew,
+    /// Skip verification entirely (disposable test machines only)
+    Off,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SshHost {
@@ -8,6 +42,63 @@ pub struct SshHost {
     pub port: Option<u16>,
     pub description: Option<String>,
     pub group: Option<String>,
+
+    /// Private key passed with `-i`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<String>,
+    /// Jump hosts passed with `-J`, in connection order
+    #[serde(
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub proxy_jump: Vec<String>,
+    /// `-L` specs such as `8080:localhost:80`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local_forwards: Vec<String>,
+    /// `-R` specs such as `9000:localhost:9000`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remote_forwards: Vec<String>,
+    /// `-D` SOCKS port, optionally prefixed with a bind address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamic_forward: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forward_agent: Option<bool>,
+    /// Environment variables sent with `SetEnv`
+    #[serde(default, alias = "set_env", skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Any other ssh option, passed as `-o Key=Value`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub host_key_policy: HostKeyPolicy,
+    /// Verify SFTP transfers with SHA-256, overriding `[sftp] verify`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_transfers: Option<bool>,
+    /// Compress the SFTP connection, overriding `[sftp] compression`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress_transfers: Option<bool>,
+    /// KiB per second each SFTP transfer may use, overriding
+    /// `[sftp] bandwidth_limit_kb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_limit_kb: Option<u64>,
+    /// Directory the local SFTP panel starts in, `~` is the home directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_start_dir: Option<String>,
+    /// Directory the remote SFTP panel starts in, relative to the login
+    /// directory unless absolute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_start_dir: Option<String>,
+    /// Favorite local directories in SFTP mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local_bookmarks: Vec<String>,
+    /// Favorite remote directories in SFTP mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remote_bookmarks: Vec<String>,
+
+    /// known_hosts file holding a key accepted for this session only
+    #[serde(skip)]
+    pub session_known_hosts: Option<Arc<SessionKnownHosts>>,
 }
 
 impl SshHost {
@@ -19,6 +110,206 @@ impl SshHost {
             port: Some(22),
             description: None,
             group: None,
+            identity_file: None,
+            proxy_jump: Vec::new(),
+            local_forwards: Vec::new(),
+            remote_forwards: Vec::new(),
+            dynamic_forward: None,
+            forward_agent: None,
+            env: BTreeMap::new(),
+            options: BTreeMap::new(),
+            host_key_policy: HostKeyPolicy::default(),
+            verify_transfers: None,
+            compress_transfers: None,
+            bandwidth_limit_kb: None,
+            local_start_dir: None,
+            remote_start_dir: None,
+            local_bookmarks: Vec::new(),
+            remote_bookmarks: Vec::new(),
+            session_known_hosts: None,
         }
     }
-}
\ No newline at end of file
+
+    /// `user@host` target for ssh
+    pub fn destination(&self) -> String {
+        format!("{}@{}", self.user, self.host)
+    }
+
+    /// Arguments shared by every ssh invocation for this host
+    fn common_args(&self, include_session_options: bool) -> Vec<String> {
+        let mut args = Vec::new();
+
+        // Host key options come first so the host's own options can't undo them
+        if self.host_key_policy == HostKeyPolicy::Off {
+            args.push("-o".to_string());
+            args.push("StrictHostKeyChecking=no".to_string());
+        } else if let Some(known_hosts) = &self.session_known_hosts {
+            args.push("-o".to_string());
+            args.push(format!(
+                "UserKnownHostsFile={}",
+                quote_option_arg(&known_hosts.file().to_string_lossy())
+            ));
+        }
+
+        if let Some(identity_file) = &self.identity_file {
+            args.push("-i".to_string());
+            args.push(identity_file.clone());
+        }
+        if !self.proxy_jump.is_empty() {
+            args.push("-J".to_string());
+            args.push(self.proxy_jump.join(","));
+        }
+        for (key, value) in &self.options {
+            if !include_session_options
+                && SESSION_ONLY_OPTIONS.contains(&key.to_lowercase().as_str())
+            {
+                continue;
+            }
+            args.push("-o".to_string());
+            args.push(format!("{}={}", key, value));
+        }
+
+        args
+    }
+
+    /// Arguments for an interactive ssh session, including forwards, agent
+    /// forwarding and environment. The destination is not included.
+    pub fn ssh_session_args(&self) -> Vec<String> {
+        let mut args = vec!["-p".to_string(), self.port.unwrap_or(22).to_string()];
+        args.extend(self.common_args(true));
+
+        for spec in &self.local_forwards {
+            args.push("-L".to_string());
+            args.push(spec.clone());
+        }
+        for spec in &self.remote_forwards {
+            args.push("-R".to_string());
+            args.push(spec.clone());
+        }
+        if let Some(spec) = &self.dynamic_forward {
+            args.push("-D".to_string());
+            args.push(spec.clone());
+        }
+        match self.forward_agent {
+            Some(true) => args.push("-A".to_string()),
+            Some(false) => args.push("-a".to_string()),
+            None => {}
+        }
+        if !self.env.is_empty() {
+            let vars: Vec<String> = self
+                .env
+                .iter()
+                .map(|(key, value)| quote_option_arg(&format!("{}={}", key, value)))
+                .collect();
+            args.push("-o".to_string());
+            args.push(format!("SetEnv={}", vars.join(" ")));
+        }
+
+        args
+    }
+
+    /// Arguments for a non-interactive ssh command (connection tests, remote
+    /// listings). The destination is not included.
+    pub fn ssh_command_args(&self) -> Vec<String> {
+        let mut args = vec!["-p".to_string(), self.port.unwrap_or(22).to_string()];
+        args.extend(self.common_args(false));
+        args
+    }
+}
+
+/// Quote an option argument for ssh's config-line parser if it contains spaces
+pub fn quote_option_arg(arg: &str) -> String {
+    if arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+fn is_default_policy(policy: &HostKeyPolicy) -> bool {
+    *policy == HostKeyPolicy::default()
+}
+
+/// Accept either `"a,b"` or `["a", "b"]` for list fields in hosts.toml
+fn string_or_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(s) => s
+            .split(',')
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect(),
+        StringOrList::List(list) => list,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_host() -> SshHost {
+        toml::from_str(
+            r#"
+            alias = "app"
+            host = "10.0.0.10"
+            user = "deploy"
+            port = 2222
+            identity_file = "~/.ssh/app_ed25519"
+            proxy_jump = "jump1, ops@jump2:2200"
+            local_forwards = ["8080:localhost:80"]
+            remote_forwards = ["9000:localhost:9000"]
+            dynamic_forward = "1080"
+            forward_agent = true
+            env = { LANG = "C.UTF-8" }
+            options = { ServerAliveInterval = "15", RequestTTY = "force" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn proxy_jump_accepts_string_or_list() {
+        assert_eq!(sample_host().proxy_jump, vec!["jump1", "ops@jump2:2200"]);
+
+        let host: SshHost = toml::from_str(
+            r#"
+            alias = "a"
+            host = "b"
+            user = "c"
+            proxy_jump = ["x", "y"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(host.proxy_jump, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn session_args_include_forwards_and_env() {
+        let args = sample_host().ssh_session_args().join(" ");
+        assert_eq!(
+            args,
+            "-p 2222 -i ~/.ssh/app_ed25519 -J jump1,ops@jump2:2200 \
+             -o RequestTTY=force -o ServerAliveInterval=15 \
+             -L 8080:localhost:80 -R 9000:localhost:9000 -D 1080 -A \
+             -o SetEnv=LANG=C.UTF-8"
+        );
+    }
+
+    #[test]
+    fn command_args_skip_session_options() {
+        let host = sample_host();
+        assert_eq!(
+            host.ssh_command_args().join(" "),
+            "-p 2222 -i ~/.ssh/app_ed25519 -J jump1,ops@jump2:2200 -o ServerAliveInterval=15"
+        );
+    }
+}
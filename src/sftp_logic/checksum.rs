@@ -0,0 +1,217 @@
+//! SHA-256 verification of transferred files
+//!
+//! The local copy is hashed here. The remote one is hashed by the SFTP server
+//! when it supports the `check-file-name` extension, and otherwise by running
+//! `sha256sum` over ssh.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use super::session::SftpSession;
+use crate::shell;
+use crate::models::SshHost;
+
+/// A file whose two sides have different SHA-256 hashes after a transfer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumMismatch {
+    pub local_path: PathBuf,
+    pub remote_path: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SHA-256 of the copy differs from the source")
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// The server can't hash files, it has neither the `check-file-name`
+/// extension nor `sha256sum`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationUnavailable;
+
+impl fmt::Display for VerificationUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Verification unavailable, the server has no sha256sum")
+    }
+}
+
+impl std::error::Error for VerificationUnavailable {}
+
+/// Compare the SHA-256 hashes of a local file and a remote file, returning
+/// the hash they share or failing with a `ChecksumMismatch`
+pub fn verify(
+    session: &SftpSession,
+    host: &SshHost,
+    local_path: &Path,
+    remote_path: &str,
+) -> Result<String> {
+    let local = sha256_local(local_path)?;
+    let remote = sha256_remote(session, host, remote_path)?;
+    if local != remote {
+        tracing::warn!(
+            "SHA-256 mismatch: {} is {}, {} is {}",
+            local_path.display(),
+            local,
+            remote_path,
+            remote
+        );
+        return Err(ChecksumMismatch {
+            local_path: local_path.to_path_buf(),
+            remote_path: remote_path.to_string(),
+        }
+        .into());
+    }
+    Ok(local)
+}
+
+/// SHA-256 of a local file as lowercase hex
+pub fn sha256_local(path: &Path) -> Result<String> {
+    sha256_local_prefix(path, u64::MAX)
+}
+
+/// SHA-256 of the first `length` bytes of a local file
+pub(super) fn sha256_local_prefix(path: &Path, length: u64) -> Result<String> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file.take(length), &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// SHA-256 of a remote file as lowercase hex
+pub(super) fn sha256_remote(session: &SftpSession, host: &SshHost, path: &str) -> Result<String> {
+    sha256_remote_prefix(session, host, path, None)
+}
+
+/// SHA-256 of the first `length` bytes of a remote file, of all of it when
+/// `None`
+pub(super) fn sha256_remote_prefix(
+    session: &SftpSession,
+    host: &SshHost,
+    path: &str,
+    length: Option<u64>,
+) -> Result<String> {
+    sha256_remote_with(session, path, length, |command| {
+        Command::new("ssh")
+            .args(host.ssh_command_args())
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg("-o")
+            .arg("LogLevel=ERROR")
+            .arg(host.destination())
+            .arg(command)
+            .stdin(Stdio::null())
+            .output()
+    })
+}
+
+/// `sha256_remote_prefix` with `run` running a shell command on the server
+fn sha256_remote_with<R>(
+    session: &SftpSession,
+    path: &str,
+    length: Option<u64>,
+    run: R,
+) -> Result<String>
+where
+    R: FnOnce(&str) -> io::Result<Output>,
+{
+    let command = match length {
+        Some(0) => bail!("Nothing to hash in {}", path),
+        Some(length) => format!("head -c {} -- {} | sha256sum", length, shell::quote(path)),
+        None => format!("sha256sum -- {}", shell::quote(path)),
+    };
+    if let Some(hash) = session.sha256(path, length.unwrap_or(0))? {
+        return Ok(hash);
+    }
+
+    let output = run(&command).context("Failed to run ssh for sha256sum")?;
+    // 127 is the shell's "command not found"
+    if output.status.code() == Some(127) {
+        return Err(VerificationUnavailable.into());
+    }
+    if !output.status.success() {
+        bail!(
+            "sha256sum failed on the server: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.split_whitespace().next() {
+        Some(hash) if hash.len() == 64 => Ok(hash.to_lowercase()),
+        None => Err(VerificationUnavailable.into()),
+        _ => bail!("Unexpected sha256sum output: {}", stdout.trim()),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{self, scratch_dir};
+    use super::*;
+
+    #[test]
+    fn hashes_both_sides_and_reports_mismatches() {
+        let dir = scratch_dir("checksum");
+        let session = test_server::connect();
+        let host = test_server::host();
+        std::fs::write(dir.join("local"), "hello\n").unwrap();
+        std::fs::write(dir.join("same"), "hello\n").unwrap();
+        std::fs::write(dir.join("other"), "hellO\n").unwrap();
+
+        assert_eq!(
+            sha256_local(&dir.join("local")).unwrap(),
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+        );
+        let remote = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        verify(&session, &host, &dir.join("local"), &remote("same")).unwrap();
+        let error = verify(&session, &host, &dir.join("local"), &remote("other")).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ChecksumMismatch>(),
+            Some(&ChecksumMismatch {
+                local_path: dir.join("local"),
+                remote_path: remote("other"),
+            })
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_sha256sum_without_check_file() {
+        let dir = scratch_dir("checksum-fallback");
+        let session = test_server::connect_without_check_file();
+        let file = dir.join("file");
+        std::fs::write(&file, "hello\n").unwrap();
+        let path = file.to_string_lossy();
+
+        let shell = |command: &str| Command::new("/bin/sh").arg("-c").arg(command).output();
+        assert_eq!(
+            sha256_remote_with(&session, &path, None, shell).unwrap(),
+            sha256_local(&file).unwrap()
+        );
+        assert_eq!(
+            sha256_remote_with(&session, &path, Some(4), shell).unwrap(),
+            sha256_local_prefix(&file, 4).unwrap()
+        );
+
+        // Nothing on PATH, so no sha256sum either
+        let empty = scratch_dir("checksum-empty-path");
+        let bare_shell = |command: &str| {
+            let mut shell = Command::new("/bin/sh");
+            shell.arg("-c").arg(command).env("PATH", &empty).output()
+        };
+        let error = sha256_remote_with(&session, &path, None, bare_shell).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&VerificationUnavailable));
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(empty).unwrap();
+    }
+}
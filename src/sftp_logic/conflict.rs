@@ -0,0 +1,309 @@
+//! Uploads and downloads whose target already exists
+//!
+//! Before a transfer is queued its target is looked up. When something is
+//! there already, the transfer waits in a `ConflictPrompt` until the user
+//! overwrites it, skips it, copies under a numbered name or overwrites only
+//! older targets, for that one conflict or for all the remaining ones.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+use super::queue::TransferDirection;
+use super::session::join_remote;
+use super::types::AppSftpState;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictChoice {
+    Overwrite,
+    Skip,
+    /// Copy next to the target as `name (1).ext`
+    Rename,
+    /// Overwrite when the source was modified later, skip otherwise
+    OverwriteIfNewer,
+}
+
+/// What is known about one side of a conflict
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EntryStat {
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    /// Seconds since the epoch
+    pub mtime: Option<i64>,
+}
+
+/// A transfer whose target exists
+#[derive(Debug, Clone)]
+pub struct TransferConflict {
+    pub direction: TransferDirection,
+    pub name: String,
+    pub is_dir: bool,
+    pub local_path: PathBuf,
+    pub remote_path: String,
+    pub source: EntryStat,
+    pub target: EntryStat,
+}
+
+/// Conflicts waiting for a decision, the first one is shown
+#[derive(Debug, Clone)]
+pub struct ConflictPrompt {
+    pub pending: VecDeque<TransferConflict>,
+    /// Conflicts found, to show how far along the prompt is
+    pub total: usize,
+    /// Whether the next choice also decides all remaining conflicts
+    pub apply_to_all: bool,
+    queued: usize,
+    skipped: usize,
+}
+
+impl ConflictPrompt {
+    pub fn current(&self) -> Option<&TransferConflict> {
+        self.pending.front()
+    }
+
+    /// Position of the shown conflict, counting from 1
+    pub fn position(&self) -> usize {
+        self.total - self.pending.len() + 1
+    }
+}
+
+impl AppSftpState {
+    /// Queue transfers of `entries` in the current directories. Those whose
+    /// target exists are held back and asked about. Returns how many were
+    /// queued right away.
+    pub(super) fn queue_unless_conflicting(
+        &mut self,
+        direction: TransferDirection,
+        entries: &[(String, bool)],
+    ) -> usize {
+        let mut conflicts = VecDeque::new();
+        let mut queued = 0;
+        for (name, is_dir) in entries {
+            let local_path = self.local_current_path.join(name);
+            let remote_path = join_remote(&self.remote_current_path, name);
+            let (source, target) = match direction {
+                TransferDirection::Upload => {
+                    (self.local_stat(&local_path), self.remote_stat(&remote_path))
+                }
+                TransferDirection::Download => {
+                    (self.remote_stat(&remote_path), self.local_stat(&local_path))
+                }
+            };
+            match target {
+                Some(target) => conflicts.push_back(TransferConflict {
+                    direction,
+                    name: name.clone(),
+                    is_dir: *is_dir,
+                    local_path,
+                    remote_path,
+                    source: source.unwrap_or_default(),
+                    target,
+                }),
+                None => {
+                    self.queue
+                        .push(direction, name, local_path, remote_path, *is_dir, self.verify);
+                    queued += 1;
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            self.conflicts = Some(ConflictPrompt {
+                total: conflicts.len(),
+                pending: conflicts,
+                apply_to_all: false,
+                queued: 0,
+                skipped: 0,
+            });
+        }
+        queued
+    }
+
+    pub fn toggle_conflict_apply_to_all(&mut self) {
+        if let Some(prompt) = &mut self.conflicts {
+            prompt.apply_to_all = !prompt.apply_to_all;
+        }
+    }
+
+    /// Decide the shown conflict, or all remaining ones when the choice
+    /// applies to all, and start what was queued once none are left
+    pub fn resolve_conflict(&mut self, choice: ConflictChoice) {
+        let Some(mut prompt) = self.conflicts.take() else {
+            return;
+        };
+        let count = if prompt.apply_to_all { prompt.pending.len() } else { 1 };
+        for conflict in prompt.pending.drain(..count).collect::<Vec<_>>() {
+            if self.resolve(conflict, choice) {
+                prompt.queued += 1;
+            } else {
+                prompt.skipped += 1;
+            }
+        }
+        if prompt.pending.is_empty() {
+            self.finish_conflicts(&prompt);
+        } else {
+            self.conflicts = Some(prompt);
+        }
+    }
+
+    /// Skip every remaining conflict
+    pub fn cancel_conflicts(&mut self) {
+        if let Some(mut prompt) = self.conflicts.take() {
+            prompt.skipped += prompt.pending.len();
+            prompt.pending.clear();
+            self.finish_conflicts(&prompt);
+        }
+    }
+
+    fn finish_conflicts(&mut self, prompt: &ConflictPrompt) {
+        let mut message = format!("Queued {} existing entries", prompt.queued);
+        if prompt.skipped > 0 {
+            message.push_str(&format!(", skipped {}", prompt.skipped));
+        }
+        self.set_status_message(&message);
+        self.start_transfers();
+    }
+
+    /// Queue the transfer of `conflict` as `choice` says, `false` when it
+    /// is skipped
+    fn resolve(&mut self, mut conflict: TransferConflict, choice: ConflictChoice) -> bool {
+        let newer = match (conflict.source.mtime, conflict.target.mtime) {
+            (Some(source), Some(target)) => source > target,
+            _ => false,
+        };
+        match choice {
+            ConflictChoice::Skip => return false,
+            ConflictChoice::OverwriteIfNewer if !newer => return false,
+            ConflictChoice::Overwrite | ConflictChoice::OverwriteIfNewer => {}
+            ConflictChoice::Rename => {
+                let Some(name) = self.free_name(&conflict) else {
+                    return false;
+                };
+                match conflict.direction {
+                    TransferDirection::Upload => {
+                        conflict.remote_path = join_remote(&self.remote_current_path, &name)
+                    }
+                    TransferDirection::Download => conflict.local_path.set_file_name(&name),
+                }
+                conflict.name = name;
+            }
+        }
+        self.queue.push(
+            conflict.direction,
+            &conflict.name,
+            conflict.local_path,
+            conflict.remote_path,
+            conflict.is_dir,
+            self.verify,
+        );
+        true
+    }
+
+    /// First `name (n)` that doesn't exist on the target side
+    fn free_name(&self, conflict: &TransferConflict) -> Option<String> {
+        (1..1000).map(|n| numbered_name(&conflict.name, n, conflict.is_dir)).find(|name| {
+            match conflict.direction {
+                TransferDirection::Upload => self
+                    .remote_stat(&join_remote(&self.remote_current_path, name))
+                    .is_none(),
+                TransferDirection::Download => {
+                    self.local_stat(&self.local_current_path.join(name)).is_none()
+                }
+            }
+        })
+    }
+
+    fn local_stat(&self, path: &std::path::Path) -> Option<EntryStat> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(EntryStat {
+            is_dir: metadata.is_dir(),
+            size: metadata.is_file().then_some(metadata.len()),
+            mtime: Some(metadata.mtime()),
+        })
+    }
+
+    fn remote_stat(&self, path: &str) -> Option<EntryStat> {
+        let attrs = self.session.get().stat(path).ok()?;
+        Some(EntryStat {
+            is_dir: attrs.is_dir(),
+            size: attrs.size.filter(|_| !attrs.is_dir()),
+            mtime: attrs.mtime.map(i64::from),
+        })
+    }
+}
+
+/// `report (2).txt` for `report.txt`, extensions are kept for files only
+fn numbered_name(name: &str, n: usize, is_dir: bool) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !is_dir && !stem.is_empty() => {
+            format!("{} ({}).{}", stem, n, extension)
+        }
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir, settle};
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn asks_before_replacing_existing_targets() {
+        let dir = scratch_dir("conflict");
+        let (local, remote) = (dir.join("local"), dir.join("remote"));
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        let day_ago = SystemTime::now() - Duration::from_secs(86_400);
+        for (path, text, modified) in [
+            (local.join("new.txt"), "new", None),
+            (local.join("newer.txt"), "local", None),
+            (remote.join("newer.txt"), "remote", Some(day_ago)),
+            (local.join("older.txt"), "local", Some(day_ago)),
+            (remote.join("older.txt"), "remote", None),
+        ] {
+            std::fs::write(&path, text).unwrap();
+            if let Some(time) = modified {
+                let file = std::fs::File::options().write(true).open(&path).unwrap();
+                file.set_modified(time).unwrap();
+            }
+        }
+
+        let (mut state, mut rx) = browser(&local, &remote);
+
+        state
+            .local_marked
+            .extend(["new.txt", "newer.txt", "older.txt"].map(String::from));
+        state.upload_selected();
+        let prompt = state.conflicts.as_ref().unwrap();
+        assert_eq!((prompt.position(), prompt.total), (1, 2));
+        assert_eq!(prompt.current().unwrap().name, "newer.txt");
+        assert_eq!(prompt.current().unwrap().target.size, Some(6));
+
+        // Only the older target is replaced
+        state.toggle_conflict_apply_to_all();
+        state.resolve_conflict(ConflictChoice::OverwriteIfNewer);
+        assert!(state.conflicts.is_none());
+        assert_eq!(
+            state.status_message.as_deref(),
+            Some("Queued 1 existing entries, skipped 1")
+        );
+
+        state.local_selected = state
+            .local_files
+            .iter()
+            .position(|item| item.name() == "older.txt")
+            .unwrap();
+        state.upload_selected();
+        state.resolve_conflict(ConflictChoice::Rename);
+
+        settle(&mut state, &mut rx).await;
+        let read = |name: &str| std::fs::read_to_string(remote.join(name)).unwrap();
+        assert_eq!(read("new.txt"), "new");
+        assert_eq!(read("newer.txt"), "local");
+        assert_eq!(read("older.txt"), "remote");
+        assert_eq!(read("older (1).txt"), "local");
+        assert_eq!(numbered_name(".bashrc", 2, false), ".bashrc (2)");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
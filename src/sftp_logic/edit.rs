@@ -0,0 +1,230 @@
+//! Edit a remote file in the local editor
+//!
+//! The file is downloaded to a private temporary directory and opened in the
+//! editor while the TUI is suspended. When the editor leaves it changed, it is
+//! written back over the remote file, unless the remote file changed in the
+//! meantime: then the upload waits for the user to confirm.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, DirBuilder, File};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::checksum::sha256_local;
+use super::session::{join_remote, open_flags, FileAttributes, SftpSession};
+use super::types::{AppSftpState, FileItem, PanelSide};
+
+/// Tells apart the temporary directories of edits in one run
+static EDIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A remote file downloaded for editing
+#[derive(Debug, Clone)]
+pub struct RemoteEdit {
+    pub remote_path: String,
+    /// The copy handed to the editor
+    pub local_path: PathBuf,
+    /// The remote file as downloaded, to notice changes made by others
+    remote_attrs: FileAttributes,
+    /// SHA-256 of the download, to notice changes made in the editor
+    hash: String,
+}
+
+impl RemoteEdit {
+    /// File name of the remote file
+    pub fn name(&self) -> &str {
+        self.remote_path.rsplit('/').next().unwrap_or(&self.remote_path)
+    }
+
+    /// Remove the local copy and its directory
+    pub fn discard(&self) {
+        if let Some(dir) = self.local_path.parent() {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl AppSftpState {
+    /// Download the file selected in the remote panel for editing. `None`
+    /// when nothing editable is selected, with the reason in the status.
+    pub fn start_remote_edit(&mut self) -> Result<Option<RemoteEdit>> {
+        let name = match (self.active_panel, self.remote_files.get(self.remote_selected)) {
+            (PanelSide::Remote, Some(FileItem::File { name, .. })) => name.clone(),
+            _ => {
+                self.set_status_message("Select a file in the remote panel to edit");
+                return Ok(None);
+            }
+        };
+        let remote_path = join_remote(&self.remote_current_path, &name);
+        let edit = download(&self.session.get(), &remote_path, &name)
+            .with_context(|| format!("Failed to download {}", remote_path))?;
+        Ok(Some(edit))
+    }
+
+    /// Upload the edited copy when the editor changed it. If the remote file
+    /// changed too, the edit is kept in `pending_edit` until confirmed.
+    pub fn finish_remote_edit(&mut self, edit: RemoteEdit) {
+        match sha256_local(&edit.local_path) {
+            Ok(hash) if hash == edit.hash => {
+                self.set_status_message(&format!("{} not modified", edit.name()));
+                edit.discard();
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.set_status_message(&format!("Failed to read the edited copy: {:#}", e));
+                edit.discard();
+                return;
+            }
+        }
+
+        let now = self.session.get().stat(&edit.remote_path);
+        let changed = match &now {
+            Ok(attrs) => {
+                attrs.mtime != edit.remote_attrs.mtime || attrs.size != edit.remote_attrs.size
+            }
+            // Deleted on the server while it was open
+            Err(_) => true,
+        };
+        if changed {
+            tracing::warn!("{} changed on the server while it was edited", edit.remote_path);
+            self.pending_edit = Some(edit);
+        } else {
+            self.upload_edit(edit);
+        }
+    }
+
+    /// Answer for an edit whose remote file changed while it was open:
+    /// upload it anyway or keep the copy where it is
+    pub fn confirm_edit_upload(&mut self, overwrite: bool) {
+        let Some(edit) = self.pending_edit.take() else {
+            return;
+        };
+        if overwrite {
+            self.upload_edit(edit);
+        } else {
+            self.set_status_message(&format!(
+                "Not uploaded, your changes are in {}",
+                edit.local_path.display()
+            ));
+        }
+    }
+
+    /// Write the edited copy over the remote file, keeping its mode and
+    /// owner
+    fn upload_edit(&mut self, edit: RemoteEdit) {
+        match upload(&self.session.get(), &edit) {
+            Ok(()) => {
+                edit.discard();
+                let message = match self.refresh_remote() {
+                    Ok(()) => format!("Uploaded changes to {}", edit.name()),
+                    Err(e) => format!("Remote refresh error: {}", e),
+                };
+                self.set_status_message(&message);
+            }
+            Err(e) => {
+                tracing::error!("Failed to upload {}: {:#}", edit.remote_path, e);
+                self.set_status_message(&format!(
+                    "Upload failed: {:#}. Your changes are in {}",
+                    e,
+                    edit.local_path.display()
+                ));
+            }
+        }
+    }
+}
+
+fn download(session: &SftpSession, remote_path: &str, name: &str) -> Result<RemoteEdit> {
+    let remote_attrs = session.stat(remote_path)?;
+    if !remote_attrs.is_file() {
+        bail!("{} is not a regular file", remote_path);
+    }
+
+    // Only the user can read what is being edited
+    let dir = std::env::temp_dir().join(format!(
+        "sshr-edit-{}-{}",
+        std::process::id(),
+        EDIT_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let local_path = dir.join(name);
+
+    let result = (|| {
+        let mut output = File::create(&local_path)
+            .with_context(|| format!("Failed to create {}", local_path.display()))?;
+        let handle = session.open(remote_path, open_flags::READ)?;
+        let read = session.read_to(&handle, 0, &mut output, |_| {});
+        session.close(handle)?;
+        read?;
+        sha256_local(&local_path)
+    })();
+    match result {
+        Ok(hash) => Ok(RemoteEdit {
+            remote_path: remote_path.to_string(),
+            local_path,
+            remote_attrs,
+            hash,
+        }),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            Err(e)
+        }
+    }
+}
+
+/// Truncate and rewrite the remote file in place, so it keeps its mode,
+/// owner and hard links
+fn upload(session: &SftpSession, edit: &RemoteEdit) -> Result<()> {
+    let mut input = File::open(&edit.local_path)
+        .with_context(|| format!("Failed to open {}", edit.local_path.display()))?;
+    let flags = open_flags::WRITE | open_flags::CREATE | open_flags::TRUNCATE;
+    let handle = session.open(&edit.remote_path, flags)?;
+    let written = session.write_from(&handle, 0, &mut input, |_| {});
+    session.close(handle)?;
+    written?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir};
+    use super::*;
+
+    #[test]
+    fn uploads_edits_and_waits_when_the_remote_file_changed() {
+        let dir = scratch_dir("edit");
+        std::fs::write(dir.join("app.conf"), "port = 80\n").unwrap();
+        let (mut state, _rx) = browser(&dir, &dir);
+        state.active_panel = PanelSide::Remote;
+        state.remote_selected = 1;
+
+        // Unchanged copies are dropped
+        let edit = state.start_remote_edit().unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(&edit.local_path).unwrap(), "port = 80\n");
+        let copy = edit.local_path.clone();
+        state.finish_remote_edit(edit);
+        assert!(!copy.exists());
+        assert_eq!(state.status_message.as_deref(), Some("app.conf not modified"));
+
+        let edit = state.start_remote_edit().unwrap().unwrap();
+        std::fs::write(&edit.local_path, "port = 8080\n").unwrap();
+        state.finish_remote_edit(edit);
+        assert_eq!(std::fs::read_to_string(dir.join("app.conf")).unwrap(), "port = 8080\n");
+
+        // Someone else changed the file while it was open
+        let edit = state.start_remote_edit().unwrap().unwrap();
+        std::fs::write(&edit.local_path, "port = 443\n").unwrap();
+        std::fs::write(dir.join("app.conf"), "port = 10443\n").unwrap();
+        state.finish_remote_edit(edit);
+        assert!(state.pending_edit.is_some());
+        assert_eq!(std::fs::read_to_string(dir.join("app.conf")).unwrap(), "port = 10443\n");
+        state.confirm_edit_upload(true);
+        assert!(state.pending_edit.is_none());
+        assert_eq!(std::fs::read_to_string(dir.join("app.conf")).unwrap(), "port = 443\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+//! Rename, delete, create and change the mode or owner of entries in the
+//! local and remote panels
+//!
+//! Each operation opens a `FilePrompt` over the panels, asking for a name, a
+//! mode or an owner, or for confirmation before deleting. Operations on
+//! several marked entries go on past the ones that fail.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use super::session::{join_remote, open_flags, FileAttributes, SftpSession};
+use super::types::{AppSftpState, FileItem, PanelSide};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOperation {
+    Rename,
+    MakeDir,
+    NewFile,
+    Chmod,
+    Chown,
+    Delete,
+    /// Change the panel's directory to a typed path
+    GoTo,
+    /// Set the rate limit of the selected transfer
+    Limit,
+}
+
+impl FileOperation {
+    pub fn title(self) -> &'static str {
+        match self {
+            FileOperation::Rename => "Rename",
+            FileOperation::MakeDir => "New Directory",
+            FileOperation::NewFile => "New File",
+            FileOperation::Chmod => "Change Mode",
+            FileOperation::Chown => "Change Owner",
+            FileOperation::Delete => "Delete",
+            FileOperation::GoTo => "Go To",
+            FileOperation::Limit => "Rate Limit",
+        }
+    }
+
+    /// What the input line asks for, `None` for operations that only ask to
+    /// confirm
+    pub fn input_label(self) -> Option<&'static str> {
+        match self {
+            FileOperation::Rename => Some("New name"),
+            FileOperation::MakeDir => Some("Directory name"),
+            FileOperation::NewFile => Some("File name"),
+            FileOperation::Chmod => Some("Octal mode, e.g. 644"),
+            FileOperation::Chown => Some("Owner as user, user:group or :group"),
+            FileOperation::Delete => None,
+            FileOperation::GoTo => Some("Path, absolute, relative or starting with ~"),
+            FileOperation::Limit => Some("KiB per second, empty for no limit"),
+        }
+    }
+}
+
+/// A file operation waiting for input or confirmation
+#[derive(Debug, Clone)]
+pub struct FilePrompt {
+    pub operation: FileOperation,
+    /// Local or remote, the panel the operation applies to
+    pub side: PanelSide,
+    /// Names in the panel's directory the operation applies to, empty when
+    /// creating an entry
+    pub targets: Vec<String>,
+    pub input: String,
+}
+
+impl AppSftpState {
+    /// Open the prompt for `operation` on the active panel
+    pub fn start_file_operation(&mut self, operation: FileOperation) {
+        let (files, selected, marked) = match self.active_panel {
+            PanelSide::Local => (&self.local_files, self.local_selected, &self.local_marked),
+            PanelSide::Remote => (&self.remote_files, self.remote_selected, &self.remote_marked),
+            PanelSide::Queue => {
+                self.set_status_message("Switch to the local or remote panel first");
+                return;
+            }
+        };
+
+        let targets: Vec<String> = match operation {
+            FileOperation::MakeDir | FileOperation::NewFile | FileOperation::GoTo => Vec::new(),
+            // Only ever the selected entry, marks are for the other operations
+            FileOperation::Rename => files
+                .get(selected)
+                .map(FileItem::name)
+                .filter(|name| *name != "..")
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+            _ => Self::entries_to_transfer(files, selected, marked)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+        };
+        let creates = matches!(
+            operation,
+            FileOperation::MakeDir | FileOperation::NewFile | FileOperation::GoTo
+        );
+        if targets.is_empty() && !creates {
+            self.set_status_message("Please select a file or directory");
+            return;
+        }
+
+        let input = match (operation, targets.as_slice()) {
+            (FileOperation::Rename, [name]) => name.clone(),
+            (FileOperation::Chmod | FileOperation::Chown, [name]) => self
+                .current_mode_or_owner(operation, name)
+                .unwrap_or_default(),
+            (FileOperation::GoTo, _) => match self.active_panel {
+                PanelSide::Remote => self.remote_current_path.clone(),
+                _ => self.local_current_path.to_string_lossy().into_owned(),
+            },
+            _ => String::new(),
+        };
+        self.prompt = Some(FilePrompt {
+            operation,
+            side: self.active_panel,
+            targets,
+            input,
+        });
+    }
+
+    /// Mode as octal or owner as `uid:gid` of an entry, to start editing from
+    fn current_mode_or_owner(&self, operation: FileOperation, name: &str) -> Result<String> {
+        let (mode, uid, gid) = match self.active_panel {
+            PanelSide::Remote => {
+                let attrs = self
+                    .session
+                    .get()
+                    .stat(&join_remote(&self.remote_current_path, name))?;
+                (attrs.permissions, attrs.uid, attrs.gid)
+            }
+            _ => {
+                let metadata = fs::metadata(self.local_current_path.join(name))?;
+                (Some(metadata.mode()), Some(metadata.uid()), Some(metadata.gid()))
+            }
+        };
+        let value = match (operation, mode, uid, gid) {
+            (FileOperation::Chmod, Some(mode), _, _) => format!("{:o}", mode & 0o7777),
+            (FileOperation::Chown, _, Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+            _ => String::new(),
+        };
+        Ok(value)
+    }
+
+    /// Run the operation of the open prompt and show how it went in the
+    /// status overlay
+    pub fn submit_file_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        match prompt.operation {
+            FileOperation::GoTo => {
+                if let Err(e) = self.go_to(prompt.side, prompt.input.trim()) {
+                    self.set_status_message(&format!("Go to failed: {:#}", e));
+                }
+                return;
+            }
+            FileOperation::Limit => {
+                self.limit_selected_transfer(prompt.input.trim());
+                return;
+            }
+            _ => {}
+        }
+        let result = match prompt.side {
+            PanelSide::Remote => self.run_remote_operation(&prompt),
+            _ => self.run_local_operation(&prompt),
+        };
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => format!("{} failed: {:#}", prompt.operation.title(), e),
+        };
+
+        let refreshed = match prompt.side {
+            PanelSide::Remote => {
+                self.remote_marked.clear();
+                self.refresh_remote()
+            }
+            _ => {
+                self.local_marked.clear();
+                self.refresh_local()
+            }
+        };
+        match refreshed {
+            Ok(()) => self.set_status_message(&message),
+            Err(e) => self.set_status_message(&format!("{}, refresh failed: {}", message, e)),
+        }
+    }
+
+    fn run_local_operation(&self, prompt: &FilePrompt) -> Result<String> {
+        let dir = &self.local_current_path;
+        let input = prompt.input.trim();
+        match prompt.operation {
+            FileOperation::Rename => {
+                let (from, to) = (&prompt.targets[0], entry_name(input)?);
+                if fs::symlink_metadata(dir.join(to)).is_ok() {
+                    bail!("{} already exists", to);
+                }
+                fs::rename(dir.join(from), dir.join(to))?;
+                Ok(format!("Renamed {} to {}", from, to))
+            }
+            FileOperation::MakeDir => {
+                let name = entry_name(input)?;
+                fs::create_dir(dir.join(name))?;
+                Ok(format!("Created directory {}", name))
+            }
+            FileOperation::NewFile => {
+                let name = entry_name(input)?;
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(dir.join(name))?;
+                Ok(format!("Created file {}", name))
+            }
+            FileOperation::Chmod => {
+                let mode = parse_mode(input)?;
+                for_each_target("Changed mode of", &prompt.targets, |name| {
+                    Ok(fs::set_permissions(dir.join(name), fs::Permissions::from_mode(mode))?)
+                })
+            }
+            FileOperation::Chown => {
+                let (uid, gid) = parse_owner(input, |file| Ok(fs::read_to_string(file)?))?;
+                for_each_target("Changed owner of", &prompt.targets, |name| {
+                    Ok(std::os::unix::fs::chown(dir.join(name), uid, gid)?)
+                })
+            }
+            FileOperation::Delete => for_each_target("Deleted", &prompt.targets, |name| {
+                remove_local(&dir.join(name))
+            }),
+            FileOperation::GoTo | FileOperation::Limit => {
+                unreachable!("going to a path or limiting a transfer changes no files")
+            }
+        }
+    }
+
+    fn run_remote_operation(&self, prompt: &FilePrompt) -> Result<String> {
+        let session = self.session.get();
+        let path = |name: &str| join_remote(&self.remote_current_path, name);
+        let input = prompt.input.trim();
+        match prompt.operation {
+            FileOperation::Rename => {
+                let (from, to) = (&prompt.targets[0], entry_name(input)?);
+                session.rename(&path(from), &path(to))?;
+                Ok(format!("Renamed {} to {}", from, to))
+            }
+            FileOperation::MakeDir => {
+                let name = entry_name(input)?;
+                session.mkdir(&path(name))?;
+                Ok(format!("Created directory {}", name))
+            }
+            FileOperation::NewFile => {
+                let name = entry_name(input)?;
+                let flags = open_flags::WRITE | open_flags::CREATE | open_flags::EXCL;
+                session.close(session.open(&path(name), flags)?)?;
+                Ok(format!("Created file {}", name))
+            }
+            FileOperation::Chmod => {
+                let mode = parse_mode(input)?;
+                let attrs = FileAttributes {
+                    permissions: Some(mode),
+                    ..FileAttributes::default()
+                };
+                for_each_target("Changed mode of", &prompt.targets, |name| {
+                    session.setstat(&path(name), &attrs)
+                })
+            }
+            FileOperation::Chown => {
+                let (uid, gid) = parse_owner(input, |file| read_remote_text(&session, file))?;
+                for_each_target("Changed owner of", &prompt.targets, |name| {
+                    // SFTP sets the user and group together
+                    let current = session.stat(&path(name))?;
+                    let attrs = FileAttributes {
+                        uid: uid.or(current.uid),
+                        gid: gid.or(current.gid),
+                        ..FileAttributes::default()
+                    };
+                    session.setstat(&path(name), &attrs)
+                })
+            }
+            FileOperation::Delete => for_each_target("Deleted", &prompt.targets, |name| {
+                let attrs = session.lstat(&path(name))?;
+                remove_remote(&session, &path(name), &attrs)
+            }),
+            FileOperation::GoTo | FileOperation::Limit => {
+                unreachable!("going to a path or limiting a transfer changes no files")
+            }
+        }
+    }
+}
+
+/// Apply `operation` to every target, going on past failures. The message
+/// names the target when there is one.
+fn for_each_target<F>(done: &str, targets: &[String], mut operation: F) -> Result<String>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    let failures: Vec<String> = targets
+        .iter()
+        .filter_map(|name| {
+            operation(name)
+                .err()
+                .map(|e| format!("{}: {:#}", name, e))
+        })
+        .collect();
+    match (failures.first(), targets) {
+        (None, [name]) => Ok(format!("{} {}", done, name)),
+        (None, _) => Ok(format!("{} {} entries", done, targets.len())),
+        (Some(failure), [_]) => Err(anyhow!("{}", failure)),
+        (Some(failure), _) => bail!(
+            "{} of {} entries, first {}",
+            failures.len(),
+            targets.len(),
+            failure
+        ),
+    }
+}
+
+/// A new name in the current directory
+fn entry_name(input: &str) -> Result<&str> {
+    match input {
+        "" => bail!("The name is empty"),
+        "." | ".." => bail!("'{}' can't be used as a name", input),
+        name if name.contains('/') => bail!("Names can't contain '/'"),
+        name => Ok(name),
+    }
+}
+
+/// Octal permission bits such as `644` or `0755`
+fn parse_mode(input: &str) -> Result<u32> {
+    match u32::from_str_radix(input, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => bail!("'{}' is not an octal mode such as 644", input),
+    }
+}
+
+/// User and group ids of `user`, `user:group` or `:group`, each a name or a
+/// number. Names are looked up in /etc/passwd and /etc/group as read by
+/// `read`.
+fn parse_owner<R>(input: &str, read: R) -> Result<(Option<u32>, Option<u32>)>
+where
+    R: Fn(&str) -> Result<String>,
+{
+    let (user, group) = match input.split_once(':') {
+        Some((user, group)) => (user, group),
+        None => (input, ""),
+    };
+    let lookup = |name: &str, file: &str| -> Result<Option<u32>> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(id) = name.parse() {
+            return Ok(Some(id));
+        }
+        let entries = read(file).with_context(|| format!("Failed to read {}", file))?;
+        entries
+            .lines()
+            .map(|line| line.split(':').collect::<Vec<_>>())
+            .find(|fields| fields.first() == Some(&name))
+            .and_then(|fields| fields.get(2)?.parse().ok())
+            .map(Some)
+            .with_context(|| format!("No '{}' in {}", name, file))
+    };
+    let ids = (lookup(user, "/etc/passwd")?, lookup(group, "/etc/group")?);
+    if ids == (None, None) {
+        bail!("No user or group given");
+    }
+    Ok(ids)
+}
+
+/// Delete a file, link or whole directory without following links
+pub(super) fn remove_local(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Delete a remote entry with the attributes `attrs`, directories with
+/// everything in them
+pub(super) fn remove_remote(session: &SftpSession, path: &str, attrs: &FileAttributes) -> Result<()> {
+    if !attrs.is_dir() {
+        return session.remove(path);
+    }
+    for entry in session.read_dir(path)? {
+        remove_remote(session, &join_remote(path, &entry.name), &entry.attrs)?;
+    }
+    session.rmdir(path)
+}
+
+fn read_remote_text(session: &SftpSession, path: &str) -> Result<String> {
+    let handle = session.open(path, open_flags::READ)?;
+    let mut data = Vec::new();
+    let read = session.read_to(&handle, 0, &mut data, |_| {});
+    session.close(handle)?;
+    read?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir};
+    use super::*;
+
+    fn run(state: &mut AppSftpState, side: PanelSide, operation: FileOperation, input: &str) {
+        state.active_panel = side;
+        state.start_file_operation(operation);
+        state.prompt.as_mut().unwrap().input = input.to_string();
+        state.submit_file_prompt();
+    }
+
+    fn select(state: &mut AppSftpState, side: PanelSide, name: &str) {
+        let files = match side {
+            PanelSide::Remote => &state.remote_files,
+            _ => &state.local_files,
+        };
+        let index = files.iter().position(|item| item.name() == name).unwrap();
+        match side {
+            PanelSide::Remote => state.remote_selected = index,
+            _ => state.local_selected = index,
+        }
+    }
+
+    #[test]
+    fn operates_on_both_panels() {
+        let dir = scratch_dir("file-ops");
+        for side in ["local", "remote"] {
+            fs::create_dir_all(dir.join(side).join("tree/deeper")).unwrap();
+            fs::write(dir.join(side).join("tree/deeper/file"), "x").unwrap();
+        }
+        let (mut state, _rx) = browser(&dir.join("local"), &dir.join("remote"));
+
+        for (side, root) in [(PanelSide::Local, "local"), (PanelSide::Remote, "remote")] {
+            let root = dir.join(root);
+            run(&mut state, side, FileOperation::MakeDir, "made");
+            assert!(root.join("made").is_dir());
+            run(&mut state, side, FileOperation::NewFile, "empty");
+            assert_eq!(fs::read(root.join("empty")).unwrap(), b"");
+            run(&mut state, side, FileOperation::NewFile, "empty");
+            assert!(state.status_message.as_ref().unwrap().starts_with("New File failed"));
+
+            select(&mut state, side, "empty");
+            run(&mut state, side, FileOperation::Rename, "renamed");
+            assert!(root.join("renamed").exists() && !root.join("empty").exists());
+            select(&mut state, side, "renamed");
+            run(&mut state, side, FileOperation::Rename, "made");
+            assert!(state.status_message.as_ref().unwrap().starts_with("Rename failed"));
+
+            select(&mut state, side, "renamed");
+            run(&mut state, side, FileOperation::Chmod, "600");
+            let mode = fs::metadata(root.join("renamed")).unwrap().mode();
+            assert_eq!(mode & 0o7777, 0o600);
+            run(&mut state, side, FileOperation::Chmod, "abc");
+            assert!(state.status_message.as_ref().unwrap().contains("not an octal mode"));
+
+            // Changing to the current group is allowed without privileges
+            let gid = fs::metadata(root.join("renamed")).unwrap().gid();
+            run(&mut state, side, FileOperation::Chown, &format!(":{}", gid));
+            assert_eq!(
+                state.status_message.as_deref(),
+                Some("Changed owner of renamed")
+            );
+
+            // Marked entries, a directory with everything in it
+            match side {
+                PanelSide::Remote => &mut state.remote_marked,
+                _ => &mut state.local_marked,
+            }
+            .extend(["tree".to_string(), "renamed".to_string()]);
+            run(&mut state, side, FileOperation::Delete, "");
+            assert_eq!(state.status_message.as_deref(), Some("Deleted 2 entries"));
+            assert!(!root.join("tree").exists() && !root.join("renamed").exists());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parses_modes_and_owners() {
+        assert_eq!(parse_mode("0755").unwrap(), 0o755);
+        assert!(parse_mode("10000").is_err());
+        let files = |file: &str| {
+            Ok(match file {
+                "/etc/passwd" => "root:x:0:0::/root:/bin/sh\nme:x:1000:1000::/home/me:/bin/sh\n",
+                _ => "wheel:x:10:\nstaff:x:50:me\n",
+            }
+            .to_string())
+        };
+        assert_eq!(parse_owner("me", files).unwrap(), (Some(1000), None));
+        assert_eq!(parse_owner("me:staff", files).unwrap(), (Some(1000), Some(50)));
+        assert_eq!(parse_owner(":10", files).unwrap(), (None, Some(10)));
+        assert!(parse_owner("nobody", files).is_err());
+        assert!(parse_owner(":", files).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! History of finished transfers, kept across sessions
+//!
+//! Every transfer that completes or fails is appended to a JSON-lines file
+//! in the config directory. SFTP mode lists the ones of its host to run them
+//! again, and `sshr history` prints them.
+
+use anyhow::{Context, Result};
+use ratatui::widgets::ListState;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::queue::{QueuedTransfer, TransferDirection, TransferId};
+use super::types::AppSftpState;
+
+/// One finished transfer, a line of the history file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    /// When it ended, RFC 3339
+    pub time: String,
+    /// Alias of the host
+    pub host: String,
+    pub direction: TransferDirection,
+    pub local_path: PathBuf,
+    pub remote_path: String,
+    #[serde(default)]
+    pub is_dir: bool,
+    /// Bytes copied, for directories of all their files
+    pub bytes: u64,
+    /// Time spent running, without pauses
+    pub seconds: f64,
+    /// SHA-256 both sides share, for verified files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TransferRecord {
+    /// Record of `item` ending now, with the error it failed with
+    pub fn of(
+        host: &str,
+        item: &QueuedTransfer,
+        sha256: Option<String>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            time: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            host: host.to_string(),
+            direction: item.direction,
+            local_path: item.local_path.clone(),
+            remote_path: item.remote_path.clone(),
+            is_dir: item.is_dir,
+            bytes: item.stats.bytes_done(),
+            seconds: item
+                .stats
+                .elapsed(Instant::now())
+                .unwrap_or_default()
+                .as_secs_f64(),
+            sha256,
+            error,
+        }
+    }
+
+    /// Name of the transferred file or directory
+    pub fn name(&self) -> String {
+        match self.direction {
+            TransferDirection::Upload => self
+                .local_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            TransferDirection::Download => self
+                .remote_path
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// Add `record` at the end of the history file
+pub fn append(path: &Path, record: &TransferRecord) -> Result<()> {
+    let mut line = serde_json::to_string(record).context("Failed to serialize transfer")?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Records of the history file, oldest first. Lines that don't parse are
+/// left out.
+pub fn load(path: &Path) -> Result<Vec<TransferRecord>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::warn!("Skipping a transfer history line: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Past transfers of the host, newest first, to run one again
+#[derive(Debug, Clone)]
+pub struct HistoryView {
+    pub records: Vec<TransferRecord>,
+    pub selected: usize,
+    pub list_state: ListState,
+}
+
+impl HistoryView {
+    pub fn navigate_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn navigate_down(&mut self) {
+        if self.selected + 1 < self.records.len() {
+            self.selected += 1;
+        }
+    }
+}
+
+impl AppSftpState {
+    /// Append the end of transfer `id` to the history file, when there is
+    /// one
+    pub(super) fn record_transfer(
+        &self,
+        id: TransferId,
+        sha256: Option<String>,
+        error: Option<String>,
+    ) {
+        let Some(path) = &self.history_file else {
+            return;
+        };
+        let Some(item) = self.queue.items.iter().find(|item| item.id == id) else {
+            return;
+        };
+        let record = TransferRecord::of(&self.host.alias, item, sha256, error);
+        if let Err(e) = append(path, &record) {
+            tracing::warn!("Failed to record the transfer of {}: {:#}", item.name, e);
+        }
+    }
+
+    /// Open the past transfers of this host
+    pub fn open_history(&mut self) {
+        let Some(path) = self.history_file.clone() else {
+            return;
+        };
+        let records = match load(&path) {
+            Ok(records) => records,
+            Err(e) => {
+                self.set_status_message(&format!("Failed to load the history: {:#}", e));
+                return;
+            }
+        };
+        let records: Vec<TransferRecord> = records
+            .into_iter()
+            .rev()
+            .filter(|record| record.host == self.host.alias)
+            .collect();
+        if records.is_empty() {
+            self.set_status_message("No transfers recorded for this host yet");
+            return;
+        }
+        self.history = Some(HistoryView {
+            records,
+            selected: 0,
+            list_state: ListState::default(),
+        });
+    }
+
+    /// Queue the transfer selected in the history again. It replaces its
+    /// target without asking, like retrying does.
+    pub fn rerun_selected_history(&mut self) {
+        let Some(view) = self.history.take() else {
+            return;
+        };
+        let Some(record) = view.records.get(view.selected) else {
+            return;
+        };
+        let name = record.name();
+        self.queue.push(
+            record.direction,
+            &name,
+            record.local_path.clone(),
+            record.remote_path.clone(),
+            record.is_dir,
+            self.verify,
+        );
+        self.set_status_message(&format!("Queued {} again", name));
+        self.start_transfers();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir, settle};
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn records_finished_transfers_and_runs_them_again() {
+        let dir = scratch_dir("history");
+        let (local, remote) = (dir.join("local"), dir.join("remote"));
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        std::fs::write(local.join("a.txt"), "alpha").unwrap();
+
+        let (mut state, mut rx) = browser(&local, &remote);
+        state.history_file = Some(dir.join("history.jsonl"));
+        state.verify = true;
+
+        state.local_marked.insert("a.txt".to_string());
+        state.upload_selected();
+        settle(&mut state, &mut rx).await;
+
+        // A line that doesn't parse doesn't hide the others
+        let path = dir.join("history.jsonl");
+        std::fs::write(&path, std::fs::read_to_string(&path).unwrap() + "not json\n").unwrap();
+        let records = load(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].direction, TransferDirection::Upload);
+        assert_eq!((records[0].bytes, records[0].error.as_deref()), (5, None));
+        assert_eq!(records[0].sha256.as_ref().map(String::len), Some(64));
+
+        std::fs::remove_file(remote.join("a.txt")).unwrap();
+        state.open_history();
+        assert_eq!(state.history.as_ref().unwrap().records[0].name(), "a.txt");
+        state.rerun_selected_history();
+        settle(&mut state, &mut rx).await;
+        assert_eq!(std::fs::read_to_string(remote.join("a.txt")).unwrap(), "alpha");
+        assert_eq!(load(&path).unwrap().len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+//! Which entries the SFTP panels show, in what order, and moving around
+//! them
+
+use std::cmp::Ordering;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::widgets::ListState;
+
+use super::types::{AppSftpState, FileItem, PanelSide};
+use crate::config::{SftpConfig, SortKey};
+
+/// How the panels are sorted. `..` always comes first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+    pub directories_first: bool,
+}
+
+impl SortOrder {
+    pub fn from_config(config: &SftpConfig) -> Self {
+        Self {
+            key: config.sort,
+            descending: config.sort_descending,
+            directories_first: config.directories_first,
+        }
+    }
+
+    /// e.g. "name ↑, directories first"
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {}{}",
+            self.key.label(),
+            if self.descending { "↓" } else { "↑" },
+            if self.directories_first { ", directories first" } else { "" }
+        )
+    }
+
+    pub fn sort(&self, items: &mut [FileItem]) {
+        items.sort_by(|a, b| self.compare(a, b));
+    }
+
+    fn compare(&self, a: &FileItem, b: &FileItem) -> Ordering {
+        let is_parent = |item: &FileItem| item.name() == "..";
+        let is_dir = |item: &FileItem| matches!(item, FileItem::Directory { .. });
+        let first = is_parent(b).cmp(&is_parent(a));
+        if first != Ordering::Equal {
+            return first;
+        }
+        if self.directories_first {
+            let first = is_dir(b).cmp(&is_dir(a));
+            if first != Ordering::Equal {
+                return first;
+            }
+        }
+
+        let by_name = a.name().cmp(b.name());
+        let order = match self.key {
+            SortKey::Name => by_name,
+            SortKey::Size => size(a).cmp(&size(b)).then(by_name),
+            SortKey::Modified => a.meta().mtime.cmp(&b.meta().mtime).then(by_name),
+            SortKey::Extension => extension(a).cmp(&extension(b)).then(by_name),
+        };
+        if self.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+}
+
+/// Directories count as empty
+fn size(item: &FileItem) -> u64 {
+    match item {
+        FileItem::File { size, .. } => *size,
+        FileItem::Directory { .. } => 0,
+    }
+}
+
+/// Lowercase extension, empty for directories, dotfiles and names without one
+fn extension(item: &FileItem) -> String {
+    match item {
+        FileItem::File { name, .. } => match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => extension.to_lowercase(),
+            _ => String::new(),
+        },
+        FileItem::Directory { .. } => String::new(),
+    }
+}
+
+impl AppSftpState {
+    /// Sort by the next key: name, size, modified, extension
+    pub fn cycle_sort_key(&mut self) {
+        self.sort.key = self.sort.key.next();
+        self.apply_sort();
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.sort.descending = !self.sort.descending;
+        self.apply_sort();
+    }
+
+    pub fn toggle_directories_first(&mut self) {
+        self.sort.directories_first = !self.sort.directories_first;
+        self.apply_sort();
+    }
+
+    /// Show or hide the metadata columns
+    pub fn toggle_details(&mut self) {
+        self.details = !self.details;
+    }
+
+    /// Sort both panels again, keeping the selected entries selected
+    fn apply_sort(&mut self) {
+        self.sort.sort(&mut self.local_all);
+        self.sort.sort(&mut self.remote_all);
+        self.update_local_view(true);
+        self.update_remote_view(true);
+        self.set_status_message(&format!("Sorted by {}", self.sort.describe()));
+    }
+
+    /// Show or hide dotfiles in both panels
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.update_local_view(true);
+        self.update_remote_view(true);
+        self.set_status_message(if self.show_hidden {
+            "Showing hidden files"
+        } else {
+            "Hiding hidden files"
+        });
+    }
+
+    /// Rebuild `local_files` from `local_all`. With `keep_selection` the
+    /// selected entry stays selected while it is shown, otherwise only the
+    /// position is kept.
+    pub(super) fn update_local_view(&mut self, keep_selection: bool) {
+        let selected = keep_selection
+            .then(|| self.local_files.get(self.local_selected))
+            .flatten()
+            .map(|item| item.name().to_string());
+        self.local_files = visible(&self.local_all, self.show_hidden, &self.local_filter);
+        reselect(
+            &self.local_files,
+            &mut self.local_selected,
+            &mut self.local_list_state,
+            selected,
+        );
+    }
+
+    /// Rebuild `remote_files` from `remote_all`, like `update_local_view`
+    pub(super) fn update_remote_view(&mut self, keep_selection: bool) {
+        let selected = keep_selection
+            .then(|| self.remote_files.get(self.remote_selected))
+            .flatten()
+            .map(|item| item.name().to_string());
+        self.remote_files = visible(&self.remote_all, self.show_hidden, &self.remote_filter);
+        reselect(
+            &self.remote_files,
+            &mut self.remote_selected,
+            &mut self.remote_list_state,
+            selected,
+        );
+    }
+
+    /// Start typing a filter for the active file panel
+    pub fn start_filter(&mut self) {
+        if self.active_panel != PanelSide::Queue {
+            self.filtering = true;
+        }
+    }
+
+    /// Filter of the active file panel, `None` for the queue
+    pub fn active_filter(&self) -> Option<&str> {
+        match self.active_panel {
+            PanelSide::Local => Some(&self.local_filter),
+            PanelSide::Remote => Some(&self.remote_filter),
+            PanelSide::Queue => None,
+        }
+    }
+
+    /// Change the filter of the active file panel and show what matches it
+    pub fn set_filter(&mut self, filter: String) {
+        match self.active_panel {
+            PanelSide::Local => {
+                self.local_filter = filter;
+                self.update_local_view(true);
+            }
+            PanelSide::Remote => {
+                self.remote_filter = filter;
+                self.update_remote_view(true);
+            }
+            PanelSide::Queue => {}
+        }
+    }
+
+    /// Move the selection of the active file panel a page up
+    pub fn page_up(&mut self) {
+        let page = self.page_size.max(1);
+        self.move_cursor(|selected, _| selected.saturating_sub(page));
+    }
+
+    /// Move the selection of the active file panel a page down
+    pub fn page_down(&mut self) {
+        let page = self.page_size.max(1);
+        self.move_cursor(|selected, len| (selected + page).min(len - 1));
+    }
+
+    pub fn select_first(&mut self) {
+        self.move_cursor(|_, _| 0);
+    }
+
+    pub fn select_last(&mut self) {
+        self.move_cursor(|_, len| len - 1);
+    }
+
+    /// Take the next key as the letter to jump to
+    pub fn start_jump(&mut self) {
+        if self.active_panel != PanelSide::Queue {
+            self.jumping = true;
+            self.set_status_message("Jump to the next name starting with...");
+        }
+    }
+
+    /// Select the next entry after the selected one whose name starts with
+    /// `letter`, ignoring case
+    pub fn jump_to(&mut self, letter: char) {
+        let letter = letter.to_lowercase().to_string();
+        let starts = |item: &FileItem| item.name().to_lowercase().starts_with(&letter);
+        let files = match self.active_panel {
+            PanelSide::Local => &self.local_files,
+            PanelSide::Remote => &self.remote_files,
+            PanelSide::Queue => return,
+        };
+        let found = (1..=files.len())
+            .map(|offset| (self.selected_in_active() + offset) % files.len())
+            .find(|&i| starts(&files[i]));
+        if let Some(found) = found {
+            self.move_cursor(|_, _| found);
+        }
+    }
+
+    fn selected_in_active(&self) -> usize {
+        match self.active_panel {
+            PanelSide::Local => self.local_selected,
+            PanelSide::Remote => self.remote_selected,
+            PanelSide::Queue => 0,
+        }
+    }
+
+    /// Select `to(selected, len)` in the active file panel when it has any
+    /// entries
+    pub(super) fn move_cursor<F: FnOnce(usize, usize) -> usize>(&mut self, to: F) {
+        let (files, selected, list_state) = match self.active_panel {
+            PanelSide::Local => {
+                (&self.local_files, &mut self.local_selected, &mut self.local_list_state)
+            }
+            PanelSide::Remote => {
+                (&self.remote_files, &mut self.remote_selected, &mut self.remote_list_state)
+            }
+            PanelSide::Queue => return,
+        };
+        if !files.is_empty() {
+            *selected = to(*selected, files.len());
+            list_state.select(Some(*selected));
+        }
+    }
+}
+
+/// Entries of `all` to show: `..` always, dotfiles only with `show_hidden`,
+/// and names that fuzzily match `filter` when there is one, in the order of
+/// `all`
+fn visible(all: &[FileItem], show_hidden: bool, filter: &str) -> Vec<FileItem> {
+    let matcher = SkimMatcherV2::default();
+    all.iter()
+        .filter(|item| {
+            let name = item.name();
+            name == ".."
+                || ((show_hidden || !name.starts_with('.'))
+                    && (filter.is_empty() || matcher.fuzzy_match(name, filter).is_some()))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Select the entry named `name` when there is one, otherwise keep the
+/// selected position within `files`
+fn reselect(
+    files: &[FileItem],
+    selected: &mut usize,
+    list_state: &mut ListState,
+    name: Option<String>,
+) {
+    if let Some(found) = name.and_then(|name| files.iter().position(|item| item.name() == name)) {
+        *selected = found;
+    } else if *selected >= files.len() {
+        *selected = files.len().saturating_sub(1);
+    }
+    list_state.select(Some(*selected));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir};
+    use super::super::types::FileMeta;
+    use super::*;
+
+    fn file(name: &str, size: u64, mtime: i64) -> FileItem {
+        FileItem::File {
+            name: name.to_string(),
+            size,
+            meta: FileMeta {
+                mtime: Some(mtime),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn dir(name: &str) -> FileItem {
+        FileItem::Directory {
+            name: name.to_string(),
+            meta: FileMeta::default(),
+        }
+    }
+
+    fn sorted(key: SortKey, descending: bool, directories_first: bool) -> Vec<String> {
+        let mut items = vec![
+            file("b.txt", 30, 1),
+            dir("src"),
+            file("a.rs", 10, 3),
+            FileItem::parent(),
+            file(".profile", 20, 2),
+            file("c.RS", 10, 4),
+        ];
+        let order = SortOrder { key, descending, directories_first };
+        order.sort(&mut items);
+        items.iter().map(|item| item.name().to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_by_each_key_keeping_parent_first() {
+        assert_eq!(
+            sorted(SortKey::Name, false, true),
+            ["..", "src", ".profile", "a.rs", "b.txt", "c.RS"]
+        );
+        assert_eq!(
+            sorted(SortKey::Name, true, true),
+            ["..", "src", "c.RS", "b.txt", "a.rs", ".profile"]
+        );
+        assert_eq!(
+            sorted(SortKey::Size, false, false),
+            ["..", "src", "a.rs", "c.RS", ".profile", "b.txt"]
+        );
+        assert_eq!(
+            sorted(SortKey::Modified, true, false),
+            ["..", "c.RS", "a.rs", ".profile", "b.txt", "src"]
+        );
+        assert_eq!(
+            sorted(SortKey::Extension, false, true),
+            ["..", "src", ".profile", "a.rs", "c.RS", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn filters_hides_and_moves_around_the_local_panel() {
+        let dir = scratch_dir("listing");
+        for name in [".env", "access.log", "app.rs", "error.log", "main.rs"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let (mut state, _rx) = browser(&dir, &dir);
+        let names = |state: &AppSftpState| -> Vec<String> {
+            state.local_files.iter().map(|item| item.name().to_string()).collect()
+        };
+        let selected = |state: &AppSftpState| state.local_files[state.local_selected].name().to_string();
+        assert_eq!(names(&state), ["..", ".env", "access.log", "app.rs", "error.log", "main.rs"]);
+
+        state.toggle_hidden();
+        assert_eq!(names(&state), ["..", "access.log", "app.rs", "error.log", "main.rs"]);
+
+        // The selection stays on its entry while the filter narrows the list
+        state.jump_to('E');
+        assert_eq!(selected(&state), "error.log");
+        state.set_filter("log".to_string());
+        assert_eq!(names(&state), ["..", "access.log", "error.log"]);
+        assert_eq!(selected(&state), "error.log");
+        state.set_filter(String::new());
+
+        state.page_size = 3;
+        state.select_first();
+        state.page_down();
+        assert_eq!(selected(&state), "error.log");
+        state.page_down();
+        assert_eq!(selected(&state), "main.rs");
+        state.jump_to('a');
+        assert_eq!(selected(&state), "access.log");
+        state.jump_to('a');
+        assert_eq!(selected(&state), "app.rs");
+        state.select_last();
+        state.page_up();
+        assert_eq!(selected(&state), "access.log");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
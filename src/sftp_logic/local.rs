@@ -1,15 +1,21 @@
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path};
-use super::types::{FileItem, AppSftpState};
+use super::types::{FileItem, FileMeta, AppSftpState};
+use crate::config::RecentDirs;
 
 impl AppSftpState {
     /// Refresh the local file list
     pub fn refresh_local(&mut self) -> Result<()> {
-        self.local_files = Self::read_local_directory(&self.local_current_path)?;
-        if self.local_selected >= self.local_files.len() {
-            self.local_selected = self.local_files.len().saturating_sub(1);
-        }
+        self.local_all = Self::read_local_directory(&self.local_current_path)?;
+        self.sort.sort(&mut self.local_all);
+        self.update_local_view(false);
+        RecentDirs::visit(
+            &mut self.recent.local,
+            &self.local_current_path.to_string_lossy(),
+        );
         self.clear_status_message();
         Ok(())
     }
@@ -38,7 +44,7 @@ impl AppSftpState {
     pub fn open_local_selected(&mut self) -> Result<()> {
         if let Some(item) = self.local_files.get(self.local_selected) {
             match item {
-                FileItem::Directory { name } => {
+                FileItem::Directory { name, .. } => {
                     if name == ".." {
                         if let Some(parent) = self.local_current_path.parent() {
                             self.local_current_path = parent.to_path_buf();
@@ -47,6 +53,8 @@ impl AppSftpState {
                         self.local_current_path = self.local_current_path.join(name);
                     }
                     self.local_selected = 0;
+                    self.local_marked.clear();
+                    self.local_filter.clear();
                     self.local_list_state.select(Some(self.local_selected));
                     self.refresh_local()?;
                 }
@@ -63,6 +71,8 @@ impl AppSftpState {
         if let Some(parent) = self.local_current_path.parent() {
             self.local_current_path = parent.to_path_buf();
             self.local_selected = 0;
+            self.local_marked.clear();
+            self.local_filter.clear();
             self.refresh_local()?;
         }
         Ok(())
@@ -74,49 +84,66 @@ impl AppSftpState {
         
         // Add parent directory entry if not at root
         if path.parent().is_some() {
-            items.push(FileItem::Directory {
-                name: "..".to_string(),
-            });
+            items.push(FileItem::parent());
         }
         
         let entries = fs::read_dir(path).context("Failed to read local directory")?;
+        let users = id_names("/etc/passwd");
+        let groups = id_names("/etc/group");
         
         for entry in entries {
             let entry = entry.context("Failed to read directory entry")?;
             let file_name = entry.file_name().to_string_lossy().to_string();
-            let metadata = entry.metadata().context("Failed to read file metadata")?;
+            let mut metadata = entry.metadata().context("Failed to read file metadata")?;
+            
+            // Symlinks are shown as what they point to, broken ones as files
+            let mut link_target = None;
+            if metadata.is_symlink() {
+                link_target = fs::read_link(entry.path())
+                    .ok()
+                    .map(|target| target.to_string_lossy().into_owned());
+                if let Ok(target) = fs::metadata(entry.path()) {
+                    metadata = target;
+                }
+            }
+            let name_of = |names: &HashMap<u32, String>, id: u32| {
+                names.get(&id).cloned().unwrap_or_else(|| id.to_string())
+            };
+            let meta = FileMeta {
+                mtime: Some(metadata.mtime()),
+                permissions: Some(metadata.mode() & 0o7777),
+                owner: Some(name_of(&users, metadata.uid())),
+                group: Some(name_of(&groups, metadata.gid())),
+                link_target,
+            };
             
             if metadata.is_dir() {
-                items.push(FileItem::Directory { name: file_name });
+                items.push(FileItem::Directory { name: file_name, meta });
             } else {
                 items.push(FileItem::File {
                     name: file_name,
                     size: metadata.len(),
+                    meta,
                 });
             }
         }
         
-        // Sort: directories first, then files, both alphabetically
-        items.sort_by(|a, b| {
-            use std::cmp::Ordering;
-            match (a, b) {
-                (FileItem::Directory { name: name_a }, FileItem::Directory { name: name_b }) => {
-                    if name_a == ".." {
-                        Ordering::Less
-                    } else if name_b == ".." {
-                        Ordering::Greater
-                    } else {
-                        name_a.cmp(name_b)
-                    }
-                }
-                (FileItem::Directory { .. }, FileItem::File { .. }) => Ordering::Less,
-                (FileItem::File { .. }, FileItem::Directory { .. }) => Ordering::Greater,
-                (FileItem::File { name: name_a, .. }, FileItem::File { name: name_b, .. }) => {
-                    name_a.cmp(name_b)
-                }
-            }
-        });
-        
         Ok(items)
     }
-}
\ No newline at end of file
+}
+
+/// Names by id from /etc/passwd or /etc/group, empty when it can't be read
+fn id_names(file: &str) -> HashMap<u32, String> {
+    let Ok(entries) = fs::read_to_string(file) else {
+        return HashMap::new();
+    };
+    entries
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
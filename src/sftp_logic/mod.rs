@@ -1,10 +1,25 @@
 //! SFTP module for handling local and remote file operations
 
+pub mod checksum;
+pub mod conflict;
+pub mod edit;
+pub mod file_ops;
+pub mod history;
+pub mod listing;
+pub mod mouse;
 mod local;
+pub mod places;
+pub mod preview;
+pub mod queue;
 mod remote;
+pub mod session;
 mod state;
+pub mod sync;
+#[cfg(test)]
+mod test_server;
 mod transfer;
+mod tree;
 pub mod types;
 
 pub use types::AppSftpState;
-pub use types::{FileItem, PanelSide};
\ No newline at end of file
+pub use types::FileItem;
\ No newline at end of file
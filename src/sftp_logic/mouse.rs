@@ -0,0 +1,215 @@
+//! Mouse in SFTP mode
+//!
+//! A click selects the entry under the pointer and activates its panel, a
+//! double click opens it, and the wheel scrolls the panel under the pointer.
+//! Pressing on an entry of one file panel and releasing over the other one
+//! transfers it, or the marked entries when it is one of them.
+
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
+
+use super::types::{AppSftpState, PanelSide};
+
+/// Entries a wheel step scrolls
+const SCROLL_STEP: usize = 3;
+
+/// Index of the item of a bordered list at `column`, `row`, when the list
+/// shows its items from `offset` on
+pub fn list_index(area: Rect, offset: usize, column: u16, row: u16) -> Option<usize> {
+    let inside = column > area.x
+        && column + 1 < area.right()
+        && row > area.y
+        && row + 1 < area.bottom();
+    inside.then(|| offset + (row - area.y - 1) as usize)
+}
+
+impl AppSftpState {
+    /// Whether a dialog or a typed filter takes the input, leaving the mouse
+    /// out
+    fn has_dialog(&self) -> bool {
+        self.prompt.is_some()
+            || self.conflicts.is_some()
+            || self.pending_edit.is_some()
+            || self.transfer_summary.is_some()
+            || self.sync.is_some()
+            || self.places.is_some()
+            || self.history.is_some()
+            || self.filtering
+            || self.jumping
+    }
+
+    /// Panel drawn at `column`, `row` and the index of the entry there
+    fn hit(&self, column: u16, row: u16) -> Option<(PanelSide, Option<usize>)> {
+        let (side, area) = self
+            .panel_areas
+            .iter()
+            .find(|(_, area)| area.contains((column, row).into()))?;
+        let offset = match side {
+            PanelSide::Local => self.local_list_state.offset(),
+            PanelSide::Remote => self.remote_list_state.offset(),
+            PanelSide::Queue => self.queue.list_state.offset(),
+        };
+        Some((*side, list_index(*area, offset, column, row)))
+    }
+
+    /// Handle a mouse event, `double_click` when a left click follows
+    /// another one on the same row
+    pub fn handle_mouse(&mut self, event: MouseEvent, double_click: bool) {
+        if self.has_dialog() {
+            self.drag = None;
+            return;
+        }
+        let hit = self.hit(event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some((side, Some(index))) = hit else {
+                    return;
+                };
+                if !self.select_at(side, index) {
+                    return;
+                }
+                if double_click {
+                    self.drag = None;
+                    if let Err(e) = self.open_selected() {
+                        self.set_status_message(&format!("Error: {}", e));
+                    }
+                } else if side != PanelSide::Queue {
+                    self.drag = Some(side);
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                let (Some(from), Some((to, _))) = (self.drag.take(), hit) else {
+                    return;
+                };
+                match (from, to) {
+                    (PanelSide::Local, PanelSide::Remote) => self.drop_selected(from),
+                    (PanelSide::Remote, PanelSide::Local) => self.drop_selected(from),
+                    _ => {}
+                }
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let Some((side, _)) = hit else {
+                    return;
+                };
+                self.active_panel = side;
+                let up = event.kind == MouseEventKind::ScrollUp;
+                match side {
+                    PanelSide::Queue if up => self.queue.navigate_up(),
+                    PanelSide::Queue => self.queue.navigate_down(),
+                    _ if up => self.move_cursor(|selected, _| selected.saturating_sub(SCROLL_STEP)),
+                    _ => self.move_cursor(|selected, len| (selected + SCROLL_STEP).min(len - 1)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Activate `side` and select its entry `index`. Returns false when
+    /// there is no such entry.
+    fn select_at(&mut self, side: PanelSide, index: usize) -> bool {
+        let len = match side {
+            PanelSide::Local => self.local_files.len(),
+            PanelSide::Remote => self.remote_files.len(),
+            PanelSide::Queue => self.queue.items.len(),
+        };
+        if index >= len {
+            return false;
+        }
+        self.active_panel = side;
+        match side {
+            PanelSide::Queue => {
+                self.queue.selected = index;
+                self.queue.list_state.select(Some(index));
+            }
+            _ => self.move_cursor(|_, _| index),
+        }
+        true
+    }
+
+    /// Transfer the entry selected in `from` to the other panel. Marks are
+    /// kept when the dragged entry isn't one of them, and used up otherwise.
+    fn drop_selected(&mut self, from: PanelSide) {
+        let (files, selected, marked) = match from {
+            PanelSide::Remote => (&self.remote_files, self.remote_selected, &mut self.remote_marked),
+            _ => (&self.local_files, self.local_selected, &mut self.local_marked),
+        };
+        let Some(name) = files.get(selected).map(|item| item.name().to_string()) else {
+            return;
+        };
+        let marks = if marked.contains(&name) {
+            Default::default()
+        } else {
+            std::mem::take(marked)
+        };
+        match from {
+            PanelSide::Remote => {
+                self.download_selected();
+                self.remote_marked = marks;
+            }
+            _ => {
+                self.upload_selected();
+                self.local_marked = marks;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir};
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn clicks_select_and_dragging_transfers() {
+        let area = Rect::new(10, 5, 20, 6);
+        assert_eq!(list_index(area, 0, 11, 6), Some(0));
+        assert_eq!(list_index(area, 7, 28, 9), Some(10));
+        assert_eq!(list_index(area, 0, 10, 6), None);
+        assert_eq!(list_index(area, 0, 11, 10), None);
+
+        let dir = scratch_dir("mouse");
+        let (local, remote) = (dir.join("local"), dir.join("remote"));
+        std::fs::create_dir_all(local.join("docs")).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        std::fs::write(local.join("a.txt"), "a").unwrap();
+        std::fs::write(local.join("b.txt"), "b").unwrap();
+
+        let (mut state, _rx) = browser(&local, &remote);
+        state.panel_areas = vec![
+            (PanelSide::Local, Rect::new(0, 0, 40, 20)),
+            (PanelSide::Remote, Rect::new(40, 0, 40, 20)),
+        ];
+        let names: Vec<&str> = state.local_files.iter().map(|item| item.name()).collect();
+        assert_eq!(names, ["..", "docs", "a.txt", "b.txt"]);
+
+        // A double click on a directory opens it, the wheel scrolls
+        state.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 5, 2), false);
+        state.handle_mouse(mouse(MouseEventKind::Up(MouseButton::Left), 5, 2), false);
+        state.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 5, 2), true);
+        assert_eq!(state.local_current_path, local.join("docs"));
+        state.go_local_back().unwrap();
+        state.handle_mouse(mouse(MouseEventKind::ScrollDown, 5, 2), false);
+        assert_eq!(state.local_selected, 3);
+
+        // Dragging an unmarked entry to the remote panel uploads only it
+        state.local_marked.insert("b.txt".to_string());
+        state.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 5, 3), false);
+        assert_eq!(state.drag, Some(PanelSide::Local));
+        state.handle_mouse(mouse(MouseEventKind::Up(MouseButton::Left), 50, 8), false);
+        let queued: Vec<&str> = state.queue.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(queued, ["a.txt"]);
+        assert!(state.local_marked.contains("b.txt"));
+        assert_eq!(state.active_panel, PanelSide::Local);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+//! Start directories, bookmarks, recent directories and going to a typed
+//! path in the SFTP panels
+//!
+//! Start directories and bookmarks are set per host in hosts.toml. Recent
+//! directories are kept in `recent` as the panels change directory, and
+//! saved per host when SFTP mode is left.
+
+use anyhow::{bail, Context, Result};
+use ratatui::widgets::ListState;
+use std::path::{Path, PathBuf};
+
+use super::session::{join_remote, SftpSession};
+use super::types::{AppSftpState, PanelSide};
+use crate::config::RecentDirs;
+use crate::models::SshHost;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaceKind {
+    Bookmark,
+    Recent,
+}
+
+/// Bookmarks and recent directories of one panel, to pick one to go to
+#[derive(Debug, Clone)]
+pub struct Places {
+    pub side: PanelSide,
+    pub entries: Vec<(PlaceKind, String)>,
+    pub selected: usize,
+    pub list_state: ListState,
+}
+
+impl Places {
+    pub fn navigate_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn navigate_down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+}
+
+/// Local directory for `input`: `~` is the home directory, relative paths
+/// start from `base`
+fn expand_local(input: &str, base: &Path) -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_default();
+    match input {
+        "~" => home,
+        _ => match input.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => base.join(input),
+        },
+    }
+}
+
+/// Remote directory for `input`: `~` is the login directory `home`, relative
+/// paths start from `base`
+fn expand_remote(input: &str, base: &str, home: &str) -> String {
+    match input {
+        "~" => home.to_string(),
+        _ => match input.strip_prefix("~/") {
+            Some(rest) => join_remote(home, rest),
+            None if input.starts_with('/') => input.to_string(),
+            None => join_remote(base, input),
+        },
+    }
+}
+
+/// Resolve a remote directory, failing when it is something else
+fn remote_dir(session: &SftpSession, path: &str) -> Result<String> {
+    let path = session.realpath(path)?;
+    if !session.stat(&path)?.is_dir() {
+        bail!("{} is not a directory", path);
+    }
+    Ok(path)
+}
+
+fn local_dir(path: &Path) -> Result<PathBuf> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("{} not found", path.display()))?;
+    if !path.is_dir() {
+        bail!("{} is not a directory", path.display());
+    }
+    Ok(path)
+}
+
+/// Directories the panels start in: the host's start directories, or the
+/// working directory and the login directory `home`. The problem with a
+/// start directory that can't be used comes along.
+pub(super) fn start_directories(
+    host: &SshHost,
+    session: &SftpSession,
+    current_dir: PathBuf,
+    home: String,
+) -> (PathBuf, String, Option<String>) {
+    let mut problem = None;
+    let local = match &host.local_start_dir {
+        Some(dir) => local_dir(&expand_local(dir, &current_dir)).unwrap_or_else(|e| {
+            problem = Some(format!("Local start directory: {:#}", e));
+            current_dir.clone()
+        }),
+        None => current_dir,
+    };
+    let remote = match &host.remote_start_dir {
+        Some(dir) => remote_dir(session, &expand_remote(dir, &home, &home)).unwrap_or_else(|e| {
+            problem = Some(format!("Remote start directory {}: {:#}", dir, e));
+            home.clone()
+        }),
+        None => home,
+    };
+    (local, remote, problem)
+}
+
+impl AppSftpState {
+    /// Take the recent directories of earlier sessions, keeping the ones
+    /// visited since the state was created in front
+    pub fn restore_recent(&mut self, earlier: RecentDirs) {
+        let current = std::mem::replace(&mut self.recent, earlier);
+        for dir in current.local.iter().rev() {
+            RecentDirs::visit(&mut self.recent.local, dir);
+        }
+        for dir in current.remote.iter().rev() {
+            RecentDirs::visit(&mut self.recent.remote, dir);
+        }
+    }
+
+    /// Bookmark the directory of the active panel, or drop its bookmark.
+    /// Returns whether the host's bookmarks changed.
+    pub fn toggle_bookmark(&mut self) -> bool {
+        let (bookmarks, dir) = match self.active_panel {
+            PanelSide::Local => (
+                &mut self.host.local_bookmarks,
+                self.local_current_path.to_string_lossy().into_owned(),
+            ),
+            PanelSide::Remote => (&mut self.host.remote_bookmarks, self.remote_current_path.clone()),
+            PanelSide::Queue => {
+                self.set_status_message("Switch to the local or remote panel first");
+                return false;
+            }
+        };
+        let message = if bookmarks.contains(&dir) {
+            bookmarks.retain(|bookmark| *bookmark != dir);
+            format!("Removed bookmark {}", dir)
+        } else {
+            bookmarks.push(dir.clone());
+            format!("Bookmarked {}", dir)
+        };
+        self.set_status_message(&message);
+        true
+    }
+
+    /// Open the bookmarks and recent directories of the active panel
+    pub fn open_places(&mut self) {
+        let (bookmarks, recent, current) = match self.active_panel {
+            PanelSide::Local => (
+                &self.host.local_bookmarks,
+                &self.recent.local,
+                self.local_current_path.to_string_lossy().into_owned(),
+            ),
+            PanelSide::Remote => (
+                &self.host.remote_bookmarks,
+                &self.recent.remote,
+                self.remote_current_path.clone(),
+            ),
+            PanelSide::Queue => {
+                self.set_status_message("Switch to the local or remote panel first");
+                return;
+            }
+        };
+        let entries: Vec<(PlaceKind, String)> = bookmarks
+            .iter()
+            .map(|dir| (PlaceKind::Bookmark, dir.clone()))
+            .chain(
+                recent
+                    .iter()
+                    .filter(|dir| **dir != current)
+                    .map(|dir| (PlaceKind::Recent, dir.clone())),
+            )
+            .collect();
+        if entries.is_empty() {
+            self.set_status_message("No bookmarks or recent directories yet");
+            return;
+        }
+        self.places = Some(Places {
+            side: self.active_panel,
+            entries,
+            selected: 0,
+            list_state: ListState::default(),
+        });
+    }
+
+    /// Go to the place selected in the open list
+    pub fn go_to_selected_place(&mut self) {
+        let Some(places) = self.places.take() else {
+            return;
+        };
+        if let Some((_, dir)) = places.entries.get(places.selected) {
+            if let Err(e) = self.go_to(places.side, dir) {
+                self.set_status_message(&format!("Go to failed: {:#}", e));
+            }
+        }
+    }
+
+    /// Change the directory of a panel to `input`, absolute, relative to the
+    /// panel's directory or starting with `~`
+    pub fn go_to(&mut self, side: PanelSide, input: &str) -> Result<()> {
+        match side {
+            PanelSide::Remote => {
+                let session = self.session.get();
+                let home = session.realpath(".")?;
+                let path = expand_remote(input, &self.remote_current_path, &home);
+                self.remote_current_path = remote_dir(&session, &path)?;
+                self.remote_selected = 0;
+                self.remote_marked.clear();
+                self.remote_filter.clear();
+                self.remote_list_state.select(Some(0));
+                self.refresh_remote()
+            }
+            _ => {
+                let path = expand_local(input, &self.local_current_path);
+                self.local_current_path = local_dir(&path)?;
+                self.local_selected = 0;
+                self.local_marked.clear();
+                self.local_filter.clear();
+                self.local_list_state.select(Some(0));
+                self.refresh_local()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{self, scratch_dir};
+    use super::*;
+
+    #[test]
+    fn starts_in_host_directories_and_remembers_visits() {
+        let dir = scratch_dir("places");
+        std::fs::create_dir_all(dir.join("remote/logs")).unwrap();
+        std::fs::create_dir_all(dir.join("local")).unwrap();
+        let remote = dir.join("remote").to_string_lossy().into_owned();
+
+        let mut host = test_server::host();
+        host.local_start_dir = Some(dir.join("local").to_string_lossy().into_owned());
+        host.remote_start_dir = Some(format!("{}/missing", remote));
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let shared = test_server::shared();
+        let mut state = AppSftpState::with_session(&host, shared, Default::default(), tx).unwrap();
+        assert_eq!(state.local_current_path, dir.join("local"));
+        assert!(state.status_message.as_deref().unwrap().starts_with("Remote start directory"));
+
+        state.go_to(PanelSide::Remote, &remote).unwrap();
+        state.go_to(PanelSide::Remote, "logs").unwrap();
+        assert_eq!(state.remote_current_path, format!("{}/logs", remote));
+        assert!(state.go_to(PanelSide::Remote, "nowhere").is_err());
+
+        state.active_panel = PanelSide::Remote;
+        assert!(state.toggle_bookmark());
+        assert_eq!(state.host.remote_bookmarks, vec![format!("{}/logs", remote)]);
+        state.restore_recent(RecentDirs {
+            local: Vec::new(),
+            remote: vec!["/srv".to_string(), remote.clone()],
+        });
+        assert_eq!(state.recent.remote[..2], [format!("{}/logs", remote), remote.clone()]);
+        assert_eq!(state.recent.remote.last().unwrap(), "/srv");
+
+        // Bookmarks first, then the recent directories but the current one
+        state.open_places();
+        let places = state.places.as_ref().unwrap();
+        assert_eq!(places.entries[0], (PlaceKind::Bookmark, format!("{}/logs", remote)));
+        assert_eq!(places.entries[1], (PlaceKind::Recent, remote.clone()));
+        state.places.as_mut().unwrap().selected = 1;
+        state.go_to_selected_place();
+        assert_eq!(state.remote_current_path, remote);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
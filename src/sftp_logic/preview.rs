@@ -0,0 +1,536 @@
+//! Quick look at the entry selected in a file panel
+//!
+//! Files show their first `preview_kb` kilobytes, as text with simple
+//! highlighting for common formats or as a hex dump when they look binary.
+//! Directories show how many entries they have and the size of their files.
+//! Previews are read on a worker thread only while the pane is shown, and
+//! for remote entries only once the selection stays put for a moment. Only
+//! regular files are read, a FIFO would never end.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::session::{join_remote, open_flags, SftpSession};
+use super::types::{AppSftpState, FileItem, PanelSide};
+use crate::app_event::TransferEvent;
+
+/// How long the remote selection has to stay put before it is previewed
+const REMOTE_DELAY: Duration = Duration::from_millis(150);
+/// Bytes per line of the hex view
+const HEX_WIDTH: usize = 16;
+
+/// Preview of one entry
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub side: PanelSide,
+    /// Full path of the entry, what the preview is kept for
+    pub path: String,
+    pub name: String,
+    pub content: PreviewContent,
+}
+
+#[derive(Debug, Clone)]
+pub enum PreviewContent {
+    Text {
+        lines: Vec<String>,
+        syntax: Syntax,
+        /// The file goes on past what was read
+        truncated: bool,
+    },
+    Binary {
+        bytes: Vec<u8>,
+        truncated: bool,
+    },
+    /// Direct entries of a directory, not counting what is below them
+    Directory {
+        directories: usize,
+        files: usize,
+        bytes: u64,
+    },
+    /// The `..` entry
+    Parent,
+    Error(String),
+}
+
+/// Formats the text view knows how to color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Plain,
+    /// `key = value` and `key: value` files: TOML, INI, YAML, .env, ...
+    Config,
+    Json,
+    /// Shell, Python and other languages with `#` comments
+    Script,
+    /// C-like languages with `//` comments
+    Code,
+    Markdown,
+    Log,
+}
+
+impl Syntax {
+    /// Guess from the file name, and from a `#!` first line
+    pub fn detect(name: &str, first_line: &str) -> Self {
+        let lower = name.to_lowercase();
+        let extension = lower.rsplit_once('.').map(|(_, extension)| extension).unwrap_or("");
+        match extension {
+            "toml" | "ini" | "conf" | "cfg" | "cnf" | "yaml" | "yml" | "env" | "properties"
+            | "service" | "timer" | "desktop" => Syntax::Config,
+            "json" | "jsonl" | "geojson" => Syntax::Json,
+            "sh" | "bash" | "zsh" | "fish" | "py" | "rb" | "pl" | "r" => Syntax::Script,
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "js" | "jsx" | "ts" | "tsx" | "go"
+            | "java" | "kt" | "swift" | "cs" | "php" | "scala" => Syntax::Code,
+            "md" | "markdown" => Syntax::Markdown,
+            "log" | "out" | "err" => Syntax::Log,
+            _ if lower.starts_with(".env") || lower.ends_with("rc") => Syntax::Config,
+            _ if first_line.starts_with("#!") => Syntax::Script,
+            _ => Syntax::Plain,
+        }
+    }
+}
+
+/// Kind of a piece of highlighted text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Plain,
+    Comment,
+    Key,
+    Str,
+    Number,
+    Heading,
+    Error,
+    Warning,
+}
+
+impl AppSftpState {
+    /// Show or hide the preview pane
+    pub fn toggle_preview(&mut self) {
+        self.show_preview = !self.show_preview;
+        if !self.show_preview {
+            self.preview = None;
+            self.preview_pending = None;
+            self.preview_loading = None;
+        }
+    }
+
+    /// Start reading the preview of the selected entry of the active file
+    /// panel when it isn't the one shown or being read yet. Called from the
+    /// event loop while the pane is shown, the result comes back as a
+    /// `TransferEvent::Preview`.
+    pub fn update_preview(&mut self) {
+        let (side, item) = match self.active_panel {
+            PanelSide::Local => (PanelSide::Local, self.local_files.get(self.local_selected)),
+            PanelSide::Remote => (PanelSide::Remote, self.remote_files.get(self.remote_selected)),
+            PanelSide::Queue => return,
+        };
+        let Some(item) = item.cloned() else {
+            self.preview = None;
+            return;
+        };
+        let path = match side {
+            PanelSide::Remote => join_remote(&self.remote_current_path, item.name()),
+            _ => self.local_current_path.join(item.name()).to_string_lossy().into_owned(),
+        };
+        let entry = (side, path.clone());
+        let shown = |preview: &Preview| preview.side == side && preview.path == path;
+        if self.preview.as_ref().is_some_and(shown) || self.preview_loading == Some(entry) {
+            return;
+        }
+
+        if side == PanelSide::Remote {
+            match &self.preview_pending {
+                Some((pending, since)) if *pending == path => {
+                    if since.elapsed() < REMOTE_DELAY {
+                        return;
+                    }
+                }
+                _ => {
+                    self.preview_pending = Some((path, Instant::now()));
+                    return;
+                }
+            }
+        }
+        self.preview_pending = None;
+
+        let Some(tx) = self.transfer_tx.clone() else {
+            return;
+        };
+        self.preview_loading = Some((side, path.clone()));
+        let session = self.session.clone();
+        let limit = self.config.preview_kb * 1024;
+        tokio::task::spawn_blocking(move || {
+            let content = if item.name() == ".." {
+                Ok(PreviewContent::Parent)
+            } else {
+                match (side, &item) {
+                    (PanelSide::Remote, FileItem::Directory { .. }) => {
+                        remote_directory(&session.get(), &path)
+                    }
+                    (PanelSide::Remote, FileItem::File { .. }) => {
+                        remote_head(&session.get(), &path, limit)
+                            .map(|(data, truncated)| file_content(item.name(), data, truncated))
+                    }
+                    (_, FileItem::Directory { .. }) => local_directory(Path::new(&path)),
+                    (_, FileItem::File { .. }) => local_head(Path::new(&path), limit)
+                        .map(|(data, truncated)| file_content(item.name(), data, truncated)),
+                }
+            };
+            let preview = Preview {
+                side,
+                path,
+                name: item.name().to_string(),
+                content: content.unwrap_or_else(|e| PreviewContent::Error(format!("{:#}", e))),
+            };
+            let _ = tx.blocking_send(TransferEvent::Preview(Box::new(preview)));
+        });
+    }
+
+    /// Show a preview read by `update_preview`, unless the selection moved
+    /// on to another entry in the meantime
+    pub fn apply_preview(&mut self, preview: Preview) {
+        if self.preview_loading.as_ref() == Some(&(preview.side, preview.path.clone())) {
+            self.preview_loading = None;
+            self.preview = Some(preview);
+        }
+    }
+}
+
+/// Up to `limit` bytes from the start of a local file, and whether there is
+/// more
+fn local_head(path: &Path, limit: usize) -> Result<(Vec<u8>, bool)> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !metadata.is_file() {
+        bail!("Not a regular file");
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut data = Vec::new();
+    file.take(limit as u64 + 1)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let truncated = data.len() > limit;
+    data.truncate(limit);
+    Ok((data, truncated))
+}
+
+fn remote_head(session: &SftpSession, path: &str, limit: usize) -> Result<(Vec<u8>, bool)> {
+    if !session.stat(path)?.is_file() {
+        bail!("Not a regular file");
+    }
+    let handle = session.open(path, open_flags::READ)?;
+    let data = session.read_at(&handle, 0, limit + 1);
+    session.close(handle)?;
+    let mut data = data?;
+    let truncated = data.len() > limit;
+    data.truncate(limit);
+    Ok((data, truncated))
+}
+
+fn local_directory(path: &Path) -> Result<PreviewContent> {
+    let (mut directories, mut files, mut bytes) = (0, 0, 0);
+    for entry in fs::read_dir(path).with_context(|| format!("Failed to read {}", path.display()))? {
+        let metadata = fs::metadata(entry?.path());
+        match metadata {
+            Ok(metadata) if metadata.is_dir() => directories += 1,
+            Ok(metadata) => {
+                files += 1;
+                bytes += metadata.len();
+            }
+            // Broken links
+            Err(_) => files += 1,
+        }
+    }
+    Ok(PreviewContent::Directory { directories, files, bytes })
+}
+
+fn remote_directory(session: &SftpSession, path: &str) -> Result<PreviewContent> {
+    let (mut directories, mut files, mut bytes) = (0, 0, 0);
+    for entry in session.read_dir(path)? {
+        if entry.attrs.is_dir() {
+            directories += 1;
+        } else {
+            files += 1;
+            bytes += entry.attrs.size.unwrap_or(0);
+        }
+    }
+    Ok(PreviewContent::Directory { directories, files, bytes })
+}
+
+/// Text when `data` is UTF-8 without NUL bytes, allowing a character cut at
+/// the end, otherwise binary
+fn file_content(name: &str, data: Vec<u8>, truncated: bool) -> PreviewContent {
+    let text = match std::str::from_utf8(&data) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) if !text.contains('\0') => {
+            let lines: Vec<String> = text.lines().map(|line| line.replace('\t', "    ")).collect();
+            let syntax = Syntax::detect(name, lines.first().map(String::as_str).unwrap_or(""));
+            PreviewContent::Text { lines, syntax, truncated }
+        }
+        _ => PreviewContent::Binary { bytes: data, truncated },
+    }
+}
+
+/// `hexdump -C` style lines: offset, bytes and the printable ones
+pub fn hex_lines(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", i * HEX_WIDTH, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Split a line into highlighted pieces
+pub fn highlight(syntax: Syntax, line: &str) -> Vec<(Highlight, &str)> {
+    match syntax {
+        Syntax::Plain => vec![(Highlight::Plain, line)],
+        Syntax::Log => {
+            let upper = line.to_uppercase();
+            let is_error = ["ERROR", "FATAL", "CRIT", "PANIC", "FAIL"]
+                .iter()
+                .any(|word| upper.contains(word));
+            let kind = if is_error {
+                Highlight::Error
+            } else if upper.contains("WARN") {
+                Highlight::Warning
+            } else {
+                Highlight::Plain
+            };
+            vec![(kind, line)]
+        }
+        Syntax::Markdown => {
+            let trimmed = line.trim_start();
+            let kind = if trimmed.starts_with('#') {
+                Highlight::Heading
+            } else if trimmed.starts_with("```") || trimmed.starts_with('>') {
+                Highlight::Comment
+            } else {
+                Highlight::Plain
+            };
+            vec![(kind, line)]
+        }
+        Syntax::Config => {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with('#') || trimmed.starts_with(';') {
+                return vec![(Highlight::Comment, line)];
+            }
+            if trimmed.starts_with('[') {
+                return vec![(Highlight::Heading, line)];
+            }
+            // The key runs up to the first `=` or `:` when no quote comes
+            // before it
+            let separator = line
+                .find(['=', ':', '"', '\''])
+                .filter(|&at| matches!(line.as_bytes()[at], b'=' | b':'));
+            match separator {
+                Some(at) => {
+                    let mut pieces = vec![
+                        (Highlight::Key, &line[..at]),
+                        (Highlight::Plain, &line[at..at + 1]),
+                    ];
+                    pieces.extend(scan(&line[at + 1..], &["#"]));
+                    pieces
+                }
+                None => scan(line, &["#"]),
+            }
+        }
+        Syntax::Json => {
+            let mut pieces = scan(line, &[]);
+            // Strings followed by `:` are keys
+            for i in 0..pieces.len().saturating_sub(1) {
+                if pieces[i].0 == Highlight::Str && pieces[i + 1].1.trim_start().starts_with(':') {
+                    pieces[i].0 = Highlight::Key;
+                }
+            }
+            pieces
+        }
+        Syntax::Script => scan(line, &["#"]),
+        Syntax::Code => scan(line, &["//", "/*", "*/"]),
+    }
+}
+
+/// Strings, numbers and a comment running to the end of the line, which
+/// starts at any of `comments` outside strings
+fn scan<'a>(line: &'a str, comments: &[&str]) -> Vec<(Highlight, &'a str)> {
+    let bytes = line.as_bytes();
+    let is_word = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_';
+    let mut pieces = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+    let flush = |pieces: &mut Vec<(Highlight, &'a str)>, from: usize, to: usize| {
+        if from < to {
+            pieces.push((Highlight::Plain, &line[from..to]));
+        }
+    };
+    // Only ASCII bytes start a piece, so every cut is on a character boundary
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if comments.iter().any(|comment| bytes[i..].starts_with(comment.as_bytes())) {
+            flush(&mut pieces, plain_start, i);
+            pieces.push((Highlight::Comment, &line[i..]));
+            return pieces;
+        }
+        let end = if byte == b'"' || byte == b'\'' {
+            let mut end = i + 1;
+            while end < bytes.len() && bytes[end] != byte {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            Some((Highlight::Str, (end + 1).min(bytes.len())))
+        } else if byte.is_ascii_digit() && (i == 0 || !is_word(bytes[i - 1])) {
+            let mut end = i + 1;
+            while end < bytes.len() && (is_word(bytes[end]) || bytes[end] == b'.') {
+                end += 1;
+            }
+            Some((Highlight::Number, end))
+        } else {
+            None
+        };
+        match end {
+            Some((kind, end)) => {
+                flush(&mut pieces, plain_start, i);
+                pieces.push((kind, &line[i..end]));
+                i = end;
+                plain_start = end;
+            }
+            None => i += 1,
+        }
+    }
+    flush(&mut pieces, plain_start, bytes.len());
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, next_event, scratch_dir};
+    use super::*;
+
+    /// Start reading the preview and show it once the worker is done
+    async fn load_preview(
+        state: &mut AppSftpState,
+        rx: &mut tokio::sync::mpsc::Receiver<TransferEvent>,
+    ) {
+        state.update_preview();
+        if state.preview_loading.is_some() {
+            let event = next_event(rx).await;
+            state.apply_transfer_event(event);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn previews_local_entries_at_once_and_remote_ones_once_settled() {
+        let dir = scratch_dir("preview");
+        std::fs::create_dir(dir.join("logs")).unwrap();
+        std::fs::write(dir.join("logs/a.log"), "12345").unwrap();
+        std::fs::write(dir.join("logs/b.log"), "678").unwrap();
+        let fifo = dir.join("logs/c.pipe");
+        assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        let (mut state, mut rx) = browser(&dir, &dir.join("logs"));
+        state.toggle_preview();
+
+        state.local_selected = 1;
+        load_preview(&mut state, &mut rx).await;
+        assert!(matches!(
+            state.preview.as_ref().unwrap().content,
+            PreviewContent::Directory { directories: 0, files: 3, bytes: 8 }
+        ));
+
+        state.active_panel = PanelSide::Remote;
+        state.remote_selected = 1;
+        load_preview(&mut state, &mut rx).await;
+        assert_eq!(state.preview.as_ref().unwrap().side, PanelSide::Local);
+        std::thread::sleep(REMOTE_DELAY);
+        load_preview(&mut state, &mut rx).await;
+        let preview = state.preview.as_ref().unwrap();
+        assert_eq!(preview.name, "a.log");
+        assert!(matches!(
+            &preview.content,
+            PreviewContent::Text { lines, syntax: Syntax::Log, truncated: false } if lines == &["12345"]
+        ));
+
+        // Neither side reads from a FIFO
+        for side in [PanelSide::Remote, PanelSide::Local] {
+            if side == PanelSide::Local {
+                state.local_current_path = dir.join("logs");
+                state.refresh_local().unwrap();
+            }
+            state.active_panel = side;
+            state.local_selected = 3;
+            state.remote_selected = 3;
+            load_preview(&mut state, &mut rx).await;
+            std::thread::sleep(REMOTE_DELAY);
+            load_preview(&mut state, &mut rx).await;
+            let preview = state.preview.as_ref().unwrap();
+            assert_eq!((preview.side, preview.name.as_str()), (side, "c.pipe"));
+            assert!(matches!(
+                &preview.content,
+                PreviewContent::Error(e) if e == "Not a regular file"
+            ));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tells_text_from_binary_and_highlights_it() {
+        let PreviewContent::Text { lines, syntax, .. } =
+            file_content("app.toml", b"# app\nport = 8080\nname = \"web\"".to_vec(), false)
+        else {
+            panic!("expected text");
+        };
+        assert_eq!(syntax, Syntax::Config);
+        assert_eq!(highlight(syntax, &lines[0]), [(Highlight::Comment, "# app")]);
+        assert_eq!(
+            highlight(syntax, &lines[1]),
+            [
+                (Highlight::Key, "port "),
+                (Highlight::Plain, "="),
+                (Highlight::Plain, " "),
+                (Highlight::Number, "8080"),
+            ]
+        );
+        assert_eq!(
+            highlight(Syntax::Json, r#"{"id": 7, "tag": "a\"b"}"#),
+            [
+                (Highlight::Plain, "{"),
+                (Highlight::Key, "\"id\""),
+                (Highlight::Plain, ": "),
+                (Highlight::Number, "7"),
+                (Highlight::Plain, ", "),
+                (Highlight::Key, "\"tag\""),
+                (Highlight::Plain, ": "),
+                (Highlight::Str, r#""a\"b""#),
+                (Highlight::Plain, "}"),
+            ]
+        );
+        assert_eq!(Syntax::detect("deploy", "#!/bin/sh"), Syntax::Script);
+
+        // A character cut at the end is still text, NUL bytes are not
+        let cut = "héllo".as_bytes()[..2].to_vec();
+        assert!(matches!(file_content("a", cut, true), PreviewContent::Text { .. }));
+        let PreviewContent::Binary { bytes, .. } = file_content("a", b"\x7fELF\0\x01".to_vec(), false)
+        else {
+            panic!("expected binary");
+        };
+        assert_eq!(
+            hex_lines(&bytes),
+            ["00000000  7f 45 4c 46 00 01                                |.ELF..|"]
+        );
+    }
+}
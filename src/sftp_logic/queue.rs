@@ -0,0 +1,765 @@
+//! Queue of uploads and downloads, a few of which run at the same time
+//!
+//! Each transfer has an id that its `TransferEvent`s carry. Running transfers
+//! are paused, cancelled and limited in rate through a shared
+//! `TransferControl` that the transfer checks between chunks. While the
+//! queue is throttled, all transfers draw from one shared `Throttle`.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ratatui::widgets::ListState;
+use serde::{Deserialize, Serialize};
+
+use super::checksum::ChecksumMismatch;
+use super::types::{FileProgress, TransferSummary, TreeProgress};
+
+pub type TransferId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+impl TransferState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransferState::Queued => "queued",
+            TransferState::Running => "running",
+            TransferState::Paused => "paused",
+            TransferState::Done => "done",
+            TransferState::Failed(_) => "failed",
+            TransferState::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the transfer won't do anything more unless retried
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            TransferState::Done | TransferState::Failed(_) | TransferState::Cancelled
+        )
+    }
+}
+
+const RUN: u8 = 0;
+const PAUSE: u8 = 1;
+const CANCEL: u8 = 2;
+
+/// Bytes per second, 0 for no limit
+type Rate = Arc<AtomicU64>;
+
+fn load_rate(rate: &Rate) -> Option<u64> {
+    Some(rate.load(Ordering::SeqCst)).filter(|rate| *rate > 0)
+}
+
+/// Bytes per second that all transfers of a queue share while it's
+/// throttled, drawn from like a token bucket
+#[derive(Debug, Default)]
+struct Throttle {
+    rate: AtomicU64,
+    /// When the bytes drawn so far will have been sent at the rate
+    paid_until: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    fn rate(&self) -> Option<u64> {
+        Some(self.rate.load(Ordering::SeqCst)).filter(|rate| *rate > 0)
+    }
+
+    fn set_rate(&self, rate: Option<u64>) {
+        self.rate.store(rate.unwrap_or(0), Ordering::SeqCst);
+        *self.paid_until.lock().unwrap() = None;
+    }
+
+    /// Take `bytes` out of the budget, returning when they have been paid
+    /// for, or `None` while not throttled
+    fn draw(&self, bytes: u64) -> Option<Instant> {
+        let rate = self.rate()?;
+        let mut paid_until = self.paid_until.lock().unwrap();
+        let now = Instant::now();
+        let from = paid_until.filter(|paid| *paid > now).unwrap_or(now);
+        let due = from + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        *paid_until = Some(due);
+        Some(due)
+    }
+}
+
+/// Pause and cancel requests and the rate limit for one transfer, shared
+/// with the thread running it
+#[derive(Debug, Clone, Default)]
+pub struct TransferControl {
+    state: Arc<AtomicU8>,
+    limit: Rate,
+    /// Budget of the whole queue while it is throttled
+    throttle: Arc<Throttle>,
+}
+
+impl TransferControl {
+    pub fn pause(&self) {
+        let _ = self
+            .state
+            .compare_exchange(RUN, PAUSE, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _ = self
+            .state
+            .compare_exchange(PAUSE, RUN, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.state.store(CANCEL, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == CANCEL
+    }
+
+    /// Block while paused, failing once the transfer is cancelled
+    pub fn wait(&self) -> io::Result<()> {
+        loop {
+            match self.state.load(Ordering::SeqCst) {
+                RUN => return Ok(()),
+                // Not `Interrupted`, which `write_all` and `read_exact` retry
+                CANCEL => return Err(io::Error::other("Transfer cancelled")),
+                _ => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+
+    /// Limit of this transfer alone, in bytes per second, apart from the
+    /// queue's throttle
+    pub fn limit(&self) -> Option<u64> {
+        load_rate(&self.limit)
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit.store(limit.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// A control for another attempt, keeping the limits
+    fn restart(&self) -> Self {
+        Self {
+            state: Arc::default(),
+            limit: self.limit.clone(),
+            throttle: self.throttle.clone(),
+        }
+    }
+
+    /// Sleep until `due`, failing once the transfer is cancelled
+    fn hold_back(&self, due: Instant) -> io::Result<()> {
+        loop {
+            let now = Instant::now();
+            if now >= due {
+                return Ok(());
+            }
+            self.wait()?;
+            std::thread::sleep((due - now).min(Duration::from_millis(100)));
+        }
+    }
+}
+
+/// A reader or writer that stops while its transfer is paused, fails once
+/// it's cancelled and slows down to the transfer's rate limit and the
+/// queue's throttle
+pub struct Controlled<T> {
+    inner: T,
+    control: TransferControl,
+    /// Start of the current second and the bytes passed since
+    window: Option<(Instant, u64)>,
+}
+
+impl<T> Controlled<T> {
+    pub fn new(inner: T, control: TransferControl) -> Self {
+        Self {
+            inner,
+            control,
+            window: None,
+        }
+    }
+
+    /// Count `bytes` that went through and wait if they were too fast. The
+    /// count of the transfer's own limit starts over every second, so that
+    /// pauses and changed limits don't let a burst through.
+    fn throttle(&mut self, bytes: usize) -> io::Result<()> {
+        if let Some(due) = self.control.throttle.draw(bytes as u64) {
+            self.control.hold_back(due)?;
+        }
+        let Some(limit) = self.control.limit() else {
+            self.window = None;
+            return Ok(());
+        };
+        let now = Instant::now();
+        let (start, sent) = match self.window {
+            Some((start, sent)) if now.saturating_duration_since(start) < Duration::from_secs(1) => {
+                (start, sent + bytes as u64)
+            }
+            _ => (now, bytes as u64),
+        };
+        self.window = Some((start, sent));
+        let due = start + Duration::from_secs_f64(sent as f64 / limit as f64);
+        self.control.hold_back(due)
+    }
+}
+
+impl<R: Read> Read for Controlled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.control.wait()?;
+        let read = self.inner.read(buf)?;
+        self.throttle(read)?;
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Controlled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.control.wait()?;
+        let written = self.inner.write(buf)?;
+        self.throttle(written)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// How far back the transfer rate is measured
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Elapsed time and rate of one transfer, not counting the time it was paused
+#[derive(Debug, Clone, Default)]
+pub struct TransferStats {
+    elapsed_before: Option<Duration>,
+    running_since: Option<Instant>,
+    /// Bytes done at recent points in time, the rate is measured over these
+    samples: VecDeque<(Instant, u64)>,
+    /// Bytes already there when the transfer started, when resuming a file
+    bytes_resumed: Option<u64>,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+impl TransferStats {
+    /// The transfer started or resumed at `now`
+    pub fn start(&mut self, now: Instant) {
+        self.running_since = Some(now);
+        self.samples.clear();
+    }
+
+    /// The transfer paused or ended at `now`
+    pub fn stop(&mut self, now: Instant) {
+        if let Some(since) = self.running_since.take() {
+            let before = self.elapsed_before.unwrap_or_default();
+            self.elapsed_before = Some(before + now.saturating_duration_since(since));
+        }
+        self.samples.clear();
+    }
+
+    /// `done` of `total` bytes were transferred at `now`
+    pub fn record(&mut self, now: Instant, done: u64, total: u64) {
+        self.bytes_resumed.get_or_insert(done);
+        self.bytes_done = done;
+        self.bytes_total = total;
+        self.samples.push_back((now, done));
+        while self.samples.len() > 2
+            && now.saturating_duration_since(self.samples[0].0) > RATE_WINDOW
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Time spent running, `None` before the transfer started
+    pub fn elapsed(&self, now: Instant) -> Option<Duration> {
+        let running = self
+            .running_since
+            .map(|since| now.saturating_duration_since(since));
+        match (self.elapsed_before, running) {
+            (None, None) => None,
+            (before, running) => Some(before.unwrap_or_default() + running.unwrap_or_default()),
+        }
+    }
+
+    /// Bytes done so far, of all files for directories
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done
+    }
+
+    /// Bytes per second over the last few seconds
+    pub fn rate(&self) -> Option<f64> {
+        let (first_time, first_bytes) = *self.samples.front()?;
+        let (last_time, last_bytes) = *self.samples.back()?;
+        let seconds = last_time.saturating_duration_since(first_time).as_secs_f64();
+        if seconds < 0.2 {
+            return None;
+        }
+        Some(last_bytes.saturating_sub(first_bytes) as f64 / seconds)
+    }
+
+    /// Bytes per second over the whole time the transfer ran
+    pub fn average_rate(&self, now: Instant) -> Option<f64> {
+        let seconds = self.elapsed(now)?.as_secs_f64();
+        let transferred = self.bytes_done.saturating_sub(self.bytes_resumed.unwrap_or(0));
+        (seconds > 0.0).then(|| transferred as f64 / seconds)
+    }
+
+    /// Time left at the current rate
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.rate().filter(|rate| *rate > 0.0)?;
+        let left = self.bytes_total.saturating_sub(self.bytes_done);
+        Some(Duration::from_secs_f64(left as f64 / rate))
+    }
+}
+
+/// One file or directory in the queue
+#[derive(Debug, Clone)]
+pub struct QueuedTransfer {
+    pub id: TransferId,
+    pub direction: TransferDirection,
+    /// Name of the file or directory in its panel
+    pub name: String,
+    pub local_path: PathBuf,
+    pub remote_path: String,
+    pub is_dir: bool,
+    /// Compare SHA-256 hashes of both sides once copied
+    pub verify: bool,
+    pub state: TransferState,
+    /// File being transferred, for directories one file inside them
+    pub progress: Option<FileProgress>,
+    /// Set for directories once they are scanned
+    pub tree: Option<TreeProgress>,
+    /// Outcome of a finished directory transfer
+    pub summary: Option<TransferSummary>,
+    pub control: TransferControl,
+    pub stats: TransferStats,
+    /// Attempt number and error while waiting to reconnect after the
+    /// connection dropped
+    pub retrying: Option<(u32, String)>,
+    /// Set while the hashes are compared after copying
+    pub verifying: bool,
+    /// Whether a thread was started for this attempt, so that resuming
+    /// continues it instead of queueing it again
+    started: bool,
+}
+
+impl QueuedTransfer {
+    /// Record the progress reported by the running transfer
+    pub fn update(&mut self, progress: FileProgress, tree: Option<TreeProgress>) {
+        if let Some(tree) = tree {
+            self.tree = Some(tree);
+        }
+        let (done, total) = match &self.tree {
+            Some(tree) => (tree.bytes_done, tree.bytes_total),
+            None => (progress.done, progress.total),
+        };
+        self.stats.record(Instant::now(), done, total);
+        self.progress = Some(progress);
+        self.retrying = None;
+        self.verifying = false;
+    }
+
+    /// Whether a mismatched file is at or below the paths of this transfer,
+    /// so that finishing it replaces the file
+    pub fn covers(&self, mismatch: &ChecksumMismatch) -> bool {
+        let remote_dir = format!("{}/", self.remote_path.trim_end_matches('/'));
+        mismatch.local_path.starts_with(&self.local_path)
+            || mismatch.remote_path == self.remote_path
+            || mismatch.remote_path.starts_with(&remote_dir)
+    }
+
+    /// The connection dropped and the transfer waits to try again
+    pub fn wait_to_retry(&mut self, attempt: u32, error: String) {
+        // The next attempt may restart the count, so the rate starts over
+        let now = Instant::now();
+        self.stats.stop(now);
+        self.stats.start(now);
+        self.retrying = Some((attempt, error));
+    }
+
+    /// End the transfer in `state`
+    pub fn finish(&mut self, state: TransferState) {
+        self.stats.stop(Instant::now());
+        self.state = state;
+        self.retrying = None;
+        self.verifying = false;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferQueue {
+    pub items: Vec<QueuedTransfer>,
+    pub selected: usize,
+    pub list_state: ListState,
+    /// Bytes per second new transfers are limited to, from the host
+    pub default_limit: Option<u64>,
+    throttle: Arc<Throttle>,
+    next_id: TransferId,
+}
+
+impl TransferQueue {
+    /// An empty queue whose transfers are limited to `default_limit` bytes
+    /// per second
+    pub fn with_limit(default_limit: Option<u64>) -> Self {
+        Self {
+            default_limit,
+            ..Default::default()
+        }
+    }
+
+    /// Limit of all transfers together, in bytes per second
+    pub fn throttle(&self) -> Option<u64> {
+        self.throttle.rate()
+    }
+
+    /// Limit all transfers together, running ones included, or lift the
+    /// limit
+    pub fn set_throttle(&self, limit: Option<u64>) {
+        self.throttle.set_rate(limit);
+    }
+
+    /// Add a transfer at the end of the queue
+    pub fn push(
+        &mut self,
+        direction: TransferDirection,
+        name: &str,
+        local_path: PathBuf,
+        remote_path: String,
+        is_dir: bool,
+        verify: bool,
+    ) -> TransferId {
+        self.next_id += 1;
+        let control = TransferControl {
+            throttle: self.throttle.clone(),
+            ..Default::default()
+        };
+        control.set_limit(self.default_limit);
+        self.items.push(QueuedTransfer {
+            id: self.next_id,
+            direction,
+            name: name.to_string(),
+            local_path,
+            remote_path,
+            is_dir,
+            verify,
+            state: TransferState::Queued,
+            progress: None,
+            tree: None,
+            summary: None,
+            control,
+            stats: TransferStats::default(),
+            retrying: None,
+            verifying: false,
+            started: false,
+        });
+        self.next_id
+    }
+
+    pub fn get_mut(&mut self, id: TransferId) -> Option<&mut QueuedTransfer> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    pub fn selected_id(&self) -> Option<TransferId> {
+        self.items.get(self.selected).map(|item| item.id)
+    }
+
+    /// Transfers using one of the parallel slots; paused ones give theirs up
+    pub fn running(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.state == TransferState::Running)
+            .count()
+    }
+
+    /// Whether anything is queued, running or paused
+    pub fn is_active(&self) -> bool {
+        self.items.iter().any(|item| !item.state.is_finished())
+    }
+
+    /// Mark queued transfers as running until `limit` run at the same time,
+    /// returning the ones to start
+    pub fn start_next(&mut self, limit: usize) -> Vec<QueuedTransfer> {
+        let mut free = limit.max(1).saturating_sub(self.running());
+        let mut started = Vec::new();
+        for item in &mut self.items {
+            if free == 0 {
+                break;
+            }
+            if item.state == TransferState::Queued {
+                item.state = TransferState::Running;
+                item.stats.start(Instant::now());
+                item.started = true;
+                started.push(item.clone());
+                free -= 1;
+            }
+        }
+        started
+    }
+
+    /// Pause a queued or running transfer, or resume a paused one. Returns
+    /// the new state.
+    pub fn toggle_pause(&mut self, id: TransferId) -> Option<TransferState> {
+        let item = self.get_mut(id)?;
+        item.state = match item.state {
+            TransferState::Queued => TransferState::Paused,
+            TransferState::Running => {
+                item.control.pause();
+                item.stats.stop(Instant::now());
+                TransferState::Paused
+            }
+            TransferState::Paused if item.started => {
+                item.control.resume();
+                item.stats.start(Instant::now());
+                TransferState::Running
+            }
+            TransferState::Paused => TransferState::Queued,
+            _ => return None,
+        };
+        Some(item.state.clone())
+    }
+
+    /// Stop a transfer that isn't finished yet
+    pub fn cancel(&mut self, id: TransferId) -> bool {
+        match self.get_mut(id) {
+            Some(item) if !item.state.is_finished() => {
+                item.control.cancel();
+                item.finish(TransferState::Cancelled);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Queue a failed or cancelled transfer again from the start
+    pub fn retry(&mut self, id: TransferId) -> bool {
+        match self.get_mut(id) {
+            Some(item)
+                if matches!(
+                    item.state,
+                    TransferState::Failed(_) | TransferState::Cancelled
+                ) =>
+            {
+                item.state = TransferState::Queued;
+                item.control = item.control.restart();
+                item.stats = TransferStats::default();
+                item.started = false;
+                item.progress = None;
+                item.tree = None;
+                item.summary = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drop transfers that are done, failed or cancelled
+    pub fn clear_finished(&mut self) {
+        self.items.retain(|item| !item.state.is_finished());
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        self.list_state.select(Some(self.selected));
+    }
+
+    pub fn navigate_up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+        } else if !self.items.is_empty() {
+            self.selected = self.items.len() - 1;
+        }
+        self.list_state.select(Some(self.selected));
+    }
+
+    pub fn navigate_down(&mut self) {
+        if self.selected < self.items.len().saturating_sub(1) {
+            self.selected += 1;
+        } else {
+            self.selected = 0;
+        }
+        self.list_state.select(Some(self.selected));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_of(count: usize) -> TransferQueue {
+        let mut queue = TransferQueue::default();
+        for i in 0..count {
+            let name = format!("file{}", i);
+            queue.push(
+                TransferDirection::Upload,
+                &name,
+                PathBuf::from(&name),
+                format!("/remote/{}", name),
+                false,
+                false,
+            );
+        }
+        queue
+    }
+
+    fn states(queue: &TransferQueue) -> Vec<&'static str> {
+        queue.items.iter().map(|item| item.state.label()).collect()
+    }
+
+    #[test]
+    fn starts_up_to_the_limit() {
+        let mut queue = queue_of(4);
+        let started: Vec<_> = queue.start_next(2).iter().map(|item| item.id).collect();
+        assert_eq!(started, vec![1, 2]);
+        assert!(queue.start_next(2).is_empty());
+
+        queue.get_mut(1).unwrap().state = TransferState::Done;
+        let started: Vec<_> = queue.start_next(2).iter().map(|item| item.id).collect();
+        assert_eq!(started, vec![3]);
+        assert_eq!(states(&queue), vec!["done", "running", "running", "queued"]);
+
+        // A limit of 0 still runs one at a time
+        let mut queue = queue_of(2);
+        assert_eq!(queue.start_next(0).len(), 1);
+    }
+
+    #[test]
+    fn pauses_and_resumes() {
+        let mut queue = queue_of(3);
+        queue.start_next(1);
+
+        // Pausing the running one frees its slot
+        assert_eq!(queue.toggle_pause(1), Some(TransferState::Paused));
+        assert_eq!(queue.start_next(1).len(), 1);
+        assert_eq!(states(&queue), vec!["paused", "running", "queued"]);
+
+        // A paused queued transfer waits until resumed
+        assert_eq!(queue.toggle_pause(3), Some(TransferState::Paused));
+        queue.get_mut(2).unwrap().state = TransferState::Done;
+        assert!(queue.start_next(1).is_empty());
+        assert_eq!(queue.toggle_pause(3), Some(TransferState::Queued));
+
+        // The started one carries on where it was
+        assert_eq!(queue.toggle_pause(1), Some(TransferState::Running));
+        assert_eq!(queue.toggle_pause(2), None);
+    }
+
+    #[test]
+    fn cancels_and_retries() {
+        let mut queue = queue_of(2);
+        queue.start_next(1);
+        let control = queue.items[0].control.clone();
+
+        assert!(queue.cancel(1));
+        assert!(control.is_cancelled());
+        assert!(control.wait().is_err());
+        assert!(!queue.cancel(1));
+
+        assert!(queue.retry(1));
+        assert!(!queue.items[0].control.is_cancelled());
+        assert!(!queue.retry(2));
+        assert_eq!(states(&queue), vec!["queued", "queued"]);
+
+        queue.get_mut(2).unwrap().state = TransferState::Failed("boom".to_string());
+        queue.clear_finished();
+        assert_eq!(queue.items.len(), 1);
+        assert!(queue.is_active());
+    }
+
+    #[test]
+    fn measures_rate_and_time_without_pauses() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut stats = TransferStats::default();
+        assert_eq!(stats.elapsed(start), None);
+
+        stats.start(start);
+        stats.record(at(0), 0, 10_000);
+        assert_eq!(stats.rate(), None);
+        stats.record(at(1000), 1000, 10_000);
+        stats.record(at(2000), 2000, 10_000);
+        assert_eq!(stats.rate(), Some(1000.0));
+        assert_eq!(stats.eta(), Some(Duration::from_secs(8)));
+        assert_eq!(stats.elapsed(at(2000)), Some(Duration::from_secs(2)));
+
+        // A pause doesn't count, and the rate starts over after it
+        stats.stop(at(2000));
+        stats.start(at(60_000));
+        assert_eq!(stats.rate(), None);
+        stats.record(at(60_000), 2000, 10_000);
+        stats.record(at(61_000), 6000, 10_000);
+        assert_eq!(stats.rate(), Some(4000.0));
+        assert_eq!(stats.eta(), Some(Duration::from_secs(1)));
+        assert_eq!(stats.elapsed(at(61_000)), Some(Duration::from_secs(3)));
+
+        // Old samples drop out of the rate
+        stats.record(at(70_000), 6000, 10_000);
+        stats.record(at(71_000), 6000, 10_000);
+        assert_eq!(stats.rate(), Some(0.0));
+        assert_eq!(stats.eta(), None);
+
+        stats.stop(at(72_000));
+        assert_eq!(stats.elapsed(at(99_000)), Some(Duration::from_secs(14)));
+        assert_eq!(stats.average_rate(at(99_000)), Some(6000.0 / 14.0));
+    }
+
+    #[test]
+    fn controlled_io_stops_when_cancelled() {
+        let control = TransferControl::default();
+        let mut reader = Controlled::new(&b"data"[..], control.clone());
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+
+        control.cancel();
+        assert!(reader.read(&mut buf).is_err());
+        let mut writer = Controlled::new(Vec::new(), control);
+        assert!(writer.write_all(b"x").is_err());
+    }
+
+    #[test]
+    fn limits_the_rate_of_controlled_io() {
+        let mut queue = queue_of(0);
+        queue.default_limit = Some(400_000);
+        queue.push(TransferDirection::Upload, "a", "a".into(), "/a".into(), false, false);
+        queue.push(TransferDirection::Upload, "b", "b".into(), "/b".into(), false, false);
+        queue.set_throttle(Some(100_000));
+
+        // 30 KB of two transfers together at 100 KB/s take 0.3 seconds
+        let started = Instant::now();
+        let writers: Vec<_> = queue
+            .items
+            .iter()
+            .map(|item| {
+                let mut writer = Controlled::new(Vec::new(), item.control.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..3 {
+                        writer.write_all(&[0; 5_000]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(280));
+
+        // Retrying keeps the limits, lifting the throttle leaves the own one
+        queue.items[0].state = TransferState::Cancelled;
+        assert!(queue.retry(1));
+        queue.set_throttle(None);
+        assert_eq!(queue.throttle(), None);
+        assert_eq!(queue.items[0].control.limit(), Some(400_000));
+    }
+}
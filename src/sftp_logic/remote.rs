@@ -1,20 +1,27 @@
 use anyhow::{Context, Result};
-use std::process::Command;
-use super::types::{FileItem, AppSftpState};
+use super::session::{join_remote, remote_parent, SftpSession};
+use super::types::{FileItem, FileMeta, AppSftpState};
+use crate::config::RecentDirs;
 
 impl AppSftpState {
     /// Refresh the remote file list
     pub fn refresh_remote(&mut self) -> Result<()> {
         self.set_status_message("Loading remote directory...");
-        self.remote_files = Self::read_remote_directory(
-            &self.ssh_user, 
-            &self.ssh_host, 
-            self.ssh_port, 
-            &self.remote_current_path
-        )?;
-        if self.remote_selected >= self.remote_files.len() {
-            self.remote_selected = self.remote_files.len().saturating_sub(1);
-        }
+        let session = self.session.get();
+        let mut files = match Self::read_remote_directory(&session, &self.remote_current_path) {
+            Err(_) if session.is_closed() => {
+                let session = self
+                    .session
+                    .reconnect(&session)
+                    .context("Lost the SFTP connection and could not reconnect")?;
+                Self::read_remote_directory(&session, &self.remote_current_path)?
+            }
+            result => result?,
+        };
+        self.sort.sort(&mut files);
+        self.remote_all = files;
+        self.update_remote_view(false);
+        RecentDirs::visit(&mut self.recent.remote, &self.remote_current_path);
         self.clear_status_message();
         Ok(())
     }
@@ -43,32 +50,15 @@ impl AppSftpState {
     pub fn open_remote_selected(&mut self) -> Result<()> {
         if let Some(item) = self.remote_files.get(self.remote_selected) {
             match item {
-                FileItem::Directory { name } => {
-                    if name == ".." {
-                        // Go to parent directory
-                        if self.remote_current_path != "/" {
-                            let mut path_parts: Vec<&str> = self.remote_current_path
-                                .trim_end_matches('/')
-                                .split('/')
-                                .collect();
-                            if path_parts.len() > 1 {
-                                path_parts.pop();
-                                self.remote_current_path = if path_parts.len() == 1 {
-                                    "/".to_string()
-                                } else {
-                                    path_parts.join("/")
-                                };
-                            }
-                        }
+                FileItem::Directory { name, .. } => {
+                    self.remote_current_path = if name == ".." {
+                        remote_parent(&self.remote_current_path)
                     } else {
-                        // Enter directory
-                        self.remote_current_path = if self.remote_current_path.ends_with('/') {
-                            format!("{}{}", self.remote_current_path, name)
-                        } else {
-                            format!("{}/{}", self.remote_current_path, name)
-                        };
-                    }
+                        join_remote(&self.remote_current_path, name)
+                    };
                     self.remote_selected = 0;
+                    self.remote_marked.clear();
+                    self.remote_filter.clear();
                     self.remote_list_state.select(Some(self.remote_selected));
                     self.refresh_remote()?;
                 }
@@ -83,106 +73,137 @@ impl AppSftpState {
     /// Go up one directory in the remote file system
     pub fn go_remote_back(&mut self) -> Result<()> {
         if self.remote_current_path != "/" {
-            let mut path_parts: Vec<&str> = self.remote_current_path
-                .trim_end_matches('/')
-                .split('/')
-                .collect();
-            if path_parts.len() > 1 {
-                path_parts.pop();
-                self.remote_current_path = if path_parts.len() == 1 {
-                    "/".to_string()
-                } else {
-                    path_parts.join("/")
-                };
-                self.remote_selected = 0;
-                self.refresh_remote()?;
-            }
+            self.remote_current_path = remote_parent(&self.remote_current_path);
+            self.remote_selected = 0;
+            self.remote_marked.clear();
+            self.remote_filter.clear();
+            self.refresh_remote()?;
         }
         Ok(())
     }
     
     /// Read the contents of a remote directory
-    fn read_remote_directory(
-        user: &str,
-        host: &str,
-        port: u16,
-        remote_path: &str,
-    ) -> Result<Vec<FileItem>> {
-        let output = Command::new("ssh")
-            .arg(format!("{}@{}", user, host))
-            .arg("-p")
-            .arg(port.to_string())
-            .arg("-o")
-            .arg("ConnectTimeout=10")
-            .arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg("-o")
-            .arg("LogLevel=ERROR")
-            .arg(format!("ls -la '{}'", remote_path))
-            .output()
-            .context("Failed to execute remote ls command")?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Remote ls failed: {}", stderr));
-        }
-        
-        let stdout = String::from_utf8_lossy(&output.stdout);
+    fn read_remote_directory(session: &SftpSession, remote_path: &str) -> Result<Vec<FileItem>> {
+        let entries = session
+            .read_dir(remote_path)
+            .with_context(|| format!("Failed to list {}", remote_path))?;
         let mut items = Vec::new();
         
         // Add parent directory entry if not at root
         if remote_path != "/" {
-            items.push(FileItem::Directory {
-                name: "..".to_string(),
-            });
+            items.push(FileItem::parent());
         }
         
-        for line in stdout.lines().skip(1) { // Skip total line
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() < 9 {
-                continue;
-            }
-            
-            let permissions = parts[0];
-            let file_name = parts[8..].join(" ");
-            
-            // Skip . and .. entries (we handle .. manually)
-            if file_name == "." || file_name == ".." {
-                continue;
-            }
+        for entry in entries {
+            // Symlinks are shown as what they point to, broken ones as files
+            let path = join_remote(remote_path, &entry.name);
+            let mut link_target = None;
+            let attrs = if entry.attrs.is_symlink() {
+                link_target = session.readlink(&path).ok();
+                session.stat(&path).unwrap_or(entry.attrs)
+            } else {
+                entry.attrs
+            };
+            let (owner, group) = match owner_from_long_name(&entry.long_name) {
+                Some((owner, group)) => (Some(owner), Some(group)),
+                None => (
+                    attrs.uid.map(|uid| uid.to_string()),
+                    attrs.gid.map(|gid| gid.to_string()),
+                ),
+            };
+            let meta = FileMeta {
+                mtime: attrs.mtime.map(i64::from),
+                permissions: attrs.permissions.map(|mode| mode & 0o7777),
+                owner,
+                group,
+                link_target,
+            };
             
-            if permissions.starts_with('d') {
-                items.push(FileItem::Directory { name: file_name });
+            if attrs.is_dir() {
+                items.push(FileItem::Directory { name: entry.name, meta });
             } else {
-                let size = parts[4].parse::<u64>().unwrap_or(0);
                 items.push(FileItem::File {
-                    name: file_name,
-                    size,
+                    name: entry.name,
+                    size: attrs.size.unwrap_or(0),
+                    meta,
                 });
             }
         }
         
-        // Sort: directories first, then files, both alphabetically
-        items.sort_by(|a, b| {
-            use std::cmp::Ordering;
-            match (a, b) {
-                (FileItem::Directory { name: name_a }, FileItem::Directory { name: name_b }) => {
-                    if name_a == ".." {
-                        Ordering::Less
-                    } else if name_b == ".." {
-                        Ordering::Greater
-                    } else {
-                        name_a.cmp(name_b)
-                    }
-                }
-                (FileItem::Directory { .. }, FileItem::File { .. }) => Ordering::Less,
-                (FileItem::File { .. }, FileItem::Directory { .. }) => Ordering::Greater,
-                (FileItem::File { name: name_a, .. }, FileItem::File { name: name_b, .. }) => {
-                    name_a.cmp(name_b)
-                }
-            }
-        });
-        
         Ok(items)
     }
-}
\ No newline at end of file
+}
+
+/// Owner and group names from the `ls -l` style line servers such as
+/// OpenSSH send with each entry, e.g.
+/// `-rw-r--r--    1 deploy   www          1234 Jan  1 00:00 index.html`
+fn owner_from_long_name(long_name: &str) -> Option<(String, String)> {
+    let fields: Vec<&str> = long_name.split_whitespace().collect();
+    let is_mode = |field: &str| {
+        field.len() >= 10 && field.chars().skip(1).take(9).all(|c| "rwxsStTl-".contains(c))
+    };
+    if fields.len() < 9 || !is_mode(fields[0]) || fields[1].parse::<u64>().is_err() {
+        return None;
+    }
+    Some((fields[2].to_string(), fields[3].to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, scratch_dir};
+    use super::*;
+
+    #[test]
+    fn lists_remote_directory_through_the_session() {
+        let dir = scratch_dir("remote-panel");
+        std::fs::write(dir.join("b  file"), "12345").unwrap();
+        std::fs::create_dir(dir.join("a dir")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a dir"), dir.join("c link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("gone"), dir.join("d broken")).unwrap();
+
+        let (state, _rx) = browser(&dir, &dir);
+
+        let listed: Vec<(bool, &str)> = state
+            .remote_files
+            .iter()
+            .map(|item| (matches!(item, FileItem::Directory { .. }), item.name()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (true, ".."),
+                (true, "a dir"),
+                (true, "c link"),
+                (false, "b  file"),
+                (false, "d broken"),
+            ]
+        );
+        assert!(matches!(state.remote_files[3], FileItem::File { size: 5, .. }));
+
+        // The test server sends bare names as long names, so owners are ids
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(dir.join("b  file")).unwrap();
+        let meta = state.remote_files[3].meta();
+        assert_eq!(meta.mtime, Some(metadata.mtime()));
+        assert_eq!(meta.permissions, Some(metadata.mode() & 0o7777));
+        assert_eq!(meta.owner, Some(metadata.uid().to_string()));
+        assert_eq!(meta.link_target, None);
+        assert_eq!(
+            state.remote_files[2].meta().link_target,
+            Some(dir.join("a dir").to_string_lossy().into_owned())
+        );
+    }
+
+    #[test]
+    fn reads_owners_from_long_names() {
+        assert_eq!(
+            owner_from_long_name("-rw-r--r--    1 deploy   www   1234 Jan  1 00:00 index.html"),
+            Some(("deploy".to_string(), "www".to_string()))
+        );
+        assert_eq!(
+            owner_from_long_name("drwxr-sr-x+  12 0 0 4096 Mar 30  2024 my dir"),
+            Some(("0".to_string(), "0".to_string()))
+        );
+        assert_eq!(owner_from_long_name("index.html"), None);
+    }
+}
@@ -0,0 +1,1044 @@
+//! SFTP (version 3) client over the `sftp` subsystem of an ssh connection
+//!
+//! The session runs `ssh -s <host> sftp`, so the user's ssh config, keys,
+//! ProxyJump and known_hosts all apply, and speaks the SFTP protocol over the
+//! child's stdin/stdout. Requests can be sent from several threads at once: a
+//! reader thread hands every reply to the request that is waiting for it.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::models::SshHost;
+
+const SFTP_VERSION: u32 = 3;
+
+// Packet types
+const FXP_INIT: u8 = 1;
+const FXP_VERSION: u8 = 2;
+const FXP_OPEN: u8 = 3;
+const FXP_CLOSE: u8 = 4;
+const FXP_READ: u8 = 5;
+const FXP_WRITE: u8 = 6;
+const FXP_LSTAT: u8 = 7;
+const FXP_SETSTAT: u8 = 9;
+const FXP_OPENDIR: u8 = 11;
+const FXP_READDIR: u8 = 12;
+const FXP_REMOVE: u8 = 13;
+const FXP_MKDIR: u8 = 14;
+const FXP_RMDIR: u8 = 15;
+const FXP_REALPATH: u8 = 16;
+const FXP_STAT: u8 = 17;
+const FXP_RENAME: u8 = 18;
+const FXP_READLINK: u8 = 19;
+const FXP_STATUS: u8 = 101;
+const FXP_HANDLE: u8 = 102;
+const FXP_DATA: u8 = 103;
+const FXP_NAME: u8 = 104;
+const FXP_ATTRS: u8 = 105;
+const FXP_EXTENDED: u8 = 200;
+const FXP_EXTENDED_REPLY: u8 = 201;
+
+/// Rename that replaces an existing target, which plain SFTP v3 rename
+/// refuses to do
+const POSIX_RENAME: &str = "posix-rename@openssh.com";
+/// Hash of a file computed by the server, from the filexfer extensions draft
+const CHECK_FILE_NAME: &str = "check-file-name";
+
+// Status codes
+const FX_OK: u32 = 0;
+const FX_EOF: u32 = 1;
+const FX_NO_SUCH_FILE: u32 = 2;
+
+// Attribute flags
+const ATTR_SIZE: u32 = 0x1;
+const ATTR_UIDGID: u32 = 0x2;
+const ATTR_PERMISSIONS: u32 = 0x4;
+const ATTR_ACMODTIME: u32 = 0x8;
+const ATTR_EXTENDED: u32 = 0x8000_0000;
+
+/// Flags for `SftpSession::open`
+pub mod open_flags {
+    pub const READ: u32 = 0x01;
+    pub const WRITE: u32 = 0x02;
+    pub const CREATE: u32 = 0x08;
+    pub const TRUNCATE: u32 = 0x10;
+    /// With `CREATE`, fail when the file exists
+    pub const EXCL: u32 = 0x20;
+}
+
+/// Largest chunk read or written per request, what OpenSSH's sftp uses
+const CHUNK_SIZE: usize = 32 * 1024;
+/// Reads or writes in flight at once during a transfer
+const MAX_IN_FLIGHT: usize = 16;
+/// Lines of ssh's stderr kept to explain a failed connection
+const STDERR_TAIL_LINES: usize = 20;
+
+/// File attributes as reported by the server, every field is optional
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileAttributes {
+    pub size: Option<u64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub permissions: Option<u32>,
+    pub atime: Option<u32>,
+    pub mtime: Option<u32>,
+}
+
+impl FileAttributes {
+    const TYPE_MASK: u32 = 0o170000;
+
+    fn file_type(&self) -> Option<u32> {
+        self.permissions.map(|mode| mode & Self::TYPE_MASK)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Some(0o040000)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == Some(0o120000)
+    }
+
+    /// Regular files, and entries whose type the server didn't send
+    pub fn is_file(&self) -> bool {
+        matches!(self.file_type(), Some(0o100000) | None)
+    }
+
+    pub(super) fn encode(&self, out: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.size.is_some() {
+            flags |= ATTR_SIZE;
+        }
+        if self.uid.is_some() && self.gid.is_some() {
+            flags |= ATTR_UIDGID;
+        }
+        if self.permissions.is_some() {
+            flags |= ATTR_PERMISSIONS;
+        }
+        if self.atime.is_some() && self.mtime.is_some() {
+            flags |= ATTR_ACMODTIME;
+        }
+        put_u32(out, flags);
+        if let Some(size) = self.size {
+            put_u64(out, size);
+        }
+        if let (Some(uid), Some(gid)) = (self.uid, self.gid) {
+            put_u32(out, uid);
+            put_u32(out, gid);
+        }
+        if let Some(permissions) = self.permissions {
+            put_u32(out, permissions);
+        }
+        if let (Some(atime), Some(mtime)) = (self.atime, self.mtime) {
+            put_u32(out, atime);
+            put_u32(out, mtime);
+        }
+    }
+
+    pub(super) fn decode(reader: &mut PacketReader) -> Result<Self> {
+        let flags = reader.u32()?;
+        let mut attrs = FileAttributes::default();
+        if flags & ATTR_SIZE != 0 {
+            attrs.size = Some(reader.u64()?);
+        }
+        if flags & ATTR_UIDGID != 0 {
+            attrs.uid = Some(reader.u32()?);
+            attrs.gid = Some(reader.u32()?);
+        }
+        if flags & ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(reader.u32()?);
+        }
+        if flags & ATTR_ACMODTIME != 0 {
+            attrs.atime = Some(reader.u32()?);
+            attrs.mtime = Some(reader.u32()?);
+        }
+        if flags & ATTR_EXTENDED != 0 {
+            for _ in 0..reader.u32()? {
+                reader.bytes()?;
+                reader.bytes()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// One entry of a remote directory
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// `ls -l` style line of the entry, its format is up to the server
+    pub long_name: String,
+    pub attrs: FileAttributes,
+}
+
+/// Error status returned by the server for a request
+#[derive(Debug, Clone)]
+pub struct StatusError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match (self.message.is_empty(), self.code) {
+            (false, _) => self.message.as_str(),
+            (true, FX_EOF) => "End of file",
+            (true, FX_NO_SUCH_FILE) => "No such file",
+            (true, 3) => "Permission denied",
+            (true, 8) => "Operation unsupported",
+            (true, _) => "Failure",
+        };
+        write!(f, "{} (SFTP status {})", message, self.code)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Handle of an open remote file or directory
+#[derive(Debug)]
+pub struct Handle(Vec<u8>);
+
+struct Reply {
+    kind: u8,
+    body: Vec<u8>,
+}
+
+/// A request that was sent, waiting for its reply
+struct PendingReply(mpsc::Receiver<Reply>);
+
+impl PendingReply {
+    fn wait(self) -> Result<Reply> {
+        self.0
+            .recv()
+            .map_err(|_| anyhow::anyhow!("SFTP connection closed"))
+    }
+}
+
+type ReplySenders = Arc<Mutex<HashMap<u32, mpsc::Sender<Reply>>>>;
+
+pub struct SftpSession {
+    writer: Mutex<Box<dyn Write + Send>>,
+    waiting: ReplySenders,
+    /// Set by the reader thread once the connection is gone
+    closed: Arc<AtomicBool>,
+    next_id: AtomicU32,
+    child: Mutex<Option<Child>>,
+    /// Extensions the server announced, such as `posix-rename@openssh.com`
+    extensions: Vec<String>,
+}
+
+impl fmt::Debug for SftpSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SftpSession").finish_non_exhaustive()
+    }
+}
+
+impl SftpSession {
+    /// Start the `sftp` subsystem on `host` and do the version handshake,
+    /// with ssh compressing the connection when `compress` is set
+    pub fn connect(host: &SshHost, compress: bool) -> Result<Self> {
+        let mut child = Command::new("ssh")
+            .args(host.ssh_command_args())
+            .args(compress.then_some("-C"))
+            .arg("-o")
+            .arg("ConnectTimeout=10")
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg("-o")
+            .arg("LogLevel=ERROR")
+            .arg("-s")
+            .arg(host.destination())
+            .arg("sftp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to start ssh for the sftp subsystem")?;
+
+        let reader = child.stdout.take().context("Failed to get ssh stdout")?;
+        let writer = child.stdin.take().context("Failed to get ssh stdin")?;
+        let stderr = drain_stderr(child.stderr.take().context("Failed to get ssh stderr")?);
+
+        match Self::from_streams(reader, writer) {
+            Ok(session) => {
+                *session.child.lock().unwrap() = Some(child);
+                tracing::info!("SFTP session open to {}", host.destination());
+                Ok(session)
+            }
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let stderr = stderr.join().unwrap_or_default();
+                match stderr.trim() {
+                    "" => Err(e),
+                    stderr => Err(e.context(stderr.to_string())),
+                }
+            }
+        }
+    }
+
+    /// Speak SFTP over an already connected byte stream
+    pub fn from_streams<R, W>(mut reader: R, mut writer: W) -> Result<Self>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let mut init = Vec::new();
+        put_u32(&mut init, SFTP_VERSION);
+        write_packet(&mut writer, FXP_INIT, &init).context("Failed to start SFTP")?;
+
+        let (kind, body) = read_packet(&mut reader)
+            .context("Failed to start SFTP, is the sftp subsystem enabled on the server?")?;
+        if kind != FXP_VERSION {
+            bail!("Unexpected SFTP packet {} instead of version", kind);
+        }
+        let mut version_reader = PacketReader::new(&body);
+        let version = version_reader.u32()?;
+        if version < SFTP_VERSION {
+            bail!("Server speaks SFTP version {}, 3 is needed", version);
+        }
+        // Extensions follow as name and data pairs
+        let mut extensions = Vec::new();
+        while let (Ok(name), Ok(_)) = (version_reader.string(), version_reader.bytes()) {
+            extensions.push(name);
+        }
+
+        let waiting: ReplySenders = Arc::default();
+        let closed = Arc::new(AtomicBool::new(false));
+        let dispatch = Arc::clone(&waiting);
+        let reader_closed = Arc::clone(&closed);
+        thread::spawn(move || loop {
+            let (kind, body) = match read_packet(&mut reader) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    tracing::info!("SFTP reader stopped: {}", e);
+                    // Marked closed first so that requests sent from now on
+                    // fail right away, then dropping the senders fails every
+                    // request still waiting
+                    reader_closed.store(true, Ordering::SeqCst);
+                    dispatch.lock().unwrap().clear();
+                    return;
+                }
+            };
+            let mut body_reader = PacketReader::new(&body);
+            let Ok(id) = body_reader.u32() else {
+                tracing::warn!("SFTP packet {} without a request id", kind);
+                continue;
+            };
+            let body = body_reader.rest().to_vec();
+            match dispatch.lock().unwrap().remove(&id) {
+                Some(sender) => {
+                    let _ = sender.send(Reply { kind, body });
+                }
+                None => tracing::warn!("SFTP reply {} for unknown request {}", kind, id),
+            }
+        });
+
+        Ok(Self {
+            writer: Mutex::new(Box::new(writer)),
+            waiting,
+            closed,
+            next_id: AtomicU32::new(1),
+            child: Mutex::new(None),
+            extensions,
+        })
+    }
+
+    fn supports(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|name| name == extension)
+    }
+
+    /// Whether the connection is gone, so every request fails
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn send(&self, kind: u8, body: &[u8]) -> Result<PendingReply> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel();
+        self.waiting.lock().unwrap().insert(id, sender);
+        if self.is_closed() {
+            self.waiting.lock().unwrap().remove(&id);
+            bail!("SFTP connection closed");
+        }
+
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        put_u32(&mut packet, id);
+        packet.extend_from_slice(body);
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = write_packet(&mut *writer, kind, &packet) {
+            self.waiting.lock().unwrap().remove(&id);
+            return Err(e).context("SFTP connection closed");
+        }
+        Ok(PendingReply(receiver))
+    }
+
+    fn request(&self, kind: u8, body: &[u8]) -> Result<Reply> {
+        self.send(kind, body)?.wait()
+    }
+
+    /// Send a request made of a single path
+    fn path_request(&self, kind: u8, path: &str) -> Result<Reply> {
+        let mut body = Vec::new();
+        put_str(&mut body, path);
+        self.request(kind, &body)
+    }
+
+    /// Canonical absolute form of `path`, `.` gives the login directory
+    pub fn realpath(&self, path: &str) -> Result<String> {
+        let reply = self.path_request(FXP_REALPATH, path)?;
+        let mut names = expect_names(reply)?;
+        match names.pop() {
+            Some(entry) => Ok(entry.name),
+            None => bail!("Empty realpath reply for {}", path),
+        }
+    }
+
+    /// Where the symbolic link `path` points
+    pub fn readlink(&self, path: &str) -> Result<String> {
+        let reply = self.path_request(FXP_READLINK, path)?;
+        let mut names = expect_names(reply)?;
+        match names.pop() {
+            Some(entry) => Ok(entry.name),
+            None => bail!("Empty readlink reply for {}", path),
+        }
+    }
+
+    /// Attributes of `path`, following symlinks
+    pub fn stat(&self, path: &str) -> Result<FileAttributes> {
+        expect_attrs(self.path_request(FXP_STAT, path)?)
+    }
+
+    /// Attributes of `path` itself when it is a symlink
+    pub fn lstat(&self, path: &str) -> Result<FileAttributes> {
+        expect_attrs(self.path_request(FXP_LSTAT, path)?)
+    }
+
+    /// Change the attributes of `path` that are set in `attrs`. Owners need
+    /// both `uid` and `gid`.
+    pub fn setstat(&self, path: &str, attrs: &FileAttributes) -> Result<()> {
+        let mut body = Vec::new();
+        put_str(&mut body, path);
+        attrs.encode(&mut body);
+        expect_ok(self.request(FXP_SETSTAT, &body)?)
+    }
+
+    /// Entries of a directory, without `.` and `..`
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let handle = expect_handle(self.path_request(FXP_OPENDIR, path)?)?;
+        let mut entries = Vec::new();
+        let result = loop {
+            let mut body = Vec::new();
+            put_bytes(&mut body, &handle.0);
+            match self.request(FXP_READDIR, &body).and_then(expect_names) {
+                Ok(names) => entries.extend(names),
+                Err(e) if is_eof(&e) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.close(handle)?;
+        result?;
+
+        entries.retain(|entry| entry.name != "." && entry.name != "..");
+        Ok(entries)
+    }
+
+    pub fn open(&self, path: &str, flags: u32) -> Result<Handle> {
+        let mut body = Vec::new();
+        put_str(&mut body, path);
+        put_u32(&mut body, flags);
+        FileAttributes::default().encode(&mut body);
+        expect_handle(self.request(FXP_OPEN, &body)?)
+    }
+
+    pub fn close(&self, handle: Handle) -> Result<()> {
+        let mut body = Vec::new();
+        put_bytes(&mut body, &handle.0);
+        expect_ok(self.request(FXP_CLOSE, &body)?)
+    }
+
+    /// Read an open file from `offset` to its end into `output`, calling
+    /// `progress` with the total bytes written so far. Returns the offset the
+    /// file ended at.
+    pub fn read_to<W, F>(
+        &self,
+        handle: &Handle,
+        offset: u64,
+        output: &mut W,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        W: Write,
+        F: FnMut(u64),
+    {
+        let read_request = |offset: u64| {
+            let mut body = Vec::new();
+            put_bytes(&mut body, &handle.0);
+            put_u64(&mut body, offset);
+            put_u32(&mut body, CHUNK_SIZE as u32);
+            self.send(FXP_READ, &body)
+        };
+
+        // Reads are pipelined. A short read restarts the pipeline at the end
+        // of the data that arrived, replies to the older reads are dropped.
+        let mut written = offset;
+        let mut next_offset = offset;
+        let mut eof = false;
+        let mut in_flight = std::collections::VecDeque::new();
+        loop {
+            while !eof && in_flight.len() < MAX_IN_FLIGHT {
+                in_flight.push_back((next_offset, read_request(next_offset)?));
+                next_offset += CHUNK_SIZE as u64;
+            }
+            let Some((chunk_offset, pending)) = in_flight.pop_front() else {
+                break;
+            };
+            let reply = pending.wait()?;
+            if chunk_offset != written {
+                continue;
+            }
+            match reply.kind {
+                FXP_DATA => {
+                    let mut reader = PacketReader::new(&reply.body);
+                    let data = reader.bytes()?;
+                    // No data would ask for the same offset again forever
+                    if data.is_empty() {
+                        eof = true;
+                        continue;
+                    }
+                    output.write_all(data).context("Failed to write file")?;
+                    written += data.len() as u64;
+                    progress(written);
+                    if data.len() < CHUNK_SIZE {
+                        next_offset = written;
+                        // Still in flight replies now have stale offsets
+                    }
+                }
+                _ => match expect_ok(reply) {
+                    Err(e) if is_eof(&e) => eof = true,
+                    Err(e) => return Err(e),
+                    Ok(()) => bail!("Unexpected OK status for a read"),
+                },
+            }
+        }
+        output.flush().context("Failed to write file")?;
+        Ok(written)
+    }
+
+    /// Read up to `length` bytes of an open file at `offset`, fewer when the
+    /// file ends before
+    pub fn read_at(&self, handle: &Handle, offset: u64, length: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length {
+            let mut body = Vec::new();
+            put_bytes(&mut body, &handle.0);
+            put_u64(&mut body, offset + data.len() as u64);
+            put_u32(&mut body, (length - data.len()).min(CHUNK_SIZE) as u32);
+            let reply = self.request(FXP_READ, &body)?;
+            if reply.kind != FXP_DATA {
+                match expect_ok(reply) {
+                    Err(e) if is_eof(&e) => break,
+                    Err(e) => return Err(e),
+                    Ok(()) => bail!("Unexpected OK status for a read"),
+                }
+            }
+            let mut reader = PacketReader::new(&reply.body);
+            match reader.bytes()? {
+                [] => break,
+                chunk => data.extend_from_slice(chunk),
+            }
+        }
+        Ok(data)
+    }
+
+    /// Write all of `input` to an open file starting at `offset`, calling
+    /// `progress` with the bytes the server has confirmed so far. Returns the
+    /// offset after the last byte.
+    pub fn write_from<R, F>(
+        &self,
+        handle: &Handle,
+        offset: u64,
+        input: &mut R,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        R: Read,
+        F: FnMut(u64),
+    {
+        let mut sent = offset;
+        let mut confirmed = offset;
+        let mut done = false;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut in_flight = std::collections::VecDeque::new();
+        loop {
+            while !done && in_flight.len() < MAX_IN_FLIGHT {
+                let read = read_full(input, &mut buffer).context("Failed to read file")?;
+                if read == 0 {
+                    done = true;
+                    break;
+                }
+                let mut body = Vec::with_capacity(read + handle.0.len() + 16);
+                put_bytes(&mut body, &handle.0);
+                put_u64(&mut body, sent);
+                put_bytes(&mut body, &buffer[..read]);
+                in_flight.push_back((read as u64, self.send(FXP_WRITE, &body)?));
+                sent += read as u64;
+            }
+            let Some((length, pending)) = in_flight.pop_front() else {
+                break;
+            };
+            expect_ok(pending.wait()?)?;
+            confirmed += length;
+            progress(confirmed);
+        }
+        Ok(confirmed)
+    }
+
+    pub fn mkdir(&self, path: &str) -> Result<()> {
+        let mut body = Vec::new();
+        put_str(&mut body, path);
+        FileAttributes::default().encode(&mut body);
+        expect_ok(self.request(FXP_MKDIR, &body)?)
+    }
+
+    pub fn rmdir(&self, path: &str) -> Result<()> {
+        expect_ok(self.path_request(FXP_RMDIR, path)?)
+    }
+
+    pub fn remove(&self, path: &str) -> Result<()> {
+        expect_ok(self.path_request(FXP_REMOVE, path)?)
+    }
+
+    /// Rename `from` to `to`, failing when `to` exists
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut body = Vec::new();
+        put_str(&mut body, from);
+        put_str(&mut body, to);
+        expect_ok(self.request(FXP_RENAME, &body)?)
+    }
+
+    /// SHA-256 of the first `length` bytes of a file, or of all of it when
+    /// `length` is 0, as lowercase hex computed by the server. `None` when
+    /// the server doesn't support the `check-file-name` extension, which
+    /// OpenSSH's sftp-server doesn't.
+    pub fn sha256(&self, path: &str, length: u64) -> Result<Option<String>> {
+        if !self.supports(CHECK_FILE_NAME) {
+            return Ok(None);
+        }
+        let mut body = Vec::new();
+        put_str(&mut body, CHECK_FILE_NAME);
+        put_str(&mut body, path);
+        put_str(&mut body, "sha256");
+        // The range as one block
+        put_u64(&mut body, 0);
+        put_u64(&mut body, length);
+        put_u32(&mut body, 0);
+        let reply = expect_kind(self.request(FXP_EXTENDED, &body)?, FXP_EXTENDED_REPLY)?;
+        let mut reader = PacketReader::new(&reply);
+        // The draft starts the reply with "check-file", some servers leave
+        // it out
+        let mut algorithm = reader.string()?;
+        if algorithm == "check-file" {
+            algorithm = reader.string()?;
+        }
+        if algorithm != "sha256" {
+            bail!("Server hashed {} with {} instead of sha256", path, algorithm);
+        }
+        Ok(Some(
+            reader.rest().iter().map(|byte| format!("{:02x}", byte)).collect(),
+        ))
+    }
+
+    /// Rename `from` to `to`, replacing `to` if it exists. Atomic when the
+    /// server supports the OpenSSH posix-rename extension.
+    pub fn rename_over(&self, from: &str, to: &str) -> Result<()> {
+        if self.supports(POSIX_RENAME) {
+            let mut body = Vec::new();
+            put_str(&mut body, POSIX_RENAME);
+            put_str(&mut body, from);
+            put_str(&mut body, to);
+            return expect_ok(self.request(FXP_EXTENDED, &body)?);
+        }
+        match self.remove(to) {
+            Err(e) if !is_not_found(&e) => return Err(e),
+            _ => {}
+        }
+        self.rename(from, to)
+    }
+}
+
+impl Drop for SftpSession {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// The SFTP session of a browser, replaced by a new connection when the
+/// current one drops
+pub struct SharedSession {
+    current: Mutex<Arc<SftpSession>>,
+    connect: Box<dyn Fn() -> Result<SftpSession> + Send + Sync>,
+}
+
+impl SharedSession {
+    pub fn new<C>(session: SftpSession, connect: C) -> Self
+    where
+        C: Fn() -> Result<SftpSession> + Send + Sync + 'static,
+    {
+        Self {
+            current: Mutex::new(Arc::new(session)),
+            connect: Box::new(connect),
+        }
+    }
+
+    /// The current session
+    pub fn get(&self) -> Arc<SftpSession> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace `broken` by a new connection. Transfers that lost the same
+    /// connection share the first one made for it.
+    pub fn reconnect(&self, broken: &Arc<SftpSession>) -> Result<Arc<SftpSession>> {
+        let current = self.get();
+        if !Arc::ptr_eq(&current, broken) {
+            return Ok(current);
+        }
+        // Connecting can take a while, so it is done without the lock held
+        let session = Arc::new((self.connect)()?);
+        let mut current = self.current.lock().unwrap();
+        if Arc::ptr_eq(&current, broken) {
+            *current = session;
+        }
+        Ok(current.clone())
+    }
+}
+
+impl fmt::Debug for SharedSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSession")
+            .field("closed", &self.get().is_closed())
+            .finish()
+    }
+}
+
+/// Join a remote directory and a name
+pub fn join_remote(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Parent of a remote path, `/` for top level entries and `/` itself
+pub fn remote_parent(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => trimmed[..index].to_string(),
+    }
+}
+
+/// Log what ssh prints on stderr for as long as it runs, which keeps ssh
+/// from blocking on a full pipe, and return the last lines once it exits
+fn drain_stderr(stderr: ChildStderr) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+        for line in BufReader::new(stderr).split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+            tracing::debug!("ssh: {}", line);
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        Vec::from(tail).join("\n")
+    })
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<StatusError>()
+        .is_some_and(|status| status.code == FX_NO_SUCH_FILE)
+}
+
+fn is_eof(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<StatusError>()
+        .is_some_and(|status| status.code == FX_EOF)
+}
+
+fn status_of(reply: &Reply) -> Result<StatusError> {
+    let mut reader = PacketReader::new(&reply.body);
+    let code = reader.u32()?;
+    // The message is missing from some old servers
+    let message = reader.string().unwrap_or_default();
+    Ok(StatusError { code, message })
+}
+
+fn expect_ok(reply: Reply) -> Result<()> {
+    match reply.kind {
+        FXP_STATUS => match status_of(&reply)? {
+            status if status.code == FX_OK => Ok(()),
+            status => Err(status.into()),
+        },
+        kind => bail!("Unexpected SFTP packet {}", kind),
+    }
+}
+
+/// Turn a reply that should be `expected` into its body, or its error status
+fn expect_kind(reply: Reply, expected: u8) -> Result<Vec<u8>> {
+    if reply.kind == expected {
+        return Ok(reply.body);
+    }
+    expect_ok(reply)?;
+    bail!("Unexpected OK status instead of packet {}", expected)
+}
+
+fn expect_handle(reply: Reply) -> Result<Handle> {
+    let body = expect_kind(reply, FXP_HANDLE)?;
+    Ok(Handle(PacketReader::new(&body).bytes()?.to_vec()))
+}
+
+fn expect_attrs(reply: Reply) -> Result<FileAttributes> {
+    let body = expect_kind(reply, FXP_ATTRS)?;
+    FileAttributes::decode(&mut PacketReader::new(&body))
+}
+
+fn expect_names(reply: Reply) -> Result<Vec<DirEntry>> {
+    let body = expect_kind(reply, FXP_NAME)?;
+    let mut reader = PacketReader::new(&body);
+    let count = reader.u32()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let name = reader.string()?;
+        let long_name = String::from_utf8_lossy(reader.bytes()?).into_owned();
+        let attrs = FileAttributes::decode(&mut reader)?;
+        entries.push(DirEntry { name, long_name, attrs });
+    }
+    Ok(entries)
+}
+
+/// Fill `buffer` as far as `input` allows, returning 0 only at its end
+fn read_full<R: Read>(input: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+pub(super) fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(super) fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(super) fn put_bytes(out: &mut Vec<u8>, value: &[u8]) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value);
+}
+
+pub(super) fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_bytes(out, value.as_bytes());
+}
+
+pub(super) fn write_packet<W: Write + ?Sized>(writer: &mut W, kind: u8, body: &[u8]) -> Result<()> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    put_u32(&mut packet, body.len() as u32 + 1);
+    packet.push(kind);
+    packet.extend_from_slice(body);
+    writer.write_all(&packet)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(super) fn read_packet<R: Read + ?Sized>(reader: &mut R) -> Result<(u8, Vec<u8>)> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length == 0 || length > 4 * 1024 * 1024 {
+        bail!("Invalid SFTP packet length {}", length);
+    }
+    let mut packet = vec![0u8; length];
+    reader.read_exact(&mut packet)?;
+    let body = packet.split_off(1);
+    Ok((packet[0], body))
+}
+
+/// Reads the fields of an SFTP packet body in order
+pub(super) struct PacketReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.data.len() < length {
+            bail!("Truncated SFTP packet");
+        }
+        let (head, tail) = self.data.split_at(length);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub(super) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    pub(super) fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.bytes()?).into_owned())
+    }
+
+    pub(super) fn rest(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{self, scratch_dir};
+    use super::*;
+    use std::fs;
+
+    fn path_str(path: &std::path::Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn lists_awkward_names_and_symlinks() {
+        let dir = scratch_dir("list");
+        fs::write(dir.join("two  spaces.txt"), "a").unwrap();
+        fs::write(dir.join("it's here"), "bb").unwrap();
+        fs::create_dir(dir.join("sub dir")).unwrap();
+        std::os::unix::fs::symlink(dir.join("sub dir"), dir.join("link -> dir")).unwrap();
+
+        let session = test_server::connect();
+        let mut entries = session.read_dir(&path_str(&dir)).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["it's here", "link -> dir", "sub dir", "two  spaces.txt"]);
+
+        assert_eq!(entries[0].attrs.size, Some(2));
+        assert!(entries[1].attrs.is_symlink());
+        assert!(session.stat(&join_remote(&path_str(&dir), "link -> dir")).unwrap().is_dir());
+        assert!(entries[2].attrs.is_dir());
+    }
+
+    #[test]
+    fn round_trips_files_larger_than_the_pipeline() {
+        let dir = scratch_dir("transfer");
+        let data: Vec<u8> = (0..(CHUNK_SIZE * MAX_IN_FLIGHT * 2 + 123))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let remote = path_str(&dir.join("upload.bin"));
+
+        let session = test_server::connect();
+        let handle = session
+            .open(&remote, open_flags::WRITE | open_flags::CREATE | open_flags::TRUNCATE)
+            .unwrap();
+        let mut last = 0;
+        let end = session
+            .write_from(&handle, 0, &mut data.as_slice(), |done| last = done)
+            .unwrap();
+        session.close(handle).unwrap();
+        assert_eq!(end, data.len() as u64);
+        assert_eq!(last, data.len() as u64);
+        assert_eq!(fs::read(&remote).unwrap(), data);
+
+        let handle = session.open(&remote, open_flags::READ).unwrap();
+        let mut downloaded = Vec::new();
+        let end = session.read_to(&handle, 0, &mut downloaded, |_| {}).unwrap();
+        session.close(handle).unwrap();
+        assert_eq!(end, data.len() as u64);
+        assert_eq!(downloaded, data);
+    }
+
+    #[test]
+    fn empty_data_ends_a_read() {
+        let (client, mut server) = std::os::unix::net::UnixStream::pair().unwrap();
+        thread::spawn(move || -> Result<()> {
+            read_packet(&mut server)?;
+            let mut version = Vec::new();
+            put_u32(&mut version, SFTP_VERSION);
+            write_packet(&mut server, FXP_VERSION, &version)?;
+            loop {
+                let (_, body) = read_packet(&mut server)?;
+                let mut reply = Vec::new();
+                put_u32(&mut reply, PacketReader::new(&body).u32()?);
+                put_bytes(&mut reply, &[]);
+                write_packet(&mut server, FXP_DATA, &reply)?;
+            }
+        });
+
+        let session = SftpSession::from_streams(client.try_clone().unwrap(), client).unwrap();
+        let handle = Handle(b"file".to_vec());
+        let mut output = Vec::new();
+        assert_eq!(session.read_to(&handle, 7, &mut output, |_| {}).unwrap(), 7);
+        assert!(output.is_empty());
+        assert!(session.read_at(&handle, 0, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_operations_and_errors() {
+        let dir = scratch_dir("ops");
+        let base = path_str(&dir);
+        let session = test_server::connect();
+
+        session.mkdir(&join_remote(&base, "new")).unwrap();
+        fs::write(dir.join("a.txt"), "x").unwrap();
+        session
+            .rename(&join_remote(&base, "a.txt"), &join_remote(&base, "new/b.txt"))
+            .unwrap();
+        assert!(dir.join("new/b.txt").exists());
+
+        session.remove(&join_remote(&base, "new/b.txt")).unwrap();
+        session.rmdir(&join_remote(&base, "new")).unwrap();
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+
+        let error = session.stat(&join_remote(&base, "missing")).unwrap_err();
+        let status = error.downcast_ref::<StatusError>().unwrap();
+        assert_eq!(status.code, FX_NO_SUCH_FILE);
+        assert!(session.read_dir(&join_remote(&base, "missing")).is_err());
+    }
+
+    #[test]
+    fn remote_paths() {
+        assert_eq!(join_remote("/", "etc"), "/etc");
+        assert_eq!(join_remote("/home/me", "a b"), "/home/me/a b");
+        assert_eq!(remote_parent("/home/me/"), "/home");
+        assert_eq!(remote_parent("/home"), "/");
+        assert_eq!(remote_parent("/"), "/");
+    }
+}
@@ -1,46 +1,112 @@
-use super::types::{AppSftpState, PanelSide, UploadProgress, DownloadProgress};
+use super::listing::SortOrder;
+use super::places;
+use super::queue::TransferQueue;
+use super::session::{SftpSession, SharedSession};
+use super::types::{AppSftpState, PanelSide};
 use anyhow::{Context, Result};
 use ratatui::widgets::ListState;
 use tokio::sync::mpsc;
 use crate::app_event::TransferEvent;
+use crate::config::{RecentDirs, SftpConfig};
+use crate::models::SshHost;
+use std::collections::BTreeSet;
+use std::sync::Arc;
 
 impl AppSftpState {
-    /// Create a new instance of AppSftpState
+    /// Connect to `host` and create the state for its SFTP browser
     pub fn new(
-        ssh_user: &str,
-        ssh_host: &str,
-        ssh_port: u16,
+        host: &SshHost,
+        config: SftpConfig,
         transfer_tx: mpsc::Sender<TransferEvent>,
     ) -> Result<Self> {
+        let compress = host.compress_transfers.unwrap_or(config.compression);
+        let session = SftpSession::connect(host, compress)?;
+        let reconnect_host = host.clone();
+        let session = SharedSession::new(session, move || {
+            SftpSession::connect(&reconnect_host, compress)
+        });
+        Self::with_session(host, Arc::new(session), config, transfer_tx)
+    }
+
+    /// Create the state on top of an open SFTP session, starting the remote
+    /// panel in the login directory
+    pub fn with_session(
+        host: &SshHost,
+        session: Arc<SharedSession>,
+        config: SftpConfig,
+        transfer_tx: mpsc::Sender<TransferEvent>,
+    ) -> Result<Self> {
+        let remote_home = session
+            .get()
+            .realpath(".")
+            .context("Failed to resolve the remote home directory")?;
         let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+        let (local_start, remote_start, problem) =
+            places::start_directories(host, &session.get(), current_dir, remote_home);
+
+        let default_limit = host
+            .bandwidth_limit_kb
+            .or(config.bandwidth_limit_kb)
+            .map(|kb| kb * 1024);
 
         let mut state = Self {
             active_panel: PanelSide::Local,
             // LOCAL
-            local_current_path: current_dir,
+            local_current_path: local_start,
+            local_all: Vec::new(),
             local_files: Vec::new(),
             local_selected: 0,
             local_list_state: ListState::default(),
+            local_marked: BTreeSet::new(),
+            local_filter: String::new(),
 
             // REMOTE
-            remote_current_path: "/".to_string(),
+            remote_current_path: remote_start,
+            remote_all: Vec::new(),
             remote_files: Vec::new(),
             remote_selected: 0,
             remote_list_state: ListState::default(),
+            remote_marked: BTreeSet::new(),
+            remote_filter: String::new(),
 
-            ssh_host: ssh_host.to_string(),
-            ssh_user: ssh_user.to_string(),
-            ssh_port,
+            host: host.clone(),
+            session,
+            verify: host.verify_transfers.unwrap_or(config.verify),
+            mismatched: Vec::new(),
+            sort: SortOrder::from_config(&config),
+            details: config.details,
+            show_hidden: config.show_hidden,
+            filtering: false,
+            jumping: false,
+            page_size: 10,
+            show_preview: false,
+            preview: None,
+            preview_pending: None,
+            preview_loading: None,
+            recent: RecentDirs::default(),
+            places: None,
+            config,
             status_message: None,
+            panel_areas: Vec::new(),
+            drag: None,
             status_message_time: None,
-            upload_progress: None,
-            download_progress: None,
+            queue: TransferQueue::with_limit(default_limit),
+            conflicts: None,
+            transfer_summary: None,
+            prompt: None,
+            pending_edit: None,
+            sync: None,
+            history_file: None,
+            history: None,
             transfer_tx: Some(transfer_tx),
         };
 
         // Load initial directory contents
         state.refresh_local()?;
         state.refresh_remote()?;
+        if let Some(problem) = problem {
+            state.set_status_message(&problem);
+        }
 
         Ok(state)
     }
@@ -58,11 +124,13 @@ impl AppSftpState {
         self.status_message_time = None;
     }
 
-    /// Switch the active panel between local and remote
+    /// Switch the active panel between local, remote and the transfer
+    /// queue when it has anything in it
     pub fn switch_panel(&mut self) {
         self.active_panel = match self.active_panel {
             PanelSide::Local => PanelSide::Remote,
-            PanelSide::Remote => PanelSide::Local,
+            PanelSide::Remote if !self.queue.items.is_empty() => PanelSide::Queue,
+            PanelSide::Remote | PanelSide::Queue => PanelSide::Local,
         };
     }
 
@@ -74,6 +142,9 @@ impl AppSftpState {
             PanelSide::Remote => {
                 self.navigate_remote_up();
             }
+            PanelSide::Queue => {
+                self.queue.navigate_up();
+            }
         };
     }
 
@@ -85,6 +156,9 @@ impl AppSftpState {
             PanelSide::Remote => {
                 self.navigate_remote_down();
             }
+            PanelSide::Queue => {
+                self.queue.navigate_down();
+            }
         };
     }
 
@@ -96,8 +170,34 @@ impl AppSftpState {
             PanelSide::Remote => {
                 let _ = self.open_remote_selected();
             }
+            PanelSide::Queue => self.show_selected_transfer(),
         };
         Ok(())
     }
 
+    /// Go up one directory in the active panel
+    pub fn go_back(&mut self) -> Result<()> {
+        match self.active_panel {
+            PanelSide::Local => self.go_local_back(),
+            PanelSide::Remote => self.go_remote_back(),
+            PanelSide::Queue => Ok(()),
+        }
+    }
+
+    /// Mark or unmark the selected entry of a file panel for a transfer and
+    /// move to the next one
+    pub fn toggle_mark(&mut self) {
+        let (files, selected, marked) = match self.active_panel {
+            PanelSide::Local => (&self.local_files, self.local_selected, &mut self.local_marked),
+            PanelSide::Remote => (&self.remote_files, self.remote_selected, &mut self.remote_marked),
+            PanelSide::Queue => return,
+        };
+        if let Some(item) = files.get(selected) {
+            let name = item.name().to_string();
+            if name != ".." && !marked.remove(&name) {
+                marked.insert(name);
+            }
+        }
+        self.navigate_down();
+    }
 }
@@ -0,0 +1,701 @@
+//! Synchronize the local and the remote directory
+//!
+//! Both current directories are scanned recursively and their files compared
+//! by size and modification time, or by SHA-256 when asked. The differences
+//! can then be applied in one direction, mirroring that side, or entry by
+//! entry towards whichever side is older. A plan of every step is shown and
+//! confirmed before anything changes. The comparison runs on a worker
+//! thread and copies go through the transfer queue.
+
+use anyhow::Result;
+use ratatui::widgets::ListState;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+use super::checksum::{sha256_local, sha256_remote};
+use super::file_ops::{remove_local, remove_remote};
+use super::queue::TransferDirection;
+use super::session::{join_remote, SftpSession};
+use super::tree::{self, TreePlan};
+use super::types::AppSftpState;
+use crate::app_event::TransferEvent;
+use crate::config::SymlinkMode;
+use crate::models::SshHost;
+
+/// How an entry differs between the two directories
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncChange {
+    LocalOnly,
+    RemoteOnly,
+    /// In both, modified later on the local side
+    LocalNewer,
+    RemoteNewer,
+    /// In both with the same modification time but different contents
+    Differs,
+    /// A file on one side and a directory on the other
+    TypeConflict,
+}
+
+impl SyncChange {
+    pub fn label(self) -> &'static str {
+        match self {
+            SyncChange::LocalOnly => "local only",
+            SyncChange::RemoteOnly => "remote only",
+            SyncChange::LocalNewer => "local newer",
+            SyncChange::RemoteNewer => "remote newer",
+            SyncChange::Differs => "differs",
+            SyncChange::TypeConflict => "file vs dir",
+        }
+    }
+}
+
+/// An entry as found on one side
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncSide {
+    pub is_dir: bool,
+    /// Size of a file, or of all files in a directory
+    pub size: u64,
+    /// Modification time of a file in seconds since the epoch
+    pub mtime: Option<i64>,
+}
+
+/// A difference between the two directories. Directories found on one side
+/// only are a single entry, without the files in them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncEntry {
+    /// Path relative to both directories, with `/` as separator
+    pub path: String,
+    pub change: SyncChange,
+    pub local: Option<SyncSide>,
+    pub remote: Option<SyncSide>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncDirection {
+    /// Make the remote directory a copy of the local one
+    Upload,
+    /// Make the local directory a copy of the remote one
+    Download,
+    /// Copy the marked entries to the side that is missing them or older
+    Marked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncAction {
+    Upload,
+    Download,
+    DeleteLocal,
+    DeleteRemote,
+}
+
+/// One thing applying a plan does
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncStep {
+    pub action: SyncAction,
+    pub path: String,
+    pub is_dir: bool,
+    /// Bytes copied or deleted
+    pub bytes: u64,
+    /// Whether a copy replaces a file modified later than its source
+    pub replaces_newer: bool,
+}
+
+/// What applying a direction will do, shown before anything changes
+#[derive(Debug, Clone)]
+pub struct SyncPlan {
+    pub direction: SyncDirection,
+    /// Deletes come first, so copies can take the place of what they delete
+    pub steps: Vec<SyncStep>,
+    /// Entries left alone, with the reason
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+impl SyncPlan {
+    /// Number of steps doing `action` and the bytes they copy or delete
+    pub fn total(&self, action: SyncAction) -> (usize, u64) {
+        self.steps
+            .iter()
+            .filter(|step| step.action == action)
+            .fold((0, 0), |(count, bytes), step| (count + 1, bytes + step.bytes))
+    }
+}
+
+/// Differences between the current directories, open until applied or
+/// closed
+#[derive(Debug, Clone)]
+pub struct SyncView {
+    pub local_root: PathBuf,
+    pub remote_root: String,
+    pub entries: Vec<SyncEntry>,
+    pub selected: usize,
+    pub list_state: ListState,
+    /// Paths marked for a sync of the marked entries
+    pub marked: BTreeSet<String>,
+    /// Whether files were compared by SHA-256 rather than modification time
+    pub checksum: bool,
+    /// Whether mirroring deletes what the mirrored side doesn't have
+    pub delete: bool,
+    /// Entries the scans left out, like special files or skipped links
+    pub skipped: usize,
+    /// Plan waiting for confirmation
+    pub plan: Option<SyncPlan>,
+}
+
+impl SyncView {
+    pub fn navigate_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn navigate_down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Mark or unmark the selected entry and move to the next one
+    pub fn toggle_mark(&mut self) {
+        if let Some(entry) = self.entries.get(self.selected) {
+            if !self.marked.remove(&entry.path) {
+                self.marked.insert(entry.path.clone());
+            }
+        }
+        self.navigate_down();
+    }
+
+    pub fn toggle_delete(&mut self) {
+        self.delete = !self.delete;
+    }
+
+    /// The steps to take `direction`. Marked syncs cover the marked
+    /// entries, or the selected one when nothing is marked.
+    pub fn plan(&self, direction: SyncDirection) -> SyncPlan {
+        let mut plan = SyncPlan {
+            direction,
+            steps: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut copies = Vec::new();
+        let entries: Vec<&SyncEntry> = match direction {
+            SyncDirection::Marked if self.marked.is_empty() => {
+                self.entries.get(self.selected).into_iter().collect()
+            }
+            SyncDirection::Marked => self
+                .entries
+                .iter()
+                .filter(|entry| self.marked.contains(&entry.path))
+                .collect(),
+            _ => self.entries.iter().collect(),
+        };
+
+        for entry in entries {
+            let copy = |action, side: &SyncSide, replaces_newer| SyncStep {
+                action,
+                path: entry.path.clone(),
+                is_dir: side.is_dir,
+                bytes: side.size,
+                replaces_newer,
+            };
+            let (local, remote) = (entry.local.as_ref(), entry.remote.as_ref());
+            match (direction, entry.change, local, remote) {
+                // Mirrors copy whatever differs from the mirrored side
+                (SyncDirection::Upload, SyncChange::TypeConflict, Some(source), Some(target))
+                | (
+                    SyncDirection::Download,
+                    SyncChange::TypeConflict,
+                    Some(target),
+                    Some(source),
+                ) => {
+                    if self.delete {
+                        let (copy_action, delete_action) = match direction {
+                            SyncDirection::Upload => {
+                                (SyncAction::Upload, SyncAction::DeleteRemote)
+                            }
+                            _ => (SyncAction::Download, SyncAction::DeleteLocal),
+                        };
+                        plan.steps.push(copy(delete_action, target, false));
+                        copies.push(copy(copy_action, source, false));
+                    } else {
+                        let reason = "file and directory, deleting is turned off";
+                        plan.skipped.push((entry.path.clone(), reason));
+                    }
+                }
+                (SyncDirection::Upload, _, Some(l), _) => copies.push(copy(
+                    SyncAction::Upload,
+                    l,
+                    entry.change == SyncChange::RemoteNewer,
+                )),
+                (SyncDirection::Download, _, _, Some(r)) => copies.push(copy(
+                    SyncAction::Download,
+                    r,
+                    entry.change == SyncChange::LocalNewer,
+                )),
+                (SyncDirection::Upload, _, None, Some(r)) if self.delete => {
+                    plan.steps.push(copy(SyncAction::DeleteRemote, r, false))
+                }
+                (SyncDirection::Download, _, Some(l), None) if self.delete => {
+                    plan.steps.push(copy(SyncAction::DeleteLocal, l, false))
+                }
+                (SyncDirection::Upload | SyncDirection::Download, _, _, _) => {
+                    plan.skipped.push((entry.path.clone(), "deleting is turned off"))
+                }
+                // Marked entries go towards the older or missing side
+                (
+                    SyncDirection::Marked,
+                    SyncChange::LocalOnly | SyncChange::LocalNewer,
+                    Some(l),
+                    _,
+                ) => copies.push(copy(SyncAction::Upload, l, false)),
+                (
+                    SyncDirection::Marked,
+                    SyncChange::RemoteOnly | SyncChange::RemoteNewer,
+                    _,
+                    Some(r),
+                ) => copies.push(copy(SyncAction::Download, r, false)),
+                (SyncDirection::Marked, SyncChange::TypeConflict, _, _) => plan
+                    .skipped
+                    .push((entry.path.clone(), "a file on one side, a directory on the other")),
+                (SyncDirection::Marked, _, _, _) => plan
+                    .skipped
+                    .push((entry.path.clone(), "no side is newer, use a mirror direction")),
+            }
+        }
+        plan.steps.extend(copies);
+        plan
+    }
+}
+
+impl AppSftpState {
+    /// Compare the current local and remote directories on a worker thread,
+    /// the differences come back as a `TransferEvent::Compared`
+    pub fn start_sync(&mut self, checksum: bool) {
+        let Some(tx) = self.transfer_tx.clone() else {
+            return;
+        };
+        let delete = self.sync.as_ref().is_some_and(|view| view.delete);
+        self.sync = None;
+        self.set_status_message("Comparing directories...");
+        let session = self.session.clone();
+        let host = self.host.clone();
+        let local_root = self.local_current_path.clone();
+        let remote_root = self.remote_current_path.clone();
+        let symlinks = self.config.symlinks;
+        tokio::task::spawn_blocking(move || {
+            let session = session.get();
+            let result =
+                compare_directories(&session, &host, local_root, remote_root, symlinks, checksum)
+                    .map(|view| SyncView { delete, ..view })
+                    .map_err(|e| format!("{:#}", e));
+            let _ = tx.blocking_send(TransferEvent::Compared(Box::new(result)));
+        });
+    }
+
+    /// Open the differences found by `start_sync`, or say that there are
+    /// none
+    pub fn apply_comparison(&mut self, result: Result<SyncView, String>) {
+        match result {
+            Ok(view) if view.entries.is_empty() => {
+                let mut message = "Local and remote directories are in sync".to_string();
+                if view.skipped > 0 {
+                    message.push_str(&format!(", {} entries skipped", view.skipped));
+                }
+                self.set_status_message(&message);
+            }
+            Ok(view) => {
+                self.status_message = None;
+                self.sync = Some(view);
+            }
+            Err(e) => {
+                tracing::error!("Failed to compare directories: {}", e);
+                self.set_status_message(&format!("Compare failed: {}", e));
+            }
+        }
+    }
+
+    /// Work out the steps of `direction` and show them for confirmation
+    pub fn plan_sync(&mut self, direction: SyncDirection) {
+        let Some(view) = &mut self.sync else {
+            return;
+        };
+        let plan = view.plan(direction);
+        if plan.steps.is_empty() && plan.skipped.is_empty() {
+            self.set_status_message("Nothing to synchronize");
+            return;
+        }
+        view.plan = Some(plan);
+    }
+
+    /// Run the confirmed plan: delete first, then queue the copies. Copies
+    /// whose delete failed are left out.
+    pub fn apply_sync(&mut self) {
+        let Some(view) = self.sync.take() else {
+            return;
+        };
+        let Some(plan) = view.plan else {
+            return;
+        };
+        let session = self.session.get();
+        let mut deleted = 0;
+        let mut failed = Vec::new();
+        for step in &plan.steps {
+            let result = match step.action {
+                SyncAction::DeleteLocal => remove_local(&view.local_root.join(&step.path)),
+                SyncAction::DeleteRemote => {
+                    let path = join_remote(&view.remote_root, &step.path);
+                    session
+                        .lstat(&path)
+                        .and_then(|attrs| remove_remote(&session, &path, &attrs))
+                }
+                SyncAction::Upload | SyncAction::Download => continue,
+            };
+            match result {
+                Ok(()) => deleted += 1,
+                Err(e) => {
+                    tracing::error!("Failed to delete {}: {:#}", step.path, e);
+                    failed.push((step.path.clone(), format!("{:#}", e)));
+                }
+            }
+        }
+
+        let mut queued = 0;
+        for step in &plan.steps {
+            let direction = match step.action {
+                SyncAction::Upload => TransferDirection::Upload,
+                SyncAction::Download => TransferDirection::Download,
+                SyncAction::DeleteLocal | SyncAction::DeleteRemote => continue,
+            };
+            if failed.iter().any(|(path, _)| *path == step.path) {
+                continue;
+            }
+            self.queue.push(
+                direction,
+                &step.path,
+                view.local_root.join(&step.path),
+                join_remote(&view.remote_root, &step.path),
+                step.is_dir,
+                self.verify,
+            );
+            queued += 1;
+        }
+
+        if deleted > 0 {
+            let _ = self.refresh_local();
+            let _ = self.refresh_remote();
+        }
+        let mut message = format!("Sync queued {} transfers, deleted {} entries", queued, deleted);
+        if let Some((path, error)) = failed.first() {
+            message.push_str(&format!(". Failed to delete {}: {}", path, error));
+            if failed.len() > 1 {
+                message.push_str(&format!(" and {} more", failed.len() - 1));
+            }
+        }
+        self.set_status_message(&message);
+        self.start_transfers();
+    }
+}
+
+/// Entries of a scan by path, directories with the size of their files
+/// Scan both directories and list their differences
+fn compare_directories(
+    session: &SftpSession,
+    host: &SshHost,
+    local_root: PathBuf,
+    remote_root: String,
+    symlinks: SymlinkMode,
+    checksum: bool,
+) -> Result<SyncView> {
+    let local = tree::scan_local(&local_root, symlinks)?;
+    let remote = tree::scan_remote(session, &remote_root, symlinks)?;
+
+    let entries = compare(&local, &remote, |path, l, r| {
+        if l.size != r.size {
+            return false;
+        }
+        if !checksum {
+            return l.mtime.is_none() || r.mtime.is_none() || l.mtime == r.mtime;
+        }
+        let remote_path = join_remote(&remote_root, path);
+        let hashes = sha256_local(&local_root.join(path))
+            .and_then(|local| Ok((local, sha256_remote(session, host, &remote_path)?)));
+        match hashes {
+            Ok((local, remote)) => local == remote,
+            Err(e) => {
+                tracing::warn!("Failed to hash {}: {:#}", path, e);
+                false
+            }
+        }
+    });
+    Ok(SyncView {
+        local_root,
+        remote_root,
+        entries,
+        selected: 0,
+        list_state: ListState::default(),
+        marked: BTreeSet::new(),
+        checksum,
+        delete: false,
+        skipped: local.skipped.len() + remote.skipped.len(),
+        plan: None,
+    })
+}
+
+fn sides(plan: &TreePlan) -> HashMap<&str, SyncSide> {
+    let mut sides = HashMap::new();
+    for dir in &plan.dirs {
+        let side = SyncSide {
+            is_dir: true,
+            size: 0,
+            mtime: None,
+        };
+        sides.insert(dir.as_str(), side);
+    }
+    for (path, size) in &plan.files {
+        let side = SyncSide {
+            is_dir: false,
+            size: *size,
+            mtime: plan.mtimes.get(path).copied(),
+        };
+        sides.insert(path.as_str(), side);
+        let mut parent = path.as_str();
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            if let Some(side) = sides.get_mut(dir) {
+                side.size += size;
+            }
+            parent = dir;
+        }
+    }
+    sides
+}
+
+/// Differences between two scans in path order. Files in both are the same
+/// when `same_file` says so.
+fn compare<F>(local: &TreePlan, remote: &TreePlan, mut same_file: F) -> Vec<SyncEntry>
+where
+    F: FnMut(&str, &SyncSide, &SyncSide) -> bool,
+{
+    let local_sides = sides(local);
+    let remote_sides = sides(remote);
+    let paths: BTreeSet<&str> = local_sides.keys().chain(remote_sides.keys()).copied().collect();
+
+    // Directories listed as a whole, their contents are left out
+    let mut whole: HashSet<&str> = HashSet::new();
+    let mut entries = Vec::new();
+    for path in paths {
+        let mut parent = path;
+        let mut inside_whole = false;
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            inside_whole |= whole.contains(dir);
+            parent = dir;
+        }
+        if inside_whole {
+            continue;
+        }
+
+        let local = local_sides.get(path).copied();
+        let remote = remote_sides.get(path).copied();
+        let change = match (&local, &remote) {
+            (Some(_), None) => SyncChange::LocalOnly,
+            (None, Some(_)) => SyncChange::RemoteOnly,
+            (Some(l), Some(r)) if l.is_dir != r.is_dir => SyncChange::TypeConflict,
+            (Some(l), Some(_)) if l.is_dir => continue,
+            (Some(l), Some(r)) if same_file(path, l, r) => continue,
+            (Some(l), Some(r)) => match (l.mtime, r.mtime) {
+                (Some(a), Some(b)) if a > b => SyncChange::LocalNewer,
+                (Some(a), Some(b)) if a < b => SyncChange::RemoteNewer,
+                _ => SyncChange::Differs,
+            },
+            (None, None) => continue,
+        };
+        if local.is_some_and(|side| side.is_dir) || remote.is_some_and(|side| side.is_dir) {
+            whole.insert(path);
+        }
+        entries.push(SyncEntry {
+            path: path.to_string(),
+            change,
+            local,
+            remote,
+        });
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_server::{browser, next_event, scratch_dir, settle};
+    use super::*;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio::sync::mpsc;
+
+    fn scan(files: &[(&str, u64, i64)], dirs: &[&str]) -> TreePlan {
+        TreePlan {
+            dirs: dirs.iter().map(|dir| dir.to_string()).collect(),
+            files: files.iter().map(|(path, size, _)| (path.to_string(), *size)).collect(),
+            mtimes: files.iter().map(|(path, _, mtime)| (path.to_string(), *mtime)).collect(),
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compares_scans_and_plans_each_direction() {
+        let local = scan(
+            &[("same", 1, 10), ("newer", 2, 20), ("older", 3, 10), ("new/a", 4, 10)],
+            &["new", "conflict"],
+        );
+        let remote = scan(
+            &[
+                ("same", 1, 10),
+                ("newer", 2, 10),
+                ("older", 3, 20),
+                ("gone", 5, 10),
+                ("conflict", 6, 10),
+            ],
+            &[],
+        );
+        let entries = compare(&local, &remote, |_, l, r| l.size == r.size && l.mtime == r.mtime);
+        let changes: Vec<_> = entries.iter().map(|e| (e.path.as_str(), e.change)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("conflict", SyncChange::TypeConflict),
+                ("gone", SyncChange::RemoteOnly),
+                ("new", SyncChange::LocalOnly),
+                ("newer", SyncChange::LocalNewer),
+                ("older", SyncChange::RemoteNewer),
+            ]
+        );
+        assert_eq!(entries[2].local.unwrap().size, 4);
+
+        let mut view = SyncView {
+            local_root: PathBuf::new(),
+            remote_root: String::new(),
+            entries,
+            selected: 0,
+            list_state: ListState::default(),
+            marked: BTreeSet::new(),
+            checksum: false,
+            delete: false,
+            skipped: 0,
+            plan: None,
+        };
+        let steps = |plan: &SyncPlan| {
+            plan.steps
+                .iter()
+                .map(|step| (step.action, step.path.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let plan = view.plan(SyncDirection::Upload);
+        assert_eq!(
+            steps(&plan),
+            vec![
+                (SyncAction::Upload, "new".to_string()),
+                (SyncAction::Upload, "newer".to_string()),
+                (SyncAction::Upload, "older".to_string()),
+            ]
+        );
+        assert!(plan.steps[2].replaces_newer);
+        assert_eq!(plan.skipped.len(), 2);
+
+        view.toggle_delete();
+        let plan = view.plan(SyncDirection::Download);
+        assert_eq!(
+            steps(&plan),
+            vec![
+                (SyncAction::DeleteLocal, "conflict".to_string()),
+                (SyncAction::DeleteLocal, "new".to_string()),
+                (SyncAction::Download, "conflict".to_string()),
+                (SyncAction::Download, "gone".to_string()),
+                (SyncAction::Download, "newer".to_string()),
+                (SyncAction::Download, "older".to_string()),
+            ]
+        );
+
+        // Marked entries go both ways and never delete
+        view.marked.extend(["gone", "new", "newer", "older", "conflict"].map(String::from));
+        let plan = view.plan(SyncDirection::Marked);
+        assert_eq!(
+            steps(&plan),
+            vec![
+                (SyncAction::Download, "gone".to_string()),
+                (SyncAction::Upload, "new".to_string()),
+                (SyncAction::Upload, "newer".to_string()),
+                (SyncAction::Download, "older".to_string()),
+            ]
+        );
+        assert_eq!(plan.skipped.len(), 1);
+    }
+
+    /// Compare the directories and apply the result once it comes back
+    async fn sync(
+        state: &mut AppSftpState,
+        rx: &mut mpsc::Receiver<TransferEvent>,
+        checksum: bool,
+    ) {
+        state.start_sync(checksum);
+        let event = next_event(rx).await;
+        state.apply_transfer_event(event);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mirrors_the_local_directory_to_the_server() {
+        let dir = scratch_dir("sync");
+        let (local, remote) = (dir.join("local"), dir.join("remote"));
+        std::fs::create_dir_all(local.join("docs")).unwrap();
+        std::fs::create_dir_all(remote.join("old")).unwrap();
+        std::fs::write(local.join("docs/readme"), "hello\n").unwrap();
+        std::fs::write(local.join("app.conf"), "port = 80\n").unwrap();
+        std::fs::write(remote.join("app.conf"), "port = 81\n").unwrap();
+        std::fs::write(remote.join("old/notes"), "bye\n").unwrap();
+        // Same size and time, only a checksum tells them apart
+        for (side, text) in [(&local, "aaaa"), (&remote, "bbbb")] {
+            std::fs::write(side.join("same"), text).unwrap();
+            let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            std::fs::File::options()
+                .write(true)
+                .open(side.join("same"))
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        }
+        let yesterday = SystemTime::now() - Duration::from_secs(86_400);
+        std::fs::File::options()
+            .write(true)
+            .open(remote.join("app.conf"))
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+
+        let (mut state, mut rx) = browser(&local, &remote);
+
+        sync(&mut state, &mut rx, false).await;
+        let view = state.sync.as_ref().unwrap();
+        let changes: Vec<_> = view.entries.iter().map(|e| (e.path.as_str(), e.change)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("app.conf", SyncChange::LocalNewer),
+                ("docs", SyncChange::LocalOnly),
+                ("old", SyncChange::RemoteOnly),
+            ]
+        );
+        sync(&mut state, &mut rx, true).await;
+        assert_eq!(state.sync.as_ref().unwrap().entries.len(), 4);
+
+        state.sync.as_mut().unwrap().toggle_delete();
+        state.plan_sync(SyncDirection::Upload);
+        state.apply_sync();
+        assert!(state.sync.is_none());
+        assert!(!remote.join("old").exists());
+        settle(&mut state, &mut rx).await;
+        assert_eq!(std::fs::read_to_string(remote.join("docs/readme")).unwrap(), "hello\n");
+        assert_eq!(std::fs::read_to_string(remote.join("same")).unwrap(), "aaaa");
+
+        // Copies keep their modification time, so nothing differs now
+        sync(&mut state, &mut rx, false).await;
+        assert!(state.sync.is_none());
+        assert_eq!(
+            state.status_message.as_deref(),
+            Some("Local and remote directories are in sync")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
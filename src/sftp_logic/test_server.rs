@@ -0,0 +1,360 @@
+//! Minimal SFTP server standing in for sshd's sftp-server in tests
+//!
+//! It serves the local file system over a Unix socket pair, with remote
+//! paths being plain local paths. Also home to the fixtures the SFTP tests
+//! share.
+
+use super::session::{put_bytes, put_str, put_u32, read_packet, write_packet, PacketReader};
+use super::session::{FileAttributes, SftpSession, SharedSession};
+use super::queue::TransferState;
+use super::types::AppSftpState;
+use crate::app_event::TransferEvent;
+use crate::models::SshHost;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+enum Response {
+    Status(u32),
+    Packet(u8),
+}
+
+enum Open {
+    File(File),
+    Dir(Option<PathBuf>),
+}
+
+/// Connect a session to a stand-in server running on its own thread
+pub fn connect() -> SftpSession {
+    connect_to(None, true)
+}
+
+/// Connect to a server that drops the connection when it gets one READ or
+/// WRITE request more than `limit`
+pub fn connect_dropping_after(limit: usize) -> SftpSession {
+    connect_to(Some(limit), true)
+}
+
+/// Connect to a server that doesn't hash files, like OpenSSH's sftp-server
+pub fn connect_without_check_file() -> SftpSession {
+    connect_to(None, false)
+}
+
+/// A browser session that reconnects to a new stand-in server
+pub fn shared() -> Arc<SharedSession> {
+    Arc::new(SharedSession::new(connect(), || Ok(connect())))
+}
+
+fn connect_to(limit: Option<usize>, check_file: bool) -> SftpSession {
+    let (client, server) = UnixStream::pair().unwrap();
+    thread::spawn(move || {
+        let _ = serve(server, limit, check_file);
+    });
+    let reader = client.try_clone().unwrap();
+    SftpSession::from_streams(reader, client).unwrap()
+}
+
+/// A fresh, empty directory for one test
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sshr-sftp-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The host the tests connect to, for what needs one besides the session
+pub fn host() -> SshHost {
+    SshHost::new("test".into(), "localhost".into(), "me".into())
+}
+
+/// State browsing the existing directories `local` and `remote` through a
+/// stand-in server, and the receiver of its transfer events
+pub fn browser(local: &Path, remote: &Path) -> (AppSftpState, mpsc::Receiver<TransferEvent>) {
+    let (tx, rx) = mpsc::channel(100);
+    let mut state = AppSftpState::with_session(&host(), shared(), Default::default(), tx).unwrap();
+    state.local_current_path = local.to_path_buf();
+    state.remote_current_path = remote.to_string_lossy().into_owned();
+    state.refresh_local().unwrap();
+    state.refresh_remote().unwrap();
+    (state, rx)
+}
+
+/// The next event from the workers, failing when none comes in time
+pub async fn next_event(rx: &mut mpsc::Receiver<TransferEvent>) -> TransferEvent {
+    tokio::time::timeout(Duration::from_secs(10), rx.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+/// Apply transfer events until nothing is queued or running
+pub async fn settle(state: &mut AppSftpState, rx: &mut mpsc::Receiver<TransferEvent>) {
+    while state.queue.items.iter().any(|item| {
+        matches!(item.state, TransferState::Queued | TransferState::Running)
+    }) {
+        let event = next_event(rx).await;
+        state.apply_transfer_event(event);
+    }
+}
+
+fn attributes(metadata: &fs::Metadata) -> FileAttributes {
+    FileAttributes {
+        size: Some(metadata.len()),
+        uid: Some(metadata.uid()),
+        gid: Some(metadata.gid()),
+        permissions: Some(metadata.mode()),
+        atime: Some(metadata.atime() as u32),
+        mtime: Some(metadata.mtime() as u32),
+    }
+}
+
+fn serve(mut stream: UnixStream, mut limit: Option<usize>, check_file: bool) -> Result<()> {
+    let (_, _) = read_packet(&mut stream)?;
+    let mut version = Vec::new();
+    put_u32(&mut version, 3);
+    put_str(&mut version, "posix-rename@openssh.com");
+    put_str(&mut version, "1");
+    if check_file {
+        put_str(&mut version, "check-file-name");
+        put_str(&mut version, "sha256");
+    }
+    write_packet(&mut stream, 2, &version)?;
+
+    let mut handles: HashMap<u32, Open> = HashMap::new();
+    let mut next_handle = 0u32;
+    loop {
+        let (kind, body) = read_packet(&mut stream)?;
+        if kind == 5 || kind == 6 {
+            match &mut limit {
+                Some(0) => {
+                    stream.shutdown(std::net::Shutdown::Both)?;
+                    return Ok(());
+                }
+                Some(left) => *left -= 1,
+                None => {}
+            }
+        }
+        let mut request = PacketReader::new(&body);
+        let id = request.u32()?;
+        let mut reply = Vec::new();
+        put_u32(&mut reply, id);
+
+        let result: std::io::Result<Response> = (|| {
+            let path = |request: &mut PacketReader| -> std::io::Result<PathBuf> {
+                Ok(PathBuf::from(request.string().map_err(std::io::Error::other)?))
+            };
+            let handle = |request: &mut PacketReader| -> std::io::Result<u32> {
+                let bytes = request.bytes().map_err(std::io::Error::other)?;
+                Ok(u32::from_be_bytes(bytes.try_into().map_err(std::io::Error::other)?))
+            };
+            let number = |request: &mut PacketReader| request.u32().map_err(std::io::Error::other);
+
+            match kind {
+                // OPEN
+                3 => {
+                    let path = path(&mut request)?;
+                    let flags = number(&mut request)?;
+                    let file = OpenOptions::new()
+                        .read(flags & 0x01 != 0)
+                        .write(flags & 0x02 != 0)
+                        .append(flags & 0x04 != 0)
+                        .create(flags & 0x08 != 0)
+                        .truncate(flags & 0x10 != 0)
+                        .create_new(flags & 0x20 != 0)
+                        .open(path)?;
+                    next_handle += 1;
+                    handles.insert(next_handle, Open::File(file));
+                    put_bytes(&mut reply, &next_handle.to_be_bytes());
+                    Ok(Response::Packet(102))
+                }
+                // OPENDIR
+                11 => {
+                    let path = path(&mut request)?;
+                    fs::read_dir(&path)?;
+                    next_handle += 1;
+                    handles.insert(next_handle, Open::Dir(Some(path)));
+                    put_bytes(&mut reply, &next_handle.to_be_bytes());
+                    Ok(Response::Packet(102))
+                }
+                // CLOSE
+                4 => {
+                    handles.remove(&handle(&mut request)?);
+                    Ok(Response::Status(0))
+                }
+                // READ
+                5 => {
+                    let handle = handle(&mut request)?;
+                    let offset = request.u64().map_err(std::io::Error::other)?;
+                    let length = number(&mut request)? as usize;
+                    let Some(Open::File(file)) = handles.get_mut(&handle) else {
+                        return Err(std::io::ErrorKind::InvalidInput.into());
+                    };
+                    file.seek(SeekFrom::Start(offset))?;
+                    let mut data = Vec::new();
+                    file.take(length as u64).read_to_end(&mut data)?;
+                    if data.is_empty() {
+                        return Ok(Response::Status(1));
+                    }
+                    put_bytes(&mut reply, &data);
+                    Ok(Response::Packet(103))
+                }
+                // WRITE
+                6 => {
+                    let handle = handle(&mut request)?;
+                    let offset = request.u64().map_err(std::io::Error::other)?;
+                    let data = request.bytes().map_err(std::io::Error::other)?;
+                    let Some(Open::File(file)) = handles.get_mut(&handle) else {
+                        return Err(std::io::ErrorKind::InvalidInput.into());
+                    };
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.write_all(data)?;
+                    Ok(Response::Status(0))
+                }
+                // READDIR
+                12 => {
+                    let handle = handle(&mut request)?;
+                    let Some(Open::Dir(dir)) = handles.get_mut(&handle) else {
+                        return Err(std::io::ErrorKind::InvalidInput.into());
+                    };
+                    let Some(dir) = dir.take() else {
+                        return Ok(Response::Status(1));
+                    };
+                    let entries: Vec<_> = fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+                    put_u32(&mut reply, entries.len() as u32);
+                    for entry in entries {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        put_str(&mut reply, &name);
+                        put_str(&mut reply, &name);
+                        attributes(&fs::symlink_metadata(entry.path())?).encode(&mut reply);
+                    }
+                    Ok(Response::Packet(104))
+                }
+                // LSTAT, STAT
+                7 | 17 => {
+                    let path = path(&mut request)?;
+                    let metadata = if kind == 7 {
+                        fs::symlink_metadata(path)?
+                    } else {
+                        fs::metadata(path)?
+                    };
+                    attributes(&metadata).encode(&mut reply);
+                    Ok(Response::Packet(105))
+                }
+                // SETSTAT
+                9 => {
+                    let path = path(&mut request)?;
+                    let attrs = FileAttributes::decode(&mut request)
+                        .map_err(std::io::Error::other)?;
+                    if let Some(mode) = attrs.permissions {
+                        fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o7777))?;
+                    }
+                    if let (Some(uid), Some(gid)) = (attrs.uid, attrs.gid) {
+                        std::os::unix::fs::chown(&path, Some(uid), Some(gid))?;
+                    }
+                    if let Some(mtime) = attrs.mtime {
+                        let mtime = std::time::UNIX_EPOCH
+                            + std::time::Duration::from_secs(mtime.into());
+                        fs::File::options().write(true).open(&path)?.set_modified(mtime)?;
+                    }
+                    Ok(Response::Status(0))
+                }
+                // REMOVE
+                13 => fs::remove_file(path(&mut request)?).map(|_| Response::Status(0)),
+                // MKDIR
+                14 => fs::create_dir(path(&mut request)?).map(|_| Response::Status(0)),
+                // RMDIR
+                15 => fs::remove_dir(path(&mut request)?).map(|_| Response::Status(0)),
+                // REALPATH
+                16 => {
+                    let path = path(&mut request)?;
+                    let path = if path == Path::new(".") {
+                        std::env::temp_dir()
+                    } else {
+                        fs::canonicalize(path)?
+                    };
+                    put_u32(&mut reply, 1);
+                    put_str(&mut reply, &path.to_string_lossy());
+                    put_str(&mut reply, "");
+                    FileAttributes::default().encode(&mut reply);
+                    Ok(Response::Packet(104))
+                }
+                // RENAME
+                18 => {
+                    let from = path(&mut request)?;
+                    let to = path(&mut request)?;
+                    if to.exists() {
+                        return Err(std::io::ErrorKind::AlreadyExists.into());
+                    }
+                    fs::rename(from, to).map(|_| Response::Status(0))
+                }
+                // READLINK
+                19 => {
+                    let target = fs::read_link(path(&mut request)?)?;
+                    put_u32(&mut reply, 1);
+                    put_str(&mut reply, &target.to_string_lossy());
+                    put_str(&mut reply, "");
+                    FileAttributes::default().encode(&mut reply);
+                    Ok(Response::Packet(104))
+                }
+                // EXTENDED
+                200 => match request.string().map_err(std::io::Error::other)?.as_str() {
+                    "posix-rename@openssh.com" => {
+                        let from = path(&mut request)?;
+                        let to = path(&mut request)?;
+                        fs::rename(from, to).map(|_| Response::Status(0))
+                    }
+                    // Ranges from the start as one block
+                    "check-file-name" => {
+                        let path = path(&mut request)?;
+                        request.string().map_err(std::io::Error::other)?;
+                        request.u64().map_err(std::io::Error::other)?;
+                        let length = match request.u64().map_err(std::io::Error::other)? {
+                            0 => u64::MAX,
+                            length => length,
+                        };
+                        let mut hasher = Sha256::new();
+                        std::io::copy(&mut File::open(path)?.take(length), &mut hasher)?;
+                        put_str(&mut reply, "check-file");
+                        put_str(&mut reply, "sha256");
+                        reply.extend_from_slice(&hasher.finalize());
+                        Ok(Response::Packet(201))
+                    }
+                    _ => Err(std::io::ErrorKind::Unsupported.into()),
+                },
+                _ => Err(std::io::ErrorKind::Unsupported.into()),
+            }
+        })();
+
+        let kind = match result {
+            Ok(Response::Status(code)) => {
+                put_u32(&mut reply, code);
+                put_str(&mut reply, "");
+                put_str(&mut reply, "");
+                101
+            }
+            Ok(Response::Packet(kind)) => kind,
+            Err(e) => {
+                let code = match e.kind() {
+                    std::io::ErrorKind::NotFound => 2,
+                    std::io::ErrorKind::PermissionDenied => 3,
+                    std::io::ErrorKind::Unsupported => 8,
+                    _ => 4,
+                };
+                put_u32(&mut reply, code);
+                put_str(&mut reply, &e.to_string());
+                put_str(&mut reply, "");
+                101
+            }
+        };
+        write_packet(&mut stream, kind, &reply)?;
+    }
+}
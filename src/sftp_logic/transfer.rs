@@ -1,343 +1,1045 @@
-use super::types::AppSftpState;
+use super::checksum::{self, ChecksumMismatch};
+use super::file_ops::{FileOperation, FilePrompt};
+use super::queue::{
+    Controlled, QueuedTransfer, TransferControl, TransferDirection, TransferId, TransferState,
+};
+use super::session::{join_remote, open_flags, FileAttributes, SftpSession};
+use super::tree;
+use super::types::{AppSftpState, FileProgress, PanelSide, TransferSummary, TreeProgress};
+use super::FileItem;
 use crate::app_event::TransferEvent;
+use crate::config::SymlinkMode;
+use crate::models::SshHost;
 use anyhow::{Context, Result};
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::process::Command;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Wait before the first retry after losing the connection, doubled for
+/// every retry after it
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 impl AppSftpState {
-    /// Upload a file to the remote server
-    pub fn upload_file(&mut self) {
-        if let Some(super::FileItem::File { name, .. }) =
-            self.local_files.get(self.local_selected).cloned()
-        {
-            let name = name.clone();
-            let local_path = self.local_current_path.join(&name);
-            let remote_path = if self.remote_current_path.ends_with('/') {
-                format!("{}{}", self.remote_current_path, name)
-            } else {
-                format!("{}/{}", self.remote_current_path, name)
-            };
+    /// Queue uploads of the marked local entries, or of the selected one,
+    /// asking about those that exist on the server
+    pub fn upload_selected(&mut self) {
+        let entries =
+            Self::entries_to_transfer(&self.local_files, self.local_selected, &self.local_marked);
+        if entries.is_empty() {
+            self.set_status_message("Please select a file or directory to upload");
+            return;
+        }
+        let queued = self.queue_unless_conflicting(TransferDirection::Upload, &entries);
+        self.local_marked.clear();
+        if queued == entries.len() {
+            self.set_status_message(&Self::queued_message("upload", &entries));
+        }
+        self.start_transfers();
+    }
+
+    /// Queue downloads of the marked remote entries, or of the selected one,
+    /// asking about those that exist locally
+    pub fn download_selected(&mut self) {
+        let entries = Self::entries_to_transfer(
+            &self.remote_files,
+            self.remote_selected,
+            &self.remote_marked,
+        );
+        if entries.is_empty() {
+            self.set_status_message("Please select a file or directory to download");
+            return;
+        }
+        let queued = self.queue_unless_conflicting(TransferDirection::Download, &entries);
+        self.remote_marked.clear();
+        if queued == entries.len() {
+            self.set_status_message(&Self::queued_message("download", &entries));
+        }
+        self.start_transfers();
+    }
 
-            let ssh_user = self.ssh_user.clone();
-            let ssh_host = self.ssh_host.clone();
-            let ssh_port = self.ssh_port;
-            let tx = self.transfer_tx.clone().unwrap();
+    /// Names of the marked entries in listing order, or of the selected entry
+    /// when nothing is marked, with whether they are directories
+    pub(super) fn entries_to_transfer(
+        files: &[FileItem],
+        selected: usize,
+        marked: &BTreeSet<String>,
+    ) -> Vec<(String, bool)> {
+        let entry = |item: &FileItem| {
+            (
+                item.name().to_string(),
+                matches!(item, FileItem::Directory { .. }),
+            )
+        };
+        if marked.is_empty() {
+            files
+                .get(selected)
+                .filter(|item| item.name() != "..")
+                .map(entry)
+                .into_iter()
+                .collect()
+        } else {
+            files
+                .iter()
+                .filter(|item| marked.contains(item.name()))
+                .map(entry)
+                .collect()
+        }
+    }
 
-            tokio::spawn(async move {
-                let name_clone = name.clone();
+    fn queued_message(kind: &str, entries: &[(String, bool)]) -> String {
+        match entries {
+            [(name, _)] => format!("Queued {} of {}", kind, name),
+            _ => format!("Queued {} {}s", entries.len(), kind),
+        }
+    }
+
+    /// Start queued transfers while fewer than the configured number run
+    pub fn start_transfers(&mut self) {
+        for job in self.queue.start_next(self.config.concurrent_transfers) {
+            self.spawn_transfer(job);
+        }
+    }
+
+    fn spawn_transfer(&self, job: QueuedTransfer) {
+        let shared = self.session.clone();
+        let host = self.host.clone();
+        let symlinks = self.config.symlinks;
+        let retries = self.config.retries;
+        let tx = self.transfer_tx.clone().unwrap();
+
+        tokio::spawn(async move {
+            let id = job.id;
+            let name = job.name.clone();
+            let control = job.control.clone();
+            let job = Arc::new(job);
+            let mut attempt = 0;
+            let result = loop {
+                let session = shared.get();
+                let run_session = session.clone();
+                let run_job = job.clone();
+                let run_host = host.clone();
                 let progress_tx = tx.clone();
-                let result = Self::sftp_upload(
-                    &ssh_user,
-                    &ssh_host,
-                    ssh_port,
-                    &local_path,
-                    &remote_path,
-                    move |uploaded, total| {
-                        let _ = progress_tx.try_send(TransferEvent::UploadProgress(
-                            name_clone.clone(),
-                            uploaded,
-                            total,
-                        ));
-                    },
-                )
-                .await;
+                let result = tokio::task::spawn_blocking(move || {
+                    Self::run_transfer(
+                        &run_session,
+                        &run_host,
+                        &run_job,
+                        symlinks,
+                        |file, tree| {
+                            let _ = progress_tx.try_send(TransferEvent::Progress(id, file, tree));
+                        },
+                        || {
+                            let _ = progress_tx.blocking_send(TransferEvent::Verifying(id));
+                        },
+                    )
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.into()));
 
+                // Only a dropped connection is worth another try, the rest
+                // would fail the same way again
                 match result {
-                    Ok(_) => {
-                        tracing::info!("Successfully uploaded {}", name);
-                        let _ = tx.send(TransferEvent::UploadComplete(name.clone())).await;
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to upload {}", name);
-                        tracing::error!("Error: {}", e.to_string());
+                    Err(e) if !control.is_cancelled() && session.is_closed() && attempt < retries => {
+                        attempt += 1;
+                        tracing::warn!("Lost the connection transferring {}: {:#}", name, e);
                         let _ = tx
-                            .send(TransferEvent::UploadError(name.clone(), e.to_string()))
+                            .send(TransferEvent::Retrying(id, attempt, format!("{:#}", e)))
                             .await;
+                        if !Self::backoff(attempt, &control).await {
+                            break Err(e);
+                        }
+                        let reconnect = tokio::task::spawn_blocking({
+                            let shared = shared.clone();
+                            move || shared.reconnect(&session)
+                        });
+                        // A failed reconnect leaves the closed session in
+                        // place, so the next attempt fails right away
+                        if let Ok(Err(e)) = reconnect.await {
+                            tracing::warn!("Failed to reconnect: {:#}", e);
+                        }
                     }
+                    result => break result,
                 }
-            });
-        } else {
-            self.set_status_message("Please select a file to upload");
-        }
-    }
+            };
 
-    /// Download a file from the remote server
-    pub fn download_file(&mut self) {
-        if let Some(super::FileItem::File { name, .. }) =
-            self.remote_files.get(self.remote_selected).cloned()
-        {
-            let name = name.clone();
-            let remote_path = if self.remote_current_path.ends_with('/') {
-                format!("{}{}", self.remote_current_path, name)
-            } else {
-                format!("{}/{}", self.remote_current_path, name)
+            let event = match result {
+                Ok((Some(summary), _)) if !summary.mismatched.is_empty() => {
+                    tracing::warn!("Some files of {} differ from their source", name);
+                    TransferEvent::Mismatch(id, summary.mismatched.clone(), Some(summary))
+                }
+                Ok((summary, sha256)) => {
+                    tracing::info!("Successfully transferred {}", name);
+                    TransferEvent::Complete(id, summary, sha256)
+                }
+                Err(_) if control.is_cancelled() => {
+                    tracing::info!("Cancelled transfer of {}", name);
+                    TransferEvent::Cancelled(id)
+                }
+                Err(e) => match e.downcast::<ChecksumMismatch>() {
+                    Ok(mismatch) => TransferEvent::Mismatch(id, vec![mismatch], None),
+                    Err(e) => {
+                        tracing::error!("Failed to transfer {}: {:#}", name, e);
+                        TransferEvent::Failed(id, format!("{:#}", e))
+                    }
+                },
             };
-            let local_path = self.local_current_path.join(&name);
+            let _ = tx.send(event).await;
+        });
+    }
 
-            // self.set_status_message(&format!("Downloading {}...", name));
+    /// Wait before the given retry, 1s doubling up to a minute. Returns
+    /// false when the transfer is cancelled meanwhile.
+    async fn backoff(attempt: u32, control: &TransferControl) -> bool {
+        let delay = RETRY_DELAY * 2u32.pow(attempt.saturating_sub(1).min(6));
+        let until = tokio::time::Instant::now() + delay.min(MAX_RETRY_DELAY);
+        while tokio::time::Instant::now() < until {
+            if control.is_cancelled() {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        !control.is_cancelled()
+    }
 
-            let ssh_user = self.ssh_user.clone();
-            let ssh_host = self.ssh_host.clone();
-            let ssh_port = self.ssh_port;
-            let tx = self.transfer_tx.clone().unwrap();
+    /// Run one queued transfer to the end, returning the summary of a
+    /// directory transfer and the SHA-256 of a verified file. Verified
+    /// transfers call `verifying` once copied and fail with a
+    /// `ChecksumMismatch` when a single file differs.
+    fn run_transfer<F, V>(
+        session: &SftpSession,
+        host: &SshHost,
+        job: &QueuedTransfer,
+        symlinks: SymlinkMode,
+        mut progress: F,
+        mut verifying: V,
+    ) -> Result<(Option<TransferSummary>, Option<String>)>
+    where
+        F: FnMut(FileProgress, Option<TreeProgress>),
+        V: FnMut(),
+    {
+        let control = &job.control;
+        let verify = job.verify.then_some(host);
+        let file_progress = |file_name: &str, done: u64, total: u64| FileProgress {
+            file_name: file_name.to_string(),
+            done,
+            total,
+        };
 
-            tokio::spawn(async move {
-                let name_clone = name.clone();
-                let progress_tx = tx.clone();
-                let result = Self::sftp_download(
-                    &ssh_user,
-                    &ssh_host,
-                    ssh_port,
-                    &remote_path,
-                    &local_path,
-                    move |downloaded, total| {
-                        tracing::info!("Downloading try send {}", name_clone);
-                        let _ = progress_tx.try_send(TransferEvent::DownloadProgress(
-                            name_clone.clone(),
-                            downloaded,
-                            total,
-                        ));
+        match (job.direction, job.is_dir) {
+            (TransferDirection::Upload, false) => {
+                Self::sftp_upload(
+                    session,
+                    host,
+                    &job.local_path,
+                    &job.remote_path,
+                    control,
+                    |done, total| progress(file_progress(&job.name, done, total), None),
+                )?;
+                let sha256 = match verify {
+                    Some(host) => {
+                        verifying();
+                        Some(checksum::verify(session, host, &job.local_path, &job.remote_path)?)
+                    }
+                    None => None,
+                };
+                Ok((None, sha256))
+            }
+            (TransferDirection::Download, false) => {
+                Self::sftp_download(
+                    session,
+                    host,
+                    &job.remote_path,
+                    &job.local_path,
+                    control,
+                    |done, total| progress(file_progress(&job.name, done, total), None),
+                )?;
+                let sha256 = match verify {
+                    Some(host) => {
+                        verifying();
+                        Some(checksum::verify(session, host, &job.local_path, &job.remote_path)?)
+                    }
+                    None => None,
+                };
+                Ok((None, sha256))
+            }
+            (TransferDirection::Upload, true) => {
+                let plan = tree::scan_local(&job.local_path, symlinks)?;
+                let mut summary = tree::upload_tree(
+                    session,
+                    host,
+                    &job.local_path,
+                    &job.remote_path,
+                    &plan,
+                    control,
+                    |file, done, total, tree| {
+                        progress(file_progress(file, done, total), Some(tree.clone()))
                     },
-                )
-                .await;
+                )?;
+                if let Some(host) = verify {
+                    verifying();
+                    Self::verify_tree(session, host, job, &plan, &mut summary)?;
+                }
+                Ok((Some(summary), None))
+            }
+            (TransferDirection::Download, true) => {
+                let plan = tree::scan_remote(session, &job.remote_path, symlinks)?;
+                let mut summary = tree::download_tree(
+                    session,
+                    host,
+                    &job.remote_path,
+                    &job.local_path,
+                    &plan,
+                    control,
+                    |file, done, total, tree| {
+                        progress(file_progress(file, done, total), Some(tree.clone()))
+                    },
+                )?;
+                if let Some(host) = verify {
+                    verifying();
+                    Self::verify_tree(session, host, job, &plan, &mut summary)?;
+                }
+                Ok((Some(summary), None))
+            }
+        }
+    }
 
-                match result {
-                    Ok(_) => {
-                        tracing::info!("Successfully downloaded {}", name);
-                        let _ = tx.send(TransferEvent::DownloadComplete(name.clone())).await;
+    /// Compare the hashes of the files a directory transfer copied, moving
+    /// the ones that differ to the failed entries of its summary
+    fn verify_tree(
+        session: &SftpSession,
+        host: &SshHost,
+        job: &QueuedTransfer,
+        plan: &tree::TreePlan,
+        summary: &mut TransferSummary,
+    ) -> Result<()> {
+        for (relative, size) in &plan.files {
+            job.control.wait()?;
+            if summary.failed.iter().any(|(failed, _)| failed == relative) {
+                continue;
+            }
+            let local_path = job.local_path.join(relative);
+            let remote_path = join_remote(&job.remote_path, relative);
+            let Err(e) = checksum::verify(session, host, &local_path, &remote_path) else {
+                continue;
+            };
+            match e.downcast::<ChecksumMismatch>() {
+                Ok(mismatch) => {
+                    summary.failed.push((relative.clone(), mismatch.to_string()));
+                    summary.mismatched.push(mismatch);
+                    summary.files -= 1;
+                    summary.bytes -= size;
+                }
+                Err(e) if session.is_closed() => return Err(e),
+                Err(e) => {
+                    let reason = format!("Could not verify: {:#}", e);
+                    summary.failed.push((relative.clone(), reason));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply an update from a running transfer and start the next queued ones
+    pub fn apply_transfer_event(&mut self, event: TransferEvent) {
+        match event {
+            TransferEvent::Progress(id, file, tree) => {
+                if let Some(item) = self.queue.get_mut(id) {
+                    if matches!(item.state, TransferState::Running | TransferState::Paused) {
+                        item.update(file, tree);
                     }
-                    Err(e) => {
-                        tracing::error!("Failed to download {}", name);
-                        tracing::error!("Error: {}", e.to_string());
-                        let _ = tx
-                            .send(TransferEvent::DownloadError(name.clone(), e.to_string()))
-                            .await;
+                }
+            }
+            TransferEvent::Complete(id, summary, sha256) => {
+                let Some(item) = self.queue.get_mut(id) else {
+                    return;
+                };
+                item.finish(TransferState::Done);
+                self.mismatched.retain(|mismatch| !item.covers(mismatch));
+                let direction = item.direction;
+                let (title, verb) = match direction {
+                    TransferDirection::Upload => ("Uploaded", "uploaded"),
+                    TransferDirection::Download => ("Downloaded", "downloaded"),
+                };
+                let message = match summary {
+                    Some(mut summary) => {
+                        summary.title = format!("{} {}", title, item.name);
+                        item.summary = Some(summary.clone());
+                        let message = summary.headline();
+                        if summary.has_problems() {
+                            self.transfer_summary = Some(summary);
+                        }
+                        message
                     }
+                    None => format!("Successfully {} {}", verb, item.name),
+                };
+                self.record_transfer(id, sha256, None);
+                self.set_status_message(&message);
+                let _ = match direction {
+                    TransferDirection::Upload => self.refresh_remote(),
+                    TransferDirection::Download => self.refresh_local(),
+                };
+            }
+            TransferEvent::Failed(id, error) => {
+                let Some(item) = self.queue.get_mut(id) else {
+                    return;
+                };
+                if item.state != TransferState::Cancelled {
+                    item.finish(TransferState::Failed(error.clone()));
+                    let kind = match item.direction {
+                        TransferDirection::Upload => "Upload",
+                        TransferDirection::Download => "Download",
+                    };
+                    let message = format!("{} failed for {}: {}", kind, item.name, error);
+                    self.record_transfer(id, None, Some(error));
+                    self.set_status_message(&message);
+                }
+            }
+            TransferEvent::Cancelled(id) => {
+                if let Some(item) = self.queue.get_mut(id) {
+                    item.finish(TransferState::Cancelled);
+                }
+            }
+            TransferEvent::Verifying(id) => {
+                if let Some(item) = self.queue.get_mut(id) {
+                    item.verifying = item.state == TransferState::Running;
                 }
-            });
+            }
+            TransferEvent::Mismatch(id, mismatched, summary) => {
+                let Some(item) = self.queue.get_mut(id) else {
+                    return;
+                };
+                let error = match mismatched.len() {
+                    1 => "SHA-256 mismatch".to_string(),
+                    count => format!("SHA-256 mismatch in {} files", count),
+                };
+                item.finish(TransferState::Failed(error.clone()));
+                let direction = item.direction;
+                let message = format!("{} for {}, the copy differs from the source", error, item.name);
+                if let Some(mut summary) = summary {
+                    summary.title = match direction {
+                        TransferDirection::Upload => format!("Uploaded {}", item.name),
+                        TransferDirection::Download => format!("Downloaded {}", item.name),
+                    };
+                    item.summary = Some(summary.clone());
+                    self.transfer_summary = Some(summary);
+                }
+                self.mismatched.retain(|mismatch| !item.covers(mismatch));
+                self.mismatched.extend(mismatched);
+                self.record_transfer(id, None, Some(error));
+                self.set_status_message(&message);
+                let _ = match direction {
+                    TransferDirection::Upload => self.refresh_remote(),
+                    TransferDirection::Download => self.refresh_local(),
+                };
+            }
+            TransferEvent::Retrying(id, attempt, error) => {
+                if let Some(item) = self.queue.get_mut(id) {
+                    if matches!(item.state, TransferState::Running | TransferState::Paused) {
+                        item.wait_to_retry(attempt, error);
+                    }
+                }
+            }
+            TransferEvent::Preview(preview) => self.apply_preview(*preview),
+            TransferEvent::Compared(result) => self.apply_comparison(*result),
+        }
+        self.start_transfers();
+    }
+
+    /// Check transfers queued from now on with SHA-256, or stop checking them
+    pub fn toggle_verify(&mut self) {
+        self.verify = !self.verify;
+        self.set_status_message(if self.verify {
+            "New transfers are verified with SHA-256"
         } else {
-            self.set_status_message("Please select a file to download");
+            "New transfers are no longer verified"
+        });
+    }
+
+    /// Names in the local directory that are or hold a mismatched file
+    pub fn local_mismatches(&self) -> BTreeSet<String> {
+        self.mismatched
+            .iter()
+            .filter_map(|mismatch| mismatch.local_path.strip_prefix(&self.local_current_path).ok())
+            .filter_map(|rest| rest.components().next())
+            .map(|name| name.as_os_str().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Names in the remote directory that are or hold a mismatched file
+    pub fn remote_mismatches(&self) -> BTreeSet<String> {
+        let dir = format!("{}/", self.remote_current_path.trim_end_matches('/'));
+        self.mismatched
+            .iter()
+            .filter_map(|mismatch| mismatch.remote_path.strip_prefix(&dir))
+            .filter_map(|rest| rest.split('/').next())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Id of the transfer selected in the queue panel
+    fn selected_transfer(&mut self) -> Option<TransferId> {
+        if self.active_panel != PanelSide::Queue {
+            self.set_status_message("Switch to the transfer queue first");
+            return None;
+        }
+        self.queue.selected_id()
+    }
+
+    /// Pause the selected transfer, or resume it when paused
+    pub fn toggle_pause_selected_transfer(&mut self) {
+        let Some(id) = self.selected_transfer() else {
+            return;
+        };
+        if self.queue.toggle_pause(id).is_none() {
+            self.set_status_message("Only queued or running transfers can be paused");
         }
+        self.start_transfers();
     }
 
-    /// Upload a file using SCP with progress tracking
-    async fn sftp_upload<F>(
-        user: &str,
-        host: &str,
-        port: u16,
+    pub fn cancel_selected_transfer(&mut self) {
+        let Some(id) = self.selected_transfer() else {
+            return;
+        };
+        if !self.queue.cancel(id) {
+            self.set_status_message("The transfer has already finished");
+        }
+        self.start_transfers();
+    }
+
+    pub fn retry_selected_transfer(&mut self) {
+        let Some(id) = self.selected_transfer() else {
+            return;
+        };
+        if !self.queue.retry(id) {
+            self.set_status_message("Only failed or cancelled transfers can be retried");
+        }
+        self.start_transfers();
+    }
+
+    /// Ask for the rate limit of the selected transfer
+    pub fn start_transfer_limit(&mut self) {
+        let Some(id) = self.selected_transfer() else {
+            return;
+        };
+        let Some(item) = self.queue.get_mut(id) else {
+            return;
+        };
+        if item.state.is_finished() {
+            self.set_status_message("The transfer has already finished");
+            return;
+        }
+        self.prompt = Some(FilePrompt {
+            operation: FileOperation::Limit,
+            side: PanelSide::Queue,
+            targets: vec![item.name.clone()],
+            input: item
+                .control
+                .limit()
+                .map(|limit| (limit / 1024).to_string())
+                .unwrap_or_default(),
+        });
+    }
+
+    /// Limit the selected transfer to `input` KiB per second, or lift its
+    /// limit when `input` is empty or 0
+    pub(super) fn limit_selected_transfer(&mut self, input: &str) {
+        let limit = match input {
+            "" => None,
+            input => match input.parse::<u64>() {
+                Ok(kb) => Some(kb * 1024).filter(|limit| *limit > 0),
+                Err(_) => {
+                    self.set_status_message(&format!("{} is not a number of KiB", input));
+                    return;
+                }
+            },
+        };
+        let Some(item) = self.queue.items.get(self.queue.selected) else {
+            return;
+        };
+        item.control.set_limit(limit);
+        let message = match limit {
+            Some(limit) => format!("{} limited to {} KiB/s", item.name, limit / 1024),
+            None => format!("{} is no longer limited", item.name),
+        };
+        self.set_status_message(&message);
+    }
+
+    /// Slow all transfers down to `[sftp] throttle_kb` between them, or let
+    /// them run at their own limits again
+    pub fn toggle_throttle(&mut self) {
+        let message = match self.queue.throttle() {
+            Some(_) => {
+                self.queue.set_throttle(None);
+                "Transfers are no longer throttled".to_string()
+            }
+            None => {
+                let limit = self.config.throttle_kb.max(1) * 1024;
+                self.queue.set_throttle(Some(limit));
+                format!("Throttling transfers to {} KiB/s", limit / 1024)
+            }
+        };
+        self.set_status_message(&message);
+    }
+
+    /// Remove finished transfers from the queue
+    pub fn clear_finished_transfers(&mut self) {
+        self.queue.clear_finished();
+        if self.queue.items.is_empty() && self.active_panel == PanelSide::Queue {
+            self.active_panel = PanelSide::Local;
+        }
+    }
+
+    /// Show the summary or error of the selected transfer
+    pub(super) fn show_selected_transfer(&mut self) {
+        let Some(item) = self.queue.items.get(self.queue.selected) else {
+            return;
+        };
+        match (&item.state, &item.summary) {
+            (TransferState::Failed(error), _) => {
+                let message = format!("{}: {}", item.name, error);
+                self.set_status_message(&message);
+            }
+            (_, Some(summary)) => self.transfer_summary = Some(summary.clone()),
+            _ => {}
+        }
+    }
+
+    /// Upload a file over the SFTP session, reporting the bytes the server
+    /// has confirmed. The data goes to `.<name>.sshr-part` next to the
+    /// target, which is renamed over it once complete, and a part left by an
+    /// interrupted upload is continued when it matches the local file.
+    pub(super) fn sftp_upload<F>(
+        session: &SftpSession,
+        host: &SshHost,
         local_path: &Path,
         remote_path: &str,
+        control: &TransferControl,
         mut progress_callback: F,
     ) -> Result<()>
     where
-        F: FnMut(u64, u64) + Send + 'static,
+        F: FnMut(u64, u64),
     {
-        let file = File::open(local_path).context("Failed to open local file")?;
+        let mut file = File::open(local_path).context("Failed to open local file")?;
         let metadata = file.metadata().context("Failed to get file metadata")?;
         let total_size = metadata.len();
 
-        let mut command = Command::new("scp")
-            .arg("-P")
-            .arg(port.to_string())
-            .arg("-o")
-            .arg("ConnectTimeout=30")
-            .arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg("-o")
-            .arg("LogLevel=ERROR")
-            .arg(local_path)
-            .arg(format!("{}@{}:{}", user, host, remote_path))
-            .stdin(std::process::Stdio::piped())
-            .spawn()
-            .context("Failed to start scp upload command")?;
-
-        let mut stdin = command
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get scp stdin"))?;
-
-        let mut file = File::open(local_path).context("Failed to reopen local file")?;
-        let mut buffer = [0u8; 8192];
-        let mut uploaded = 0;
-
-        loop {
-            let bytes_read = file.read(&mut buffer).context("Failed to read from file")?;
-            if bytes_read == 0 {
-                break;
-            }
-
-            stdin
-                .write_all(&buffer[..bytes_read])
-                .await
-                .context("Failed to write to scp")?;
-            uploaded += bytes_read as u64;
-
-            progress_callback(uploaded, total_size);
+        let part_path = remote_part_path(remote_path);
+        let size = session.stat(&part_path).ok().and_then(|attrs| attrs.size).unwrap_or(0);
+        let offset = if is_prefix(size, total_size, || {
+            Ok((
+                checksum::sha256_remote(session, host, &part_path)?,
+                checksum::sha256_local_prefix(local_path, size)?,
+            ))
+        }) {
+            size
+        } else {
+            0
+        };
 
-            // Allow other tasks to run
-            tokio::task::yield_now().await;
+        let mut flags = open_flags::WRITE | open_flags::CREATE;
+        if offset == 0 {
+            flags |= open_flags::TRUNCATE;
+        } else {
+            tracing::info!("Resuming upload of {} at {} bytes", remote_path, offset);
         }
+        file.seek(SeekFrom::Start(offset))
+            .context("Failed to read local file")?;
+        let mut input = Controlled::new(file, control.clone());
+        let handle = session
+            .open(&part_path, flags)
+            .with_context(|| format!("Failed to create {}", part_path))?;
 
-        drop(stdin); // Close stdin to signal end of data
+        progress_callback(offset, total_size);
+        let written = session.write_from(&handle, offset, &mut input, |uploaded| {
+            progress_callback(uploaded, total_size)
+        });
+        session.close(handle)?;
+        written?;
 
-        let output = command
-            .wait_with_output()
-            .await
-            .context("Failed to complete scp upload command")?;
+        session
+            .rename_over(&part_path, remote_path)
+            .with_context(|| format!("Failed to rename {} to {}", part_path, remote_path))?;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("SCP upload failed: {}", stderr));
+        // Keep the modification time, so both sides compare as the same
+        // file when synchronizing
+        if let Some(mtime) = metadata.modified().ok().and_then(unix_seconds) {
+            let times = FileAttributes {
+                atime: Some(mtime),
+                mtime: Some(mtime),
+                ..Default::default()
+            };
+            if let Err(e) = session.setstat(remote_path, &times) {
+                tracing::warn!("Failed to set the modification time of {}: {:#}", remote_path, e);
+            }
         }
-
         Ok(())
     }
 
-    /// Download a file using SCP with progress tracking
-    async fn sftp_download<F: Fn(u64, u64) + Send + 'static>(
-        user: &str,
-        host: &str,
-        port: u16,
+    /// Download a file over the SFTP session. The data goes to
+    /// `.<name>.sshr-part` next to the target, which is renamed over it once
+    /// complete, and a part left by an interrupted download is continued
+    /// when it matches the remote file.
+    pub(super) fn sftp_download<F>(
+        session: &SftpSession,
+        host: &SshHost,
         remote_path: &str,
         local_path: &Path,
-        progress_callback: F,
-    ) -> Result<()> {
-        // First, get the remote file size
-        let size_output = Command::new("ssh")
-            .arg("-p")
-            .arg(port.to_string())
-            .arg("-o")
-            .arg("ConnectTimeout=30")
-            .arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg("-o")
-            .arg("LogLevel=ERROR")
-            .arg(format!("{}@{}", user, host))
-            .arg(format!("stat -c%s {}", remote_path))
-            .output()
-            .await
-            .context("Failed to get remote file size")?;
-
-        if !size_output.status.success() {
-            let stderr = String::from_utf8_lossy(&size_output.stderr);
-            return Err(anyhow::anyhow!(
-                "Failed to get remote file size: {}",
-                stderr
-            ));
-        }
-
-        let total_size = String::from_utf8_lossy(&size_output.stdout)
-            .trim()
-            .parse::<u64>()
-            .context("Failed to parse remote file size")?;
+        control: &TransferControl,
+        mut progress_callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(u64, u64),
+    {
+        let attrs = session
+            .stat(remote_path)
+            .with_context(|| format!("Failed to stat {}", remote_path))?;
+        let total_size = attrs.size.unwrap_or(0);
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = local_path.parent() {
-            tokio::fs::create_dir_all(parent).await
-                .context("Failed to create local directory")?;
+            std::fs::create_dir_all(parent).context("Failed to create local directory")?;
         }
-
-        // Start the download in a separate task
-        let download_handle = {
-            let local_path = local_path.to_path_buf();
-            let user = user.to_string();
-            let host = host.to_string();
-            let remote_path = remote_path.to_string();
-            
-            tokio::spawn(async move {
-                Command::new("scp")
-                    .arg("-P")
-                    .arg(port.to_string())
-                    .arg("-o")
-                    .arg("ConnectTimeout=30")
-                    .arg("-o")
-                    .arg("StrictHostKeyChecking=no")
-                    .arg("-o")
-                    .arg("LogLevel=ERROR")
-                    .arg(format!("{}@{}:{}", user, host, remote_path))
-                    .arg(&local_path)
-                    .status()
-                    .await
-            })
+        let part_path = part_path(local_path);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&part_path)
+            .context("Failed to create local file")?;
+        let size = file.metadata().context("Failed to get file metadata")?.len();
+        let offset = if is_prefix(size, total_size, || {
+            Ok((
+                checksum::sha256_local(&part_path)?,
+                checksum::sha256_remote_prefix(session, host, remote_path, Some(size))?,
+            ))
+        }) {
+            tracing::info!("Resuming download of {} at {} bytes", remote_path, size);
+            size
+        } else {
+            0
         };
+        file.set_len(offset)
+            .and_then(|_| file.seek(SeekFrom::Start(offset)))
+            .context("Failed to write local file")?;
+        let mut output = Controlled::new(BufWriter::new(file), control.clone());
 
-        // Monitor the download progress
-        let start_time = std::time::Instant::now();
-        let mut last_size = 0u64;
-        
-        // Initial progress update
-        progress_callback(0, total_size);
-
-        // Create a channel for the download task to signal completion
-        let (tx, mut rx) = tokio::sync::oneshot::channel();
-        
-        // Spawn a task to wait for the download to complete
-        let download_task = tokio::spawn({
-            let download_handle = download_handle;
-            async move {
-                match download_handle.await {
-                    Ok(status_result) => {
-                        let _ = tx.send(status_result);
-                    }
-                    Err(e) => {
-                        let _ = tx.send(Err(e.into()));
-                    }
-                }
-            }
+        // Opened last, so nothing between here and the close can return early
+        let handle = session
+            .open(remote_path, open_flags::READ)
+            .with_context(|| format!("Failed to open {}", remote_path))?;
+
+        progress_callback(offset, total_size);
+        let read = session.read_to(&handle, offset, &mut output, |downloaded| {
+            progress_callback(downloaded, total_size.max(downloaded))
         });
+        session.close(handle)?;
+        // What arrived is kept for the next attempt to continue from
+        let flushed = output.flush().context("Failed to write local file");
+        read?;
+        flushed?;
 
-        // Monitor progress until download completes
-        let mut download_complete = false;
-        while !download_complete {
-            // Check if download is complete
-            match rx.try_recv() {
-                Ok(Ok(status)) => {
-                    if !status.success() {
-                        download_task.abort();
-                        return Err(anyhow::anyhow!("SCP download failed with status: {}", status));
-                    }
-                    download_complete = true;
-                }
-                Ok(Err(e)) => {
-                    download_task.abort();
-                    return Err(e.into());
-                }
-                Err(tokio::sync::oneshot::error::TryRecvError::Empty) => {
-                    // Download still in progress
-                }
-                Err(_) => {
-                    download_task.abort();
-                    return Err(anyhow::anyhow!("Download channel error"));
-                }
+        std::fs::rename(&part_path, local_path).context("Failed to rename local file")?;
+
+        if let Some(mtime) = attrs.mtime {
+            let modified = UNIX_EPOCH + Duration::from_secs(mtime.into());
+            if let Err(e) = File::options()
+                .write(true)
+                .open(local_path)
+                .and_then(|file| file.set_modified(modified))
+            {
+                tracing::warn!(
+                    "Failed to set the modification time of {}: {}",
+                    local_path.display(),
+                    e
+                );
             }
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the epoch as SFTP sends them, `None` before 1970 or after
+/// 2106
+fn unix_seconds(time: SystemTime) -> Option<u32> {
+    let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    u32::try_from(seconds).ok()
+}
+
+/// Whether a part of `size` bytes is the start of a source of `total_size`
+/// bytes, going by the SHA-256 `hashes` of the part and of as many bytes of
+/// the source. A part that can't be hashed is started over.
+fn is_prefix<H>(size: u64, total_size: u64, hashes: H) -> bool
+where
+    H: FnOnce() -> Result<(String, String)>,
+{
+    if size == 0 || size > total_size {
+        return false;
+    }
+    match hashes() {
+        Ok((part, source)) => part == source,
+        Err(e) => {
+            tracing::warn!("Starting over, the partial file couldn't be compared: {:#}", e);
+            false
+        }
+    }
+}
+
+/// Local file a download is written to until it completes. The name is
+/// sshr's own, so no file of the user's is ever continued or replaced.
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".sshr-part");
+    path.with_file_name(name)
+}
+
+/// Remote file an upload is written to until it completes, like `part_path`
+fn remote_part_path(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((dir, name)) => format!("{}/.{}.sshr-part", dir, name),
+        None => format!(".{}.sshr-part", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::session::SharedSession;
+    use super::super::test_server::{self, next_event, scratch_dir, settle};
+    use super::*;
+    use tokio::sync::mpsc;
+
+    /// State browsing `dir/local` and `dir/remote`, with three local files
+    fn queue_browser(
+        dir: &Path,
+        concurrent_transfers: usize,
+    ) -> (AppSftpState, mpsc::Receiver<TransferEvent>) {
+        let local = dir.join("local");
+        let remote = dir.join("remote");
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        for (name, size) in [("a", 10), ("b", 200_000), ("c", 3)] {
+            std::fs::write(local.join(name), vec![b'x'; size]).unwrap();
+        }
+        let (mut state, rx) = test_server::browser(&local, &remote);
+        state.config.concurrent_transfers = concurrent_transfers;
+        (state, rx)
+    }
+
+    fn states(state: &AppSftpState) -> Vec<&'static str> {
+        state
+            .queue
+            .items
+            .iter()
+            .map(|item| item.state.label())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_marked_uploads_in_parallel() {
+        let dir = scratch_dir("queue-parallel");
+        let (mut state, mut rx) = queue_browser(&dir, 2);
+        state
+            .local_marked
+            .extend(["a".to_string(), "b".to_string(), "c".to_string()]);
+
+        state.upload_selected();
+        assert!(state.local_marked.is_empty());
+        assert_eq!(states(&state), vec!["running", "running", "queued"]);
 
-            // Get current file size
-            if let Ok(metadata) = tokio::fs::metadata(local_path).await {
-                let current_size = metadata.len();
-                if current_size > last_size {
-                    progress_callback(current_size, total_size);
-                    last_size = current_size;
+        settle(&mut state, &mut rx).await;
+        assert_eq!(states(&state), vec!["done", "done", "done"]);
+        assert_eq!(std::fs::read(dir.join("remote/b")).unwrap().len(), 200_000);
+        assert_eq!(state.remote_files.len(), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pauses_cancels_and_retries_queued_transfers() {
+        let dir = scratch_dir("queue-control");
+        let (mut state, mut rx) = queue_browser(&dir, 1);
+        state
+            .local_marked
+            .extend(["a".to_string(), "b".to_string(), "c".to_string()]);
+        state.upload_selected();
+        state.active_panel = PanelSide::Queue;
+
+        // Pause "b" and cancel "c" while they wait
+        state.queue.selected = 1;
+        state.toggle_pause_selected_transfer();
+        state.queue.selected = 2;
+        state.cancel_selected_transfer();
+        settle(&mut state, &mut rx).await;
+        assert_eq!(states(&state), vec!["done", "paused", "cancelled"]);
+        assert!(!dir.join("remote/b").exists());
+
+        state.queue.selected = 1;
+        state.toggle_pause_selected_transfer();
+        state.queue.selected = 2;
+        state.retry_selected_transfer();
+        settle(&mut state, &mut rx).await;
+        assert_eq!(states(&state), vec!["done", "done", "done"]);
+        assert!(dir.join("remote/c").exists());
+
+        state.clear_finished_transfers();
+        assert!(state.queue.items.is_empty());
+        assert_eq!(state.active_panel, PanelSide::Local);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cancels_a_running_download() {
+        let dir = scratch_dir("queue-cancel");
+        let (mut state, mut rx) = queue_browser(&dir, 1);
+        std::fs::write(dir.join("remote/big"), vec![1u8; 4_000_000]).unwrap();
+        state.refresh_remote().unwrap();
+        state.remote_marked.insert("big".to_string());
+        state.download_selected();
+        state.active_panel = PanelSide::Queue;
+
+        state.toggle_pause_selected_transfer();
+        assert_eq!(states(&state), vec!["paused"]);
+        state.cancel_selected_transfer();
+        let event = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match rx.recv().await.unwrap() {
+                    TransferEvent::Progress(..) => continue,
+                    event => return event,
                 }
             }
+        })
+        .await
+        .unwrap();
+        assert!(matches!(event, TransferEvent::Cancelled(1)));
+        state.apply_transfer_event(event);
+        assert_eq!(states(&state), vec!["cancelled"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 
-            // Check if download is taking too long without progress
-            if start_time.elapsed().as_secs() > 300 && last_size == 0 { // 5 minutes without progress
-                download_task.abort();
-                return Err(anyhow::anyhow!("Download timed out with no progress"));
-            }
+    /// Bytes that differ from one position to the next, so that a part at
+    /// the wrong offset does not match
+    fn pattern(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn resumes_a_matching_part_and_restarts_another() {
+        let dir = scratch_dir("resume");
+        let session = test_server::connect();
+        let host = test_server::host();
+        let control = TransferControl::default();
+        let data = pattern(300_000);
+        let source = dir.join("source");
+        std::fs::write(&source, &data).unwrap();
+        let target = dir.join("target");
+        let target_part = dir.join(".target.sshr-part");
+        let remote_target = target.to_string_lossy().into_owned();
+        // A file of the user's that only looks like a part
+        let unrelated = dir.join("target.part");
+        std::fs::write(&unrelated, &data[..100_000]).unwrap();
+
+        let mut changed_middle = data[..100_000].to_vec();
+        changed_middle[50_000] ^= 1;
+        for (part, resumed_at) in [
+            (data[..100_000].to_vec(), 100_000),
+            (vec![7; 100_000], 0),
+            (changed_middle, 0),
+        ] {
+            std::fs::write(&target_part, &part).unwrap();
+            let mut first = None;
+            let progress = |done, _| {
+                first.get_or_insert(done);
+            };
+            AppSftpState::sftp_upload(&session, &host, &source, &remote_target, &control, progress)
+                .unwrap();
+            assert_eq!(first, Some(resumed_at));
+            assert_eq!(std::fs::read(&target).unwrap(), data);
+            assert!(!target_part.exists());
 
-            // Don't check too frequently
-            if !download_complete {
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            std::fs::write(&target_part, &part).unwrap();
+            let mut first = None;
+            let remote_source = source.to_string_lossy().into_owned();
+            let progress = |done, _| {
+                first.get_or_insert(done);
+            };
+            AppSftpState::sftp_download(&session, &host, &remote_source, &target, &control, progress)
+                .unwrap();
+            assert_eq!(first, Some(resumed_at));
+            assert_eq!(std::fs::read(&target).unwrap(), data);
+            assert!(!target_part.exists());
+        }
+        assert_eq!(std::fs::read(&unrelated).unwrap(), &data[..100_000]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconnects_and_resumes_after_the_connection_drops() {
+        let dir = scratch_dir("queue-reconnect");
+        let (mut state, mut rx) = queue_browser(&dir, 1);
+        let data = pattern(200_000);
+        std::fs::write(dir.join("local/b"), &data).unwrap();
+        state.session = Arc::new(SharedSession::new(
+            test_server::connect_dropping_after(3),
+            || Ok(test_server::connect()),
+        ));
+
+        state.local_selected = 2;
+        state.upload_selected();
+        let mut resumed_at = None;
+        while state.queue.running() > 0 {
+            let event = next_event(&mut rx).await;
+            match &event {
+                TransferEvent::Retrying(_, attempt, _) => {
+                    assert_eq!(*attempt, 1);
+                    resumed_at = Some(None);
+                }
+                TransferEvent::Progress(_, file, _) if resumed_at == Some(None) => {
+                    resumed_at = Some(Some(file.done));
+                }
+                _ => {}
             }
+            state.apply_transfer_event(event);
         }
 
-        // Final progress update
-        progress_callback(total_size, total_size);
+        assert_eq!(states(&state), vec!["done"]);
+        assert!(resumed_at.flatten().unwrap() > 0);
+        assert_eq!(std::fs::read(dir.join("remote/b")).unwrap(), data);
+        assert!(!dir.join("remote/.b.sshr-part").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 
-        Ok(())
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verifies_transfers_and_marks_mismatches() {
+        let dir = scratch_dir("queue-verify");
+        let (mut state, mut rx) = queue_browser(&dir, 1);
+        state.toggle_verify();
+        state.local_selected = 1;
+        state.upload_selected();
+        assert!(state.queue.items[0].verify);
+        settle(&mut state, &mut rx).await;
+        assert_eq!(states(&state), vec!["done"]);
+
+        // A mismatch marks the file on both sides until it is copied again
+        let mismatch = ChecksumMismatch {
+            local_path: state.local_current_path.join("a"),
+            remote_path: join_remote(&state.remote_current_path, "a"),
+        };
+        state.queue.items[0].state = TransferState::Running;
+        state.apply_transfer_event(TransferEvent::Mismatch(1, vec![mismatch], None));
+        assert_eq!(states(&state), vec!["failed"]);
+        assert_eq!(state.local_mismatches(), BTreeSet::from(["a".to_string()]));
+        assert_eq!(state.remote_mismatches(), BTreeSet::from(["a".to_string()]));
+
+        state.active_panel = PanelSide::Queue;
+        state.retry_selected_transfer();
+        settle(&mut state, &mut rx).await;
+        assert_eq!(states(&state), vec!["done"]);
+        assert!(state.mismatched.is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
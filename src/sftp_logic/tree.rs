@@ -0,0 +1,531 @@
+//! Recursive directory transfers
+//!
+//! A directory is scanned first into a `TreePlan`, so the number of files and
+//! bytes is known before anything is transferred. Entries that can't be
+//! transferred (special files, skipped or broken links, link loops) are
+//! recorded in the plan, and files that fail don't stop the rest.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use super::queue::TransferControl;
+use super::session::{join_remote, SftpSession};
+use super::types::{AppSftpState, TransferSummary, TreeProgress};
+use crate::config::SymlinkMode;
+use crate::models::SshHost;
+
+/// Everything a directory transfer will create. Paths are relative to the
+/// transferred directory and use `/` as separator.
+#[derive(Debug, Default)]
+pub struct TreePlan {
+    /// Directories to create, parents before children
+    pub dirs: Vec<String>,
+    /// Files with their sizes
+    pub files: Vec<(String, u64)>,
+    /// Modification times of the files in seconds since the epoch, when
+    /// known
+    pub mtimes: HashMap<String, i64>,
+    /// Entries left out, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+impl TreePlan {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+fn join_relative(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// Scan a local directory for an upload
+pub fn scan_local(root: &Path, symlinks: SymlinkMode) -> Result<TreePlan> {
+    let mut plan = TreePlan::default();
+    let mut visited = HashSet::new();
+    visited.insert(
+        fs::canonicalize(root).with_context(|| format!("Failed to read {}", root.display()))?,
+    );
+    scan_local_dir(root, "", symlinks, &mut visited, &mut plan)
+        .with_context(|| format!("Failed to read {}", root.display()))?;
+    Ok(plan)
+}
+
+fn scan_local_dir(
+    dir: &Path,
+    relative: &str,
+    symlinks: SymlinkMode,
+    visited: &mut HashSet<std::path::PathBuf>,
+    plan: &mut TreePlan,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        let relative = join_relative(relative, &name);
+
+        let mut metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                plan.skipped.push((relative, e.to_string()));
+                continue;
+            }
+        };
+        if metadata.file_type().is_symlink() {
+            if symlinks == SymlinkMode::Skip {
+                plan.skipped.push((relative, "symbolic link".to_string()));
+                continue;
+            }
+            metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    plan.skipped
+                        .push((relative, "broken symbolic link".to_string()));
+                    continue;
+                }
+            };
+        }
+
+        if metadata.is_dir() {
+            let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if !visited.insert(canonical) {
+                plan.skipped
+                    .push((relative, "symbolic link loop".to_string()));
+                continue;
+            }
+            plan.dirs.push(relative.clone());
+            if let Err(e) = scan_local_dir(&path, &relative, symlinks, visited, plan) {
+                plan.skipped.push((relative, e.to_string()));
+            }
+        } else if metadata.is_file() {
+            plan.mtimes.insert(relative.clone(), metadata.mtime());
+            plan.files.push((relative, metadata.len()));
+        } else {
+            plan.skipped
+                .push((relative, "not a regular file".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Scan a remote directory for a download
+pub fn scan_remote(session: &SftpSession, root: &str, symlinks: SymlinkMode) -> Result<TreePlan> {
+    let mut plan = TreePlan::default();
+    let mut visited = HashSet::new();
+    visited.insert(
+        session
+            .realpath(root)
+            .with_context(|| format!("Failed to read {}", root))?,
+    );
+    scan_remote_dir(session, root, "", symlinks, &mut visited, &mut plan)
+        .with_context(|| format!("Failed to read {}", root))?;
+    Ok(plan)
+}
+
+fn scan_remote_dir(
+    session: &SftpSession,
+    dir: &str,
+    relative: &str,
+    symlinks: SymlinkMode,
+    visited: &mut HashSet<String>,
+    plan: &mut TreePlan,
+) -> Result<()> {
+    let mut entries = session.read_dir(dir)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for entry in entries {
+        let path = join_remote(dir, &entry.name);
+        let relative = join_relative(relative, &entry.name);
+
+        let mut attrs = entry.attrs;
+        if attrs.is_symlink() {
+            if symlinks == SymlinkMode::Skip {
+                plan.skipped.push((relative, "symbolic link".to_string()));
+                continue;
+            }
+            attrs = match session.stat(&path) {
+                Ok(attrs) => attrs,
+                Err(_) => {
+                    plan.skipped
+                        .push((relative, "broken symbolic link".to_string()));
+                    continue;
+                }
+            };
+        }
+
+        if attrs.is_dir() {
+            let canonical = session.realpath(&path).unwrap_or_else(|_| path.clone());
+            if !visited.insert(canonical) {
+                plan.skipped
+                    .push((relative, "symbolic link loop".to_string()));
+                continue;
+            }
+            plan.dirs.push(relative.clone());
+            if let Err(e) = scan_remote_dir(session, &path, &relative, symlinks, visited, plan) {
+                plan.skipped.push((relative, e.to_string()));
+            }
+        } else if attrs.is_file() {
+            if let Some(mtime) = attrs.mtime {
+                plan.mtimes.insert(relative.clone(), mtime.into());
+            }
+            plan.files.push((relative, attrs.size.unwrap_or(0)));
+        } else {
+            plan.skipped
+                .push((relative, "not a regular file".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Create a remote directory unless it already exists
+fn ensure_remote_dir(session: &SftpSession, path: &str) -> Result<()> {
+    match session.stat(path) {
+        Ok(attrs) if attrs.is_dir() => Ok(()),
+        Ok(_) => bail!("{} exists and is not a directory", path),
+        Err(_) => session.mkdir(path),
+    }
+}
+
+/// Whether `relative` is inside one of the directories in `failed_dirs`
+fn under_failed_dir(relative: &str, failed_dirs: &[(String, String)]) -> bool {
+    failed_dirs.iter().any(|(dir, _)| {
+        relative
+            .strip_prefix(dir.as_str())
+            .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Copy the files of a plan one by one with `copy`, which reports the bytes
+/// of the current file done. `progress` gets the current file, its bytes done
+/// and size, and the progress over the whole plan. Stops with an error once
+/// the transfer is cancelled or the connection is lost.
+fn run_plan<C, F>(
+    session: &SftpSession,
+    plan: &TreePlan,
+    failed_dirs: Vec<(String, String)>,
+    control: &TransferControl,
+    mut copy: C,
+    mut progress: F,
+) -> Result<TransferSummary>
+where
+    C: FnMut(&str, &mut dyn FnMut(u64)) -> Result<()>,
+    F: FnMut(&str, u64, u64, &TreeProgress),
+{
+    let mut tree = TreeProgress {
+        files_total: plan.files.len(),
+        bytes_total: plan.total_bytes(),
+        ..TreeProgress::default()
+    };
+    let mut summary = TransferSummary {
+        skipped: plan.skipped.clone(),
+        failed: failed_dirs,
+        ..TransferSummary::default()
+    };
+
+    let failed_count = summary.failed.len();
+    for (relative, size) in &plan.files {
+        control.wait()?;
+        if under_failed_dir(relative, &summary.failed[..failed_count]) {
+            summary.failed.push((
+                relative.clone(),
+                "directory could not be created".to_string(),
+            ));
+            continue;
+        }
+
+        let done_before = tree.bytes_done;
+        progress(relative, 0, *size, &tree);
+        let result = copy(relative, &mut |done| {
+            tree.bytes_done = done_before + done.min(*size);
+            progress(relative, done, *size, &tree);
+        });
+        match result {
+            Ok(()) => {
+                summary.files += 1;
+                summary.bytes += size;
+            }
+            // Without a connection the remaining files would fail too, so
+            // the whole transfer fails and can be retried
+            Err(e) if control.is_cancelled() || session.is_closed() => return Err(e),
+            Err(e) => {
+                tracing::error!("Failed to transfer {}: {:#}", relative, e);
+                summary.failed.push((relative.clone(), format!("{:#}", e)));
+            }
+        }
+        tree.files_done += 1;
+        tree.bytes_done = done_before + size;
+        progress(relative, *size, *size, &tree);
+    }
+    Ok(summary)
+}
+
+/// Upload the files of a plan from `local_root` into `remote_root`
+pub fn upload_tree<F>(
+    session: &SftpSession,
+    host: &SshHost,
+    local_root: &Path,
+    remote_root: &str,
+    plan: &TreePlan,
+    control: &TransferControl,
+    progress: F,
+) -> Result<TransferSummary>
+where
+    F: FnMut(&str, u64, u64, &TreeProgress),
+{
+    ensure_remote_dir(session, remote_root)?;
+
+    let mut failed_dirs = Vec::new();
+    for dir in &plan.dirs {
+        if under_failed_dir(dir, &failed_dirs) {
+            continue;
+        }
+        if let Err(e) = ensure_remote_dir(session, &join_remote(remote_root, dir)) {
+            failed_dirs.push((dir.clone(), format!("{:#}", e)));
+        }
+    }
+
+    run_plan(
+        session,
+        plan,
+        failed_dirs,
+        control,
+        |relative, done| {
+            AppSftpState::sftp_upload(
+                session,
+                host,
+                &local_root.join(relative),
+                &join_remote(remote_root, relative),
+                control,
+                |uploaded, _| done(uploaded),
+            )
+        },
+        progress,
+    )
+}
+
+/// Download the files of a plan from `remote_root` into `local_root`
+pub fn download_tree<F>(
+    session: &SftpSession,
+    host: &SshHost,
+    remote_root: &str,
+    local_root: &Path,
+    plan: &TreePlan,
+    control: &TransferControl,
+    progress: F,
+) -> Result<TransferSummary>
+where
+    F: FnMut(&str, u64, u64, &TreeProgress),
+{
+    fs::create_dir_all(local_root)
+        .with_context(|| format!("Failed to create {}", local_root.display()))?;
+
+    let mut failed_dirs = Vec::new();
+    for dir in &plan.dirs {
+        if let Err(e) = fs::create_dir_all(local_root.join(dir)) {
+            failed_dirs.push((dir.clone(), e.to_string()));
+        }
+    }
+
+    run_plan(
+        session,
+        plan,
+        failed_dirs,
+        control,
+        |relative, done| {
+            AppSftpState::sftp_download(
+                session,
+                host,
+                &join_remote(remote_root, relative),
+                &local_root.join(relative),
+                control,
+                |downloaded, _| done(downloaded),
+            )
+        },
+        progress,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp_logic::test_server::{connect, host, scratch_dir};
+    use std::os::unix::fs::symlink;
+
+    /// root/a.txt, root/sub/b.txt, root/sub/deeper/c.txt and a few links
+    fn sample_tree(root: &Path) {
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::write(root.join("a.txt"), "aaaa").unwrap();
+        fs::write(root.join("sub/b.txt"), "bb").unwrap();
+        fs::write(root.join("sub/deeper/c.txt"), vec![7u8; 100_000]).unwrap();
+        symlink(root.join("a.txt"), root.join("link.txt")).unwrap();
+        symlink(root.join("missing"), root.join("broken")).unwrap();
+        symlink(root.join("sub"), root.join("sub/deeper/loop")).unwrap();
+    }
+
+    fn skipped(plan: &TreePlan) -> Vec<(&str, &str)> {
+        plan.skipped
+            .iter()
+            .map(|(path, reason)| (path.as_str(), reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn scans_local_tree_following_links() {
+        let dir = scratch_dir("tree-scan-local");
+        let root = dir.join("root");
+        sample_tree(&root);
+
+        let plan = scan_local(&root, SymlinkMode::Follow).unwrap();
+        assert_eq!(plan.dirs, vec!["sub", "sub/deeper"]);
+        assert_eq!(
+            plan.files,
+            vec![
+                ("a.txt".to_string(), 4),
+                ("link.txt".to_string(), 4),
+                ("sub/b.txt".to_string(), 2),
+                ("sub/deeper/c.txt".to_string(), 100_000),
+            ]
+        );
+        assert_eq!(plan.total_bytes(), 100_010);
+        assert_eq!(
+            skipped(&plan),
+            vec![
+                ("broken", "broken symbolic link"),
+                ("sub/deeper/loop", "symbolic link loop"),
+            ]
+        );
+
+        let plan = scan_local(&root, SymlinkMode::Skip).unwrap();
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(
+            skipped(&plan),
+            vec![
+                ("broken", "symbolic link"),
+                ("link.txt", "symbolic link"),
+                ("sub/deeper/loop", "symbolic link"),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scans_remote_tree_like_local() {
+        let dir = scratch_dir("tree-scan-remote");
+        let root = dir.join("root");
+        sample_tree(&root);
+        let session = connect();
+
+        for mode in [SymlinkMode::Follow, SymlinkMode::Skip] {
+            let local = scan_local(&root, mode).unwrap();
+            let remote = scan_remote(&session, &root.to_string_lossy(), mode).unwrap();
+            assert_eq!(remote.dirs, local.dirs);
+            assert_eq!(remote.files, local.files);
+            assert_eq!(remote.skipped, local.skipped);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn uploads_and_downloads_tree() {
+        let dir = scratch_dir("tree-round-trip");
+        let source = dir.join("source");
+        sample_tree(&source);
+        let session = connect();
+        let host = host();
+
+        let uploaded = dir.join("uploaded");
+        let plan = scan_local(&source, SymlinkMode::Follow).unwrap();
+        let mut last = TreeProgress::default();
+        let summary = upload_tree(
+            &session,
+            &host,
+            &source,
+            &uploaded.to_string_lossy(),
+            &plan,
+            &TransferControl::default(),
+            |_, _, _, tree| last = tree.clone(),
+        )
+        .unwrap();
+        assert_eq!((summary.files, summary.bytes), (4, 100_010));
+        assert_eq!(summary.skipped.len(), 2);
+        assert!(summary.failed.is_empty());
+        assert_eq!((last.files_done, last.files_total), (4, 4));
+        assert_eq!((last.bytes_done, last.bytes_total), (100_010, 100_010));
+        assert!(!uploaded.join("link.txt").is_symlink());
+        assert_eq!(
+            fs::read(uploaded.join("sub/deeper/c.txt")).unwrap(),
+            vec![7u8; 100_000]
+        );
+
+        let downloaded = dir.join("downloaded");
+        let remote_root = uploaded.to_string_lossy();
+        let plan = scan_remote(&session, &remote_root, SymlinkMode::Follow).unwrap();
+        let summary =
+            download_tree(
+                &session,
+                &host,
+                &remote_root,
+                &downloaded,
+                &plan,
+                &TransferControl::default(),
+                |_, _, _, _| {},
+            )
+            .unwrap();
+        assert_eq!((summary.files, summary.bytes), (4, 100_010));
+        assert!(summary.skipped.is_empty());
+        assert_eq!(
+            fs::read_to_string(downloaded.join("sub/b.txt")).unwrap(),
+            "bb"
+        );
+        assert_eq!(
+            fs::read(downloaded.join("sub/deeper/c.txt")).unwrap(),
+            vec![7u8; 100_000]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_going_past_failures() {
+        let dir = scratch_dir("tree-failures");
+        let source = dir.join("source");
+        sample_tree(&source);
+        let session = connect();
+        let host = host();
+
+        // A file where the upload wants the "sub" directory
+        let target = dir.join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("sub"), "in the way").unwrap();
+
+        let plan = scan_local(&source, SymlinkMode::Skip).unwrap();
+        let summary = upload_tree(
+            &session,
+            &host,
+            &source,
+            &target.to_string_lossy(),
+            &plan,
+            &TransferControl::default(),
+            |_, _, _, _| {},
+        )
+        .unwrap();
+        assert_eq!(summary.files, 1);
+        let failed: Vec<&str> = summary
+            .failed
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(failed, vec!["sub", "sub/b.txt", "sub/deeper/c.txt"]);
+        assert!(summary.failed[0].1.contains("not a directory"));
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "aaaa");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,37 +1,109 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
+use ratatui::layout::Rect;
 use ratatui::widgets::ListState;
 use tokio::sync::mpsc;
 use crate::app_event::TransferEvent;
+use crate::config::{RecentDirs, SftpConfig};
+use crate::models::SshHost;
+use super::checksum::ChecksumMismatch;
+use super::conflict::ConflictPrompt;
+use super::edit::RemoteEdit;
+use super::file_ops::FilePrompt;
+use super::history::HistoryView;
+use super::queue::TransferQueue;
+use super::listing::SortOrder;
+use super::places::Places;
+use super::preview::Preview;
+use super::session::SharedSession;
+use super::sync::SyncView;
 
 /// Represents a file or directory item in the file browser
 #[derive(Debug, Clone)]
 pub enum FileItem {
-    Directory { name: String },
-    File { name: String, size: u64 },
+    Directory { name: String, meta: FileMeta },
+    File { name: String, size: u64, meta: FileMeta },
+}
+
+/// Details of a listed entry, each left out when the file system or the
+/// server doesn't tell
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileMeta {
+    /// Modification time in seconds since the epoch
+    pub mtime: Option<i64>,
+    /// Permission bits without the file type, e.g. 0o644
+    pub permissions: Option<u32>,
+    /// User name, or the uid when there is no name for it
+    pub owner: Option<String>,
+    /// Group name, or the gid when there is no name for it
+    pub group: Option<String>,
+    /// Where the entry points when it is a symbolic link
+    pub link_target: Option<String>,
 }
 
 /// Represents which panel (local or remote) is currently active
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PanelSide {
     Local,
     Remote,
+    Queue,
 }
 
-/// Represents upload progress information
-#[derive(Debug, Clone)]
-pub struct UploadProgress {
-    pub file_name: String,
-    pub uploaded_size: u64,
-    pub total_size: u64,
+/// Progress over all files of a directory transfer
+#[derive(Debug, Clone, Default)]
+pub struct TreeProgress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
 }
 
-/// Represents download progress information
+/// Progress of the file being transferred
 #[derive(Debug, Clone)]
-pub struct DownloadProgress {
+pub struct FileProgress {
     pub file_name: String,
-    pub downloaded_size: u64,
-    pub total_size: u64,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// Outcome of a directory transfer, entries are paths relative to the
+/// transferred directory with the reason they were left out
+#[derive(Debug, Clone, Default)]
+pub struct TransferSummary {
+    /// e.g. "Uploaded configs"
+    pub title: String,
+    pub files: usize,
+    pub bytes: u64,
+    pub skipped: Vec<(String, String)>,
+    pub failed: Vec<(String, String)>,
+    /// Files that were copied but whose SHA-256 differs, also in `failed`
+    pub mismatched: Vec<ChecksumMismatch>,
+}
+
+impl TransferSummary {
+    /// One line for the status bar, e.g. "Uploaded configs: 12 files, 1 skipped"
+    pub fn headline(&self) -> String {
+        let mut line = format!(
+            "{}: {} file{}",
+            self.title,
+            self.files,
+            if self.files == 1 { "" } else { "s" }
+        );
+        if !self.skipped.is_empty() {
+            line.push_str(&format!(", {} skipped", self.skipped.len()));
+        }
+        if !self.failed.is_empty() {
+            line.push_str(&format!(", {} failed", self.failed.len()));
+        }
+        line
+    }
+
+    /// Whether there is anything left out worth showing the summary for
+    pub fn has_problems(&self) -> bool {
+        !self.skipped.is_empty() || !self.failed.is_empty()
+    }
 }
 
 /// Main application state for the SFTP file browser
@@ -42,39 +114,116 @@ pub struct AppSftpState {
     
     // Local panel state
     pub local_current_path: PathBuf,
+    /// Everything in the local directory, sorted
+    pub local_all: Vec<FileItem>,
+    /// The entries of `local_all` that are shown
     pub local_files: Vec<FileItem>,
     pub local_selected: usize,
     pub local_list_state: ListState,
+    /// Names marked for a transfer in the current local directory
+    pub local_marked: BTreeSet<String>,
+    /// Fuzzy filter on the names of the local directory
+    pub local_filter: String,
     
     // Remote panel state
     pub remote_current_path: String,
+    /// Everything in the remote directory, sorted
+    pub remote_all: Vec<FileItem>,
+    /// The entries of `remote_all` that are shown
     pub remote_files: Vec<FileItem>,
     pub remote_selected: usize,
     pub remote_list_state: ListState,
+    /// Names marked for a transfer in the current remote directory
+    pub remote_marked: BTreeSet<String>,
+    /// Fuzzy filter on the names of the remote directory
+    pub remote_filter: String,
     
     // SFTP connection info
-    pub ssh_host: String,
-    pub ssh_user: String,
-    pub ssh_port: u16,
+    pub host: SshHost,
+    /// SFTP session used for listings and transfers, open as long as the
+    /// state and reconnected when the connection drops
+    pub session: Arc<SharedSession>,
+    pub config: SftpConfig,
+    /// Whether new transfers are checked with SHA-256 afterwards
+    pub verify: bool,
+    /// Files that differ from their source after a verified transfer, shown
+    /// in the panels until transferred again
+    pub mismatched: Vec<ChecksumMismatch>,
+    /// Order of both panels
+    pub sort: SortOrder,
+    /// Whether the panels show the metadata columns of `config.columns`
+    pub details: bool,
+    /// Whether dotfiles are shown
+    pub show_hidden: bool,
+    /// Keys go to the filter of the active panel
+    pub filtering: bool,
+    /// The next key is a letter to jump to
+    pub jumping: bool,
+    /// Entries a file panel shows at once, for PageUp and PageDown
+    pub page_size: usize,
+    /// Whether the preview pane takes the place of the inactive file panel
+    pub show_preview: bool,
+    pub preview: Option<Preview>,
+    /// Remote entry selected since when, previewed once it stays selected
+    pub preview_pending: Option<(String, Instant)>,
+    /// Entry whose preview a worker thread is reading
+    pub preview_loading: Option<(PanelSide, String)>,
+    /// Directories visited on this host, saved when SFTP mode is left
+    pub recent: RecentDirs,
+    /// Bookmarks and recent directories to pick from
+    pub places: Option<Places>,
     
     // UI state
     pub status_message: Option<String>,
+    // Where the file panels and the queue were last drawn, for the mouse
+    pub panel_areas: Vec<(PanelSide, Rect)>,
+    // File panel an entry is being dragged from
+    pub drag: Option<PanelSide>,
     pub status_message_time: Option<Instant>,
     
-    // Upload progress
-    pub upload_progress: Option<UploadProgress>,
-    // Download progress
-    pub download_progress: Option<DownloadProgress>,
+    // Uploads and downloads, finished ones until cleared
+    pub queue: TransferQueue,
+    // Transfers held back because their target exists
+    pub conflicts: Option<ConflictPrompt>,
+    // Skipped and failed entries of a directory transfer
+    pub transfer_summary: Option<TransferSummary>,
+    // Rename, delete and the other file operations waiting for input
+    pub prompt: Option<FilePrompt>,
+    // Edited remote file whose upload waits for confirmation, because the
+    // remote file changed while it was open
+    pub pending_edit: Option<RemoteEdit>,
+    // Differences between the local and the remote directory while they
+    // are synchronized
+    pub sync: Option<SyncView>,
+    // File finished transfers are appended to, none in tests
+    pub history_file: Option<PathBuf>,
+    // Past transfers of this host while they are browsed
+    pub history: Option<HistoryView>,
 
     // Transfer event sender
     pub transfer_tx: Option<mpsc::Sender<TransferEvent>>,
 }
 
 impl FileItem {
+    /// The `..` entry leading to the parent directory
+    pub fn parent() -> Self {
+        FileItem::Directory {
+            name: "..".to_string(),
+            meta: FileMeta::default(),
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
-            FileItem::Directory { name } => name,
+            FileItem::Directory { name, .. } => name,
             FileItem::File { name, .. } => name,
         }
     }
+
+    pub fn meta(&self) -> &FileMeta {
+        match self {
+            FileItem::Directory { meta, .. } => meta,
+            FileItem::File { meta, .. } => meta,
+        }
+    }
 }
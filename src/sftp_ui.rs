@@ -1,21 +1,45 @@
-use crate::sftp_logic::types::{
-    AppSftpState, DownloadProgress, FileItem, PanelSide, UploadProgress,
-};
+use crate::config::Column;
+use crate::sftp_logic::conflict::{ConflictPrompt, EntryStat};
+use crate::sftp_logic::edit::RemoteEdit;
+use crate::sftp_logic::file_ops::{FileOperation, FilePrompt};
+use crate::sftp_logic::history::HistoryView;
+use crate::sftp_logic::places::{PlaceKind, Places};
+use crate::sftp_logic::preview::{hex_lines, highlight, Highlight, Preview, PreviewContent};
+use crate::sftp_logic::queue::{QueuedTransfer, TransferDirection, TransferQueue, TransferState};
+use crate::sftp_logic::sync::{SyncAction, SyncChange, SyncDirection, SyncPlan, SyncSide, SyncView};
+use crate::sftp_logic::types::{AppSftpState, FileItem, PanelSide, TransferSummary};
+use crate::keybindings::{Action, Keymap, Mode};
+use crate::theme::Palette;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
 use ratatui::{
-    backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-pub fn draw_sftp<B: Backend>(f: &mut Frame, sftp_state: &mut AppSftpState) {
+pub fn draw_sftp(
+    f: &mut Frame,
+    sftp_state: &mut AppSftpState,
+    theme: &Palette,
+    keymap: &Keymap,
+) {
+    f.render_widget(Block::default().style(theme.base()), f.size());
+
+    // The transfer queue shows up to six transfers once anything is queued
+    let queue_height = match sftp_state.queue.items.len() {
+        0 => 0,
+        count => count.min(6) as u16 + 2,
+    };
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(3),    // Main SFTP content
-            Constraint::Length(3), // Footer with controls
+            Constraint::Min(3),               // Main SFTP content
+            Constraint::Length(queue_height), // Transfer queue
+            Constraint::Length(3),            // Footer with controls
         ])
         .split(f.size());
 
@@ -25,69 +49,157 @@ pub fn draw_sftp<B: Backend>(f: &mut Frame, sftp_state: &mut AppSftpState) {
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(main_chunks[0]);
 
+    // PageUp and PageDown move by what a panel shows inside its borders
+    sftp_state.page_size = panels[0].height.saturating_sub(2) as usize;
+    sftp_state.panel_areas.clear();
+
+    let local_mismatches = sftp_state.local_mismatches();
+    let remote_mismatches = sftp_state.remote_mismatches();
+    let columns = if sftp_state.details {
+        sftp_state.config.columns.clone()
+    } else {
+        Vec::new()
+    };
+    let columns = &columns;
+
+    // The preview takes the place of the inactive file panel
+    let preview_slot = match (sftp_state.show_preview, sftp_state.active_panel) {
+        (true, PanelSide::Local) => Some(PanelSide::Remote),
+        (true, PanelSide::Remote) => Some(PanelSide::Local),
+        _ => None,
+    };
+
     // Draw local panel (left)
-    draw_file_panel::<B>(
-        f,
-        panels[0],
-        &mut sftp_state.local_list_state,
-        &sftp_state.local_files,
-        sftp_state.local_selected,
-        &format!("Local: {}", sftp_state.local_current_path.display()),
-        sftp_state.active_panel == PanelSide::Local,
-    );
+    if preview_slot == Some(PanelSide::Local) {
+        draw_preview(f, panels[0], sftp_state.preview.as_ref(), theme);
+    } else {
+        sftp_state.panel_areas.push((PanelSide::Local, panels[0]));
+        draw_file_panel(
+            f,
+            panels[0],
+            &mut sftp_state.local_list_state,
+            &sftp_state.local_files,
+            &sftp_state.local_marked,
+            &local_mismatches,
+            columns,
+            sftp_state.local_selected,
+            &panel_title(
+                format!("Local: {}", sftp_state.local_current_path.display()),
+                &sftp_state.local_filter,
+                sftp_state.filtering && sftp_state.active_panel == PanelSide::Local,
+            ),
+            sftp_state.active_panel == PanelSide::Local,
+            theme,
+        );
+    }
 
     // Draw remote panel (right)
-    draw_file_panel::<B>(
-        f,
-        panels[1],
-        &mut sftp_state.remote_list_state,
-        &sftp_state.remote_files,
-        sftp_state.remote_selected,
-        &format!("Remote: {}", sftp_state.remote_current_path),
-        sftp_state.active_panel == PanelSide::Remote,
-    );
+    if preview_slot == Some(PanelSide::Remote) {
+        draw_preview(f, panels[1], sftp_state.preview.as_ref(), theme);
+    } else {
+        sftp_state.panel_areas.push((PanelSide::Remote, panels[1]));
+        draw_file_panel(
+            f,
+            panels[1],
+            &mut sftp_state.remote_list_state,
+            &sftp_state.remote_files,
+            &sftp_state.remote_marked,
+            &remote_mismatches,
+            columns,
+            sftp_state.remote_selected,
+            &panel_title(
+                format!("Remote: {}", sftp_state.remote_current_path),
+                &sftp_state.remote_filter,
+                sftp_state.filtering && sftp_state.active_panel == PanelSide::Remote,
+            ),
+            sftp_state.active_panel == PanelSide::Remote,
+            theme,
+        );
+    }
+
+    if queue_height > 0 {
+        sftp_state.panel_areas.push((PanelSide::Queue, main_chunks[1]));
+        draw_transfer_queue(
+            f,
+            main_chunks[1],
+            &mut sftp_state.queue,
+            sftp_state.active_panel == PanelSide::Queue,
+            theme,
+        );
+    }
 
     // Draw footer with controls
-    draw_sftp_footer::<B>(f, main_chunks[1], sftp_state);
+    draw_sftp_footer(f, main_chunks[2], sftp_state, theme, keymap);
+
+    if let Some(view) = &mut sftp_state.sync {
+        draw_sync(f, view, theme, keymap);
+    }
+
+    if let Some(places) = &mut sftp_state.places {
+        draw_places(f, places, theme, keymap);
+    }
+
+    if let Some(history) = &mut sftp_state.history {
+        draw_history(f, history, theme, keymap);
+    }
 
     // Draw status message if exists
     if let Some(ref message) = sftp_state.status_message {
-        draw_status_overlay::<B>(f, message);
+        draw_status_overlay(f, message, theme);
+    }
+
+    if let Some(ref summary) = sftp_state.transfer_summary {
+        draw_transfer_summary(f, summary, theme);
+    }
+
+    if let Some(ref prompt) = sftp_state.prompt {
+        draw_file_prompt(f, prompt, theme);
     }
 
-    // Draw upload progress if active
-    if let Some(ref progress) = sftp_state.upload_progress {
-        draw_upload_progress::<B>(f, progress);
+    if let Some(ref conflicts) = sftp_state.conflicts {
+        draw_transfer_conflict(f, conflicts, theme, keymap);
     }
 
-    // Draw download progress if active
-    if let Some(ref progress) = sftp_state.download_progress {
-        draw_download_progress::<B>(f, progress);
+    if let Some(ref edit) = sftp_state.pending_edit {
+        draw_edit_conflict(f, edit, theme);
     }
 }
 
-fn draw_file_panel<B: Backend>(
+/// Location of a panel with its filter, and a cursor while it is typed
+fn panel_title(location: String, filter: &str, typing: bool) -> String {
+    if filter.is_empty() && !typing {
+        return location;
+    }
+    format!("{} [/{}{}]", location, filter, if typing { "▏" } else { "" })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_file_panel(
     f: &mut Frame,
     area: Rect,
     list_state: &mut ListState,
     files: &[FileItem],
+    marked: &BTreeSet<String>,
+    mismatched: &BTreeSet<String>,
+    columns: &[Column],
     selected: usize,
     title: &str,
     is_active: bool,
+    theme: &Palette,
 ) {
     let border_style = if is_active {
-        Style::default().fg(Color::Green)
+        Style::default().fg(theme.primary)
     } else {
-        Style::default().fg(Color::Gray)
+        Style::default().fg(theme.secondary)
     };
 
     let title_style = if is_active {
         Style::default()
-            .fg(Color::Green)
+            .fg(theme.primary)
             .add_modifier(Modifier::BOLD)
     } else {
         Style::default()
-            .fg(Color::Gray)
+            .fg(theme.secondary)
             .add_modifier(Modifier::BOLD)
     };
 
@@ -97,65 +209,105 @@ fn draw_file_panel<B: Backend>(
         .title(title)
         .title_style(title_style);
 
+    // Leave out the last columns when they would squeeze the names
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let mut columns = columns;
+    while !columns.is_empty() && inner_width < columns_width(columns) + MIN_NAME_WIDTH + 5 {
+        columns = &columns[..columns.len() - 1];
+    }
+    let columns_width = columns_width(columns);
+
     let list_items: Vec<ListItem> = files
         .iter()
         .enumerate()
         .map(|(i, file)| {
             let is_selected = i == selected && is_active;
+            let is_marked = marked.contains(file.name());
+            let is_mismatched = mismatched.contains(file.name());
             let mut spans = vec![];
 
-            // Selection indicator
+            // Selection and mark indicators, `!` for a copy that differs
+            // from its source
             spans.push(Span::styled(
-                if is_selected { "> " } else { "  " },
-                Style::default().fg(Color::Yellow),
+                format!(
+                    "{}{}",
+                    if is_selected { ">" } else { " " },
+                    if is_marked {
+                        "*"
+                    } else if is_mismatched {
+                        "!"
+                    } else {
+                        " "
+                    }
+                ),
+                Style::default().fg(if is_mismatched { theme.error } else { theme.warning }),
             ));
 
             // File type icon and name
             let (icon, name_color) = match file {
-                FileItem::Directory { name } => {
+                FileItem::Directory { name, .. } => {
                     if name == ".." {
-                        ("↰ ", Color::Cyan)
+                        ("↰ ", theme.highlight)
                     } else {
-                        ("📁 ", Color::Blue)
+                        ("📁 ", theme.highlight)
                     }
                 }
-                FileItem::File {
-                    name: _name,
-                    size: _,
-                } => ("📄 ", Color::White),
+                FileItem::File { .. } => ("📄 ", theme.text),
             };
 
-            spans.push(Span::styled(icon, Style::default().fg(Color::Yellow)));
+            spans.push(Span::styled(icon, Style::default().fg(theme.warning)));
 
-            let name = match file {
-                FileItem::Directory { name } => name,
-                FileItem::File { name, size: _ } => name,
-            };
+            let mut name_style = Style::default().fg(if is_selected {
+                theme.on_fill()
+            } else if is_marked {
+                theme.success
+            } else if is_mismatched {
+                theme.error
+            } else {
+                name_color
+            });
+            if is_marked {
+                name_style = name_style.add_modifier(Modifier::BOLD);
+            }
+            let detail_style = Style::default().fg(if is_selected {
+                theme.on_fill()
+            } else {
+                theme.secondary
+            });
 
-            spans.push(Span::styled(
-                name,
-                Style::default().fg(if is_selected {
-                    Color::Black
-                } else {
-                    name_color
-                }),
-            ));
+            if columns.is_empty() {
+                spans.push(Span::styled(file.name(), name_style));
 
-            // File size for files
-            if let FileItem::File { size, .. } = file {
-                spans.push(Span::styled(
-                    format!(" ({})", format_file_size(*size)),
-                    Style::default().fg(if is_selected {
-                        Color::Black
-                    } else {
-                        Color::Gray
-                    }),
-                ));
+                // File size for files
+                if let FileItem::File { size, .. } = file {
+                    spans.push(Span::styled(
+                        format!(" ({})", format_file_size(*size)),
+                        detail_style,
+                    ));
+                }
+            } else {
+                // The name fills the width left by the columns, so they line
+                // up on the right
+                let used: usize = spans.iter().map(Span::width).sum();
+                let mut name = file.name().to_string();
+                if let (true, Some(target)) =
+                    (columns.contains(&Column::Link), &file.meta().link_target)
+                {
+                    name.push_str(" → ");
+                    name.push_str(target);
+                }
+                let name_width = inner_width.saturating_sub(used + columns_width);
+                spans.push(Span::styled(fit(&name, name_width), name_style));
+                for column in columns {
+                    if let Some(text) = column_text(*column, file) {
+                        spans.push(Span::styled(format!(" {}", text), detail_style));
+                    }
+                }
             }
 
             let style = if is_selected {
                 Style::default()
-                    .bg(Color::Green)
+                    .bg(theme.primary)
                     .add_modifier(Modifier::BOLD)
             } else {
                 Style::default()
@@ -169,7 +321,263 @@ fn draw_file_panel<B: Backend>(
     f.render_stateful_widget(list, area, list_state);
 }
 
-fn draw_sftp_footer<B: Backend>(f: &mut Frame, area: Rect, sftp_state: &AppSftpState) {
+/// Head of the selected file, or what the selected directory holds
+fn draw_preview(f: &mut Frame, area: Rect, preview: Option<&Preview>, theme: &Palette) {
+    let text_style = Style::default().fg(theme.text);
+    let desc_style = Style::default().fg(theme.secondary);
+    let inner_height = area.height.saturating_sub(2) as usize;
+
+    let (title, lines) = match preview {
+        None => (" Preview ".to_string(), vec![Line::from(Span::styled("Loading...", desc_style))]),
+        Some(preview) => {
+            let read = |truncated: bool| if truncated { " (start)" } else { "" };
+            match &preview.content {
+                PreviewContent::Text { lines, syntax, truncated } => (
+                    format!(" Preview: {}{} ", preview.name, read(*truncated)),
+                    lines
+                        .iter()
+                        .take(inner_height)
+                        .map(|line| {
+                            Line::from(
+                                highlight(*syntax, line)
+                                    .into_iter()
+                                    .map(|(kind, text)| {
+                                        Span::styled(text, highlight_style(kind, theme))
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )
+                        })
+                        .collect(),
+                ),
+                PreviewContent::Binary { bytes, truncated } => (
+                    format!(" Preview: {} (binary){} ", preview.name, read(*truncated)),
+                    hex_lines(bytes)
+                        .into_iter()
+                        .take(inner_height)
+                        .map(|line| Line::from(Span::styled(line, text_style)))
+                        .collect(),
+                ),
+                PreviewContent::Directory { directories, files, bytes } => (
+                    format!(" Preview: {} ", preview.name),
+                    vec![
+                        Line::from(Span::styled(
+                            format!(
+                                "{} entries: {} directories, {} files",
+                                directories + files,
+                                directories,
+                                files
+                            ),
+                            text_style,
+                        )),
+                        Line::from(Span::styled(
+                            format!("Files total {}", format_file_size(*bytes)),
+                            text_style,
+                        )),
+                        Line::from(Span::styled(
+                            "Not counting what is in subdirectories",
+                            desc_style,
+                        )),
+                    ],
+                ),
+                PreviewContent::Parent => (
+                    " Preview ".to_string(),
+                    vec![Line::from(Span::styled("Parent directory", desc_style))],
+                ),
+                PreviewContent::Error(error) => (
+                    format!(" Preview: {} ", preview.name),
+                    vec![Line::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(theme.error),
+                    ))],
+                ),
+            }
+        }
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.secondary))
+        .title(title)
+        .title_style(Style::default().fg(theme.secondary).add_modifier(Modifier::BOLD));
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn highlight_style(kind: Highlight, theme: &Palette) -> Style {
+    match kind {
+        Highlight::Plain => Style::default().fg(theme.text),
+        Highlight::Comment => Style::default().fg(theme.secondary),
+        Highlight::Key => Style::default().fg(theme.primary),
+        Highlight::Str => Style::default().fg(theme.success),
+        Highlight::Number => Style::default().fg(theme.warning),
+        Highlight::Heading => Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+        Highlight::Error => Style::default().fg(theme.error),
+        Highlight::Warning => Style::default().fg(theme.warning),
+    }
+}
+
+fn draw_transfer_queue(
+    f: &mut Frame,
+    area: Rect,
+    queue: &mut TransferQueue,
+    is_active: bool,
+    theme: &Palette,
+) {
+    let color = if is_active { theme.primary } else { theme.secondary };
+    let running = queue.running();
+    let waiting = queue
+        .items
+        .iter()
+        .filter(|item| item.state == TransferState::Queued)
+        .count();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(color))
+        .title(format!(" Transfers: {} running, {} queued ", running, waiting))
+        .title_style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+
+    let list_items: Vec<ListItem> = queue
+        .items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let is_selected = i == queue.selected && is_active;
+            let style = if is_selected {
+                Style::default()
+                    .bg(theme.primary)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(transfer_line(item, is_selected, theme)).style(style)
+        })
+        .collect();
+
+    queue.list_state.select(Some(queue.selected));
+    let list = List::new(list_items).block(block);
+    f.render_stateful_widget(list, area, &mut queue.list_state);
+}
+
+/// One row of the transfer queue: direction, name, state and progress
+fn transfer_line<'a>(item: &'a QueuedTransfer, is_selected: bool, theme: &Palette) -> Line<'a> {
+    let text = |color| Style::default().fg(if is_selected { theme.on_fill() } else { color });
+    let state_color = match item.state {
+        TransferState::Queued | TransferState::Cancelled => theme.secondary,
+        TransferState::Running => theme.highlight,
+        TransferState::Paused => theme.warning,
+        TransferState::Done => theme.success,
+        TransferState::Failed(_) => theme.error,
+    };
+    let arrow = match item.direction {
+        TransferDirection::Upload => "↑ ",
+        TransferDirection::Download => "↓ ",
+    };
+
+    let mut spans = vec![
+        Span::styled(if is_selected { "> " } else { "  " }, text(theme.warning)),
+        Span::styled(arrow, text(theme.warning)),
+        Span::styled(item.name.as_str(), text(theme.text)),
+        Span::styled(format!("  {:<9}", item.state.label()), text(state_color)),
+    ];
+
+    let detail = match (&item.state, &item.tree, &item.progress) {
+        (TransferState::Running, _, _) if item.verifying => "verifying SHA-256...".to_string(),
+        (TransferState::Failed(error), _, _) => error.clone(),
+        (TransferState::Queued | TransferState::Cancelled, _, _) => String::new(),
+        (_, Some(tree), _) => format!(
+            "{} {}/{} files, {} / {}",
+            progress_bar(tree.bytes_done, tree.bytes_total),
+            tree.files_done,
+            tree.files_total,
+            format_file_size(tree.bytes_done),
+            format_file_size(tree.bytes_total)
+        ),
+        (_, None, Some(progress)) if !item.is_dir => format!(
+            "{} {} / {}",
+            progress_bar(progress.done, progress.total),
+            format_file_size(progress.done),
+            format_file_size(progress.total)
+        ),
+        (TransferState::Running, None, _) if item.is_dir => "scanning...".to_string(),
+        _ => String::new(),
+    };
+    spans.push(Span::styled(detail, text(theme.secondary)));
+
+    let now = Instant::now();
+    let elapsed = item.stats.elapsed(now).map(format_duration);
+    // The transfer's own rate limit, the footer shows the queue's throttle
+    let limit = item
+        .control
+        .limit()
+        .map(|limit| format!(" (max {}/s)", format_file_size(limit)))
+        .unwrap_or_default();
+    let timing = match (&item.state, elapsed) {
+        (TransferState::Running, elapsed) => format!(
+            "  {}{}  ETA {}  {}",
+            item.stats
+                .rate()
+                .map_or("-".to_string(), |rate| format!("{}/s", format_file_size(rate as u64))),
+            limit,
+            item.stats.eta().map_or("-".to_string(), format_duration),
+            elapsed.unwrap_or_default()
+        ),
+        (TransferState::Queued, _) => format!(" {}", limit),
+        (TransferState::Paused, Some(elapsed)) => format!("  {} elapsed{}", elapsed, limit),
+        (TransferState::Done, Some(elapsed)) => match item.stats.average_rate(now) {
+            Some(rate) => format!("  in {}, {}/s", elapsed, format_file_size(rate as u64)),
+            None => format!("  in {}", elapsed),
+        },
+        _ => String::new(),
+    };
+    match &item.retrying {
+        Some((attempt, error)) => spans.push(Span::styled(
+            format!("  connection lost, retry {}: {}", attempt, error),
+            text(theme.warning),
+        )),
+        None => spans.push(Span::styled(timing, text(theme.highlight))),
+    }
+
+    // The file a directory transfer is at
+    if let (TransferState::Running | TransferState::Paused, true, Some(progress)) =
+        (&item.state, item.tree.is_some(), &item.progress)
+    {
+        spans.push(Span::styled(
+            format!("  {}", progress.file_name),
+            text(theme.secondary),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour on
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// A ten-cell bar with the percentage, e.g. `█████░░░░░  50%`
+fn progress_bar(done: u64, total: u64) -> String {
+    let percent = if total > 0 {
+        (done.min(total) as f64 / total as f64 * 100.0) as usize
+    } else {
+        0
+    };
+    let filled = percent / 10;
+    format!("{}{} {:>3}%", "█".repeat(filled), "░".repeat(10 - filled), percent)
+}
+
+fn draw_sftp_footer(
+    f: &mut Frame,
+    area: Rect,
+    sftp_state: &AppSftpState,
+    theme: &Palette,
+    keymap: &Keymap,
+) {
     let footer_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -179,32 +587,119 @@ fn draw_sftp_footer<B: Backend>(f: &mut Frame, area: Rect, sftp_state: &AppSftpS
         ])
         .split(area);
 
-    // Navigation help
-    let nav_text = "↑/↓: Navigate  [Enter]: Open  [Backspace]: Back  [Tab]: Switch Panel";
-    let nav_help = Paragraph::new(nav_text).style(Style::default().fg(Color::Gray));
+    // Navigation help, or the filter keys while one is typed
+    let nav_text = if sftp_state.filtering {
+        "Type to filter  [Enter]: Keep  [Esc]: Clear  [Up/Down]: Move".to_string()
+    } else {
+        hint_text(keymap.hints(
+            Mode::Sftp,
+            &[
+                (Action::Up, "Up"),
+                (Action::Down, "Down"),
+                (Action::Open, "Open"),
+                (Action::Back, "Back"),
+                (Action::SwitchPanel, "Switch Panel"),
+                (Action::Mark, "Mark"),
+                (Action::Filter, "Filter"),
+                (Action::JumpToLetter, "Jump"),
+            ],
+        ))
+    };
+    let nav_help = Paragraph::new(nav_text).style(Style::default().fg(theme.secondary));
 
     // Action help
-    let action_text = "[u]: Upload  [d]: Download  [r]: Refresh  [q]: Quit SFTP";
-    let action_help = Paragraph::new(action_text).style(Style::default().fg(Color::Yellow));
+    let action_text = hint_text(keymap.hints(
+        Mode::Sftp,
+        &[
+            (Action::Upload, "Upload"),
+            (Action::Download, "Download"),
+            (Action::Reload, "Refresh"),
+            (Action::ToggleVerify, "Verify"),
+            (Action::CycleSort, "Sort"),
+            (Action::ReverseSort, "Reverse"),
+            (Action::ToggleDetails, "Details"),
+            (Action::ToggleHidden, "Hidden"),
+            (Action::TogglePreview, "Preview"),
+            (Action::Quit, "Quit SFTP"),
+        ],
+    ));
+    let action_help = Paragraph::new(action_text).style(Style::default().fg(theme.warning));
+
+    // Queue help
+    let queue_text = hint_text(keymap.hints(
+        Mode::Sftp,
+        &[
+            (Action::PauseTransfer, "Pause/Resume"),
+            (Action::CancelTransfer, "Cancel"),
+            (Action::RetryTransfer, "Retry"),
+            (Action::ClearTransfers, "Clear Finished"),
+            (Action::LimitTransfer, "Limit"),
+            (Action::ToggleThrottle, "Throttle All"),
+            (Action::History, "History"),
+        ],
+    ));
+    let queue_help = Paragraph::new(queue_text).style(Style::default().fg(theme.secondary));
+
+    // File operation help
+    let file_text = hint_text(keymap.hints(
+        Mode::Sftp,
+        &[
+            (Action::Rename, "Rename"),
+            (Action::DeleteEntry, "Delete"),
+            (Action::MakeDir, "New Dir"),
+            (Action::NewFile, "New File"),
+            (Action::Chmod, "Chmod"),
+            (Action::Chown, "Chown"),
+            (Action::EditFile, "Edit"),
+            (Action::Sync, "Sync"),
+            (Action::GoTo, "Go To"),
+            (Action::Bookmark, "Bookmark"),
+            (Action::Places, "Places"),
+        ],
+    ));
+    let file_help = Paragraph::new(file_text).style(Style::default().fg(theme.secondary));
 
     // Status/Info
+    let throttle = sftp_state
+        .queue
+        .throttle()
+        .map(|limit| format!("Throttled to {}/s | ", format_file_size(limit)))
+        .unwrap_or_default();
     let active_panel_text = format!(
-        "Active: {} Panel",
+        "{}{}Sort: {} | Active: {} Panel",
+        throttle,
+        if sftp_state.verify { "SHA-256 verify | " } else { "" },
+        sftp_state.sort.describe(),
         match sftp_state.active_panel {
             PanelSide::Local => "Local",
             PanelSide::Remote => "Remote",
+            PanelSide::Queue => "Queue",
         }
     );
     let status_help = Paragraph::new(active_panel_text)
-        .style(Style::default().fg(Color::Cyan))
+        .style(Style::default().fg(theme.highlight))
         .alignment(ratatui::layout::Alignment::Right);
 
     f.render_widget(nav_help, footer_chunks[0]);
     f.render_widget(action_help, footer_chunks[1]);
+    if sftp_state.active_panel == PanelSide::Queue {
+        f.render_widget(queue_help, footer_chunks[2]);
+    } else {
+        f.render_widget(file_help, footer_chunks[2]);
+    }
     f.render_widget(status_help, footer_chunks[2]);
 }
 
-fn draw_status_overlay<B: Backend>(f: &mut Frame, message: &str) {
+/// `[key]: Description` pairs separated by two spaces
+fn hint_text(hints: Vec<(String, &str)>) -> String {
+    hints
+        .iter()
+        .map(|(keys, description)| format!("[{}]: {}", keys, description))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+fn draw_status_overlay(f: &mut Frame, message: &str, theme: &Palette) {
     let area = centered_rect(60, 5, f.size());
 
     let block = Block::default()
@@ -212,129 +707,758 @@ fn draw_status_overlay<B: Backend>(f: &mut Frame, message: &str) {
         .title(" Status ")
         .title_style(
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
         )
-        .border_style(Style::default().fg(Color::Yellow));
+        .border_style(Style::default().fg(theme.warning));
 
     let paragraph = Paragraph::new(message)
         .block(block)
-        .style(Style::default().fg(Color::White))
+        .style(Style::default().fg(theme.text))
         .alignment(ratatui::layout::Alignment::Center);
 
     f.render_widget(Clear, area);
     f.render_widget(paragraph, area);
 }
 
-fn draw_upload_progress<B: Backend>(f: &mut Frame, progress: &UploadProgress) {
-    // Use a wider area to accommodate the file name
-    let area = bottom_right_rect(40, 6, f.size());
+/// Entries a directory transfer skipped or failed on, until a key is pressed
+fn draw_transfer_summary(f: &mut Frame, summary: &TransferSummary, theme: &Palette) {
+    let area = centered_rect(70, 60, f.size());
 
-    // Truncate the file name if it's too long
-    let max_name_width = 30;
-    let truncated_name = if progress.file_name.len() > max_name_width {
-        format!(
-            "..{}",
-            &progress.file_name[progress.file_name.len() - (max_name_width - 2)..]
-        )
-    } else {
-        progress.file_name.clone()
-    };
-
-    // Calculate percentage
-    let percent = if progress.total_size > 0 {
-        ((progress.uploaded_size as f64 / progress.total_size as f64) * 100.0) as u16
-    } else {
-        0
-    };
+    let mut lines = vec![Line::from(format!(
+        "{} files ({}) transferred",
+        summary.files,
+        format_file_size(summary.bytes)
+    ))];
+    for (heading, entries, color) in [
+        ("Failed", &summary.failed, theme.error),
+        ("Skipped", &summary.skipped, theme.warning),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!("{} ({})", heading, entries.len()),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        )));
+        for (path, reason) in entries {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {}", path), Style::default().fg(theme.text)),
+                Span::styled(format!("  {}", reason), Style::default().fg(theme.secondary)),
+            ]));
+        }
+    }
 
-    // Create a block with the file name as title
     let block = Block::default()
         .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.warning))
+        .title(format!(" {} ", summary.title))
+        .title_bottom(" Press any key to close ")
         .title_style(
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
-        )
-        .border_style(Style::default().fg(Color::Yellow))
-        .title(format!(" {} ", truncated_name));
+        );
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .style(theme.base())
+        .wrap(ratatui::widgets::Wrap { trim: false });
 
-    // Create the gauge with file sizes as label
-    let gauge = Gauge::default()
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Asks what to do with a transfer whose target exists, comparing both
+/// sides
+fn draw_transfer_conflict(
+    f: &mut Frame,
+    prompt: &ConflictPrompt,
+    theme: &Palette,
+    keymap: &Keymap,
+) {
+    let Some(conflict) = prompt.current() else {
+        return;
+    };
+    let area = centered_rect(60, 40, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let key = |action| {
+        let label = keymap.label(Mode::Conflict, action).unwrap_or_else(|| "-".into());
+        Span::styled(format!("[{}]", label), key_style)
+    };
+    let desc_style = Style::default().fg(theme.secondary);
+    let text_style = Style::default().fg(theme.text);
+
+    let (source_side, target_side) = match conflict.direction {
+        TransferDirection::Upload => ("Local", "Remote"),
+        TransferDirection::Download => ("Remote", "Local"),
+    };
+    let describe = |stat: &EntryStat| {
+        let size = match (stat.is_dir, stat.size) {
+            (true, _) => "directory".to_string(),
+            (false, Some(size)) => format_file_size(size),
+            (false, None) => "?".to_string(),
+        };
+        let time = stat
+            .mtime
+            .and_then(|mtime| chrono::DateTime::from_timestamp(mtime, 0))
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "unknown time".to_string());
+        format!("{:>10}  modified {}", size, time)
+    };
+    let newer = match (conflict.source.mtime, conflict.target.mtime) {
+        (Some(source), Some(target)) if source > target => "the source is newer",
+        (Some(source), Some(target)) if source < target => "the target is newer",
+        (Some(_), Some(_)) => "both have the same time",
+        _ => "",
+    };
+
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(
+                conflict.name.as_str(),
+                Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(" exists on the {} side", target_side.to_lowercase()),
+                text_style,
+            ),
+        ]),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(format!("{:<6} (source) ", source_side), desc_style),
+            Span::styled(describe(&conflict.source), text_style),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("{:<6} (target) ", target_side), desc_style),
+            Span::styled(describe(&conflict.target), text_style),
+        ]),
+        Line::from(Span::styled(newer, desc_style)),
+    ];
+    if conflict.is_dir && conflict.target.is_dir {
+        lines.push(Line::from(Span::styled(
+            "Overwriting copies into the directory, replacing files with the same names",
+            desc_style,
+        )));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        key(Action::Overwrite),
+        Span::styled(" Overwrite  ", desc_style),
+        key(Action::Skip),
+        Span::styled(" Skip  ", desc_style),
+        key(Action::Rename),
+        Span::styled(" Rename  ", desc_style),
+        key(Action::OverwriteIfNewer),
+        Span::styled(" Overwrite if newer", desc_style),
+    ]));
+    lines.push(Line::from(vec![
+        key(Action::ApplyToAll),
+        Span::styled(
+            if prompt.apply_to_all {
+                format!(" [x] Apply to all {} remaining  ", prompt.pending.len())
+            } else {
+                format!(" [ ] Apply to all {} remaining  ", prompt.pending.len())
+            },
+            desc_style,
+        ),
+        key(Action::Cancel),
+        Span::styled(" Skip the rest", desc_style),
+    ]));
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" File Exists ({} of {}) ", prompt.position(), prompt.total))
+        .title_style(Style::default().fg(theme.warning).add_modifier(Modifier::BOLD))
+        .border_style(Style::default().fg(theme.warning));
+    let paragraph = Paragraph::new(lines)
         .block(block)
-        .gauge_style(Style::default().fg(Color::Green).bg(Color::Black))
-        .label(format!(
-            "{} / {}",
-            format_file_size(progress.uploaded_size),
-            format_file_size(progress.total_size)
-        ))
-        .percent(percent);
+        .style(theme.base())
+        .wrap(ratatui::widgets::Wrap { trim: false });
 
     f.render_widget(Clear, area);
-    f.render_widget(gauge, area);
+    f.render_widget(paragraph, area);
 }
 
-fn draw_download_progress<B: Backend>(f: &mut Frame, progress: &DownloadProgress) {
-    // Use a wider area to accommodate the file name
-    let area = bottom_right_rect(40, 6, f.size());
+/// Asks whether an edited file may overwrite the changes made on the server
+/// while it was open
+fn draw_edit_conflict(f: &mut Frame, edit: &RemoteEdit, theme: &Palette) {
+    let area = centered_rect(60, 20, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let text_style = Style::default().fg(theme.text);
 
-    // Truncate the file name if it's too long
-    let max_name_width = 30;
-    let truncated_name = if progress.file_name.len() > max_name_width {
-        format!(
-            "..{}",
-            &progress.file_name[progress.file_name.len() - (max_name_width - 2)..]
-        )
+    let lines = vec![
+        Line::from(vec![
+            Span::styled(
+                edit.name(),
+                Style::default().fg(theme.warning).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(" changed on the server while you edited it", text_style),
+        ]),
+        Line::from(Span::styled(
+            "Uploading replaces those changes with yours",
+            desc_style,
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[y]", key_style),
+            Span::styled(" Upload anyway  ", desc_style),
+            Span::styled("[any key]", key_style),
+            Span::styled(" Keep my copy locally", desc_style),
+        ]),
+    ];
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Remote File Changed ")
+        .title_style(Style::default().fg(theme.warning).add_modifier(Modifier::BOLD))
+        .border_style(Style::default().fg(theme.warning));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .style(theme.base())
+        .wrap(ratatui::widgets::Wrap { trim: false });
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// Input line of a file operation, or the confirmation before deleting
+fn draw_file_prompt(f: &mut Frame, prompt: &FilePrompt, theme: &Palette) {
+    let area = centered_rect(60, 20, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let text_style = Style::default().fg(theme.text);
+    let color = if prompt.operation == FileOperation::Delete {
+        theme.error
     } else {
-        progress.file_name.clone()
+        theme.primary
     };
 
-    // Calculate percentage
-    let percent = if progress.total_size > 0 {
-        ((progress.downloaded_size as f64 / progress.total_size as f64) * 100.0) as u16
-    } else {
-        0
+    let targets = match prompt.targets.as_slice() {
+        [] => String::new(),
+        [name] => name.clone(),
+        names => format!("{} entries", names.len()),
+    };
+    let lines = match prompt.operation.input_label() {
+        Some(label) => vec![
+            Line::from(Span::styled(
+                if targets.is_empty() {
+                    format!("{}:", label)
+                } else {
+                    format!("{} for {}:", label, targets)
+                },
+                desc_style,
+            )),
+            Line::from(vec![
+                Span::styled(prompt.input.as_str(), text_style),
+                Span::styled("█", Style::default().fg(theme.highlight)),
+            ]),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("[Enter]", key_style),
+                Span::styled(" Apply  ", desc_style),
+                Span::styled("[Ctrl+U]", key_style),
+                Span::styled(" Clear  ", desc_style),
+                Span::styled("[Esc]", key_style),
+                Span::styled(" Cancel", desc_style),
+            ]),
+        ],
+        None => vec![
+            Line::from(vec![
+                Span::styled("Delete ", text_style),
+                Span::styled(
+                    targets,
+                    Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+                ),
+                Span::styled("?", text_style),
+            ]),
+            Line::from(Span::styled(
+                "Directories are deleted with everything in them",
+                desc_style,
+            )),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("[y]", key_style),
+                Span::styled(" Delete  ", desc_style),
+                Span::styled("[any key]", key_style),
+                Span::styled(" Cancel", desc_style),
+            ]),
+        ],
     };
 
-    // Create a block with the file name as title
+    let side = match prompt.side {
+        PanelSide::Local => "Local",
+        PanelSide::Remote => "Remote",
+        PanelSide::Queue => "Queue",
+    };
     let block = Block::default()
         .borders(Borders::ALL)
-        .title_style(
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        )
-        .border_style(Style::default().fg(Color::Yellow))
-        .title(format!(" Download Progress {} ", truncated_name));
+        .border_style(Style::default().fg(color))
+        .title(format!(" {} ({}) ", prompt.operation.title(), side))
+        .title_style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .style(theme.base())
+        .wrap(ratatui::widgets::Wrap { trim: false });
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
 
-    // Create the gauge with file sizes as label
-    let gauge = Gauge::default()
+/// Differences between the local and the remote directory, with the plan
+/// of a sync on top once one is chosen
+fn draw_sync(f: &mut Frame, view: &mut SyncView, theme: &Palette, keymap: &Keymap) {
+    let area = centered_rect(90, 80, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let key = |action| {
+        let label = keymap.label(Mode::Sync, action).unwrap_or_else(|| "-".into());
+        Span::styled(format!("[{}]", label), key_style)
+    };
+
+    let mut title = format!(
+        " Compare ({}): {} differences ",
+        if view.checksum { "SHA-256" } else { "size and time" },
+        view.entries.len()
+    );
+    if view.skipped > 0 {
+        title.push_str(&format!(", {} skipped ", view.skipped));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.primary))
+        .title(title)
+        .title_style(Style::default().fg(theme.primary).add_modifier(Modifier::BOLD))
+        .title_bottom(Line::from(vec![
+            Span::raw(" "),
+            key(Action::Upload),
+            Span::styled(" Mirror to remote  ", desc_style),
+            key(Action::Download),
+            Span::styled(" Mirror to local  ", desc_style),
+            key(Action::Mark),
+            Span::styled(" Mark  ", desc_style),
+            key(Action::SyncMarked),
+            Span::styled(" Sync marked  ", desc_style),
+            key(Action::ToggleDelete),
+            Span::styled(
+                if view.delete { " Deleting: on  " } else { " Deleting: off  " },
+                desc_style,
+            ),
+            key(Action::ToggleChecksum),
+            Span::styled(" Checksum  ", desc_style),
+            Span::styled("[Esc]", key_style),
+            Span::styled(" Close ", desc_style),
+        ]));
+
+    let side_text = |side: Option<&SyncSide>| match side {
+        None => "-".to_string(),
+        Some(side) if side.is_dir => format!("dir, {}", format_file_size(side.size)),
+        Some(side) => {
+            let time = side
+                .mtime
+                .and_then(|mtime| chrono::DateTime::from_timestamp(mtime, 0))
+                .map(|time| {
+                    time.with_timezone(&chrono::Local)
+                        .format(" %Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            format!("{}{}", format_file_size(side.size), time)
+        }
+    };
+    let items: Vec<ListItem> = view
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let is_selected = i == view.selected;
+            let color = match entry.change {
+                SyncChange::LocalOnly | SyncChange::LocalNewer => theme.success,
+                SyncChange::RemoteOnly | SyncChange::RemoteNewer => theme.primary,
+                SyncChange::Differs | SyncChange::TypeConflict => theme.warning,
+            };
+            let text = |color| {
+                Style::default().fg(if is_selected { theme.on_fill() } else { color })
+            };
+            let mark = if view.marked.contains(&entry.path) { "* " } else { "  " };
+            let line = Line::from(vec![
+                Span::styled(mark, text(theme.highlight)),
+                Span::styled(format!("{:<13}", entry.change.label()), text(color)),
+                Span::styled(entry.path.as_str(), text(theme.text)),
+                Span::styled(
+                    format!(
+                        "  local: {}  remote: {}",
+                        side_text(entry.local.as_ref()),
+                        side_text(entry.remote.as_ref())
+                    ),
+                    text(theme.secondary),
+                ),
+            ]);
+            let style = if is_selected {
+                Style::default()
+                    .bg(theme.primary)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    view.list_state.select(Some(view.selected));
+    let list = List::new(items).block(block).style(theme.base());
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut view.list_state);
+
+    if let Some(plan) = &view.plan {
+        draw_sync_plan(f, plan, theme);
+    }
+}
+
+/// Bookmarks and recent directories of a panel to go to
+fn draw_places(f: &mut Frame, places: &mut Places, theme: &Palette, keymap: &Keymap) {
+    let area = centered_rect(60, 50, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let go = keymap.label(Mode::Sftp, Action::Open).unwrap_or_else(|| "-".into());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.primary))
+        .title(match places.side {
+            PanelSide::Remote => " Places (Remote) ",
+            _ => " Places (Local) ",
+        })
+        .title_style(Style::default().fg(theme.primary).add_modifier(Modifier::BOLD))
+        .title_bottom(Line::from(vec![
+            Span::styled(format!(" [{}]", go), key_style),
+            Span::styled(" Go  ", desc_style),
+            Span::styled("[Esc]", key_style),
+            Span::styled(" Close ", desc_style),
+        ]));
+
+    let items: Vec<ListItem> = places
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, (kind, dir))| {
+            let is_selected = i == places.selected;
+            let (label, color) = match kind {
+                PlaceKind::Bookmark => ("bookmark  ", theme.highlight),
+                PlaceKind::Recent => ("recent    ", theme.secondary),
+            };
+            let text = |color| {
+                Style::default().fg(if is_selected { theme.on_fill() } else { color })
+            };
+            let line = Line::from(vec![
+                Span::styled(label, text(color)),
+                Span::styled(dir.as_str(), text(theme.text)),
+            ]);
+            let style = if is_selected {
+                Style::default()
+                    .bg(theme.primary)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    places.list_state.select(Some(places.selected));
+    let list = List::new(items).block(block).style(theme.base());
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut places.list_state);
+}
+
+/// Past transfers of the host, newest first
+fn draw_history(f: &mut Frame, history: &mut HistoryView, theme: &Palette, keymap: &Keymap) {
+    let area = centered_rect(90, 70, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let run_again = keymap.label(Mode::Sftp, Action::Open).unwrap_or_else(|| "-".into());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.primary))
+        .title(format!(" Transfer History: {} ", history.records.len()))
+        .title_style(Style::default().fg(theme.primary).add_modifier(Modifier::BOLD))
+        .title_bottom(Line::from(vec![
+            Span::styled(format!(" [{}]", run_again), key_style),
+            Span::styled(" Run again  ", desc_style),
+            Span::styled("[Esc]", key_style),
+            Span::styled(" Close ", desc_style),
+        ]));
+
+    let items: Vec<ListItem> = history
+        .records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let is_selected = i == history.selected;
+            let text = |color| {
+                Style::default().fg(if is_selected { theme.on_fill() } else { color })
+            };
+            let time = chrono::DateTime::parse_from_rfc3339(&record.time)
+                .map(|time| {
+                    time.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_else(|_| record.time.clone());
+            let (arrow, from, to) = match record.direction {
+                TransferDirection::Upload => (
+                    "↑ ",
+                    record.local_path.display().to_string(),
+                    record.remote_path.clone(),
+                ),
+                TransferDirection::Download => (
+                    "↓ ",
+                    record.remote_path.clone(),
+                    record.local_path.display().to_string(),
+                ),
+            };
+            let (outcome, color) = match &record.error {
+                Some(error) => (format!("  failed: {}", error), theme.error),
+                None => (
+                    format!(
+                        "  {} in {}{}",
+                        format_file_size(record.bytes),
+                        format_duration(Duration::from_secs_f64(record.seconds.max(0.0))),
+                        if record.sha256.is_some() { ", verified" } else { "" }
+                    ),
+                    theme.success,
+                ),
+            };
+            let line = Line::from(vec![
+                Span::styled(format!("{}  ", time), text(theme.secondary)),
+                Span::styled(arrow, text(theme.warning)),
+                Span::styled(from, text(theme.text)),
+                Span::styled(" → ", text(theme.secondary)),
+                Span::styled(to, text(theme.text)),
+                Span::styled(outcome, text(color)),
+            ]);
+            let style = if is_selected {
+                Style::default()
+                    .bg(theme.primary)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    history.list_state.select(Some(history.selected));
+    let list = List::new(items).block(block).style(theme.base());
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut history.list_state);
+}
+
+/// Dry run of a sync: what it copies and deletes, asking to go ahead
+fn draw_sync_plan(f: &mut Frame, plan: &SyncPlan, theme: &Palette) {
+    let area = centered_rect(70, 60, f.size());
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+    let text_style = Style::default().fg(theme.text);
+
+    let mut lines = Vec::new();
+    for (action, verb, color) in [
+        (SyncAction::Upload, "Upload", theme.success),
+        (SyncAction::Download, "Download", theme.primary),
+        (SyncAction::DeleteRemote, "Delete on the server", theme.error),
+        (SyncAction::DeleteLocal, "Delete locally", theme.error),
+    ] {
+        let (count, bytes) = plan.total(action);
+        if count > 0 {
+            lines.push(Line::from(Span::styled(
+                format!("{} {} entries ({})", verb, count, format_file_size(bytes)),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            )));
+        }
+    }
+    let replaced = plan.steps.iter().filter(|step| step.replaces_newer).count();
+    if replaced > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("{} of the copies replace a newer file", replaced),
+            Style::default().fg(theme.warning),
+        )));
+    }
+    if !plan.skipped.is_empty() {
+        lines.push(Line::from(Span::styled(
+            format!("Skip {} entries", plan.skipped.len()),
+            Style::default().fg(theme.warning),
+        )));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("[y]", key_style),
+        Span::styled(" Apply  ", desc_style),
+        Span::styled("[any key]", key_style),
+        Span::styled(" Back", desc_style),
+    ]));
+    lines.push(Line::from(""));
+
+    for step in &plan.steps {
+        let action = match step.action {
+            SyncAction::Upload => "upload   ",
+            SyncAction::Download => "download ",
+            SyncAction::DeleteLocal | SyncAction::DeleteRemote => "delete   ",
+        };
+        let mut path = step.path.clone();
+        if step.is_dir {
+            path.push('/');
+        }
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {}", action), desc_style),
+            Span::styled(path, text_style),
+        ]));
+    }
+    for (path, reason) in &plan.skipped {
+        lines.push(Line::from(vec![
+            Span::styled("  skip     ", desc_style),
+            Span::styled(path.as_str(), text_style),
+            Span::styled(format!("  {}", reason), desc_style),
+        ]));
+    }
+
+    let title = match plan.direction {
+        SyncDirection::Upload => " Mirror local to remote ",
+        SyncDirection::Download => " Mirror remote to local ",
+        SyncDirection::Marked => " Sync marked entries ",
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.warning))
+        .title(title)
+        .title_style(Style::default().fg(theme.warning).add_modifier(Modifier::BOLD));
+    let paragraph = Paragraph::new(lines)
         .block(block)
-        .gauge_style(Style::default().fg(Color::Green).bg(Color::Black))
-        .label(format!(
-            "{} / {}",
-            format_file_size(progress.downloaded_size),
-            format_file_size(progress.total_size)
-        ))
-        .percent(percent);
+        .style(theme.base())
+        .wrap(ratatui::widgets::Wrap { trim: false });
 
     f.render_widget(Clear, area);
-    f.render_widget(gauge, area);
+    f.render_widget(paragraph, area);
 }
 
-fn bottom_right_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0), Constraint::Length(height)])
-        .split(r);
+/// Narrowest name the detail columns leave room for
+const MIN_NAME_WIDTH: usize = 12;
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(100 - percent_x),
-            Constraint::Percentage(percent_x),
-        ])
-        .split(popup_layout[1])[1]
+/// Width of each column with the space before it
+fn columns_width(columns: &[Column]) -> usize {
+    columns
+        .iter()
+        .map(|column| match column {
+            Column::Size => 10,
+            Column::Modified => 17,
+            Column::Permissions => 11,
+            Column::Owner => 17,
+            Column::Link => 0,
+        })
+        .sum()
+}
+
+/// Text of a detail column, padded to its width. Links are part of the name.
+fn column_text(column: Column, file: &FileItem) -> Option<String> {
+    let meta = file.meta();
+    let text = match column {
+        Column::Size => match file {
+            FileItem::File { size, .. } => format!("{:>9}", format_file_size(*size)),
+            FileItem::Directory { .. } => " ".repeat(9),
+        },
+        Column::Modified => format!(
+            "{:16}",
+            meta.mtime
+                .and_then(|mtime| chrono::DateTime::from_timestamp(mtime, 0))
+                .map(|mtime| {
+                    let mtime = mtime.with_timezone(&chrono::Local);
+                    mtime.format("%Y-%m-%d %H:%M").to_string()
+                })
+                .unwrap_or_default()
+        ),
+        Column::Permissions => format!(
+            "{:10}",
+            meta.permissions
+                .map(|mode| mode_string(file, mode))
+                .unwrap_or_default()
+        ),
+        Column::Owner => {
+            let owner = match (&meta.owner, &meta.group) {
+                (Some(owner), Some(group)) => format!("{}:{}", owner, group),
+                (Some(owner), None) => owner.clone(),
+                (None, _) => String::new(),
+            };
+            fit(&owner, 16)
+        }
+        Column::Link => return None,
+    };
+    Some(text)
+}
+
+/// `ls -l` style mode, e.g. `drwxr-xr-x`
+fn mode_string(file: &FileItem, mode: u32) -> String {
+    let mut text = String::with_capacity(10);
+    text.push(if file.meta().link_target.is_some() {
+        'l'
+    } else if matches!(file, FileItem::Directory { .. }) {
+        'd'
+    } else {
+        '-'
+    });
+    // Set-user-id, set-group-id and sticky bits replace the execute flags
+    let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    for (shift, (bit, letter)) in [6, 3, 0].into_iter().zip(special) {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 0o1 != 0, mode & bit != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+/// `text` cut with `…` or padded with spaces to `width` columns
+fn fit(text: &str, width: usize) -> String {
+    let char_width = |c: char| Span::raw(c.to_string()).width();
+    let mut fitted = String::new();
+    let mut used = 0;
+    let total: usize = text.chars().map(char_width).sum();
+    if total <= width {
+        fitted.push_str(text);
+        used = total;
+    } else if width > 0 {
+        for c in text.chars() {
+            if used + char_width(c) + 1 > width {
+                break;
+            }
+            fitted.push(c);
+            used += char_width(c);
+        }
+        fitted.push('…');
+        used += 1;
+    }
+    fitted.push_str(&" ".repeat(width.saturating_sub(used)));
+    fitted
 }
 
 fn format_file_size(size: u64) -> String {
@@ -0,0 +1,29 @@
+//! POSIX shell quoting, for command lines shown to the user and commands
+//! run on the server over ssh
+
+/// Quote `arg` as one shell word, leaving it alone when nothing in it needs
+/// quoting
+pub fn quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(quote("deploy@10.0.0.1"), "deploy@10.0.0.1");
+        assert_eq!(quote("SetEnv=A=\"b c\""), "'SetEnv=A=\"b c\"'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote(""), "''");
+    }
+}
@@ -0,0 +1,692 @@
+//! Parser for OpenSSH client configuration files (`~/.ssh/config`)
+//!
+//! Understands `Include` (with glob patterns), multi-pattern `Host` lines,
+//! `Match` blocks, `key=value` syntax, quoted arguments and comments.
+//! Options are resolved with OpenSSH semantics: blocks are evaluated in file
+//! order and the first value obtained for each keyword wins.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::{quote_option_arg, SshHost};
+
+/// Maximum nesting of `Include` directives, same limit as OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Keywords that may be given several times, every occurrence is kept
+const MULTI_VALUE_KEYWORDS: &[&str] = &[
+    "certificatefile",
+    "dynamicforward",
+    "identityfile",
+    "localforward",
+    "remoteforward",
+    "sendenv",
+];
+
+/// Condition guarding a block of options
+#[derive(Debug, Clone, PartialEq)]
+pub enum Criteria {
+    /// Options appearing before the first `Host` or `Match` line
+    Global,
+    /// `Host pattern...`, patterns may use `*`, `?` and `!` negation
+    Host(Vec<String>),
+    /// `Match criteria...`, kept as the raw argument tokens
+    Match(Vec<String>),
+}
+
+/// A run of options sharing the same condition
+#[derive(Debug, Clone)]
+pub struct ConfigBlock {
+    pub criteria: Criteria,
+    /// Options in file order, keywords lowercased
+    pub options: Vec<(String, Vec<String>)>,
+}
+
+/// A parsed ssh_config, with all `Include` directives expanded in place
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    pub blocks: Vec<ConfigBlock>,
+}
+
+/// Effective options for one host after applying every matching block
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedHost {
+    options: Vec<(String, Vec<String>)>,
+}
+
+impl ResolvedHost {
+    /// First argument of the effective value for `keyword`
+    pub fn get(&self, keyword: &str) -> Option<&str> {
+        self.get_args(keyword)
+            .and_then(|args| args.first())
+            .map(|s| s.as_str())
+    }
+
+    /// All arguments of the effective value for `keyword`
+    pub fn get_args(&self, keyword: &str) -> Option<&[String]> {
+        let keyword = keyword.to_lowercase();
+        self.options
+            .iter()
+            .find(|(k, _)| *k == keyword)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Every value for a multi-value keyword such as `IdentityFile`
+    #[cfg(test)]
+    pub fn get_all(&self, keyword: &str) -> Vec<&[String]> {
+        let keyword = keyword.to_lowercase();
+        self.options
+            .iter()
+            .filter(|(k, _)| *k == keyword)
+            .map(|(_, v)| v.as_slice())
+            .collect()
+    }
+
+    /// Iterate over the effective options in resolution order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.options.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    fn apply(&mut self, keyword: &str, args: &[String]) {
+        if MULTI_VALUE_KEYWORDS.contains(&keyword)
+            || !self.options.iter().any(|(k, _)| k == keyword)
+        {
+            self.options.push((keyword.to_string(), args.to_vec()));
+        }
+    }
+}
+
+impl SshConfig {
+    /// Load and parse a config file, resolving relative `Include` paths
+    /// against the directory that contains it (`~/.ssh` for the user config)
+    pub fn load(path: &Path) -> Result<Self> {
+        let base_dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let mut parser = Parser::new(base_dir);
+        parser.parse_file(path, Criteria::Global, 0)?;
+        Ok(Self {
+            blocks: parser.blocks,
+        })
+    }
+
+    /// Parse config content held in memory
+    #[cfg(test)]
+    pub fn parse_str(content: &str, base_dir: &Path) -> Result<Self> {
+        let mut parser = Parser::new(base_dir.to_path_buf());
+        parser.parse_content(content, Path::new("<string>"), Criteria::Global, 0)?;
+        Ok(Self {
+            blocks: parser.blocks,
+        })
+    }
+
+    /// Aliases of every concrete (non-wildcard, non-negated) `Host` pattern,
+    /// in the order they first appear
+    pub fn host_aliases(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut aliases = Vec::new();
+        for block in &self.blocks {
+            if let Criteria::Host(patterns) = &block.criteria {
+                for pattern in patterns {
+                    if is_concrete_pattern(pattern) && seen.insert(pattern.clone()) {
+                        aliases.push(pattern.clone());
+                    }
+                }
+            }
+        }
+        aliases
+    }
+
+    /// Compute the effective options for `alias`
+    pub fn resolve(&self, alias: &str) -> ResolvedHost {
+        let local_user = local_username();
+        let mut resolved = ResolvedHost::default();
+
+        for block in &self.blocks {
+            let matches = match &block.criteria {
+                Criteria::Global => true,
+                Criteria::Host(patterns) => match_pattern_list(alias, patterns),
+                Criteria::Match(criteria) => {
+                    let host = resolved.get("hostname").unwrap_or(alias).to_string();
+                    let user = resolved.get("user").unwrap_or(&local_user).to_string();
+                    evaluate_match(criteria, alias, &host, &user, &local_user)
+                }
+            };
+            if matches {
+                for (keyword, args) in &block.options {
+                    resolved.apply(keyword, args);
+                }
+            }
+        }
+
+        resolved
+    }
+
+    /// Build an `SshHost` for every concrete host alias
+    pub fn hosts(&self) -> Vec<SshHost> {
+        self.host_aliases()
+            .into_iter()
+            .map(|alias| {
+                let resolved = self.resolve(&alias);
+                host_from_resolved(alias, &resolved)
+            })
+            .collect()
+    }
+}
+
+/// Convert resolved options into the app's host model. Options without a
+/// dedicated field are kept in `SshHost::options` and passed back to ssh
+/// with `-o`.
+fn host_from_resolved(alias: String, resolved: &ResolvedHost) -> SshHost {
+    let host = resolved
+        .get("hostname")
+        .map(|h| h.replace("%h", &alias).replace("%%", "%"))
+        .unwrap_or_else(|| alias.clone());
+    let user = resolved
+        .get("user")
+        .map(str::to_string)
+        .unwrap_or_else(local_username);
+
+    let mut ssh_host = SshHost::new(alias, host, user);
+    if let Some(port) = resolved.get("port").and_then(|p| p.parse::<u16>().ok()) {
+        ssh_host.port = Some(port);
+    }
+
+    for (keyword, args) in resolved.iter() {
+        let Some(first) = args.first() else {
+            continue;
+        };
+        match keyword {
+            "hostname" | "user" | "port" => {}
+            "identityfile" => {
+                // ssh accepts several keys, the first one is the preferred key
+                if ssh_host.identity_file.is_none() {
+                    ssh_host.identity_file = Some(first.clone());
+                }
+            }
+            "proxyjump" => {
+                if !first.eq_ignore_ascii_case("none") {
+                    ssh_host.proxy_jump = first.split(',').map(str::to_string).collect();
+                }
+            }
+            "localforward" => ssh_host.local_forwards.push(args.join(":")),
+            "remoteforward" => ssh_host.remote_forwards.push(args.join(":")),
+            "dynamicforward" => {
+                if ssh_host.dynamic_forward.is_none() {
+                    ssh_host.dynamic_forward = Some(first.clone());
+                }
+            }
+            "forwardagent" => match first.to_lowercase().as_str() {
+                "yes" => ssh_host.forward_agent = Some(true),
+                "no" => ssh_host.forward_agent = Some(false),
+                _ => {
+                    ssh_host
+                        .options
+                        .insert("forwardagent".to_string(), quote_option_arg(first));
+                }
+            },
+            "setenv" => {
+                for var in args {
+                    if let Some((key, value)) = var.split_once('=') {
+                        ssh_host
+                            .env
+                            .entry(key.to_string())
+                            .or_insert_with(|| value.to_string());
+                    }
+                }
+            }
+            _ => {
+                let value: Vec<String> = args.iter().map(|a| quote_option_arg(a)).collect();
+                ssh_host
+                    .options
+                    .entry(keyword.to_string())
+                    .or_insert_with(|| value.join(" "));
+            }
+        }
+    }
+
+    ssh_host
+}
+
+struct Parser {
+    base_dir: PathBuf,
+    blocks: Vec<ConfigBlock>,
+}
+
+impl Parser {
+    fn new(base_dir: PathBuf) -> Self {
+        Self {
+            base_dir,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path, criteria: Criteria, depth: usize) -> Result<()> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read SSH config file {}", path.display()))?;
+        self.parse_content(&content, path, criteria, depth)
+    }
+
+    fn parse_content(
+        &mut self,
+        content: &str,
+        path: &Path,
+        mut criteria: Criteria,
+        depth: usize,
+    ) -> Result<()> {
+        // Index of the block this file is currently appending to. A nested
+        // Include may push blocks of its own, after which options following
+        // the Include need a fresh block carrying our criteria again.
+        let mut current_block: Option<usize> = None;
+
+        for (line_no, line) in content.lines().enumerate() {
+            let tokens = tokenize(line)
+                .with_context(|| format!("{}:{}: invalid line", path.display(), line_no + 1))?;
+            let Some((keyword, args)) = tokens.split_first() else {
+                continue;
+            };
+            let keyword = keyword.to_lowercase();
+
+            match keyword.as_str() {
+                "host" => {
+                    if args.is_empty() {
+                        anyhow::bail!(
+                            "{}:{}: Host requires at least one pattern",
+                            path.display(),
+                            line_no + 1
+                        );
+                    }
+                    criteria = Criteria::Host(args.to_vec());
+                    current_block = Some(self.push_block(criteria.clone()));
+                }
+                "match" => {
+                    if args.is_empty() {
+                        anyhow::bail!(
+                            "{}:{}: Match requires criteria",
+                            path.display(),
+                            line_no + 1
+                        );
+                    }
+                    criteria = Criteria::Match(args.to_vec());
+                    current_block = Some(self.push_block(criteria.clone()));
+                }
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        anyhow::bail!(
+                            "{}:{}: Include nested too deeply",
+                            path.display(),
+                            line_no + 1
+                        );
+                    }
+                    for pattern in args {
+                        for file in self.expand_include(pattern)? {
+                            self.parse_file(&file, criteria.clone(), depth + 1)?;
+                        }
+                    }
+                }
+                _ => {
+                    let index = match current_block {
+                        Some(index) if index + 1 == self.blocks.len() => index,
+                        _ => self.push_block(criteria.clone()),
+                    };
+                    current_block = Some(index);
+                    self.blocks[index].options.push((keyword, args.to_vec()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_block(&mut self, criteria: Criteria) -> usize {
+        self.blocks.push(ConfigBlock {
+            criteria,
+            options: Vec::new(),
+        });
+        self.blocks.len() - 1
+    }
+
+    /// Expand an `Include` argument into the list of files it names
+    fn expand_include(&self, pattern: &str) -> Result<Vec<PathBuf>> {
+        let expanded = expand_tilde(pattern);
+        let full = if expanded.is_absolute() {
+            expanded
+        } else {
+            PathBuf::from(glob::Pattern::escape(&self.base_dir.to_string_lossy())).join(expanded)
+        };
+
+        let mut files: Vec<PathBuf> = glob::glob(&full.to_string_lossy())
+            .with_context(|| format!("Invalid Include pattern '{}'", pattern))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Split a config line into keyword and arguments.
+///
+/// The keyword may be separated from its arguments by whitespace or a single
+/// `=`. Double quotes group arguments containing spaces, and an unquoted `#`
+/// at the start of a token begins a comment.
+fn tokenize(line: &str) -> Result<Vec<String>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+
+    let keyword_end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = &line[..keyword_end];
+    let mut rest = line[keyword_end..].trim_start();
+    if let Some(stripped) = rest.strip_prefix('=') {
+        rest = stripped.trim_start();
+    }
+
+    let mut tokens = vec![keyword.to_string()];
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        if first == '#' {
+            break;
+        }
+
+        let mut token = String::new();
+        let mut in_quotes = false;
+        while let Some(&c) = chars.peek() {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            } else if c.is_whitespace() && !in_quotes {
+                break;
+            } else {
+                token.push(c);
+            }
+            chars.next();
+        }
+        if in_quotes {
+            anyhow::bail!("unterminated quote");
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Expand a leading `~` to the user's home directory
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" {
+        if let Some(home) = dirs::home_dir() {
+            return home;
+        }
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+fn local_username() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "root".to_string())
+}
+
+fn is_concrete_pattern(pattern: &str) -> bool {
+    !pattern.contains(['*', '?', '!'])
+}
+
+/// OpenSSH pattern-list semantics: a negated match rejects the whole list,
+/// otherwise any positive match accepts it
+fn match_pattern_list<S: AsRef<str>>(value: &str, patterns: &[S]) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if wildcard_match(negated, value) {
+                return false;
+            }
+        } else if wildcard_match(pattern, value) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Case-insensitive glob match supporting `*` and `?`
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = star {
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Evaluate `Match` criteria for a host.
+///
+/// Criteria that cannot be evaluated without side effects or a second
+/// canonicalization pass (`exec`, `canonical`, `final`, `localnetwork`,
+/// `tagged`) never match, so their options are not applied.
+fn evaluate_match(
+    criteria: &[String],
+    original_host: &str,
+    host: &str,
+    user: &str,
+    local_user: &str,
+) -> bool {
+    let mut tokens = criteria.iter();
+    while let Some(token) = tokens.next() {
+        let lowered = token.to_lowercase();
+        let (negated, name) = match lowered.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, lowered.as_str()),
+        };
+
+        let result = match name {
+            "all" => true,
+            "canonical" | "final" => false,
+            _ => {
+                let Some(arg) = tokens.next() else {
+                    return false;
+                };
+                let patterns: Vec<&str> = arg.split(',').collect();
+                match name {
+                    "host" => match_pattern_list(host, &patterns),
+                    "originalhost" => match_pattern_list(original_host, &patterns),
+                    "user" => match_pattern_list(user, &patterns),
+                    "localuser" => match_pattern_list(local_user, &patterns),
+                    _ => false,
+                }
+            }
+        };
+
+        if result == negated {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ssh_config")
+    }
+
+    fn parse(content: &str) -> SshConfig {
+        SshConfig::parse_str(content, &fixture_dir()).unwrap()
+    }
+
+    #[test]
+    fn tokenize_handles_equals_quotes_and_comments() {
+        assert_eq!(
+            tokenize("  HostName=example.com").unwrap(),
+            vec!["HostName", "example.com"]
+        );
+        assert_eq!(tokenize("User = deploy").unwrap(), vec!["User", "deploy"]);
+        assert_eq!(
+            tokenize(r#"IdentityFile "~/.ssh/my key" # work key"#).unwrap(),
+            vec!["IdentityFile", "~/.ssh/my key"]
+        );
+        assert!(tokenize("# just a comment").unwrap().is_empty());
+        assert!(tokenize(r#"ProxyCommand "nc %h"#).is_err());
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("web-*", "web-01"));
+        assert!(wildcard_match("db-??", "DB-01"));
+        assert!(!wildcard_match("db-??", "db-001"));
+        assert!(match_pattern_list("web-01", &["*.example.com", "web-*"]));
+        assert!(!match_pattern_list("web-test", &["web-*", "!*-test"]));
+    }
+
+    #[test]
+    fn multi_pattern_host_line_creates_each_alias() {
+        let config = parse("Host alpha beta gamma\n  User ops\n");
+        assert_eq!(config.host_aliases(), vec!["alpha", "beta", "gamma"]);
+        for host in config.hosts() {
+            assert_eq!(host.user, "ops");
+            assert_eq!(host.host, host.alias);
+        }
+    }
+
+    #[test]
+    fn wildcard_hosts_are_not_listed_but_apply_defaults() {
+        let config =
+            parse("Host web\n  HostName web.example.com\n\nHost *\n  User fallback\n  Port 2200\n");
+        let hosts = config.hosts();
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].host, "web.example.com");
+        assert_eq!(hosts[0].user, "fallback");
+        assert_eq!(hosts[0].port, Some(2200));
+    }
+
+    #[test]
+    fn first_value_wins() {
+        let config = parse("Host app\n  User first\n  User second\nHost *\n  User third\n");
+        assert_eq!(config.resolve("app").get("user"), Some("first"));
+    }
+
+    #[test]
+    fn multi_value_keywords_accumulate() {
+        let config = parse("Host app\n  IdentityFile ~/.ssh/a\nHost *\n  IdentityFile ~/.ssh/b\n");
+        let resolved = config.resolve("app");
+        let files: Vec<&str> = resolved
+            .get_all("identityfile")
+            .iter()
+            .map(|a| a[0].as_str())
+            .collect();
+        assert_eq!(files, vec!["~/.ssh/a", "~/.ssh/b"]);
+    }
+
+    #[test]
+    fn match_blocks_are_preserved_and_evaluated() {
+        let config = parse(
+            "Host prod\n  HostName prod.internal\nMatch host *.internal user !root\n  Port 2222\nMatch exec \"true\"\n  Port 1\n",
+        );
+        assert!(config
+            .blocks
+            .iter()
+            .any(|b| matches!(&b.criteria, Criteria::Match(c) if c[0] == "exec")));
+        assert_eq!(config.host_aliases(), vec!["prod"]);
+
+        let resolved = config.resolve("prod");
+        let expected = if local_username() == "root" {
+            None
+        } else {
+            Some("2222")
+        };
+        assert_eq!(resolved.get("port"), expected);
+    }
+
+    #[test]
+    fn hostname_token_expansion() {
+        let config = parse("Host bastion\nHost *\n  HostName %h.corp.example.com\n");
+        assert_eq!(config.hosts()[0].host, "bastion.corp.example.com");
+    }
+
+    #[test]
+    fn fixture_with_includes() {
+        let config = SshConfig::load(&fixture_dir().join("config")).unwrap();
+        let hosts = config.hosts();
+        let aliases: Vec<&str> = hosts.iter().map(|h| h.alias.as_str()).collect();
+        assert_eq!(
+            aliases,
+            vec![
+                "work-bastion",
+                "work-app",
+                "work-db",
+                "github.com",
+                "homelab",
+                "nas",
+                "pi"
+            ]
+        );
+
+        let find = |alias: &str| hosts.iter().find(|h| h.alias == alias).unwrap();
+
+        // Defined in config.d/10-work, defaults from the global `Host *`
+        let app = find("work-app");
+        assert_eq!(app.host, "10.20.0.11");
+        assert_eq!(app.user, "deploy");
+        assert_eq!(app.port, Some(22));
+
+        // Port comes from the `Host work-*` block in the same include
+        let db = find("work-db");
+        assert_eq!(db.port, Some(5522));
+
+        // Include nested inside a Host block only applies to that block
+        let nas = find("nas");
+        assert_eq!(nas.host, "nas.home.arpa");
+        assert_eq!(nas.user, "admin");
+
+        // Options after an Include return to the including block
+        let pi = find("pi");
+        assert_eq!(pi.user, "pi");
+        assert_eq!(pi.port, Some(2022));
+
+        // ProxyJump and a quoted IdentityFile from the shared work block
+        assert_eq!(db.proxy_jump, vec!["work-bastion"]);
+        assert_eq!(db.identity_file.as_deref(), Some("~/.ssh/work key"));
+        assert_eq!(db.forward_agent, Some(false));
+        assert_eq!(find("work-bastion").forward_agent, None);
+
+        // Global defaults without a dedicated field are kept as options
+        assert_eq!(app.options.get("serveraliveinterval").map(String::as_str), Some("60"));
+
+        let github = find("github.com");
+        assert_eq!(github.user, "git");
+        assert_eq!(
+            config.resolve("github.com").get("identityfile"),
+            Some("~/.ssh/id_ed25519_github")
+        );
+    }
+}
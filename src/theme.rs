@@ -0,0 +1,185 @@
+//! Turn the themes from sshr.toml into colors for the UI
+//!
+//! Colors are `#rrggbb`/`#rgb` hex, a terminal color name such as `green` or
+//! `lightcyan`, an ANSI index, or `reset` for the terminal's own color.
+
+use anyhow::{Context, Result};
+use ratatui::style::{Color, Style};
+use std::str::FromStr;
+
+use crate::config::{Theme, ThemeColors};
+
+/// Parsed colors of the active theme, handed to every draw function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// Active borders, selected rows
+    pub primary: Color,
+    /// Inactive borders, hints and secondary details
+    pub secondary: Color,
+    pub background: Color,
+    pub text: Color,
+    /// Key hints, directories and other accents
+    pub highlight: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub success: Color,
+}
+
+impl Palette {
+    pub fn from_theme(theme: &Theme) -> Result<Self> {
+        let colors = &theme.colors;
+        let parse = |role: &str, value: &str| {
+            parse_color(value).with_context(|| format!("Theme '{}', color {}", theme.name, role))
+        };
+        Ok(Self {
+            primary: parse("primary", &colors.primary)?,
+            secondary: parse("secondary", &colors.secondary)?,
+            background: parse("background", &colors.background)?,
+            text: parse("text", &colors.text)?,
+            highlight: parse("highlight", &colors.highlight)?,
+            error: parse("error", &colors.error)?,
+            warning: parse("warning", &colors.warning)?,
+            success: parse("success", &colors.success)?,
+        })
+    }
+
+    /// Text and background of the whole screen and of overlays
+    pub fn base(&self) -> Style {
+        Style::default().fg(self.text).bg(self.background)
+    }
+
+    /// Text drawn on top of a `primary`/`warning` filled row
+    pub fn on_fill(&self) -> Color {
+        match self.background {
+            Color::Reset => Color::Black,
+            color => color,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from_theme(&builtin_themes()[0]).expect("built-in theme colors are valid")
+    }
+}
+
+/// Parse a theme color, see the module docs for the accepted forms
+pub fn parse_color(value: &str) -> Result<Color> {
+    let value = value.trim();
+    let lower = value.to_lowercase();
+    if lower == "reset" || lower == "default" || lower.is_empty() {
+        return Ok(Color::Reset);
+    }
+
+    // Accept the #rgb shorthand on top of what ratatui understands
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() == 3 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let expanded: String = hex.chars().flat_map(|c| [c, c]).collect();
+            return parse_color(&format!("#{}", expanded));
+        }
+    }
+
+    Color::from_str(value).map_err(|_| anyhow::anyhow!("Invalid color '{}'", value))
+}
+
+/// Themes that are always available. Themes in sshr.toml with the same name
+/// replace them.
+pub fn builtin_themes() -> Vec<Theme> {
+    let theme = |name: &str, colors: [&str; 8]| {
+        let [primary, secondary, background, text, highlight, error, warning, success] =
+            colors.map(String::from);
+        Theme {
+            name: name.to_string(),
+            colors: ThemeColors {
+                primary,
+                secondary,
+                background,
+                text,
+                highlight,
+                error,
+                warning,
+                success,
+            },
+        }
+    };
+
+    vec![
+        // The terminal's own palette, sshr's classic look
+        theme(
+            "dark",
+            [
+                "green", "gray", "reset", "white", "lightcyan", "red", "yellow", "green",
+            ],
+        ),
+        theme(
+            "light",
+            [
+                "#005f87", "#6c6c6c", "#ffffff", "#1c1c1c", "#0087af", "#d70000", "#af5f00",
+                "#008700",
+            ],
+        ),
+        theme(
+            "solarized",
+            [
+                "#268bd2", "#586e75", "#002b36", "#93a1a1", "#2aa198", "#dc322f", "#b58900",
+                "#859900",
+            ],
+        ),
+        theme(
+            "high-contrast",
+            [
+                "#ffff00", "#c0c0c0", "#000000", "#ffffff", "#00ffff", "#ff0000", "#ffaf00",
+                "#00ff00",
+            ],
+        ),
+    ]
+}
+
+/// Built-in themes followed by the configured ones, a configured theme
+/// replacing a built-in one with the same name
+pub fn available_themes(configured: &[Theme]) -> Vec<Theme> {
+    let mut themes = builtin_themes();
+    for theme in configured {
+        match themes.iter_mut().find(|t| t.name == theme.name) {
+            Some(existing) => *existing = theme.clone(),
+            None => themes.push(theme.clone()),
+        }
+    }
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_names_and_reset() {
+        assert_eq!(parse_color("#1a202c").unwrap(), Color::Rgb(0x1a, 0x20, 0x2c));
+        assert_eq!(parse_color("#fa0").unwrap(), Color::Rgb(0xff, 0xaa, 0x00));
+        assert_eq!(parse_color("lightcyan").unwrap(), Color::LightCyan);
+        assert_eq!(parse_color("reset").unwrap(), Color::Reset);
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("not-a-color").is_err());
+    }
+
+    #[test]
+    fn builtin_themes_are_valid() {
+        for theme in builtin_themes() {
+            Palette::from_theme(&theme).unwrap();
+        }
+        assert_eq!(Palette::default().on_fill(), Color::Black);
+    }
+
+    #[test]
+    fn configured_themes_replace_builtins() {
+        let mut custom = builtin_themes()[0].clone();
+        custom.colors.primary = "#ff0000".into();
+        let mut extra = custom.clone();
+        extra.name = "mine".into();
+
+        let themes = available_themes(&[custom, extra]);
+        let names: Vec<&str> = themes.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["dark", "light", "solarized", "high-contrast", "mine"]);
+        assert_eq!(themes[0].colors.primary, "#ff0000");
+    }
+}
@@ -1,86 +1,133 @@
 use crate::app::{App, InputMode};
+use crate::keybindings::{Action, Mode};
 use ratatui::{
-    backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::Paragraph,
     Frame,
 };
 
-pub fn draw_footer<B: Backend>(f: &mut Frame, app: &App, area: Rect) {
+pub fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme;
     let footer = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(area);
 
     let key_style = Style::default()
-        .fg(Color::LightCyan)
+        .fg(theme.highlight)
         .add_modifier(Modifier::BOLD);
-    let desc_style = Style::default().fg(Color::DarkGray);
+    let desc_style = Style::default().fg(theme.secondary);
 
     let (nav_spans, action_spans) = match app.input_mode {
         InputMode::Normal if app.is_connecting => (
             Line::from(Span::styled(
                 "Connecting to SSH host...",
-                Style::default().fg(Color::Yellow),
+                Style::default().fg(theme.warning),
             )),
             Line::from(Span::styled(
                 "[Ctrl+C] Cancel",
-                Style::default().fg(Color::Red),
+                Style::default().fg(theme.error),
             )),
         ),
         InputMode::Normal => (
-            Line::from(vec![
-                Span::styled("↑/k:", key_style),
-                Span::styled(" Up  ", desc_style),
-                Span::styled("↓/j:", key_style),
-                Span::styled(" Down  ", desc_style),
-                Span::styled("[Enter]", key_style),
-                Span::styled(" Connect  ", desc_style),
-                Span::styled("[s]", key_style),
-                Span::styled(" Search  ", desc_style),
-                Span::styled("[f]", key_style),
-                Span::styled(" SFTP", desc_style),
-            ]),
-            Line::from(vec![
-                Span::styled("[e]", key_style),
-                Span::styled(" Edit  ", desc_style),
-                Span::styled("[r]", key_style),
-                Span::styled(" Reload  ", desc_style),
-                Span::styled("[q]", key_style),
-                Span::styled(" Quit", desc_style),
-            ]),
+            hint_line(
+                app.keymap.hints(
+                    Mode::Normal,
+                    &[
+                        (Action::Up, "Up"),
+                        (Action::Down, "Down"),
+                        (Action::Connect, "Connect"),
+                        (Action::Search, "Search"),
+                        (Action::Sftp, "SFTP"),
+                    ],
+                ),
+                key_style,
+                desc_style,
+            ),
+            hint_line(
+                app.keymap.hints(
+                    Mode::Normal,
+                    &[
+                        (Action::AddHost, "Add"),
+                        (Action::EditHost, "Edit"),
+                        (Action::CloneHost, "Clone"),
+                        (Action::MoveHost, "Move"),
+                        (Action::DeleteHost, "Delete"),
+                        (Action::EditHostsFile, "Edit file"),
+                        (Action::Reload, "Reload"),
+                        (Action::NextTheme, "Theme"),
+                        (Action::Quit, "Quit"),
+                    ],
+                ),
+                key_style,
+                desc_style,
+            ),
         ),
-        InputMode::Search => (
+        InputMode::Search => {
+            let mut exit = hint_line(
+                app.keymap.hints(Mode::Search, &[(Action::Cancel, "Exit Search")]),
+                key_style,
+                desc_style,
+            );
+            exit.spans.push(Span::styled("  Type to filter", desc_style));
+            (
+                hint_line(
+                    app.keymap.hints(
+                        Mode::Search,
+                        &[
+                            (Action::Up, "Up"),
+                            (Action::Down, "Down"),
+                            (Action::Connect, "Connect"),
+                        ],
+                    ),
+                    key_style,
+                    desc_style,
+                ),
+                exit,
+            )
+        }
+        InputMode::HostForm => (
             Line::from(vec![
-                Span::styled("↑:", key_style),
-                Span::styled(" Up  ", desc_style),
-                Span::styled("↓:", key_style),
-                Span::styled(" Down  ", desc_style),
-                Span::styled("[Enter]", key_style),
-                Span::styled(" Connect", desc_style),
+                Span::styled("[Tab]", key_style),
+                Span::styled(" Next field  ", desc_style),
+                Span::styled("[Shift+Tab]", key_style),
+                Span::styled(" Previous field", desc_style),
             ]),
             Line::from(vec![
+                Span::styled("[Enter]", key_style),
+                Span::styled(" Save  ", desc_style),
                 Span::styled("[Esc]", key_style),
-                Span::styled(" Exit Search  ", desc_style),
-                Span::styled("Type to filter", desc_style),
+                Span::styled(" Cancel", desc_style),
             ]),
         ),
         InputMode::Sftp => (
-            Line::from(vec![
-                Span::styled("↑:", key_style),
-                Span::styled(" Up  ", desc_style),
-                Span::styled("↓:", key_style),
-                Span::styled(" Down  ", desc_style),
-                Span::styled("[Enter]", key_style),
-                Span::styled(" Connect", desc_style),
-            ]),
-            Line::from(vec![
-                Span::styled("[Esc]", key_style),
-                Span::styled(" Exit Search  ", desc_style),
-                Span::styled("Type to filter", desc_style),
-            ]),
+            hint_line(
+                app.keymap.hints(
+                    Mode::Sftp,
+                    &[
+                        (Action::Up, "Up"),
+                        (Action::Down, "Down"),
+                        (Action::Open, "Open"),
+                        (Action::Back, "Back"),
+                    ],
+                ),
+                key_style,
+                desc_style,
+            ),
+            hint_line(
+                app.keymap.hints(
+                    Mode::Sftp,
+                    &[
+                        (Action::Upload, "Upload"),
+                        (Action::Download, "Download"),
+                        (Action::Quit, "Quit SFTP"),
+                    ],
+                ),
+                key_style,
+                desc_style,
+            ),
         ),
     };
 
@@ -92,3 +139,16 @@ pub fn draw_footer<B: Backend>(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(nav_help, footer[0]);
     f.render_widget(action_help, footer[1]);
 }
+
+/// `[key] Description` pairs separated by two spaces
+fn hint_line(hints: Vec<(String, &str)>, key_style: Style, desc_style: Style) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (i, (keys, description)) in hints.into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw("  "));
+        }
+        spans.push(Span::styled(format!("[{}]", keys), key_style));
+        spans.push(Span::styled(format!(" {}", description), desc_style));
+    }
+    Line::from(spans)
+}
@@ -0,0 +1,168 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use super::hosts_list::centered_rect;
+use crate::app::HostForm;
+use crate::config::HostsFileError;
+use crate::theme::Palette;
+
+pub fn draw_host_form(f: &mut Frame, form: &HostForm, theme: &Palette) {
+    // Borders, blank line, error line and hints around the fields
+    let height = form.fields.len() as u16 + 6;
+    let area = centered_rect(60, height, f.size());
+
+    let label_style = Style::default().fg(theme.secondary);
+    let focused_label_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+
+    let mut content: Vec<Line> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, (field, value))| {
+            let focused = i == form.focused;
+            let mut spans = vec![
+                Span::styled(
+                    format!("{} {:<14}", if focused { ">" } else { " " }, field.label()),
+                    if focused { focused_label_style } else { label_style },
+                ),
+                Span::styled(value.clone(), Style::default().fg(theme.text)),
+            ];
+            if focused {
+                spans.push(Span::styled("█", Style::default().fg(theme.highlight)));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    content.push(Line::from(""));
+    content.push(match &form.error {
+        Some(error) => Line::from(Span::styled(
+            error.clone(),
+            Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+        )),
+        None => Line::from(""),
+    });
+    content.push(Line::from(vec![
+        Span::styled("[Tab/↑↓]", key_style),
+        Span::styled(" Field  ", desc_style),
+        Span::styled("[Ctrl+U]", key_style),
+        Span::styled(" Clear  ", desc_style),
+        Span::styled("[Enter]", key_style),
+        Span::styled(" Save  ", desc_style),
+        Span::styled("[Esc]", key_style),
+        Span::styled(" Cancel", desc_style),
+    ]));
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(form.title())
+        .title_style(
+            Style::default()
+                .fg(theme.primary)
+                .add_modifier(Modifier::BOLD),
+        )
+        .border_style(Style::default().fg(theme.primary));
+
+    let paragraph = Paragraph::new(content).block(block);
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+pub fn draw_delete_confirm(f: &mut Frame, alias: &str, theme: &Palette) {
+    let area = centered_rect(50, 5, f.size());
+
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+
+    let content = vec![
+        Line::from(vec![
+            Span::styled("Delete host ", Style::default().fg(theme.text)),
+            Span::styled(
+                alias.to_string(),
+                Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(" from hosts.toml?", Style::default().fg(theme.text)),
+        ]),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[y]", key_style),
+            Span::styled(" Delete  ", desc_style),
+            Span::styled("[any key]", key_style),
+            Span::styled(" Cancel", desc_style),
+        ]),
+    ];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Delete Host ")
+        .title_style(Style::default().fg(theme.error).add_modifier(Modifier::BOLD))
+        .border_style(Style::default().fg(theme.error));
+
+    let paragraph = Paragraph::new(content)
+        .block(block)
+        .alignment(ratatui::layout::Alignment::Center);
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+pub fn draw_hosts_file_error(f: &mut Frame, error: &HostsFileError, theme: &Palette) {
+    let area = centered_rect(70, 9, f.size());
+
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+
+    let location = match (error.line, error.column) {
+        (Some(line), Some(column)) => format!("Line {}, column {}", line, column),
+        _ => "Unknown location".to_string(),
+    };
+
+    let content = vec![
+        Line::from(Span::styled(
+            "hosts.toml could not be parsed",
+            Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(Span::styled(location, Style::default().fg(theme.warning))),
+        Line::from(Span::styled(
+            error.message.clone(),
+            Style::default().fg(theme.text),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[Enter]", key_style),
+            Span::styled(" Reopen editor at error  ", desc_style),
+            Span::styled("[Esc]", key_style),
+            Span::styled(" Keep current hosts", desc_style),
+        ]),
+    ];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Invalid hosts.toml ")
+        .title_style(Style::default().fg(theme.error).add_modifier(Modifier::BOLD))
+        .border_style(Style::default().fg(theme.error));
+
+    let paragraph = Paragraph::new(content)
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .alignment(ratatui::layout::Alignment::Center);
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,95 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use super::hosts_list::centered_rect;
+use crate::known_hosts::HostKeyInfo;
+use crate::theme::Palette;
+
+pub fn draw_host_key_dialog(f: &mut Frame, info: &HostKeyInfo, theme: &Palette) {
+    let area = centered_rect(70, 14, f.size());
+
+    // Changed keys are the dangerous case, make them stand out
+    let (accent, title, headline) = if info.changed {
+        (
+            theme.error,
+            " ⚠ Host Key Changed ",
+            "WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!",
+        )
+    } else {
+        (
+            theme.warning,
+            " 🔑 Unknown Host Key ",
+            "The authenticity of this host can't be established.",
+        )
+    };
+
+    let label_style = Style::default().fg(theme.secondary);
+    let key_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let desc_style = Style::default().fg(theme.secondary);
+
+    let mut content = vec![
+        Line::from(Span::styled(
+            headline,
+            Style::default().fg(accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("Host:        ", label_style),
+            Span::styled(
+                format!("{} ({})", info.alias, info.known_hosts_name),
+                Style::default().fg(theme.text),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("Key type:    ", label_style),
+            Span::styled(info.key_type.clone(), Style::default().fg(theme.text)),
+        ]),
+        Line::from(vec![
+            Span::styled("Fingerprint: ", label_style),
+            Span::styled(
+                info.fingerprint.clone(),
+                Style::default()
+                    .fg(theme.success)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(""),
+    ];
+
+    if info.changed {
+        content.push(Line::from(Span::styled(
+            "Someone could be eavesdropping on you right now, or the host key was rotated.",
+            Style::default().fg(theme.error),
+        )));
+        content.push(Line::from(""));
+    }
+
+    content.push(Line::from(vec![
+        Span::styled("[o]", key_style),
+        Span::styled(" Accept once  ", desc_style),
+        Span::styled("[a]", key_style),
+        Span::styled(" Accept and save  ", desc_style),
+        Span::styled("[Esc]", key_style),
+        Span::styled(" Abort", desc_style),
+    ]));
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title_style(Style::default().fg(accent).add_modifier(Modifier::BOLD))
+        .border_style(Style::default().fg(accent));
+
+    let paragraph = Paragraph::new(content)
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .alignment(ratatui::layout::Alignment::Center);
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
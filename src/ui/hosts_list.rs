@@ -1,7 +1,6 @@
 use ratatui::{
-    backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style, Stylize},
+    style::{Modifier, Style, Stylize},
     text::{Line, Span},
     widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
     Frame,
@@ -10,6 +9,8 @@ use std::time::SystemTime;
 
 use crate::app::{App, InputMode, ActivePanel};
 use super::footer::draw_footer;
+use super::host_form::{draw_delete_confirm, draw_host_form, draw_hosts_file_error};
+use super::host_key_dialog::draw_host_key_dialog;
 use super::status_bar::draw_status_bar;
 
 fn _elapsed() -> u64 {
@@ -19,8 +20,9 @@ fn _elapsed() -> u64 {
         .as_secs()
 }
 
-pub fn draw<B: Backend>(f: &mut Frame, app: &mut App) {
+pub fn draw(f: &mut Frame, app: &mut App) {
     let size = f.size();
+    f.render_widget(Block::default().style(app.theme.base()), size);
 
     // Create a layout with three sections: main content, status bar, and footer
     let chunks = Layout::default()
@@ -33,21 +35,36 @@ pub fn draw<B: Backend>(f: &mut Frame, app: &mut App) {
         .split(size);
 
     // Draw the main content with two-panel layout
-    draw_hosts_list::<B>(f, app, chunks[0]);
+    draw_hosts_list(f, app, chunks[0]);
 
     // Draw the status bar
-    draw_status_bar::<B>(f, app, chunks[1]);
+    draw_status_bar(f, app, chunks[1]);
 
     // Draw the footer with navigation help
-    draw_footer::<B>(f, app, chunks[2]);
+    draw_footer(f, app, chunks[2]);
 
     // Draw loading overlay if needed
     if app.is_connecting {
-        draw_enhanced_loading_overlay::<B>(f, app);
+        draw_enhanced_loading_overlay(f, app);
+    }
+
+    if let Some(form) = &app.host_form {
+        draw_host_form(f, form, &app.theme);
+    }
+    if let Some(alias) = &app.pending_delete {
+        draw_delete_confirm(f, alias, &app.theme);
+    }
+    if let Some(error) = &app.hosts_file_error {
+        draw_hosts_file_error(f, error, &app.theme);
+    }
+
+    // Host key confirmation goes on top of everything
+    if let Some(prompt) = &app.host_key_prompt {
+        draw_host_key_dialog(f, &prompt.info, &app.theme);
     }
 }
 
-fn draw_hosts_list<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
+fn draw_hosts_list(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
@@ -55,15 +72,17 @@ fn draw_hosts_list<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
             Constraint::Percentage(70), // Hosts panel
         ].as_ref())
         .split(area);
+    app.list_areas.clear();
 
     // Draw groups panel
-    draw_groups_panel::<B>(f, app, chunks[0]);
+    draw_groups_panel(f, app, chunks[0]);
     
     // Draw hosts panel
-    draw_hosts_panel::<B>(f, app, chunks[1]);
+    draw_hosts_panel(f, app, chunks[1]);
 }
 
-fn draw_groups_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
+fn draw_groups_panel(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme;
     let is_active = app.active_panel == ActivePanel::Groups;
     let title = format!(
         " {} 🫂 Groups ",
@@ -80,13 +99,13 @@ fn draw_groups_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
             let (text_style, bg_style) = if is_selected {
                 (
                     Style::default()
-                        .fg(Color::Black)
-                        .bg(Color::Green)
+                        .fg(theme.on_fill())
+                        .bg(theme.primary)
                         .add_modifier(Modifier::BOLD),
-                    Style::default().bg(Color::Green)
+                    Style::default().bg(theme.primary)
                 )
             } else {
-                (Style::default().fg(Color::White), Style::default())
+                (Style::default().fg(theme.text), Style::default())
             };
             
             let spans = vec![
@@ -96,7 +115,7 @@ fn draw_groups_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
                     if is_selected {
                         text_style
                     } else {
-                        text_style.fg(Color::LightYellow).add_modifier(Modifier::BOLD)
+                        text_style.fg(theme.warning).add_modifier(Modifier::BOLD)
                     }
                 )
             ];
@@ -107,7 +126,7 @@ fn draw_groups_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
         .collect();
     
     let border_style = if is_active {
-        Style::default().fg(Color::Green)
+        Style::default().fg(theme.primary)
     } else {
         Style::default()
     };
@@ -120,9 +139,11 @@ fn draw_groups_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
     );
     
     f.render_stateful_widget(list, area, &mut app.group_list_state);
+    app.list_areas.push((ActivePanel::Groups, area));
 }
 
-fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
+fn draw_hosts_panel(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme;
     let is_search_mode = app.input_mode == InputMode::Search;
     let is_active = app.active_panel == ActivePanel::Hosts;
 
@@ -141,7 +162,7 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
         let search_block = Block::default()
             .borders(Borders::ALL)
             .title(search_title)
-            .border_style(Style::default().fg(Color::Yellow));
+            .border_style(Style::default().fg(theme.warning));
         
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -151,7 +172,7 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
 
         let search_text = format!("{} {}", app.search_query, cursor);
         let search_paragraph = Paragraph::new(search_text)
-            .style(Style::default().fg(Color::White))
+            .style(Style::default().fg(theme.text))
             .block(search_block);
         
         f.render_widget(search_paragraph, search_chunks[0]);
@@ -164,14 +185,14 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
 
         (
             search_chunks[1],
-            Style::default().fg(Color::Yellow),
+            Style::default().fg(theme.warning),
             results_title,
         )
     } else {
         // --- Normal Mode UI ---
         (
             area,
-            if is_active { Style::default().fg(Color::Green) } else { Style::default() },
+            if is_active { Style::default().fg(theme.primary) } else { Style::default() },
             format!(" {} 👤 Hosts ", if is_active { ">" } else { " " }),
         )
     };
@@ -212,13 +233,13 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
             let (text_style, bg_style) = if is_selected {
                 (
                     Style::default()
-                        .fg(Color::Black)
-                        .bg(if is_search_mode { Color::Yellow } else { Color::Green })
+                        .fg(theme.on_fill())
+                        .bg(if is_search_mode { theme.warning } else { theme.primary })
                         .add_modifier(Modifier::BOLD),
-                    Style::default().bg(if is_search_mode { Color::Yellow } else { Color::Green })
+                    Style::default().bg(if is_search_mode { theme.warning } else { theme.primary })
                 )
             } else {
-                (Style::default().fg(Color::White), Style::default())
+                (Style::default().fg(theme.text), Style::default())
             };
             
             let mut spans = vec![Span::styled(prefix, text_style)];
@@ -226,22 +247,23 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
             // Add host number
             spans.push(Span::styled(
                 format!("[{}] ", i + 1),
-                text_style.add_modifier(Modifier::BOLD).fg(if is_selected { Color::Black } else { Color::LightYellow })
+                text_style.add_modifier(Modifier::BOLD).fg(if is_selected { theme.on_fill() } else { theme.warning })
             ));
             
             // Add host alias with search highlighting if in search mode
             if is_search_mode && !app.search_query.is_empty() {
+                // matched_indices are char positions, track byte offsets for slicing
                 let mut last_idx = 0;
-                for (idx, char) in host.alias.chars().enumerate() {
-                    if filtered_host.matched_indices.contains(&idx) {
+                for (pos, (idx, char)) in host.alias.char_indices().enumerate() {
+                    if filtered_host.matched_indices.contains(&pos) {
                         if idx > last_idx {
                             spans.push(Span::styled(&host.alias[last_idx..idx], text_style));
                         }
                         spans.push(Span::styled(
                             char.to_string(),
-                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                            Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
                         ));
-                        last_idx = idx + 1;
+                        last_idx = idx + char.len_utf8();
                     }
                 }
                 if last_idx < host.alias.len() {
@@ -254,7 +276,7 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
 
             // Add host details
             let details = format!(" ({}@{}:{})", host.user, host.host, host.port.unwrap_or(22));
-            spans.push(Span::styled(details, text_style.fg(Color::Gray)));
+            spans.push(Span::styled(details, text_style.fg(theme.secondary)));
             
             let item_text = Line::from(spans);
             ListItem::new(item_text).style(bg_style)
@@ -269,7 +291,7 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
         };
         List::new(vec![ListItem::new(Span::styled(
             message,
-            Style::default().fg(Color::Gray).not_italic()
+            Style::default().fg(theme.secondary).not_italic()
         ))])
     } else {
         List::new(items)
@@ -283,9 +305,11 @@ fn draw_hosts_panel<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
     let list_widget = list.block(list_block);
     
     f.render_stateful_widget(list_widget, list_area, &mut app.host_list_state);
+    app.list_areas.push((ActivePanel::Hosts, list_area));
 }
 
-fn draw_enhanced_loading_overlay<B: Backend>(f: &mut Frame, app: &App) {
+fn draw_enhanced_loading_overlay(f: &mut Frame, app: &App) {
+    let theme = app.theme;
     let area = centered_rect(60, 10, f.size());
 
     // Get current time for animation
@@ -316,77 +340,77 @@ fn draw_enhanced_loading_overlay<B: Backend>(f: &mut Frame, app: &App) {
         };
         vec![
             Line::from(vec![
-                Span::styled("🔄 ", Style::default().fg(Color::Yellow)),
+                Span::styled("🔄 ", Style::default().fg(theme.warning)),
                 Span::styled(
                     "SFTP Initialization",
                     Style::default()
-                        .fg(Color::White)
+                        .fg(theme.text)
                         .add_modifier(Modifier::BOLD),
                 ),
             ]),
             Line::from(""),
             Line::from(vec![
-                Span::styled("📡 ", Style::default().fg(Color::Blue)),
+                Span::styled("📡 ", Style::default().fg(theme.highlight)),
                 Span::styled(
                     format!("{}{}", status_text, dots),
-                    Style::default().fg(Color::Cyan),
+                    Style::default().fg(theme.highlight),
                 ),
                 Span::raw(padding),
             ]),
             Line::from(""),
             Line::from(vec![
-                Span::styled("💡 ", Style::default().fg(Color::Yellow)),
+                Span::styled("💡 ", Style::default().fg(theme.warning)),
                 Span::styled(
                     "Please wait...",
-                    Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+                    Style::default().fg(theme.secondary).add_modifier(Modifier::DIM),
                 ),
             ]),
         ]
     } else if let Some(host) = &app.connecting_host {
         vec![
             Line::from(vec![
-                Span::styled("🔗 ", Style::default().fg(Color::Yellow)),
+                Span::styled("🔗 ", Style::default().fg(theme.warning)),
                 Span::styled(
                     format!("SSH Connection to {}", host.alias),
                     Style::default()
-                        .fg(Color::White)
+                        .fg(theme.text)
                         .add_modifier(Modifier::BOLD),
                 ),
             ]),
             Line::from(""),
             Line::from(vec![
-                Span::styled("📡 ", Style::default().fg(Color::Blue)),
+                Span::styled("📡 ", Style::default().fg(theme.highlight)),
                 Span::styled(
                     format!("{}{}", status_text, dots),
-                    Style::default().fg(Color::Cyan),
+                    Style::default().fg(theme.highlight),
                 ),
                 Span::raw(padding),
             ]),
             Line::from(""),
             Line::from(vec![
-                Span::styled("Host: ", Style::default().fg(Color::Gray)),
+                Span::styled("Host: ", Style::default().fg(theme.secondary)),
                 Span::styled(
                     format!("{}@{}:{}", host.user, host.host, host.port.unwrap_or(22)),
-                    Style::default().fg(Color::Green),
+                    Style::default().fg(theme.success),
                 ),
             ]),
             Line::from(""),
             Line::from(vec![
-                Span::styled("💡 ", Style::default().fg(Color::Yellow)),
+                Span::styled("💡 ", Style::default().fg(theme.warning)),
                 Span::styled(
                     "Press Ctrl+C to cancel",
-                    Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+                    Style::default().fg(theme.secondary).add_modifier(Modifier::DIM),
                 ),
             ]),
         ]
     } else {
         vec![
             Line::from(vec![
-                Span::styled("🔗 ", Style::default().fg(Color::Yellow)),
+                Span::styled("🔗 ", Style::default().fg(theme.warning)),
                 Span::styled(
                     "SSH Connection",
                     Style::default()
-                        .fg(Color::White)
+                        .fg(theme.text)
                         .add_modifier(Modifier::BOLD),
                 ),
             ]),
@@ -394,7 +418,7 @@ fn draw_enhanced_loading_overlay<B: Backend>(f: &mut Frame, app: &App) {
             Line::from(vec![
                 Span::styled(
                     format!("{}{}", status_text, dots),
-                    Style::default().fg(Color::Cyan),
+                    Style::default().fg(theme.highlight),
                 ),
                 Span::raw(padding),
             ]),
@@ -406,10 +430,10 @@ fn draw_enhanced_loading_overlay<B: Backend>(f: &mut Frame, app: &App) {
         .title(" SSH Manager ")
         .title_style(
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.warning)
                 .add_modifier(Modifier::BOLD),
         )
-        .border_style(Style::default().fg(Color::Yellow));
+        .border_style(Style::default().fg(theme.warning));
 
     let paragraph = Paragraph::new(loading_content)
         .block(block)
@@ -420,7 +444,7 @@ fn draw_enhanced_loading_overlay<B: Backend>(f: &mut Frame, app: &App) {
     f.render_widget(paragraph, area);
 }
     
-fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
+pub(crate) fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
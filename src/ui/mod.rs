@@ -1,3 +1,5 @@
 pub mod footer;
+pub mod host_form;
+pub mod host_key_dialog;
 pub mod hosts_list;
 pub mod status_bar;
\ No newline at end of file
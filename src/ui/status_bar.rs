@@ -1,14 +1,14 @@
 
 use ratatui::{
-    backend::Backend,
     layout::{Rect},
-    style::{Color, Style},
+    style::Style,
     widgets::{Paragraph},
     Frame,
 };
 use crate::app::{App};
 
-pub fn draw_status_bar<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
+pub fn draw_status_bar(f: &mut Frame, app: &mut App, area: Rect) {
+  let theme = app.theme;
   if let Some((message, timestamp)) = &app.status_message {
       // Clear messages older than 5 seconds (except when connecting)
       let should_show = if app.is_connecting {
@@ -21,18 +21,18 @@ pub fn draw_status_bar<B: Backend>(f: &mut Frame, app: &mut App, area: Rect) {
           let style = if message.to_lowercase().contains("error")
               || message.to_lowercase().contains("failed")
           {
-              Style::default().fg(Color::Red)
+              Style::default().fg(theme.error)
           } else if message.to_lowercase().contains("success")
               || message.to_lowercase().contains("successful")
               || message.to_lowercase().contains("ended")
           {
-              Style::default().fg(Color::Green)
+              Style::default().fg(theme.success)
           } else if message.to_lowercase().contains("connecting")
               || message.to_lowercase().contains("testing")
           {
-              Style::default().fg(Color::Cyan)
+              Style::default().fg(theme.highlight)
           } else {
-              Style::default().fg(Color::Yellow)
+              Style::default().fg(theme.warning)
           };
 
           let paragraph = Paragraph::new(message.as_str())